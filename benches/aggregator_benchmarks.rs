@@ -1,13 +1,16 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
 use rstparser::aggregator::{Aggregator, DirectiveWithSource, GroupBy};
+use rstparser::directive_functions::{AllDirectivesMap, DirectivesMapOf, FunctionApplicator, PlainDirectivesMap, StoredDirective};
+use rstparser::link_data::{LinkConfig, LinkGraph, LinkTypeConfig};
 use rstparser::parser::Directive;
-use std::collections::HashMap;
+use indexmap::IndexMap;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use tempfile::tempdir;
 
 // Helper function to create a test directive
 fn create_test_directive(name: &str, index: usize, options_count: usize, content_size: usize) -> Directive {
-    let mut options = HashMap::new();
+    let mut options = IndexMap::new();
     
     for i in 0..options_count {
         options.insert(format!("option{}", i), format!("value{}", i));
@@ -23,6 +26,7 @@ fn create_test_directive(name: &str, index: usize, options_count: usize, content
         arguments: format!("arg{}", index),
         options,
         content,
+        comments: Vec::new(),
     }
 }
 
@@ -47,6 +51,11 @@ fn create_test_directives_with_source(
                 directive,
                 source_file: source_file.to_string(),
                 line_number: Some(i * 10), // Arbitrary line number
+                id: format!("{}:{}:{}", source_file, name, i * 10),
+                file_metadata: None,
+                depth: 0,
+                byte_range: None,
+                source_byte_range: None,
             });
         }
     }
@@ -91,12 +100,13 @@ fn bench_aggregate_to_json(c: &mut Criterion) {
     );
     
     // Benchmark different grouping strategies with small dataset
-    for group_by in [GroupBy::DirectiveName, GroupBy::All, GroupBy::SourceFile].iter() {
+    for group_by in [GroupBy::DirectiveName, GroupBy::All, GroupBy::SourceFile] {
         let output_subdir = output_path.join(format!("small_{:?}", group_by));
-        let aggregator = Aggregator::new(&output_subdir, *group_by);
+        let benchmark_name = format!("{:?}", group_by);
+        let aggregator = Aggregator::new(&output_subdir, group_by);
         
         group.bench_with_input(
-            BenchmarkId::new("small", format!("{:?}", group_by)), 
+            BenchmarkId::new("small", benchmark_name), 
             &directives_small,
             |b, directives| {
                 b.iter(|| aggregator.aggregate_to_json(black_box(directives.clone())))
@@ -105,12 +115,13 @@ fn bench_aggregate_to_json(c: &mut Criterion) {
     }
     
     // Benchmark different grouping strategies with medium dataset
-    for group_by in [GroupBy::DirectiveName, GroupBy::All, GroupBy::SourceFile].iter() {
+    for group_by in [GroupBy::DirectiveName, GroupBy::All, GroupBy::SourceFile] {
         let output_subdir = output_path.join(format!("medium_{:?}", group_by));
-        let aggregator = Aggregator::new(&output_subdir, *group_by);
+        let benchmark_name = format!("{:?}", group_by);
+        let aggregator = Aggregator::new(&output_subdir, group_by);
         
         group.bench_with_input(
-            BenchmarkId::new("medium", format!("{:?}", group_by)), 
+            BenchmarkId::new("medium", benchmark_name), 
             &directives_medium,
             |b, directives| {
                 b.iter(|| aggregator.aggregate_to_json(black_box(directives.clone())))
@@ -119,12 +130,13 @@ fn bench_aggregate_to_json(c: &mut Criterion) {
     }
     
     // Benchmark different grouping strategies with large dataset
-    for group_by in [GroupBy::DirectiveName, GroupBy::All, GroupBy::SourceFile].iter() {
+    for group_by in [GroupBy::DirectiveName, GroupBy::All, GroupBy::SourceFile] {
         let output_subdir = output_path.join(format!("large_{:?}", group_by));
-        let aggregator = Aggregator::new(&output_subdir, *group_by);
+        let benchmark_name = format!("{:?}", group_by);
+        let aggregator = Aggregator::new(&output_subdir, group_by);
         
         group.bench_with_input(
-            BenchmarkId::new("large", format!("{:?}", group_by)), 
+            BenchmarkId::new("large", benchmark_name), 
             &directives_large,
             |b, directives| {
                 b.iter(|| aggregator.aggregate_to_json(black_box(directives.clone())))
@@ -135,5 +147,44 @@ fn bench_aggregate_to_json(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(aggregator_benches, bench_aggregate_to_json);
+// Applies the link functions and serializes all directives, as a non-watch run does
+fn link_and_write<D: StoredDirective>(applicator: &FunctionApplicator, aggregator: &Aggregator, directives_map: &DirectivesMapOf<D>) {
+    let mut link_graph = LinkGraph::new();
+    applicator.apply_to_all(directives_map, &mut link_graph);
+    aggregator.write_map_to_writer(directives_map, &link_graph, std::io::sink()).unwrap();
+}
+
+fn bench_directive_map_storage(c: &mut Criterion) {
+    let mut group = c.benchmark_group("directive_map_storage");
+
+    let mut directives = create_test_directives_with_source(&["directive1", "directive2", "directive3"], 500, 5, 5, &["file1.rst", "file2.rst", "file3.rst"]);
+    // Link each directive to its predecessor, so that the link functions have work to do
+    for index in 1..directives.len() {
+        let previous_id = directives[index - 1].id.clone();
+        directives[index].directive.options.insert("links".to_string(), previous_id);
+    }
+    let link_config = LinkConfig {
+        link_types: vec![LinkTypeConfig { name: "links".to_string(), custom_backlink_suffix: None }],
+        ..Default::default()
+    };
+    let applicator = FunctionApplicator::new(Arc::new(link_config));
+    // Nothing is written to the output directory, the directives go to a sink
+    let output_dir = tempdir().unwrap();
+    let aggregator = Aggregator::new(output_dir.path(), GroupBy::All);
+
+    let mut plain_map: PlainDirectivesMap = PlainDirectivesMap::new();
+    let mut shared_map: AllDirectivesMap = AllDirectivesMap::new();
+    for dws in directives {
+        let file = PathBuf::from(&dws.source_file);
+        shared_map.entry(file.clone()).or_default().insert(dws.id.clone(), Arc::new(Mutex::new(dws.clone())));
+        plain_map.entry(file).or_default().insert(dws.id.clone(), dws);
+    }
+
+    group.bench_function("arc_mutex", |b| b.iter(|| link_and_write(&applicator, &aggregator, black_box(&shared_map))));
+    group.bench_function("plain", |b| b.iter(|| link_and_write(&applicator, &aggregator, black_box(&plain_map))));
+
+    group.finish();
+}
+
+criterion_group!(aggregator_benches, bench_aggregate_to_json, bench_directive_map_storage);
 criterion_main!(aggregator_benches);
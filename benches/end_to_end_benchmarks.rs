@@ -4,11 +4,11 @@ use rstparser::processor::Processor;
 use rstparser::aggregator::{Aggregator, GroupBy};
 use std::fs::{self, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tempfile::tempdir;
 
 // Helper function to create a test RST file with specified content
-fn create_test_file(dir_path: &PathBuf, filename: &str, content: &str) -> PathBuf {
+fn create_test_file(dir_path: &Path, filename: &str, content: &str) -> PathBuf {
     let file_path = dir_path.join(filename);
     let mut file = File::create(&file_path).unwrap();
     file.write_all(content.as_bytes()).unwrap();
@@ -39,7 +39,7 @@ fn create_rst_with_directives(directive_names: &[&str], directives_per_name: usi
 
 // Helper function to create a directory structure with test files
 fn create_test_directory_structure(
-    root_dir: &PathBuf,
+    root_dir: &Path,
     depth: usize,
     files_per_dir: usize,
     directive_names: &[&str],
@@ -51,7 +51,7 @@ fn create_test_directory_structure(
     // Create files in the current directory
     for i in 0..files_per_dir {
         let content = create_rst_with_directives(directive_names, directives_per_name, content_size);
-        let file_path = create_test_file(root_dir, &format!("file_{}.rst", i), &content);
+        let _file_path = create_test_file(root_dir, &format!("file_{}.rst", i), &content);
         total_files += 1;
     }
     
@@ -121,7 +121,7 @@ fn bench_end_to_end(c: &mut Criterion) {
                 
                 // Step 2: Process files to find directives
                 let processor = Processor::new(directive_names.iter().map(|&s| s.to_string()).collect());
-                let directives = processor.process_files(black_box(files)).unwrap();
+                let (directives, _) = processor.process_files(black_box(files));
                 
                 // Step 3: Aggregate directives to JSON files
                 let aggregator = Aggregator::new(&output_dir, GroupBy::DirectiveName);
@@ -2,12 +2,12 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion, Benchmark
 use rstparser::file_walker::FileWalker;
 use std::fs::{self, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::Path;
 use tempfile::tempdir;
 
 // Helper function to create a directory structure with test files
 fn create_test_directory_structure(
-    root_dir: &PathBuf,
+    root_dir: &Path,
     depth: usize,
     files_per_dir: usize,
     extensions: &[&str],
@@ -1,6 +1,5 @@
 use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
-use rstparser::parser::parse_rst_multiple; // Removed unused parse_rst
-use std::collections::HashMap;
+use rstparser::parser::{has_any_directive, parse_rst_multiple}; // Removed unused parse_rst
 
 // Helper function to create RST content with a single directive
 fn create_rst_with_single_directive(directive_name: &str, content_size: usize) -> String {
@@ -16,32 +15,6 @@ fn create_rst_with_single_directive(directive_name: &str, content_size: usize) -
     rst
 }
 
-// Helper function to create RST content with multiple different directives
-fn create_rst_with_multiple_different_directives(
-    directive_names: &[&str],
-    content_size: usize,
-) -> String {
-    let mut rst = String::new();
-
-    for (i, &name) in directive_names.iter().enumerate() {
-        rst.push_str(&format!(".. {}::\n", name));
-        rst.push_str(&format!("   :option{}: value{}\n\n", i, i));
-
-        // Add content of specified size
-        for j in 0..content_size {
-            rst.push_str(&format!(
-                "   Line {} of content for directive {}.\n",
-                j, name
-            ));
-        }
-
-        // Add some text between directives
-        rst.push_str("\nSome text between directives.\n\n");
-    }
-
-    rst
-}
-
 // Helper function to create RST content with multiple instances of each specified directive type
 fn create_rst_with_multiple_instances_of_directives(
     directive_names: &[&str], // The unique directive types
@@ -138,5 +111,22 @@ fn bench_parse_rst_multiple(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(parser_benches, bench_parse_rst, bench_parse_rst_multiple);
+fn bench_has_any_directive(c: &mut Criterion) {
+    let mut group = c.benchmark_group("no_match_file");
+
+    // A file full of other directives, none of which is the one we are looking for
+    let other_names = ["note", "warning", "tip", "code-block"];
+    let rst = create_rst_with_multiple_instances_of_directives(&other_names, 50, 10);
+
+    group.bench_with_input(BenchmarkId::new("has_any_directive", "200_directives"), &rst, |b, rst| {
+        b.iter(|| has_any_directive(black_box(rst), black_box(&["mydirective"])))
+    });
+    group.bench_with_input(BenchmarkId::new("parse_rst_multiple", "200_directives"), &rst, |b, rst| {
+        b.iter(|| parse_rst_multiple(black_box(rst), black_box(&["mydirective"])))
+    });
+
+    group.finish();
+}
+
+criterion_group!(parser_benches, bench_parse_rst, bench_parse_rst_multiple, bench_has_any_directive);
 criterion_main!(parser_benches);
@@ -1,12 +1,12 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
-use rstparser::processor::Processor;
-use std::fs::{self, File};
+use rstparser::processor::{DEFAULT_QUICK_SCAN_MARKER, Processor};
+use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tempfile::tempdir;
 
 // Helper function to create a test RST file with specified content
-fn create_test_file(dir_path: &PathBuf, filename: &str, content: &str) -> PathBuf {
+fn create_test_file(dir_path: &Path, filename: &str, content: &str) -> PathBuf {
     let file_path = dir_path.join(filename);
     let mut file = File::create(&file_path).unwrap();
     file.write_all(content.as_bytes()).unwrap();
@@ -99,5 +99,38 @@ fn bench_process_files(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(processor_benches, bench_process_file, bench_process_files);
+fn bench_quick_scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("quick_scan");
+    group.sample_size(20);
+
+    // 1000 Python files, only 100 of which contain `@rst` blocks
+    let temp_dir = tempdir().unwrap();
+    let temp_path = temp_dir.path().to_path_buf();
+    let mut file_paths = Vec::new();
+    for i in 0..1000 {
+        let mut content = String::new();
+        if i % 10 == 0 {
+            content.push_str("\"\"\"\n@rst\n.. directive1::\n   :option1: value1\n\n   Content.\n@endrst\n\"\"\"\n");
+        }
+        for j in 0..50 {
+            content.push_str(&format!("def function_{}():\n    \"\"\"Plain docstring {}.\"\"\"\n    return {}\n\n", j, j, j));
+        }
+        file_paths.push(create_test_file(&temp_path, &format!("module_{}.py", i), &content));
+    }
+
+    let targets = vec!["directive1".to_string()];
+    let full = Processor::new(targets.clone());
+    let quick = Processor::new(targets).with_quick_scan_marker(DEFAULT_QUICK_SCAN_MARKER);
+
+    group.bench_with_input(BenchmarkId::new("full_scan", 1000), &file_paths, |b, file_paths| {
+        b.iter(|| full.process_files(black_box(file_paths.clone())))
+    });
+    group.bench_with_input(BenchmarkId::new("quick_scan", 1000), &file_paths, |b, file_paths| {
+        b.iter(|| quick.process_files(black_box(file_paths.clone())))
+    });
+
+    group.finish();
+}
+
+criterion_group!(processor_benches, bench_process_file, bench_process_files, bench_quick_scan);
 criterion_main!(processor_benches);
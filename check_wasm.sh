@@ -0,0 +1,16 @@
+#!/bin/bash
+
+# Script to check that the crate without the default `fs` feature builds for the browser.
+# Installs the wasm32-unknown-unknown target with rustup if it is missing.
+
+set -e
+
+if ! rustup target list --installed | grep -q '^wasm32-unknown-unknown$'; then
+    echo "Installing the wasm32-unknown-unknown target..."
+    rustup target add wasm32-unknown-unknown
+fi
+
+echo "Checking the crate for wasm32-unknown-unknown without default features..."
+RUSTFLAGS="-D warnings" cargo check --target wasm32-unknown-unknown --no-default-features
+
+echo "Done!"
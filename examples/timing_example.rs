@@ -1,7 +1,9 @@
 use rstparser::parser::parse_rst_multiple; // Removed unused parse_rst
-use rstparser::timing::Timer;
+use rstparser::timing::{ScopedTimer, Timer, TimerSink, TimingRegistry};
 use rstparser::time_it;
 use rstparser::time_call;
+use rstparser::time_scope;
+use rstparser::timed;
 use std::fs;
 use std::path::PathBuf;
 use std::error::Error;
@@ -108,17 +110,38 @@ fn main() -> Result<(), Box<dyn Error>> {
     let large_rst = create_rst_with_multiple_directives("mydirective", 1000, 10);
     let file_path = create_test_file("large_test.rst", &large_rst);
     
-    // Time reading the file
-    let timer = Timer::new("Read file");
-    let content = fs::read_to_string(&file_path)?;
-    timer.report();
+    // Time reading the file; the ScopedTimer reports when the block ends, even if `?` returns early
+    let content = {
+        let _timer = ScopedTimer::new("Read file", TimerSink::Println);
+        fs::read_to_string(&file_path)?
+    };
     
-    // Time parsing all directives
-    let timer = Timer::new("Parse all directives");
-    let directives = parse_rst_multiple(&content, &["mydirective"]);
-    timer.report();
-    
-    println!("Found {} directives", directives.len());
+    // Time parsing all directives, keeping the duration instead of printing it
+    let (directives, duration) = timed!({ parse_rst_multiple(&content, &["mydirective"]) });
+    println!("Found {} directives in {:?}", directives.len(), duration);
+    println!();
+
+    // Example 5: Collect samples from several threads and report percentiles
+    println!("Example 5: Aggregated timings with a TimingRegistry");
+    println!("-------------------------------------------------");
+
+    let registry = TimingRegistry::new();
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            let registry = &registry;
+            let content = &content;
+            scope.spawn(move || {
+                // Records the time until the end of the closure as a "worker" sample
+                time_scope!(registry, "worker");
+                for _ in 0..5 {
+                    time_scope!(registry, "parse_rst_multiple");
+                    let _directives = parse_rst_multiple(content, &["mydirective"]);
+                }
+            });
+        }
+    });
+    print!("{}", registry.format_stats_table());
+    println!("{}", registry.stats_json()?);
     
     // Clean up
     // fs::remove_file(file_path)?;
@@ -0,0 +1,37 @@
+"""Tests of the Python bindings, see run_python_tests.sh."""
+
+import rstparser
+
+
+def test_parse_string():
+    text = "Title\n=====\n\n.. req:: Parse\n   :id: REQ-1\n\n   Content.\n\n.. note:: Ignored\n"
+    assert rstparser.parse_string(text, ["req"]) == [
+        {"name": "req", "arguments": "Parse", "options": {"id": "REQ-1"}, "content": "Content.", "line_number": 4},
+    ]
+
+
+def test_process_path_and_link_graph(tmp_path):
+    (tmp_path / "reqs.rst").write_text(".. req:: Base\n   :id: REQ-1\n\n.. req:: Derived\n   :id: REQ-2\n   :derives: REQ-1\n")
+    (tmp_path / "notes.txt").write_text(".. req:: Not searched\n   :id: REQ-3\n")
+    link_config = tmp_path / "links.toml"
+    link_config.write_text('[[links]]\nname = "derives"\n')
+
+    directives = rstparser.process_path(str(tmp_path), ["req"], extensions=["rst"], link_config=str(link_config))
+    assert [directive["id"] for directive in directives] == ["REQ-1", "REQ-2"]
+    assert directives[0]["options"]["derives_back"] == "REQ-2"
+    assert directives[1]["line_number"] == 4
+
+    graph = rstparser.link_graph(str(tmp_path), ["req"], extensions=["rst"], link_config=str(link_config))
+    assert graph["REQ-2"]["outgoing_links"] == {"derives": ["REQ-1"]}
+    assert graph["REQ-1"]["incoming_links"] == {"derives_back": ["REQ-2"]}
+
+
+def test_errors_are_raised(tmp_path):
+    link_config = tmp_path / "links.toml"
+    link_config.write_text("[[links]\n")
+    try:
+        rstparser.link_graph(str(tmp_path), ["req"], link_config=str(link_config))
+    except RuntimeError as error:
+        assert "links.toml" in str(error)
+        return
+    raise AssertionError("an invalid link configuration raises RuntimeError")
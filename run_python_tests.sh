@@ -0,0 +1,17 @@
+#!/bin/bash
+
+# Script to build the Python bindings into a virtual environment and run their tests.
+# Needs Python 3 with network access to install maturin and pytest.
+
+set -e
+VENV_DIR="${VENV_DIR:-target/python-venv}"
+
+echo "Building the Python bindings..."
+python3 -m venv "$VENV_DIR"
+"$VENV_DIR/bin/pip" install --quiet maturin pytest
+"$VENV_DIR/bin/maturin" develop --quiet
+
+echo "Running the Python binding tests..."
+"$VENV_DIR/bin/pytest" python/tests
+
+echo "Done!"
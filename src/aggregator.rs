@@ -1,33 +1,131 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
 use serde::{Serialize, Deserialize};
 use crate::parser::Directive; // This should be fine as parser is a sibling module
-use crate::link_data::LinkGraph; // Using rstparser:: as per compiler hints
+use crate::link_data::{topo_order, LinkGraph}; // Using rstparser:: as per compiler hints
+#[cfg(feature = "fs")]
+use crate::link_data::connected_components;
+use crate::directive_functions::{DirectivesMapOf, StoredDirective};
+use crate::error::RstParserError;
+use crate::rst_renderer::render_html;
+#[cfg(feature = "fs")]
+use crate::directive_functions::AllDirectivesMap;
+#[cfg(feature = "fs")]
 use std::sync::{Arc, Mutex};
+use tera::Tera;
+use thiserror::Error;
+use indexmap::IndexMap;
+#[cfg(feature = "fs")]
+use rayon::prelude::*;
+#[cfg(feature = "fs")]
+use log::debug;
+#[cfg(feature = "fs")]
+use crate::timing::{format_duration, Timer};
+use crate::timing::stage_span;
 
 /// A struct representing a directive with its source file information
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DirectiveWithSource {
     pub directive: Directive,
     pub source_file: String, // Should be canonical path
-    pub line_number: Option<usize>, // Optional line number where the directive was found
+    /// One-based line of the directive's `.. name::` marker in the RST that was parsed; for
+    /// source files other than `.rst` this is the line within the extracted RST blocks
+    pub line_number: Option<usize>,
     pub id: String, // Unique ID for this directive instance
+    /// Front-matter of the source file, if front-matter parsing is enabled and the file has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_metadata: Option<serde_json::Value>,
+    /// Number of directives this one is nested inside, 0 for top-level directives
+    #[serde(default)]
+    pub depth: usize,
+    /// Bytes of the directive block in the RST that was parsed, see `SpannedDirective::byte_range`
+    #[serde(skip)]
+    pub byte_range: Option<Range<usize>>,
+    /// The same bytes in the source file; only set for `.rst` files whose text was parsed unchanged
+    #[serde(skip)]
+    pub source_byte_range: Option<Range<usize>>,
 }
 
-/// A struct specifically for JSON output, potentially enriched with link data.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct DirectiveOutput {
+impl DirectiveWithSource {
+    /// A short, human-readable form of the ID for logs and tables: the custom `:id:` value
+    /// if one was used, otherwise the `name:line` tail of a generated `path:name:line` ID.
+    pub fn display_id(&self) -> &str {
+        self.id
+            .strip_prefix(self.source_file.as_str())
+            .and_then(|rest| rest.strip_prefix(':'))
+            .filter(|rest| !rest.is_empty())
+            .unwrap_or(&self.id)
+    }
+
+    /// Only the file name component of `source_file`.
+    pub fn short_source_file(&self) -> &str {
+        Path::new(&self.source_file)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&self.source_file)
+    }
+}
+
+/// A single line for logs: `id (name at file:line)`, with the short forms of `display_id` and
+/// `short_source_file`.
+impl fmt::Display for DirectiveWithSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({} at {}", self.display_id(), self.directive.name, self.short_source_file())?;
+        if let Some(line_number) = self.line_number {
+            write!(f, ":{}", line_number)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Orders directives by source position: source file, then line number, then the directive itself.
+/// The ID is only used as a final tie-breaker to stay consistent with `Eq`.
+impl Ord for DirectiveWithSource {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.source_file
+            .cmp(&other.source_file)
+            .then_with(|| self.line_number.cmp(&other.line_number))
+            .then_with(|| self.directive.cmp(&other.directive))
+            .then_with(|| self.id.cmp(&other.id))
+    }
+}
+
+impl PartialOrd for DirectiveWithSource {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A directive as written to the JSON output files, with the backlink options of the link graph
+/// added after its own options. Read written files back with `Aggregator::load_outputs`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirectiveOutput {
     // Fields from Directive
-    name: String,
-    arguments: String,
-    options: HashMap<String, String>, // Will include original + backlinks
-    content: String,
+    pub name: String,
+    pub arguments: String,
+    pub options: IndexMap<String, String>, // Source order, followed by backlinks
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<String>, // Only set with `ParseOptions::keep_option_comments`
     // Fields from DirectiveWithSource
-    source_file: String,
-    line_number: Option<usize>,
-    id: String,
+    pub source_file: String,
+    pub line_number: Option<usize>,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_metadata: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<usize>, // Only set with `Aggregator::with_depth`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>, // Only set with `Aggregator::with_summary`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_html: Option<String>, // Only set with `Aggregator::with_rendered_content`
+    #[serde(skip)]
+    custom_group: Option<String>, // File stem from `GroupBy::Custom`
 }
 
 impl From<&DirectiveWithSource> for DirectiveOutput {
@@ -37,26 +135,176 @@ impl From<&DirectiveWithSource> for DirectiveOutput {
             arguments: dws.directive.arguments.clone(),
             options: dws.directive.options.clone(), // Start with original options
             content: dws.directive.content.clone(),
+            comments: dws.directive.comments.clone(),
             source_file: dws.source_file.clone(),
             line_number: dws.line_number,
             id: dws.id.clone(),
+            file_metadata: dws.file_metadata.clone(),
+            depth: None,
+            summary: None,
+            content_html: None,
+            custom_group: None,
+        }
+    }
+}
+
+impl DirectiveOutput {
+    /// Converts a directive read back from an output file, without the `backlink_fields`
+    /// options that were added while writing it.
+    pub fn into_directive_with_source(self, backlink_fields: &[String]) -> DirectiveWithSource {
+        let mut options = self.options;
+        for field in backlink_fields {
+            options.shift_remove(field);
+        }
+        DirectiveWithSource {
+            directive: Directive { name: self.name, arguments: self.arguments, options, content: self.content, comments: self.comments },
+            source_file: self.source_file,
+            line_number: self.line_number,
+            id: self.id,
+            file_metadata: self.file_metadata,
+            depth: self.depth.unwrap_or(0),
+            byte_range: None,
+            source_byte_range: None,
         }
     }
 }
 
+/// Why directives could not be aggregated into output files, carried by
+/// `RstParserError::Aggregation`.
+#[derive(Debug, Error)]
+pub enum AggregatorError {
+    /// Creating the output directory, reading the template or writing a file failed.
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    /// Serializing directives to JSON failed.
+    #[error("Cannot serialize directives: {0}")]
+    Serialize(#[from] serde_json::Error),
+    /// The output directory path exists but is not a directory.
+    #[error("Output path {} is not a directory", .0.display())]
+    InvalidOutputPath(PathBuf),
+    /// The output template could not be compiled or rendered.
+    #[error("{}", with_sources(.0))]
+    Template(#[from] tera::Error),
+    /// Directives could not be ordered by the topological order `field` because these IDs form a cycle.
+    #[error("Cannot order directives by '{field}', these IDs form a cycle: {}", ids.join(", "))]
+    Cycle { field: String, ids: Vec<String> },
+    /// Directives are grouped into a file that is also written for `with_objects_inventory` or
+    /// `with_components`, e.g. `objects.json` for directives named `objects`.
+    #[error("Directives grouped into {0} would be overwritten by the file of the same name written next to them")]
+    ReservedFileName(String),
+}
+
+/// `err` followed by its sources: Tera keeps the actual problem, e.g. an unknown variable, in
+/// the error's sources.
+fn with_sources(err: &tera::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = std::error::Error::source(err);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
 
 /// A struct to handle aggregation of directives into JSON files
 pub struct Aggregator {
     output_dir: PathBuf,
     group_by: GroupBy,
+    template_path: Option<PathBuf>,
+    topo_order_field: Option<String>,
+    include_depth: bool,
+    include_summary: bool,
+    rendered_content: bool,
+    output_format: OutputFormat,
+    line_ending: LineEnding,
+    bom: bool,
+    exclusion: Option<(String, String)>,
+    objects_inventory: bool,
+    components: bool,
+    strip_prefix: Option<PathBuf>,
+}
+
+/// Context passed to output templates, once per output file.
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    directives: &'a [DirectiveOutput],
+    link_graph: &'a LinkGraph,
+}
+
+/// Format of the output files written without a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    /// One row per directive with `id`, `name`, `arguments`, `source_file`, `line_number` and
+    /// a column for every other option key of the file's directives, in sorted order.
+    Csv,
+}
+
+/// Line ending of the written output files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+/// UTF-8 byte order mark written before the contents with `Aggregator::with_bom`.
+#[cfg(feature = "fs")]
+const UTF8_BOM: &str = "\u{feff}";
+
+/// Name of the inventory written by `Aggregator::write_objects_inventory`.
+pub const OBJECTS_INVENTORY_FILE: &str = "objects.json";
+
+/// Name of the file written by `Aggregator::write_components`.
+pub const COMPONENTS_FILE: &str = "_components.json";
+
+/// How another documentation build can refer to a directive, see `Aggregator::write_objects_inventory`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InventoryEntry {
+    pub id: String,
+    pub name: String,
+    pub source_file: String,
+    /// HTML anchor of the directive's target, see `anchor_for_id`
+    pub anchor: String,
+}
+
+/// The anchor docutils generates for a target named `id`: lowercased, with every run of
+/// characters other than ASCII letters and digits replaced by `-` and no leading or trailing
+/// `-`, so `REQ_001.a` becomes `req-001-a`.
+pub fn anchor_for_id(id: &str) -> String {
+    let mut anchor = String::with_capacity(id.len());
+    for c in id.chars() {
+        if c.is_ascii_alphanumeric() {
+            anchor.push(c.to_ascii_lowercase());
+        } else if !anchor.is_empty() && !anchor.ends_with('-') {
+            anchor.push('-');
+        }
+    }
+    if anchor.ends_with('-') {
+        anchor.pop();
+    }
+    anchor
 }
 
 /// Enum to specify how directives should be grouped in output files
-#[derive(Debug, Clone, Copy)]
 pub enum GroupBy {
     DirectiveName,
     All,
     SourceFile,
+    /// Group by the file stem the function returns for each directive.
+    Custom(Box<dyn Fn(&DirectiveWithSource) -> String + Send + Sync>),
+}
+
+impl fmt::Debug for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupBy::DirectiveName => write!(f, "DirectiveName"),
+            GroupBy::All => write!(f, "All"),
+            GroupBy::SourceFile => write!(f, "SourceFile"),
+            GroupBy::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
 }
 
 impl Aggregator {
@@ -64,95 +312,634 @@ impl Aggregator {
         Aggregator {
             output_dir: output_dir.as_ref().to_path_buf(),
             group_by,
+            template_path: None,
+            topo_order_field: None,
+            include_depth: false,
+            include_summary: false,
+            rendered_content: false,
+            output_format: OutputFormat::Json,
+            line_ending: LineEnding::Lf,
+            bom: false,
+            exclusion: None,
+            objects_inventory: false,
+            components: false,
+            strip_prefix: None,
         }
     }
 
-    fn create_directive_outputs(
-        directives_map: &HashMap<PathBuf, HashMap<String, Arc<Mutex<DirectiveWithSource>>>>,
+    /// Write the output files in `format`; a template set with `with_template` takes precedence.
+    pub fn with_output_format(mut self, format: OutputFormat) -> Self {
+        self.output_format = format;
+        self
+    }
+
+    /// Add each directive's nesting `depth` (0 for top-level directives) to the output.
+    pub fn with_depth(mut self, include_depth: bool) -> Self {
+        self.include_depth = include_depth;
+        self
+    }
+
+    /// Leave directives whose option `key` is `value` (compared case-insensitively, e.g.
+    /// `:index: False` for `("index", "false")`) out of the output. They are still part of the
+    /// link graph, so the directives they link to keep their backlinks.
+    pub fn with_exclusion(mut self, key: &str, value: &str) -> Self {
+        self.exclusion = Some((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Whether `dws` is left out of the output by `with_exclusion`.
+    fn is_excluded(&self, dws: &DirectiveWithSource) -> bool {
+        self.exclusion.as_ref().is_some_and(|(key, value)| {
+            dws.directive.options.get(key).is_some_and(|option_value| option_value.trim().eq_ignore_ascii_case(value))
+        })
+    }
+
+    /// Write output files with `line_ending` instead of `\n` line endings.
+    pub fn with_line_ending(mut self, line_ending: LineEnding) -> Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    /// Start each output file with a UTF-8 byte order mark.
+    pub fn with_bom(mut self, bom: bool) -> Self {
+        self.bom = bom;
+        self
+    }
+
+    /// Also write the `objects.json` inventory of `write_objects_inventory` with
+    /// `aggregate_map_to_json_with_links`.
+    pub fn with_objects_inventory(mut self, objects_inventory: bool) -> Self {
+        self.objects_inventory = objects_inventory;
+        self
+    }
+
+    /// Also write the `_components.json` file of `write_components` with
+    /// `aggregate_map_to_json_with_links`.
+    pub fn with_components(mut self, components: bool) -> Self {
+        self.components = components;
+        self
+    }
+
+    /// Add a `summary` with the first paragraph of each directive's content to the output.
+    pub fn with_summary(mut self, include_summary: bool) -> Self {
+        self.include_summary = include_summary;
+        self
+    }
+
+    /// Add the content of each directive rendered as HTML to the output as `content_html`,
+    /// see `rst_renderer::render_html` for the supported markup.
+    pub fn with_rendered_content(mut self, rendered_content: bool) -> Self {
+        self.rendered_content = rendered_content;
+        self
+    }
+
+    /// With `GroupBy::All`, write directives so that each one comes after the directives it
+    /// links to via `field` (e.g. `depends_on`), otherwise in source order. Aggregation fails
+    /// if these links contain a cycle. Other groupings are not affected.
+    pub fn with_topo_order(mut self, field: &str) -> Self {
+        self.topo_order_field = Some(field.to_string());
+        self
+    }
+
+    /// Render each output file with a Tera template instead of serializing it as JSON. The
+    /// template gets the group's `directives` and the whole `link_graph`; values are not
+    /// escaped, and Tera's built-in filters such as `length` (e.g. `{{ d.id | length }}`) are
+    /// available. The output extension is taken from the template name without its last
+    /// extension, so `directive.json.tera` produces `<group>.json` files.
+    pub fn with_template<P: AsRef<Path>>(mut self, template_path: P) -> Self {
+        self.template_path = Some(template_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Remove `prefix` from the `source_file` of the outputs that start with it, e.g. `docs`
+    /// to write `guide/intro.rst` for `docs/guide/intro.rst`. Whole path components are
+    /// compared, so `docs` is not stripped from `docs-old/a.rst`. Outputs with stripped paths
+    /// cannot be merged with `load_output_directives`, which needs the original paths.
+    pub fn with_strip_prefix(mut self, prefix: PathBuf) -> Self {
+        self.strip_prefix = Some(prefix);
+        self
+    }
+
+    fn to_output(&self, dws: &DirectiveWithSource) -> DirectiveOutput {
+        let mut output_item = DirectiveOutput::from(dws);
+        if let Some(prefix) = &self.strip_prefix
+            && let Ok(stripped) = Path::new(&dws.source_file).strip_prefix(prefix)
+        {
+            output_item.source_file = stripped.to_string_lossy().into_owned();
+        }
+        if self.include_depth {
+            output_item.depth = Some(dws.depth);
+        }
+        if self.include_summary {
+            output_item.summary = first_paragraph(&dws.directive.content);
+        }
+        if self.rendered_content {
+            output_item.content_html = Some(render_html(&dws.directive.content));
+        }
+        if let GroupBy::Custom(group_fn) = &self.group_by {
+            output_item.custom_group = Some(group_fn(dws));
+        }
+        output_item
+    }
+
+    /// Converts the directives of the map that are not excluded, with the backlinks of
+    /// `link_graph` added to their options. The directives are converted in parallel, as only
+    /// reads are needed (sequentially without the `fs` feature).
+    fn create_directive_outputs<D: StoredDirective>(
+        &self,
+        directives_map: &DirectivesMapOf<D>,
         link_graph: &LinkGraph,
     ) -> Vec<DirectiveOutput> {
-        let mut output_directives: Vec<DirectiveOutput> = Vec::new();
-        for file_map in directives_map.values() {
-            for dws_arc in file_map.values() {
-                let dws_guard = dws_arc.lock().unwrap();
-                let mut output_item = DirectiveOutput::from(&*dws_guard); // Deref guard
-
-                // Add backlinks to options
-                if let Some(node_data) = link_graph.get(&dws_guard.id) {
-                    for (backlink_field_name, source_ids) in &node_data.incoming_links {
-                        if !source_ids.is_empty() {
-                            output_item.options.insert(backlink_field_name.clone(), source_ids.join(","));
-                        }
-                    }
+        let stored_directives: Vec<&D> = directives_map.values().flat_map(|file_map| file_map.values()).collect();
+        #[cfg(feature = "fs")]
+        let stored_directives_iter = stored_directives.par_iter();
+        #[cfg(not(feature = "fs"))]
+        let stored_directives_iter = stored_directives.iter();
+        stored_directives_iter
+            .filter_map(|stored_directive| self.to_output_with_backlinks(&stored_directive.read(), link_graph))
+            .collect()
+    }
+
+    /// The output of `dws` with its backlinks from `link_graph`, or `None` if it is excluded.
+    fn to_output_with_backlinks(&self, dws: &DirectiveWithSource, link_graph: &LinkGraph) -> Option<DirectiveOutput> {
+        if self.is_excluded(dws) {
+            return None;
+        }
+        let mut output_item = self.to_output(dws);
+
+        // Add backlinks to options
+        if let Some(node_data) = link_graph.get(&dws.id) {
+            // Sorted, so that the backlink options come in the same order on every run
+            let incoming_links: BTreeMap<&String, &Vec<String>> = node_data.incoming_links.iter().collect();
+            for (backlink_field_name, source_ids) in incoming_links {
+                if !source_ids.is_empty() {
+                    output_item.options.insert(backlink_field_name.clone(), source_ids.join(","));
                 }
-                output_directives.push(output_item);
             }
         }
-        output_directives
+        Some(output_item)
     }
-    
-    fn aggregate_outputs_to_json_internal(
+
+    /// Splits directives into output files according to `group_by`, returning the directives
+    /// written to each file keyed by its file name. With `GroupBy::SourceFile`, source files
+    /// sharing a file name share one output file.
+    fn group_outputs(
         &self,
         output_directives: Vec<DirectiveOutput>,
-    ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
-        fs::create_dir_all(&self.output_dir)?;
-        let mut output_files = Vec::new();
-
+        link_graph: &LinkGraph,
+    ) -> Result<HashMap<String, Vec<DirectiveOutput>>, AggregatorError> {
+        // File stem -> directives written to that file
+        let mut grouped: HashMap<String, Vec<DirectiveOutput>> = HashMap::new();
         match self.group_by {
             GroupBy::DirectiveName => {
-                let mut grouped: HashMap<String, Vec<&DirectiveOutput>> = HashMap::new();
-                for item_ref in &output_directives {
-                    grouped.entry(item_ref.name.clone()).or_default().push(item_ref);
-                }
-                for (name, group) in grouped {
-                    let file_path = self.output_dir.join(format!("{}.json", name));
-                    fs::write(&file_path, serde_json::to_string_pretty(&group)?)?;
-                    output_files.push(file_path);
+                for item in output_directives {
+                    grouped.entry(item.name.clone()).or_default().push(item);
                 }
             }
             GroupBy::All => {
-                let file_path = self.output_dir.join("all_directives.json");
-                fs::write(&file_path, serde_json::to_string_pretty(&output_directives)?)?;
-                output_files.push(file_path);
+                let mut all = output_directives;
+                if let Some(field) = &self.topo_order_field {
+                    all.sort_by(|a, b| (&a.source_file, a.line_number, &a.id).cmp(&(&b.source_file, b.line_number, &b.id)));
+                    let ids: Vec<String> = all.iter().map(|item| item.id.clone()).collect();
+                    let ordered_ids = topo_order(link_graph, field, &ids)
+                        .map_err(|ids| AggregatorError::Cycle { field: field.clone(), ids })?;
+                    let position: HashMap<&str, usize> = ordered_ids.iter().enumerate().map(|(index, id)| (id.as_str(), index)).collect();
+                    all.sort_by_key(|item| position[item.id.as_str()]);
+                }
+                grouped.insert("all_directives".to_string(), all);
             }
             GroupBy::SourceFile => {
-                let mut grouped: HashMap<String, Vec<&DirectiveOutput>> = HashMap::new();
-                for item_ref in &output_directives {
-                    grouped.entry(item_ref.source_file.clone()).or_default().push(item_ref);
+                for item in output_directives {
+                    let file_name = Path::new(&item.source_file).file_name().and_then(|n| n.to_str()).unwrap_or("unknown_source").to_string();
+                    grouped.entry(file_name).or_default().push(item);
                 }
-                for (source_file, group) in grouped {
-                    let file_name = Path::new(&source_file).file_name().and_then(|n| n.to_str()).unwrap_or("unknown_source").to_string();
-                    let file_path = self.output_dir.join(format!("{}.json", file_name));
-                    fs::write(&file_path, serde_json::to_string_pretty(&group)?)?;
-                    output_files.push(file_path);
+            }
+            GroupBy::Custom(_) => {
+                for item in output_directives {
+                    grouped.entry(item.custom_group.clone().unwrap_or_default()).or_default().push(item);
                 }
             }
         }
+
+        let extension = match (&self.template_path, self.output_format) {
+            (Some(template_path), _) => template_output_extension(template_path),
+            (None, OutputFormat::Json) => "json".to_string(),
+            (None, OutputFormat::Csv) => "csv".to_string(),
+        };
+        Ok(grouped.into_iter()
+            .map(|(file_stem, group)| (format!("{}.{}", file_stem, extension), group))
+            .collect())
+    }
+
+    /// Writes each group of `grouped` to its file in the output directory.
+    #[cfg(feature = "fs")]
+    fn write_output_files(
+        &self,
+        grouped: HashMap<String, Vec<DirectiveOutput>>,
+        link_graph: &LinkGraph,
+    ) -> Result<Vec<PathBuf>, AggregatorError> {
+        let timer = Timer::new("aggregate");
+        let span = stage_span!("write_outputs", directives, files);
+        if self.output_dir.exists() && !self.output_dir.is_dir() {
+            return Err(AggregatorError::InvalidOutputPath(self.output_dir.clone()));
+        }
+        fs::create_dir_all(&self.output_dir)?;
+
+        let template = self.read_template()?;
+        let renderer = new_renderer(template.as_deref())?;
+
+        let mut output_files = Vec::new();
+        let mut directive_count = 0;
+        for (file_name, group) in grouped {
+            let contents = match (&template, self.output_format) {
+                (Some(_), _) => render_template(&renderer, &group, link_graph)?,
+                (None, OutputFormat::Json) => serde_json::to_string_pretty(&group)?,
+                (None, OutputFormat::Csv) => to_csv(&group)?,
+            };
+            let file_path = self.output_dir.join(file_name);
+            fs::write(&file_path, self.finish_file_contents(contents))?;
+            output_files.push(file_path);
+            directive_count += group.len();
+        }
+        span.record_count("directives", directive_count);
+        span.record_count("files", output_files.len());
+        debug!("aggregate directives={} files={} duration={}", directive_count, output_files.len(), format_duration(timer.elapsed()));
         Ok(output_files)
     }
 
+    /// Writer-based variant of `write_output_files`, see `write_map_to_writer`.
+    fn write_outputs_internal<W: Write>(
+        &self,
+        grouped: HashMap<String, Vec<DirectiveOutput>>,
+        link_graph: &LinkGraph,
+        mut writer: W,
+    ) -> Result<(), AggregatorError> {
+        let mut grouped: Vec<(String, Vec<DirectiveOutput>)> = grouped.into_iter().collect();
+        grouped.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let template = self.read_template()?;
+        let renderer = new_renderer(template.as_deref())?;
+
+        if template.is_none() && self.output_format == OutputFormat::Csv {
+            let all: Vec<DirectiveOutput> = grouped.into_iter().flat_map(|(_, group)| group).collect();
+            writer.write_all(to_csv(&all)?.as_bytes())?;
+            writer.flush()?;
+            return Ok(());
+        }
+
+        for (_, group) in &grouped {
+            match (&template, &self.group_by) {
+                (Some(_), _) => writer.write_all(render_template(&renderer, group, link_graph)?.as_bytes())?,
+                (None, GroupBy::All) => {
+                    serde_json::to_writer_pretty(&mut writer, group)?;
+                    writeln!(writer)?;
+                }
+                (None, _) => {
+                    for item in group {
+                        serde_json::to_writer(&mut writer, item)?;
+                        writeln!(writer)?;
+                    }
+                }
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Applies the configured line ending and byte order mark to the contents of an output file.
+    #[cfg(feature = "fs")]
+    fn finish_file_contents(&self, contents: String) -> String {
+        let contents = match self.line_ending {
+            LineEnding::Lf => contents,
+            // Normalize first so existing `\r\n`s (e.g. from a template) are not doubled
+            LineEnding::CrLf => contents.replace("\r\n", "\n").replace('\n', "\r\n"),
+        };
+        if self.bom { format!("{}{}", UTF8_BOM, contents) } else { contents }
+    }
+
+    fn read_template(&self) -> Result<Option<String>, AggregatorError> {
+        match &self.template_path {
+            Some(path) => Ok(Some(fs::read_to_string(path)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Aggregates a flat list of directives without any link information.
+    #[cfg(feature = "fs")]
+    pub fn aggregate_to_json(&self, directives: Vec<DirectiveWithSource>) -> Result<Vec<PathBuf>, RstParserError> {
+        let output_directives = directives.par_iter().filter(|dws| !self.is_excluded(dws)).map(|dws| self.to_output(dws)).collect();
+        let link_graph = LinkGraph::new();
+        Ok(self.write_output_files(self.group_outputs(output_directives, &link_graph)?, &link_graph)?)
+    }
+
     // --- New methods for aggregating WITH link graph ---
+    #[cfg(feature = "fs")]
     pub fn aggregate_to_json_from_map_with_links(
         &self,
-        directives_map_arc: Arc<Mutex<HashMap<PathBuf, HashMap<String, Arc<Mutex<DirectiveWithSource>>>>>>,
+        directives_map_arc: Arc<Mutex<AllDirectivesMap>>,
         link_graph_arc: Arc<Mutex<LinkGraph>>,
-    ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    ) -> Result<Vec<PathBuf>, RstParserError> {
         let directives_map_guard = directives_map_arc.lock().unwrap();
         let link_graph_guard = link_graph_arc.lock().unwrap();
-        let output_directives = Self::create_directive_outputs(&directives_map_guard, &link_graph_guard);
+        let grouped = self.aggregate_to_map(&directives_map_guard, &link_graph_guard)?;
         drop(directives_map_guard);
-        drop(link_graph_guard);
-        self.aggregate_outputs_to_json_internal(output_directives)
+        Ok(self.write_output_files(grouped, &link_graph_guard)?)
+    }
+
+    /// Computes the files `aggregate_map_to_json_with_links` would write, sorted, without
+    /// writing anything or creating the output directory. Includes `objects.json` and
+    /// `_components.json` if they are enabled.
+    pub fn plan_output_files<D: StoredDirective>(&self, directives_map: &DirectivesMapOf<D>, link_graph: &LinkGraph) -> Result<Vec<PathBuf>, RstParserError> {
+        let grouped = self.aggregate_to_map(directives_map, link_graph)?;
+        self.check_reserved_file_names(&grouped)?;
+        let mut output_files: Vec<PathBuf> = grouped
+            .into_keys()
+            .chain(self.extra_output_files().into_iter().map(str::to_string))
+            .map(|file_name| self.output_dir.join(file_name))
+            .collect();
+        output_files.sort();
+        Ok(output_files)
+    }
+
+    /// The files `aggregate_map_to_json_with_links` writes next to the directive groups.
+    fn extra_output_files(&self) -> Vec<&'static str> {
+        [(self.objects_inventory, OBJECTS_INVENTORY_FILE), (self.components, COMPONENTS_FILE)]
+            .into_iter()
+            .filter_map(|(enabled, file_name)| enabled.then_some(file_name))
+            .collect()
+    }
+
+    /// Fails if a group of `grouped` would be written to one of the `extra_output_files`.
+    fn check_reserved_file_names(&self, grouped: &HashMap<String, Vec<DirectiveOutput>>) -> Result<(), AggregatorError> {
+        match self.extra_output_files().into_iter().find(|file_name| grouped.contains_key(*file_name)) {
+            Some(file_name) => Err(AggregatorError::ReservedFileName(file_name.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the directives to `writer` instead of creating files: with `GroupBy::All` as one
+    /// JSON array, with the other groupings as NDJSON (one directive per line, ordered by the
+    /// file they would have been written to). With a template, each group is rendered in turn;
+    /// with `OutputFormat::Csv`, all directives are written as one CSV table.
+    pub fn write_map_to_writer<D: StoredDirective, W: Write>(
+        &self,
+        directives_map: &DirectivesMapOf<D>,
+        link_graph: &LinkGraph,
+        writer: W,
+    ) -> Result<(), RstParserError> {
+        Ok(self.write_outputs_internal(self.aggregate_to_map(directives_map, link_graph)?, link_graph, writer)?)
     }
 
-    pub fn aggregate_map_to_json_with_links(
+    /// The directives `aggregate_map_to_json_with_links` would write, with their backlinks,
+    /// keyed by the name of the file in the output directory they would be written to, e.g.
+    /// `all_directives.json`. Nothing is written; fails only if `with_topo_order` finds a cycle.
+    pub fn aggregate_to_map<D: StoredDirective>(
         &self,
-        directives_map: &HashMap<PathBuf, HashMap<String, Arc<Mutex<DirectiveWithSource>>>>,
+        directives_map: &DirectivesMapOf<D>,
         link_graph: &LinkGraph,
-    ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
-        let output_directives = Self::create_directive_outputs(directives_map, link_graph);
-        self.aggregate_outputs_to_json_internal(output_directives)
+    ) -> Result<HashMap<String, Vec<DirectiveOutput>>, RstParserError> {
+        let span = stage_span!("aggregate", directives, groups);
+        let outputs = self.create_directive_outputs(directives_map, link_graph);
+        span.record_count("directives", outputs.len());
+        let grouped = self.group_outputs(outputs, link_graph)?;
+        span.record_count("groups", grouped.len());
+        Ok(grouped)
+    }
+
+    /// Reads back the directives of the `.json` files in the output directory, e.g. to merge
+    /// them with the results of a partial run. The `backlink_fields` options (e.g.
+    /// `derives_back`) are dropped, as backlinks are recomputed before writing. See
+    /// `load_outputs` for which files are read; a missing output directory yields no directives.
+    #[cfg(feature = "fs")]
+    pub fn load_output_directives(&self, backlink_fields: &[String]) -> Result<Vec<DirectiveWithSource>, RstParserError> {
+        if !self.output_dir.exists() {
+            return Ok(Vec::new());
+        }
+        Ok(Self::load_outputs(&self.output_dir)?
+            .into_iter()
+            .map(|output| output.into_directive_with_source(backlink_fields))
+            .collect())
+    }
+
+    /// Reads the directives of the `.json` files in `dir` as written, backlink options
+    /// included, in file name order. Only outputs written as JSON without a template can be
+    /// read; the `objects.json` inventory and `_components.json` are skipped, while files of
+    /// the same names holding directives, e.g. those named `objects`, are read.
+    #[cfg(feature = "fs")]
+    pub fn load_outputs(dir: &Path) -> Result<Vec<DirectiveOutput>, RstParserError> {
+        let mut paths = Vec::new();
+        let read_error = |source| RstParserError::Io { path: dir.to_path_buf(), source };
+        for entry in fs::read_dir(dir).map_err(read_error)? {
+            let path = entry.map_err(read_error)?.path();
+            if path.extension().is_some_and(|extension| extension == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut outputs = Vec::new();
+        for path in paths {
+            let contents = fs::read_to_string(&path).map_err(|source| RstParserError::Io { path: path.clone(), source })?;
+            let contents = contents.strip_prefix(UTF8_BOM).unwrap_or(&contents);
+            if (path.ends_with(OBJECTS_INVENTORY_FILE) || path.ends_with(COMPONENTS_FILE)) && !is_directive_list(contents)? {
+                continue;
+            }
+            let file_outputs: Vec<DirectiveOutput> = serde_json::from_str(contents).map_err(AggregatorError::from)?;
+            outputs.extend(file_outputs);
+        }
+        Ok(outputs)
     }
+
+    /// Writes the directives of `directives_map`, an `AllDirectivesMap` or a lock-free
+    /// `PlainDirectivesMap`, with the backlinks of `link_graph`.
+    #[cfg(feature = "fs")]
+    pub fn aggregate_map_to_json_with_links<D: StoredDirective>(
+        &self,
+        directives_map: &DirectivesMapOf<D>,
+        link_graph: &LinkGraph,
+    ) -> Result<Vec<PathBuf>, RstParserError> {
+        let grouped = self.aggregate_to_map(directives_map, link_graph)?;
+        self.check_reserved_file_names(&grouped)?;
+        let mut output_files = self.write_output_files(grouped, link_graph)?;
+        if self.objects_inventory {
+            output_files.push(self.write_objects_inventory(directives_map)?);
+        }
+        if self.components {
+            output_files.push(self.write_components(directives_map, link_graph)?);
+        }
+        Ok(output_files)
+    }
+
+    /// Writes `objects.json` to the output directory: a JSON object mapping the ID of every
+    /// directive that is not excluded to its `InventoryEntry`, sorted by ID, so that other
+    /// Sphinx builds can resolve links into the generated docs. Returns the written path.
+    #[cfg(feature = "fs")]
+    pub fn write_objects_inventory<D: StoredDirective>(&self, directives_map: &DirectivesMapOf<D>) -> Result<PathBuf, RstParserError> {
+        let mut inventory = BTreeMap::new();
+        for stored_directive in directives_map.values().flat_map(|file_map| file_map.values()) {
+            let dws = stored_directive.read();
+            if self.is_excluded(&dws) {
+                continue;
+            }
+            inventory.insert(dws.id.clone(), InventoryEntry {
+                id: dws.id.clone(),
+                name: dws.directive.name.clone(),
+                source_file: dws.source_file.clone(),
+                anchor: anchor_for_id(&dws.id),
+            });
+        }
+        let contents = serde_json::to_string_pretty(&inventory).map_err(AggregatorError::from)?;
+        Ok(self.write_single_file(OBJECTS_INVENTORY_FILE, contents)?)
+    }
+
+    /// Writes `_components.json` to the output directory: the `connected_components` of
+    /// `link_graph`, as a JSON array of ID arrays. Every directive of `directives_map` is a
+    /// node, so directives without links form components of their own. Returns the written path.
+    #[cfg(feature = "fs")]
+    pub fn write_components<D: StoredDirective>(&self, directives_map: &DirectivesMapOf<D>, link_graph: &LinkGraph) -> Result<PathBuf, RstParserError> {
+        let mut graph = link_graph.clone();
+        for stored_directive in directives_map.values().flat_map(|file_map| file_map.values()) {
+            graph.entry(stored_directive.read().id.clone()).or_default();
+        }
+        let contents = serde_json::to_string_pretty(&connected_components(&graph)).map_err(AggregatorError::from)?;
+        Ok(self.write_single_file(COMPONENTS_FILE, contents)?)
+    }
+
+    /// Writes `contents` to the file `file_name` in the output directory, creating it if needed.
+    #[cfg(feature = "fs")]
+    fn write_single_file(&self, file_name: &str, contents: String) -> Result<PathBuf, AggregatorError> {
+        if self.output_dir.exists() && !self.output_dir.is_dir() {
+            return Err(AggregatorError::InvalidOutputPath(self.output_dir.clone()));
+        }
+        fs::create_dir_all(&self.output_dir)?;
+        let file_path = self.output_dir.join(file_name);
+        fs::write(&file_path, self.finish_file_contents(contents))?;
+        Ok(file_path)
+    }
+}
+
+/// Whether the JSON `contents` are an array of directives rather than e.g. the `objects.json`
+/// inventory, an object, or `_components.json`, an array of ID arrays.
+#[cfg(feature = "fs")]
+fn is_directive_list(contents: &str) -> Result<bool, AggregatorError> {
+    let value: serde_json::Value = serde_json::from_str(contents)?;
+    Ok(value.as_array().is_some_and(|items| items.iter().all(serde_json::Value::is_object)))
+}
+
+/// The lines of `content` up to its first blank line, or `None` if the content is empty.
+fn first_paragraph(content: &str) -> Option<String> {
+    let paragraph: Vec<&str> = content
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+        .take_while(|line| !line.trim().is_empty())
+        .map(str::trim)
+        .collect();
+    (!paragraph.is_empty()).then(|| paragraph.join("\n"))
+}
+
+/// Renders directives as a CSV table with a header row. Option values that are absent are
+/// empty cells, and newlines inside option values are replaced by `|`.
+fn to_csv(directives: &[DirectiveOutput]) -> Result<String, AggregatorError> {
+    // A `:id:` option is already the `id` column.
+    let option_keys: BTreeSet<&str> = directives
+        .iter()
+        .flat_map(|item| item.options.keys().map(String::as_str))
+        .filter(|key| *key != "id")
+        .collect();
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let header = ["id", "name", "arguments", "source_file", "line_number"].into_iter().chain(option_keys.iter().copied());
+    writer.write_record(header).map_err(io::Error::from)?;
+    for item in directives {
+        let fields = [
+            item.id.clone(),
+            item.name.clone(),
+            item.arguments.clone(),
+            item.source_file.clone(),
+            item.line_number.map(|line| line.to_string()).unwrap_or_default(),
+        ];
+        let options = option_keys.iter().map(|key| {
+            item.options.get(*key).map(|value| value.lines().collect::<Vec<_>>().join("|")).unwrap_or_default()
+        });
+        writer.write_record(fields.into_iter().chain(options)).map_err(io::Error::from)?;
+    }
+    let csv = writer.into_inner().map_err(|err| err.into_error())?;
+    Ok(String::from_utf8(csv).expect("CSV of strings is UTF-8"))
+}
+
+/// Creates the renderer for output templates, with `template` registered as `output`.
+fn new_renderer(template: Option<&str>) -> Result<Tera, AggregatorError> {
+    let mut renderer = Tera::default();
+    renderer.autoescape_on(Vec::new());
+    if let Some(template_text) = template {
+        renderer.add_raw_template("output", template_text)?;
+    }
+    Ok(renderer)
+}
+
+/// Renders the `output` template of `renderer` for one group of directives.
+fn render_template(renderer: &Tera, directives: &[DirectiveOutput], link_graph: &LinkGraph) -> Result<String, AggregatorError> {
+    let context = tera::Context::from_serialize(TemplateContext { directives, link_graph })?;
+    Ok(renderer.render("output", &context)?)
+}
+
+/// The extension of files rendered with the template at `path`: the extension left after
+/// removing the template's own one (`directive.json.tera` -> `json`), or `json` if there is none.
+fn template_output_extension(path: &Path) -> String {
+    path.file_stem()
+        .map(Path::new)
+        .and_then(Path::extension)
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("json")
+        .to_string()
+}
+
+/// Counts directives per directive name, sorted by name.
+pub fn count_directives_by_name<D: StoredDirective>(directives_map: &DirectivesMapOf<D>) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for file_map in directives_map.values() {
+        for stored_directive in file_map.values() {
+            let name = stored_directive.read().directive.name.clone();
+            *counts.entry(name).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Formats counts from `count_directives_by_name` as an aligned two-column table with a total row.
+pub fn format_counts_table(counts: &BTreeMap<String, usize>) -> String {
+    let total: usize = counts.values().sum();
+    let name_width = counts.keys().map(|name| name.len()).chain(["Directive".len(), "Total".len()]).max().unwrap_or(0);
+    let count_width = counts.values().chain([&total]).map(|count| count.to_string().len()).max().unwrap_or(0).max("Count".len());
+
+    let mut table = format!("{:<name_width$}  {:>count_width$}\n", "Directive", "Count");
+    for (name, count) in counts {
+        table.push_str(&format!("{:<name_width$}  {:>count_width$}\n", name, count));
+    }
+    table.push_str(&format!("{:<name_width$}  {:>count_width$}\n", "Total", total));
+    table
+}
+
+/// Lists the `limit` directives with the most content, largest first, one per line as
+/// `display_id (name at short_source_file:line)`. Empty without directives.
+pub fn format_largest_directives<D: StoredDirective>(directives_map: &DirectivesMapOf<D>, limit: usize) -> String {
+    let mut largest: Vec<(usize, String)> = Vec::new();
+    for stored_directive in directives_map.values().flat_map(|file_map| file_map.values()) {
+        let dws = stored_directive.read();
+        let size = dws.directive.content.len();
+        // Ties with the smallest kept one are compared by text, so the result does not depend on map order
+        if largest.len() < limit || largest.last().is_some_and(|(smallest, _)| size >= *smallest) {
+            largest.push((size, dws.to_string()));
+            largest.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+            largest.truncate(limit);
+        }
+    }
+    if largest.is_empty() {
+        return String::new();
+    }
+    let mut list = String::from("Largest directives:\n");
+    for (size, directive) in largest {
+        list.push_str(&format!("  {:>10}  {}\n", format!("{} B", size), directive));
+    }
+    list
 }
 
 #[cfg(test)]
@@ -160,6 +947,7 @@ mod tests {
     use super::*;
     use std::collections::HashMap;
     use tempfile::tempdir;
+    use assert_json_diff::{assert_json_eq, assert_json_include};
     use crate::link_data::LinkNodeData; // Changed to rstparser::
 
     // Helper to create a simple DirectiveWithSource for tests
@@ -168,15 +956,69 @@ mod tests {
             directive: Directive {
                 name: name.to_string(),
                 arguments: "".to_string(),
-                options: options_map.unwrap_or_default(),
+                options: options_map.unwrap_or_default().into_iter().collect(),
                 content: format!("Content for {}", id_val),
+                comments: Vec::new(),
             },
             source_file: file.to_string(),
             line_number: Some(line),
             id: id_val.to_string(),
+            file_metadata: None,
+            depth: 0,
+            byte_range: None,
+            source_byte_range: None,
         }
     }
 
+    /// Reads an output file, with the directives sorted by ID since files hold them in map order.
+    fn read_json(path: &Path) -> serde_json::Value {
+        let mut value: serde_json::Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        if let Some(items) = value.as_array_mut() {
+            items.sort_by(|a, b| a["id"].as_str().cmp(&b["id"].as_str()));
+        }
+        value
+    }
+
+    /// `assert_json_include!` for a list of directives, which must also have as many items as
+    /// expected.
+    fn assert_directives_include(actual: serde_json::Value, expected: serde_json::Value) {
+        assert_eq!(actual.as_array().map(Vec::len), expected.as_array().map(Vec::len), "number of directives in {}", actual);
+        assert_json_include!(actual: actual, expected: expected);
+    }
+
+    #[test]
+    fn test_display_id_custom_id() {
+        let mut options = HashMap::new();
+        options.insert("id".to_string(), "REQ-001".to_string());
+        let dws = new_dws("req", "/home/user/project/src/requirements.rst", 42, "REQ-001", Some(options));
+        assert_eq!(dws.display_id(), "REQ-001");
+    }
+
+    #[test]
+    fn test_display_id_generated_id() {
+        let source_file = "/home/user/project/src/requirements.rst";
+        let dws = new_dws("req", source_file, 42, &format!("{}:req:42", source_file), None);
+        assert_eq!(dws.display_id(), "req:42");
+    }
+
+    #[test]
+    fn test_short_source_file() {
+        let dws = new_dws("req", "/home/user/project/src/requirements.rst", 42, "REQ-001", None);
+        assert_eq!(dws.short_source_file(), "requirements.rst");
+        let relative = new_dws("req", "requirements.rst", 1, "REQ-002", None);
+        assert_eq!(relative.short_source_file(), "requirements.rst");
+    }
+
+    #[test]
+    fn test_display() {
+        let dws = new_dws("req", "/home/user/project/src/requirements.rst", 42, "REQ-001", None);
+        assert_eq!(format!("{}", dws), "REQ-001 (req at requirements.rst:42)");
+
+        let mut generated = new_dws("note", "/docs/guide.rst", 7, "/docs/guide.rst:note:7", None);
+        assert_eq!(format!("{}", generated), "note:7 (note at guide.rst:7)");
+        generated.line_number = None;
+        assert_eq!(format!("{}", generated), "note:7 (note at guide.rst)");
+    }
 
     #[test]
     fn test_aggregate_by_directive_name() {
@@ -208,13 +1050,11 @@ mod tests {
         assert!(directive1_file.exists());
         assert!(directive2_file.exists());
         
-        let directive1_content: Vec<DirectiveOutput> = 
-            serde_json::from_str(&fs::read_to_string(directive1_file).unwrap()).unwrap();
-        let directive2_content: Vec<DirectiveOutput> = 
-            serde_json::from_str(&fs::read_to_string(directive2_file).unwrap()).unwrap();
-        
-        assert_eq!(directive1_content.len(), 2);
-        assert_eq!(directive2_content.len(), 1);
+        assert_directives_include(read_json(&directive1_file), serde_json::json!([
+            {"name": "directive1", "id": "d1f1", "source_file": "file1.rst"},
+            {"name": "directive1", "id": "d1f3", "source_file": "file3.rst"},
+        ]));
+        assert_directives_include(read_json(&directive2_file), serde_json::json!([{"name": "directive2", "id": "d2f2", "line_number": 20}]));
     }
     
     #[test]
@@ -241,9 +1081,10 @@ mod tests {
         assert_eq!(output_files.len(), 1);
         let all_directives_file = output_path.join("all_directives.json");
         assert!(all_directives_file.exists());
-        let content: Vec<DirectiveOutput> = 
-            serde_json::from_str(&fs::read_to_string(all_directives_file).unwrap()).unwrap();
-        assert_eq!(content.len(), 2);
+        assert_directives_include(read_json(&all_directives_file), serde_json::json!([
+            {"name": "directive1", "id": "d1f1", "content": "Content for d1f1"},
+            {"name": "directive2", "id": "d2f2", "content": "Content for d2f2"},
+        ]));
     }
     
     #[test]
@@ -274,12 +1115,8 @@ mod tests {
         assert!(file1_output.exists());
         assert!(file2_output.exists());
         
-        let file1_content: Vec<DirectiveOutput> = 
-            serde_json::from_str(&fs::read_to_string(file1_output).unwrap()).unwrap();
-        let file2_content: Vec<DirectiveOutput> = 
-            serde_json::from_str(&fs::read_to_string(file2_output).unwrap()).unwrap();
-        assert_eq!(file1_content.len(), 2);
-        assert_eq!(file2_content.len(), 1);
+        assert_directives_include(read_json(&file1_output), serde_json::json!([{"id": "d1f1"}, {"id": "d2f1"}]));
+        assert_directives_include(read_json(&file2_output), serde_json::json!([{"id": "d3f2", "source_file": "file2.rst"}]));
     }
 
     #[test]
@@ -320,23 +1157,657 @@ mod tests {
         let all_directives_file = output_path.join("all_directives.json");
         assert!(all_directives_file.exists());
 
-        let content: Vec<DirectiveOutput> = 
-            serde_json::from_str(&fs::read_to_string(all_directives_file).unwrap()).unwrap();
-        assert_eq!(content.len(), 2);
+        let content = read_json(&all_directives_file);
+        assert_directives_include(content.clone(), serde_json::json!([
+            {"id": "d1", "options": {"links_to": "d2"}},
+            {"id": "d2", "options": {"links_to_back": "d1"}},
+        ]));
+        assert!(content[0]["options"].get("links_to_back").is_none());
+        assert!(content[1]["options"].get("links_to").is_none()); // d2 has no outgoing "links_to"
+    }
+
+    #[test]
+    fn test_sort_directives_with_source_matches_document_order() {
+        let rst = ".. b::\n   :k: 1\n\n.. a::\n\n.. b::\n   :k: 0\n\n.. a:: arg\n";
+        let in_document_order: Vec<DirectiveWithSource> = crate::parser::parse_rst_multiple(rst, &["a", "b"])
+            .into_iter()
+            .map(|(directive, line)| DirectiveWithSource {
+                id: format!("doc.rst:{}:{}", directive.name, line),
+                directive,
+                source_file: "doc.rst".to_string(),
+                line_number: Some(line),
+                file_metadata: None,
+                depth: 0,
+                byte_range: None,
+                source_byte_range: None,
+            })
+            .collect();
+        let mut other_file = new_dws("a", "another.rst", 1, "other", None);
+        other_file.directive.content = String::new();
+
+        let mut sorted = in_document_order.clone();
+        sorted.reverse();
+        sorted.push(other_file.clone());
+        sorted.sort();
+
+        assert_eq!(sorted[0], other_file); // "another.rst" sorts before "doc.rst"
+        assert_eq!(&sorted[1..], &in_document_order[..]);
+    }
 
-        let output_d1 = content.iter().find(|d| d.id == "d1").unwrap();
-        let _output_d2 = content.iter().find(|d| d.id == "d2").unwrap(); // Prefixed with _
+    #[test]
+    fn test_directive_ordering_ignores_option_insertion_order() {
+        let mut first = new_dws("d", "f.rst", 1, "x", None).directive;
+        let mut second = first.clone();
+        first.options.insert("a".to_string(), "1".to_string());
+        first.options.insert("b".to_string(), "2".to_string());
+        second.options.insert("b".to_string(), "2".to_string());
+        second.options.insert("a".to_string(), "1".to_string());
+        assert_eq!(first.cmp(&second), Ordering::Equal);
 
-        assert_eq!(output_d1.options.get("links_to").unwrap(), "d2");
-        assert!(output_d1.options.get("links_to_back").is_none());
+        second.options.insert("a".to_string(), "0".to_string());
+        assert!(second < first);
+    }
 
-        // output_d1 is already defined and checked.
-        // output_d2 was used to ensure its presence and check its backlinks.
-        // Re-finding output_d1 is redundant.
-        // The check for output_d2's options is what matters.
-        let final_output_d2 = content.iter().find(|d| d.id == "d2").unwrap();
+    #[test]
+    fn test_count_directives_by_name() {
+        let mut map: AllDirectivesMap = HashMap::new();
+        let directives = [
+            new_dws("note", "a.rst", 1, "n1", None),
+            new_dws("warning", "a.rst", 5, "w1", None),
+            new_dws("note", "a.rst", 9, "n2", None),
+            new_dws("note", "b.rst", 1, "n3", None),
+        ];
+        for dws in directives {
+            map.entry(PathBuf::from(&dws.source_file)).or_default().insert(dws.id.clone(), Arc::new(Mutex::new(dws)));
+        }
+
+        let counts = count_directives_by_name(&map);
+        assert_eq!(counts.into_iter().collect::<Vec<_>>(), vec![("note".to_string(), 3), ("warning".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_format_largest_directives() {
+        let mut small = new_dws("note", "/docs/a.rst", 3, "/docs/a.rst:note:3", None);
+        small.directive.content = "x".repeat(5);
+        let mut large = new_dws("req", "/docs/b.rst", 7, "REQ-1", None);
+        large.directive.content = "x".repeat(120);
+        let mut directives_map: crate::directive_functions::PlainDirectivesMap = HashMap::new();
+        for dws in [small, large] {
+            directives_map.entry(PathBuf::from(&dws.source_file)).or_default().insert(dws.id.clone(), dws);
+        }
+
+        assert_eq!(
+            format_largest_directives(&directives_map, 5),
+            "Largest directives:\n       120 B  REQ-1 (req at b.rst:7)\n         5 B  note:3 (note at a.rst:3)\n"
+        );
+        assert_eq!(format_largest_directives(&directives_map, 1).lines().count(), 2);
+        assert_eq!(format_largest_directives(&crate::directive_functions::PlainDirectivesMap::new(), 5), "");
+    }
+
+    #[test]
+    fn test_format_counts_table() {
+        let counts = BTreeMap::from([("note".to_string(), 42), ("warning".to_string(), 13)]);
+        assert_eq!(
+            format_counts_table(&counts),
+            "Directive  Count\nnote          42\nwarning       13\nTotal         55\n"
+        );
+    }
+
+    #[test]
+    fn test_aggregate_with_template() {
+        let temp_dir = tempdir().unwrap();
+        let output_path = temp_dir.path().join("out");
+        let template_path = temp_dir.path().join("directive.json.tera");
+        fs::write(
+            &template_path,
+            r#"[{% for d in directives %}{"id": "{{ d.id }}", "id_length": {{ d.id | length }}}{% if not loop.last %}, {% endif %}{% endfor %}]"#,
+        ).unwrap();
+
+        let directives = vec![
+            new_dws("note", "a.rst", 1, "n1", None),
+            new_dws("warning", "a.rst", 5, "REQ-001", None),
+        ];
+        let aggregator = Aggregator::new(&output_path, GroupBy::DirectiveName).with_template(&template_path);
+        let mut output_files = aggregator.aggregate_to_json(directives).unwrap();
+        output_files.sort();
+        assert_eq!(output_files, vec![output_path.join("note.json"), output_path.join("warning.json")]);
+
+        let rendered: serde_json::Value = serde_json::from_str(&fs::read_to_string(output_path.join("warning.json")).unwrap()).unwrap();
+        assert_json_eq!(rendered, serde_json::json!([{ "id": "REQ-001", "id_length": 7 }]));
+
+        fs::write(&template_path, "{{ missing }}").unwrap();
+        let err = aggregator.aggregate_to_json(vec![new_dws("note", "a.rst", 1, "n1", None)]).unwrap_err();
+        assert!(matches!(err, RstParserError::Aggregation(AggregatorError::Template(_))));
+        assert!(err.to_string().contains("`missing` not found"), "{}", err);
+    }
+
+    #[test]
+    fn test_aggregate_with_crlf_and_bom() {
+        let temp_dir = tempdir().unwrap();
+        let directives = vec![new_dws("note", "a.rst", 1, "n1", None)];
+
+        let aggregator = Aggregator::new(temp_dir.path(), GroupBy::DirectiveName)
+            .with_line_ending(LineEnding::CrLf)
+            .with_bom(true);
+        aggregator.aggregate_to_json(directives.clone()).unwrap();
+        let bytes = fs::read(temp_dir.path().join("note.json")).unwrap();
+        assert!(bytes.starts_with(&[0xEF, 0xBB, 0xBF]));
+        assert!(bytes.windows(2).any(|pair| pair == b"\r\n"));
+        assert!(!bytes.iter().enumerate().any(|(index, byte)| *byte == b'\n' && bytes[index - 1] != b'\r'), "every LF should be part of a CRLF");
+
+        Aggregator::new(temp_dir.path(), GroupBy::DirectiveName).aggregate_to_json(directives).unwrap();
+        let bytes = fs::read(temp_dir.path().join("note.json")).unwrap();
+        assert_eq!(bytes[0], b'[');
+        assert!(!bytes.contains(&b'\r'));
+    }
+
+    #[test]
+    fn test_excluded_directive_keeps_its_backlinks() {
+        let temp_dir = tempdir().unwrap();
+        let hidden = new_dws("req", "a.rst", 1, "hidden", Some(HashMap::from([
+            ("index".to_string(), "False".to_string()),
+            ("links".to_string(), "target".to_string()),
+        ])));
+        let target = new_dws("req", "a.rst", 5, "target", None);
+        let mut directives_map: AllDirectivesMap = HashMap::new();
+        for dws in [hidden, target] {
+            directives_map.entry(PathBuf::from("a.rst")).or_default().insert(dws.id.clone(), Arc::new(Mutex::new(dws)));
+        }
+        let mut link_graph = LinkGraph::new();
+        link_graph.entry("target".to_string()).or_default()
+            .incoming_links.insert("links_back".to_string(), vec!["hidden".to_string()]);
+
+        let aggregator = Aggregator::new(temp_dir.path(), GroupBy::All).with_exclusion("index", "false");
+        aggregator.aggregate_map_to_json_with_links(&directives_map, &link_graph).unwrap();
+        assert_directives_include(
+            read_json(&temp_dir.path().join("all_directives.json")),
+            serde_json::json!([{"id": "target", "options": {"links_back": "hidden"}}]),
+        );
+    }
+
+    #[test]
+    fn test_json_options_in_source_order() {
+        let temp_dir = tempdir().unwrap();
+        let mut dws = new_dws("req", "a.rst", 1, "r1", None);
+        dws.directive = Directive::new("req").with_option("zeta", "1").with_option("alpha", "2").with_option("mid", "3");
+
+        Aggregator::new(temp_dir.path(), GroupBy::All).aggregate_to_json(vec![dws]).unwrap();
+        let json = fs::read_to_string(temp_dir.path().join("all_directives.json")).unwrap();
+        let positions: Vec<usize> = ["\"zeta\"", "\"alpha\"", "\"mid\""].iter().map(|key| json.find(key).unwrap()).collect();
+        assert!(positions.is_sorted(), "options are not in source order:\n{}", json);
+    }
+
+    #[test]
+    fn test_aggregate_with_summary() {
+        let temp_dir = tempdir().unwrap();
+        let mut dws = new_dws("req", "a.rst", 1, "r1", None);
+        dws.directive.content = "First line\nof the summary.\n\nSecond paragraph.\n\nThird.".to_string();
+        let mut without_content = new_dws("note", "a.rst", 9, "n1", None);
+        without_content.directive.content.clear();
+
+        let aggregator = Aggregator::new(temp_dir.path(), GroupBy::DirectiveName).with_summary(true);
+        aggregator.aggregate_to_json(vec![dws, without_content]).unwrap();
+
+        assert_json_include!(actual: read_json(&temp_dir.path().join("req.json")), expected: serde_json::json!([{"summary": "First line\nof the summary."}]));
+        let notes: serde_json::Value = serde_json::from_str(&fs::read_to_string(temp_dir.path().join("note.json")).unwrap()).unwrap();
+        assert!(notes[0].get("summary").is_none());
+    }
+
+    #[test]
+    fn test_aggregate_with_rendered_content() {
+        let temp_dir = tempdir().unwrap();
+        let mut dws = new_dws("req", "a.rst", 1, "r1", None);
+        dws.directive.content = "* item 1\n* item 2".to_string();
+
+        Aggregator::new(temp_dir.path(), GroupBy::All).with_rendered_content(true).aggregate_to_json(vec![dws.clone()]).unwrap();
+        assert_json_include!(
+            actual: read_json(&temp_dir.path().join("all_directives.json")),
+            expected: serde_json::json!([{"content_html": "<ul><li>item 1</li><li>item 2</li></ul>"}])
+        );
+
+        Aggregator::new(temp_dir.path(), GroupBy::All).aggregate_to_json(vec![dws]).unwrap();
+        let all: serde_json::Value = serde_json::from_str(&fs::read_to_string(temp_dir.path().join("all_directives.json")).unwrap()).unwrap();
+        assert!(all[0].get("content_html").is_none());
+    }
+
+    #[test]
+    fn test_aggregate_to_csv() {
+        let temp_dir = tempdir().unwrap();
+        let mut first = new_dws("req", "a.rst", 1, "r1", Some(HashMap::from([("status".to_string(), "open".to_string())])));
+        first.directive.arguments = "Parse, then \"link\"".to_string();
+        let second = new_dws("req", "a.rst", 5, "r2", Some(HashMap::from([("links".to_string(), "r1,\nr3".to_string())])));
+
+        let aggregator = Aggregator::new(temp_dir.path(), GroupBy::All).with_output_format(OutputFormat::Csv);
+        let output_files = aggregator.aggregate_to_json(vec![first, second]).unwrap();
+        assert_eq!(output_files, vec![temp_dir.path().join("all_directives.csv")]);
+
+        let csv = fs::read_to_string(&output_files[0]).unwrap();
+        let mut lines: Vec<&str> = csv.lines().collect();
+        lines[1..].sort();
+        assert_eq!(
+            lines,
+            [
+                "id,name,arguments,source_file,line_number,links,status",
+                "r1,req,\"Parse, then \"\"link\"\"\",a.rst,1,,open",
+                "r2,req,,a.rst,5,\"r1,|r3\",",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_csv_round_trips_special_characters() {
+        let temp_dir = tempdir().unwrap();
+        let mut dws = new_dws("req", "docs, old/a.rst", 1, "r1", Some(HashMap::from([("owner".to_string(), "  \"Team\", QA  ".to_string())])));
+        dws.directive.arguments = " Parse \"quoted\",\r\nthen\rlink ".to_string();
+
+        let aggregator = Aggregator::new(temp_dir.path(), GroupBy::All).with_output_format(OutputFormat::Csv);
+        let output_files = aggregator.aggregate_to_json(vec![dws]).unwrap();
+
+        let mut reader = csv::Reader::from_path(&output_files[0]).unwrap();
+        assert_eq!(reader.headers().unwrap(), vec!["id", "name", "arguments", "source_file", "line_number", "owner"]);
+        let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(records, vec![vec!["r1", "req", " Parse \"quoted\",\r\nthen\rlink ", "docs, old/a.rst", "1", "  \"Team\", QA  "]]);
+    }
+
+    #[test]
+    fn test_template_output_extension() {
+        assert_eq!(template_output_extension(Path::new("tpl/directive.json.tera")), "json");
+        assert_eq!(template_output_extension(Path::new("index.html.tera")), "html");
+        assert_eq!(template_output_extension(Path::new("directive.tera")), "json");
+    }
+
+    #[test]
+    fn test_aggregate_all_in_topo_order() {
+        let temp_dir = tempdir().unwrap();
+        let mut directives_map: AllDirectivesMap = HashMap::new();
+        let mut link_graph = LinkGraph::new();
+        // Source order is intro, advanced, basics; advanced depends on basics
+        for (id, line, depends_on) in [("intro", 1, None), ("advanced", 5, Some("basics")), ("basics", 9, None)] {
+            directives_map.entry(PathBuf::from("guide.rst")).or_default()
+                .insert(id.to_string(), Arc::new(Mutex::new(new_dws("chapter", "guide.rst", line, id, None))));
+            if let Some(target) = depends_on {
+                link_graph.entry(id.to_string()).or_default()
+                    .outgoing_links.insert("depends_on".to_string(), vec![target.to_string()]);
+            }
+        }
+
+        let aggregator = Aggregator::new(temp_dir.path(), GroupBy::All).with_topo_order("depends_on");
+        aggregator.aggregate_map_to_json_with_links(&directives_map, &link_graph).unwrap();
+        // Not `read_json`, which sorts by ID
+        let content: serde_json::Value = serde_json::from_str(&fs::read_to_string(temp_dir.path().join("all_directives.json")).unwrap()).unwrap();
+        assert_directives_include(content, serde_json::json!([{"id": "intro"}, {"id": "basics"}, {"id": "advanced"}]));
+
+        link_graph.entry("basics".to_string()).or_default()
+            .outgoing_links.insert("depends_on".to_string(), vec!["advanced".to_string()]);
+        let err = aggregator.aggregate_map_to_json_with_links(&directives_map, &link_graph).unwrap_err();
+        assert!(matches!(&err, RstParserError::Aggregation(AggregatorError::Cycle { field, .. }) if field == "depends_on"));
+        assert!(err.to_string().contains("advanced, basics"));
+    }
+
+    #[test]
+    fn test_aggregate_with_depth() {
+        let temp_dir = tempdir().unwrap();
+        let rst = ".. note:: Outer\n\n   .. note:: Inner\n";
+        let directives: Vec<DirectiveWithSource> = crate::parser::parse_rst_multiple_with_depth(rst, &["note"], &crate::parser::ParseOptions::default())
+            .into_iter()
+            .map(|(directive, line, depth)| DirectiveWithSource {
+                directive,
+                depth,
+                byte_range: None,
+                source_byte_range: None,
+                ..new_dws("note", "doc.rst", line, &format!("doc.rst:note:{}", line), None)
+            })
+            .collect();
+
+        let read_depths = |aggregator: Aggregator| -> Vec<Option<usize>> {
+            aggregator.aggregate_to_json(directives.clone()).unwrap();
+            let mut content: Vec<DirectiveOutput> =
+                serde_json::from_str(&fs::read_to_string(temp_dir.path().join("all_directives.json")).unwrap()).unwrap();
+            content.sort_by_key(|d| d.line_number);
+            content.into_iter().map(|d| d.depth).collect()
+        };
+        assert_eq!(read_depths(Aggregator::new(temp_dir.path(), GroupBy::All)), vec![None, None]);
+        assert_eq!(read_depths(Aggregator::new(temp_dir.path(), GroupBy::All).with_depth(true)), vec![Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn test_aggregate_to_map_keys_per_group_by() {
+        let mut directives_map: crate::directive_functions::PlainDirectivesMap = HashMap::new();
+        for dws in [new_dws("note", "docs/a.rst", 1, "n1", None), new_dws("note", "docs/b.rst", 3, "n2", None), new_dws("warning", "docs/b.rst", 5, "w1", None)] {
+            directives_map.entry(PathBuf::from(&dws.source_file)).or_default().insert(dws.id.clone(), dws);
+        }
+        let keys_and_ids = |aggregator: Aggregator| -> Vec<(String, Vec<String>)> {
+            let mut grouped: Vec<(String, Vec<String>)> = aggregator.aggregate_to_map(&directives_map, &LinkGraph::new()).unwrap()
+                .into_iter()
+                .map(|(file_name, group)| {
+                    let mut ids: Vec<String> = group.into_iter().map(|output| output.id).collect();
+                    ids.sort();
+                    (file_name, ids)
+                })
+                .collect();
+            grouped.sort();
+            grouped
+        };
+        let pairs = |pairs: &[(&str, &[&str])]| -> Vec<(String, Vec<String>)> {
+            pairs.iter().map(|(file_name, ids)| (file_name.to_string(), ids.iter().map(|id| id.to_string()).collect())).collect()
+        };
+
+        // Nothing is written, so the output directory does not need to exist
+        let output_dir = Path::new("does/not/exist");
+        assert_eq!(keys_and_ids(Aggregator::new(output_dir, GroupBy::DirectiveName)), pairs(&[("note.json", &["n1", "n2"]), ("warning.json", &["w1"])]));
+        assert_eq!(keys_and_ids(Aggregator::new(output_dir, GroupBy::All)), pairs(&[("all_directives.json", &["n1", "n2", "w1"])]));
+        assert_eq!(keys_and_ids(Aggregator::new(output_dir, GroupBy::SourceFile)), pairs(&[("a.rst.json", &["n1"]), ("b.rst.json", &["n2", "w1"])]));
+        let by_line = GroupBy::Custom(Box::new(|dws| if dws.line_number < Some(3) { "early".to_string() } else { "late".to_string() }));
+        assert_eq!(keys_and_ids(Aggregator::new(output_dir, by_line)), pairs(&[("early.json", &["n1"]), ("late.json", &["n2", "w1"])]));
+        let csv = Aggregator::new(output_dir, GroupBy::All).with_output_format(OutputFormat::Csv);
+        assert_eq!(keys_and_ids(csv), pairs(&[("all_directives.csv", &["n1", "n2", "w1"])]));
+        assert!(!output_dir.exists());
+    }
+
+    #[test]
+    fn test_plan_output_files_matches_written_files() {
+        let temp_dir = tempdir().unwrap();
+        let output_path = temp_dir.path().join("out");
+        let mut directives_map: AllDirectivesMap = HashMap::new();
+        for dws in [new_dws("note", "a.rst", 1, "n1", None), new_dws("warning", "b.rst", 5, "w1", None)] {
+            directives_map.entry(PathBuf::from(&dws.source_file)).or_default().insert(dws.id.clone(), Arc::new(Mutex::new(dws)));
+        }
+
+        for (group_by, extra_outputs) in [(GroupBy::DirectiveName, false), (GroupBy::All, false), (GroupBy::SourceFile, false), (GroupBy::All, true)] {
+            let aggregator = Aggregator::new(&output_path, group_by)
+                .with_objects_inventory(extra_outputs)
+                .with_components(extra_outputs);
+            let planned = aggregator.plan_output_files(&directives_map, &LinkGraph::new()).unwrap();
+            assert!(!output_path.exists(), "planning must not create the output directory");
+
+            let mut written = aggregator.aggregate_map_to_json_with_links(&directives_map, &LinkGraph::new()).unwrap();
+            written.sort();
+            assert_eq!(planned, written);
+            fs::remove_dir_all(&output_path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_aggregator_error_variants() {
+        let temp_dir = tempdir().unwrap();
+        let directives = vec![new_dws("note", "a.rst", 1, "n1", None)];
+
+        let file_path = temp_dir.path().join("not_a_dir");
+        fs::write(&file_path, "").unwrap();
+        let err = Aggregator::new(&file_path, GroupBy::All).aggregate_to_json(directives.clone()).unwrap_err();
+        assert!(matches!(&err, RstParserError::Aggregation(AggregatorError::InvalidOutputPath(path)) if *path == file_path));
+
+        let err = Aggregator::new(temp_dir.path().join("out"), GroupBy::All)
+            .with_template(temp_dir.path().join("missing.json.tera"))
+            .aggregate_to_json(directives)
+            .unwrap_err();
+        assert!(matches!(&err, RstParserError::Aggregation(AggregatorError::Io(io_err)) if io_err.kind() == std::io::ErrorKind::NotFound));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn test_write_map_to_writer() {
+        let mut directives_map: AllDirectivesMap = HashMap::new();
+        for dws in [new_dws("note", "a.rst", 1, "n1", None), new_dws("warning", "b.rst", 5, "w1", None)] {
+            directives_map.entry(PathBuf::from(&dws.source_file)).or_default().insert(dws.id.clone(), Arc::new(Mutex::new(dws)));
+        }
+
+        let mut json = Vec::new();
+        Aggregator::new("unused", GroupBy::All).write_map_to_writer(&directives_map, &LinkGraph::new(), &mut json).unwrap();
+        let all: Vec<DirectiveOutput> = serde_json::from_slice(&json).unwrap();
+        assert_eq!(all.len(), 2);
+
+        let mut ndjson = Vec::new();
+        Aggregator::new("unused", GroupBy::DirectiveName).write_map_to_writer(&directives_map, &LinkGraph::new(), &mut ndjson).unwrap();
+        let ids: Vec<String> = String::from_utf8(ndjson).unwrap().lines()
+            .map(|line| serde_json::from_str::<DirectiveOutput>(line).unwrap().id)
+            .collect();
+        assert_eq!(ids, vec!["n1", "w1"]);
+        assert!(!Path::new("unused").exists());
+    }
+
+    #[test]
+    fn test_plain_and_shared_maps_write_identical_output() {
+        use crate::directive_functions::{FunctionApplicator, PlainDirectivesMap};
+        use crate::link_data::{LinkConfig, LinkTypeConfig};
+
+        let link_config = LinkConfig {
+            link_types: vec![LinkTypeConfig { name: "derives".to_string(), custom_backlink_suffix: None }],
+            ..Default::default()
+        };
+        let applicator = FunctionApplicator::new(Arc::new(link_config));
+        let derives = |target: &str| Some(HashMap::from([("derives".to_string(), target.to_string())]));
+        let directives = [
+            new_dws("req", "a.rst", 1, "r1", None),
+            new_dws("spec", "a.rst", 5, "s1", derives("r1")),
+            // One source per target, as the order of several backlinks follows the map's iteration order
+            new_dws("spec", "b.rst", 2, "s2", derives("s1")),
+        ];
+
+        let mut plain_map: PlainDirectivesMap = HashMap::new();
+        let mut shared_map: AllDirectivesMap = HashMap::new();
+        for dws in directives {
+            let file = PathBuf::from(&dws.source_file);
+            shared_map.entry(file.clone()).or_default().insert(dws.id.clone(), Arc::new(Mutex::new(dws.clone())));
+            plain_map.entry(file).or_default().insert(dws.id.clone(), dws);
+        }
+
+        let temp_dir = tempdir().unwrap();
+        let mut plain_graph = LinkGraph::new();
+        let mut shared_graph = LinkGraph::new();
+        assert!(applicator.apply_to_all(&plain_map, &mut plain_graph).is_empty());
+        assert!(applicator.apply_to_all(&shared_map, &mut shared_graph).is_empty());
+
+        let plain_files = Aggregator::new(temp_dir.path().join("plain"), GroupBy::All)
+            .aggregate_map_to_json_with_links(&plain_map, &plain_graph)
+            .unwrap();
+        let shared_files = Aggregator::new(temp_dir.path().join("shared"), GroupBy::All)
+            .aggregate_map_to_json_with_links(&shared_map, &shared_graph)
+            .unwrap();
+        let plain_output = read_json(&plain_files[0]);
+        assert_eq!(plain_output, read_json(&shared_files[0]));
+        assert_directives_include(plain_output, serde_json::json!([
+            { "id": "r1", "options": { "derives_back": "s1" } },
+            { "id": "s1", "options": { "derives_back": "s2" } },
+            { "id": "s2", "options": { "derives": "s1" } },
+        ]));
+    }
+
+    #[test]
+    fn test_load_output_directives_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let aggregator = Aggregator::new(temp_dir.path().join("out"), GroupBy::DirectiveName);
+        assert!(aggregator.load_output_directives(&[]).unwrap().is_empty(), "nothing written yet");
+
+        let target = new_dws("req", "a.rst", 1, "target", None);
+        let source = new_dws("note", "b.rst", 3, "source", Some(HashMap::from([("links".to_string(), "target".to_string())])));
+        let mut directives_map: AllDirectivesMap = HashMap::new();
+        for dws in [target.clone(), source.clone()] {
+            directives_map.entry(PathBuf::from(&dws.source_file)).or_default().insert(dws.id.clone(), Arc::new(Mutex::new(dws)));
+        }
+        let mut link_graph = LinkGraph::new();
+        link_graph.entry("target".to_string()).or_default()
+            .incoming_links.insert("links_back".to_string(), vec!["source".to_string()]);
+        aggregator.aggregate_map_to_json_with_links(&directives_map, &link_graph).unwrap();
+
+        let mut loaded = aggregator.load_output_directives(&["links_back".to_string()]).unwrap();
+        loaded.sort();
+        assert_eq!(loaded, vec![target, source]);
+    }
+
+    #[test]
+    fn test_load_outputs_returns_written_directives_with_backlinks() {
+        let temp_dir = tempdir().unwrap();
+        let aggregator = Aggregator::new(temp_dir.path(), GroupBy::SourceFile).with_bom(true);
+        let target = new_dws("req", "a.rst", 1, "target", None);
+        let source = new_dws("req", "b.rst", 3, "source", Some(HashMap::from([("links".to_string(), "target".to_string())])));
+        let mut directives_map: AllDirectivesMap = HashMap::new();
+        for dws in [target.clone(), source.clone()] {
+            directives_map.entry(PathBuf::from(&dws.source_file)).or_default().insert(dws.id.clone(), Arc::new(Mutex::new(dws)));
+        }
+        let mut link_graph = LinkGraph::new();
+        link_graph.entry("target".to_string()).or_default()
+            .incoming_links.insert("links_back".to_string(), vec!["source".to_string()]);
+        aggregator.aggregate_map_to_json_with_links(&directives_map, &link_graph).unwrap();
+
+        let loaded = Aggregator::load_outputs(temp_dir.path()).unwrap();
+        assert_eq!(loaded[0].options.get("links_back"), Some(&"source".to_string()));
+        let mut expected_target = DirectiveOutput::from(&target);
+        expected_target.options.insert("links_back".to_string(), "source".to_string());
+        assert_eq!(loaded, vec![expected_target, DirectiveOutput::from(&source)]);
+        assert!(Aggregator::load_outputs(&temp_dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_parallel_directive_outputs_match_sequential_conversion() {
+        let aggregator = Aggregator::new("unused", GroupBy::All).with_exclusion("status", "draft");
+        let mut directives_map: crate::directive_functions::PlainDirectivesMap = HashMap::new();
+        let mut link_graph = LinkGraph::new();
+        for index in 0..2000 {
+            let status = if index % 7 == 0 { "draft" } else { "open" };
+            let dws = new_dws("req", &format!("file{}.rst", index % 13), index, &format!("R{}", index), Some(HashMap::from([("status".to_string(), status.to_string())])));
+            if index > 0 {
+                link_graph.entry(format!("R{}", index - 1)).or_default()
+                    .incoming_links.insert("links_back".to_string(), vec![dws.id.clone()]);
+            }
+            directives_map.entry(PathBuf::from(&dws.source_file)).or_default().insert(dws.id.clone(), dws);
+        }
+
+        let sequential: Vec<DirectiveOutput> = directives_map.values()
+            .flat_map(|file_map| file_map.values())
+            .filter_map(|dws| aggregator.to_output_with_backlinks(dws, &link_graph))
+            .collect();
+        let parallel = aggregator.create_directive_outputs(&directives_map, &link_graph);
+        assert_eq!(parallel.len(), 2000 - 2000_usize.div_ceil(7));
+        assert_eq!(parallel, sequential);
+    }
+
+    #[test]
+    fn test_aggregate_custom_grouping() {
+        let temp_dir = tempdir().unwrap();
+        let directives = vec![
+            new_dws("alpha", "doc.rst", 1, "a1", None),
+            new_dws("another", "doc.rst", 5, "a2", None),
+            new_dws("beta", "doc.rst", 9, "b1", None),
+        ];
+        let first_letter = GroupBy::Custom(Box::new(|dws: &DirectiveWithSource| dws.directive.name[..1].to_string()));
+        let mut output_files = Aggregator::new(temp_dir.path(), first_letter).aggregate_to_json(directives).unwrap();
+        output_files.sort();
+        assert_eq!(output_files, vec![temp_dir.path().join("a.json"), temp_dir.path().join("b.json")]);
+
+        let ids_in = |file: &str| {
+            let content: Vec<DirectiveOutput> = serde_json::from_str(&fs::read_to_string(temp_dir.path().join(file)).unwrap()).unwrap();
+            let mut ids: Vec<String> = content.into_iter().map(|d| d.id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids_in("a.json"), vec!["a1", "a2"]);
+        assert_eq!(ids_in("b.json"), vec!["b1"]);
+    }
+
+    #[test]
+    fn test_write_objects_inventory() {
+        use crate::directive_functions::PlainDirectivesMap;
+
+        let temp_dir = tempdir().unwrap();
+        let hidden = Some(HashMap::from([("index".to_string(), "false".to_string())]));
+        let directives = [
+            new_dws("req", "/docs/a.rst", 1, "REQ_001", None),
+            new_dws("spec", "/docs/a.rst", 5, "SPEC-1.a", None),
+            new_dws("req", "/docs/b.rst", 2, "REQ_002", None),
+            new_dws("req", "/docs/b.rst", 8, "hidden", hidden),
+        ];
+        let mut directives_map: PlainDirectivesMap = HashMap::new();
+        for dws in &directives {
+            directives_map.entry(PathBuf::from(&dws.source_file)).or_default().insert(dws.id.clone(), dws.clone());
+        }
+
+        let aggregator = Aggregator::new(temp_dir.path().join("out"), GroupBy::DirectiveName)
+            .with_exclusion("index", "false")
+            .with_objects_inventory(true);
+        let written = aggregator.aggregate_map_to_json_with_links(&directives_map, &LinkGraph::new()).unwrap();
+        let inventory_path = temp_dir.path().join("out").join(OBJECTS_INVENTORY_FILE);
+        assert!(written.contains(&inventory_path));
+
+        let inventory: BTreeMap<String, InventoryEntry> = serde_json::from_str(&fs::read_to_string(&inventory_path).unwrap()).unwrap();
+        assert_eq!(inventory.keys().collect::<Vec<_>>(), vec!["REQ_001", "REQ_002", "SPEC-1.a"]);
+        for dws in &directives[..3] {
+            let entry = &inventory[&dws.id];
+            assert_eq!((entry.name.as_str(), entry.source_file.as_str()), (dws.directive.name.as_str(), dws.source_file.as_str()));
+        }
+        assert_eq!(inventory["SPEC-1.a"].anchor, "spec-1-a");
+        assert_eq!(anchor_for_id("__R 1__"), "r-1");
+
+        // The inventory is not read back as directives
+        assert_eq!(aggregator.load_output_directives(&[]).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_write_components() {
+        use crate::directive_functions::PlainDirectivesMap;
+
+        let temp_dir = tempdir().unwrap();
+        let mut directives_map: PlainDirectivesMap = HashMap::new();
+        for dws in [new_dws("req", "/docs/a.rst", 1, "R1", None), new_dws("req", "/docs/a.rst", 5, "R2", None), new_dws("spec", "/docs/b.rst", 1, "S1", None)] {
+            directives_map.entry(PathBuf::from(&dws.source_file)).or_default().insert(dws.id.clone(), dws);
+        }
+        let mut link_graph = LinkGraph::new();
+        link_graph.entry("S1".to_string()).or_default().outgoing_links.insert("derives".to_string(), vec!["R1".to_string(), "MISSING".to_string()]);
+
+        let aggregator = Aggregator::new(temp_dir.path().join("out"), GroupBy::All).with_components(true);
+        let written = aggregator.aggregate_map_to_json_with_links(&directives_map, &link_graph).unwrap();
+        let components_path = temp_dir.path().join("out").join(COMPONENTS_FILE);
+        assert!(written.contains(&components_path));
+        let components: Vec<Vec<String>> = serde_json::from_str(&fs::read_to_string(&components_path).unwrap()).unwrap();
+        assert_eq!(components, vec![vec!["MISSING", "R1", "S1"], vec!["R2"]]);
+
+        // The components are not read back as directives
+        assert_eq!(aggregator.load_output_directives(&[]).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_directives_named_like_extra_outputs() {
+        use crate::directive_functions::PlainDirectivesMap;
+
+        let temp_dir = tempdir().unwrap();
+        let mut directives_map: PlainDirectivesMap = HashMap::new();
+        for dws in [new_dws("objects", "/docs/a.rst", 1, "O1", None), new_dws("req", "/docs/a.rst", 5, "R1", None)] {
+            directives_map.entry(PathBuf::from(&dws.source_file)).or_default().insert(dws.id.clone(), dws);
+        }
+
+        // Without the inventory, directives named `objects` are written to and read back from objects.json
+        let aggregator = Aggregator::new(temp_dir.path().join("out"), GroupBy::DirectiveName);
+        aggregator.aggregate_map_to_json_with_links(&directives_map, &LinkGraph::new()).unwrap();
+        let mut loaded: Vec<String> = aggregator.load_output_directives(&[]).unwrap().into_iter().map(|dws| dws.id).collect();
+        loaded.sort();
+        assert_eq!(loaded, vec!["O1", "R1"]);
+
+        // With it, the inventory would overwrite them
+        let aggregator = aggregator.with_objects_inventory(true);
+        for err in [
+            aggregator.plan_output_files(&directives_map, &LinkGraph::new()).unwrap_err(),
+            aggregator.aggregate_map_to_json_with_links(&directives_map, &LinkGraph::new()).unwrap_err(),
+        ] {
+            assert!(matches!(&err, RstParserError::Aggregation(AggregatorError::ReservedFileName(file_name)) if file_name == OBJECTS_INVENTORY_FILE));
+        }
+    }
+
+    #[test]
+    fn test_strip_prefix_from_source_file() {
+        let directives_map: AllDirectivesMap = [
+            new_dws("req", "docs/guide/intro.rst", 1, "R1", None),
+            new_dws("req", "docs-old/intro.rst", 1, "R2", None),
+            new_dws("req", "src/lib.cpp", 1, "R3", None),
+        ].into_iter().map(|dws| (PathBuf::from(&dws.source_file), HashMap::from([(dws.id.clone(), Arc::new(Mutex::new(dws)))]))).collect();
 
-        assert!(final_output_d2.options.get("links_to").is_none()); // d2 has no outgoing "links_to"
-        assert_eq!(final_output_d2.options.get("links_to_back").unwrap(), "d1");
+        let outputs = Aggregator::new("out", GroupBy::All)
+            .with_strip_prefix(PathBuf::from("docs"))
+            .aggregate_to_map(&directives_map, &LinkGraph::new())
+            .unwrap()
+            .remove("all_directives.json")
+            .unwrap();
+        let mut source_files: Vec<(&str, &str)> = outputs.iter().map(|output| (output.id.as_str(), output.source_file.as_str())).collect();
+        source_files.sort();
+        assert_eq!(source_files, vec![("R1", "guide/intro.rst"), ("R2", "docs-old/intro.rst"), ("R3", "src/lib.cpp")]);
     }
 }
@@ -0,0 +1,149 @@
+//! Python bindings, enabled with the `python` feature and built with maturin
+//! (`maturin develop`, see `pyproject.toml`) as the `rstparser` Python module.
+//!
+//! ```python
+//! import rstparser
+//!
+//! rstparser.parse_string(".. req:: Parse\n   :id: REQ-1\n", ["req"])
+//! # [{'name': 'req', 'arguments': 'Parse', 'options': {'id': 'REQ-1'}, 'content': '', 'line_number': 1}]
+//! directives = rstparser.process_path("docs", ["req"], extensions=["rst"], link_config="links.toml")
+//! graph = rstparser.link_graph("docs", ["req"], link_config="links.toml")
+//! ```
+//!
+//! Directives are returned as dicts with the fields of the JSON outputs, and errors are raised
+//! as `RuntimeError`s.
+
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use serde::Serialize;
+use crate::aggregator::{Aggregator, DirectiveOutput, GroupBy};
+use crate::link_data::{load_link_config, LinkConfig};
+use crate::parser::parse_rst_multiple;
+use crate::pipeline::{Pipeline, PipelineResult};
+
+/// A directive found by `parse_string`, with the line of its `.. name::` marker.
+#[derive(Serialize)]
+struct ParsedDirective {
+    #[serde(flatten)]
+    directive: crate::parser::Directive,
+    line_number: usize,
+}
+
+fn to_py_err(err: impl std::fmt::Display) -> PyErr {
+    PyRuntimeError::new_err(err.to_string())
+}
+
+/// Converts a JSON value into the equivalent Python object.
+fn json_to_py(py: Python<'_>, value: &serde_json::Value) -> PyResult<Py<PyAny>> {
+    Ok(match value {
+        serde_json::Value::Null => py.None(),
+        serde_json::Value::Bool(b) => b.into_pyobject(py)?.to_owned().into_any().unbind(),
+        serde_json::Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => i.into_pyobject(py)?.into_any().unbind(),
+            (None, Some(f)) => f.into_pyobject(py)?.into_any().unbind(),
+            (None, None) => n.to_string().into_pyobject(py)?.into_any().unbind(),
+        },
+        serde_json::Value::String(s) => s.into_pyobject(py)?.into_any().unbind(),
+        serde_json::Value::Array(items) => {
+            let list = PyList::empty(py);
+            for item in items {
+                list.append(json_to_py(py, item)?)?;
+            }
+            list.into_any().unbind()
+        }
+        serde_json::Value::Object(fields) => {
+            let dict = PyDict::new(py);
+            for (key, field) in fields {
+                dict.set_item(key, json_to_py(py, field)?)?;
+            }
+            dict.into_any().unbind()
+        }
+    })
+}
+
+/// Converts a serializable value into Python objects via its JSON representation.
+fn to_py<T: Serialize>(py: Python<'_>, value: &T) -> PyResult<Py<PyAny>> {
+    json_to_py(py, &serde_json::to_value(value).map_err(to_py_err)?)
+}
+
+/// Runs the pipeline on `dir` without writing outputs. The GIL is released meanwhile.
+fn run_pipeline(
+    py: Python<'_>,
+    dir: &str,
+    directives: Vec<String>,
+    extensions: Option<Vec<String>>,
+    link_config: Option<&str>,
+) -> PyResult<PipelineResult> {
+    let link_config = match link_config {
+        Some(path) => load_link_config(path).map_err(to_py_err)?,
+        None => LinkConfig::default(),
+    };
+    py.allow_threads(|| {
+        let mut pipeline = Pipeline::new().dir(dir).directives(directives).link_config(link_config);
+        if let Some(extensions) = extensions {
+            pipeline = pipeline.extensions(extensions);
+        }
+        pipeline.run()
+    }).map_err(to_py_err)
+}
+
+/// The `directives` found in `text`, in document order, as dicts with `name`, `arguments`,
+/// `options`, `content` and `line_number`.
+#[pyfunction]
+fn parse_string(py: Python<'_>, text: &str, directives: Vec<String>) -> PyResult<Py<PyAny>> {
+    let targets: Vec<&str> = directives.iter().map(String::as_str).collect();
+    let parsed: Vec<ParsedDirective> = parse_rst_multiple(text, &targets)
+        .into_iter()
+        .map(|(directive, line_number)| ParsedDirective { directive, line_number })
+        .collect();
+    to_py(py, &parsed)
+}
+
+/// The `directives` of the files below `dir`, as written to the JSON outputs (backlinks
+/// included), sorted by source file and line. `extensions` defaults to those of
+/// `FileWalker::new()`; `link_config` is the path of a link configuration TOML file.
+#[pyfunction]
+#[pyo3(signature = (dir, directives, extensions=None, link_config=None))]
+fn process_path(
+    py: Python<'_>,
+    dir: &str,
+    directives: Vec<String>,
+    extensions: Option<Vec<String>>,
+    link_config: Option<&str>,
+) -> PyResult<Py<PyAny>> {
+    let result = run_pipeline(py, dir, directives, extensions, link_config)?;
+    let mut outputs: Vec<DirectiveOutput> = Aggregator::new(dir, GroupBy::All)
+        .aggregate_to_map(&result.directives_map, &result.link_graph)
+        .map_err(to_py_err)?
+        .into_values()
+        .flatten()
+        .collect();
+    outputs.sort_by(|a, b| (&a.source_file, a.line_number, &a.id).cmp(&(&b.source_file, b.line_number, &b.id)));
+    to_py(py, &outputs)
+}
+
+/// The link graph of the `directives` below `dir`: a dict from directive ID to its
+/// `outgoing_links` and `incoming_links`, each a dict from link field to IDs. The arguments
+/// are those of `process_path`.
+#[pyfunction]
+#[pyo3(signature = (dir, directives, extensions=None, link_config=None))]
+fn link_graph(
+    py: Python<'_>,
+    dir: &str,
+    directives: Vec<String>,
+    extensions: Option<Vec<String>>,
+    link_config: Option<&str>,
+) -> PyResult<Py<PyAny>> {
+    let result = run_pipeline(py, dir, directives, extensions, link_config)?;
+    to_py(py, &result.link_graph)
+}
+
+/// The `rstparser` Python module.
+#[pymodule]
+fn rstparser(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(parse_string, module)?)?;
+    module.add_function(wrap_pyfunction!(process_path, module)?)?;
+    module.add_function(wrap_pyfunction!(link_graph, module)?)?;
+    Ok(())
+}
@@ -0,0 +1,158 @@
+//! Warnings and errors found while extracting, parsing and linking directives, with the
+//! file and line they refer to.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use log::{error, warn};
+use serde::Serialize;
+
+/// How serious a `Diagnostic` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The input was processed, but probably not as intended.
+    Warning,
+    /// Part of the input could not be processed.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// The kinds of problems reported as diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiagnosticCode {
+    /// An `@rst` block, docstring, code fence or comment is not closed.
+    UnterminatedBlock,
+    /// The front-matter block is not valid TOML.
+    InvalidFrontMatter,
+    /// Directive content exceeded the configured limit and was truncated.
+    ContentTruncated,
+    /// An `.. include::` could not be resolved or read, or was skipped.
+    IncludeFailed,
+    /// A file could not be read or decoded.
+    ProcessFailed,
+    /// A directive links to itself.
+    SelfLink,
+    /// A directive function failed or found the link graph inconsistent.
+    FunctionFailed,
+}
+
+impl DiagnosticCode {
+    /// The kebab-case name of the code, as in JSON output.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiagnosticCode::UnterminatedBlock => "unterminated-block",
+            DiagnosticCode::InvalidFrontMatter => "invalid-front-matter",
+            DiagnosticCode::ContentTruncated => "content-truncated",
+            DiagnosticCode::IncludeFailed => "include-failed",
+            DiagnosticCode::ProcessFailed => "process-failed",
+            DiagnosticCode::SelfLink => "self-link",
+            DiagnosticCode::FunctionFailed => "function-failed",
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Structured details of a diagnostic, for callers that act on them instead of on the message.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum ParseWarning {
+    /// The content of the directive `directive_id`, `original_bytes` long, was truncated.
+    ContentTruncated { directive_id: String, original_bytes: usize },
+}
+
+/// A single warning or error, located by file and (where known) one-based line.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+    pub code: DiagnosticCode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<ParseWarning>,
+}
+
+impl Diagnostic {
+    pub fn warning(file: impl Into<PathBuf>, line: Option<usize>, code: DiagnosticCode, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Warning, file: file.into(), line, message: message.into(), code, details: None }
+    }
+
+    pub fn error(file: impl Into<PathBuf>, line: Option<usize>, code: DiagnosticCode, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Error, file: file.into(), line, message: message.into(), code, details: None }
+    }
+
+    pub fn with_details(mut self, details: ParseWarning) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// Sets the file of a diagnostic that was recorded before its file was known.
+    pub(crate) fn in_file(mut self, file: &Path) -> Self {
+        self.file = file.to_path_buf();
+        self
+    }
+}
+
+/// Formats as `file:line: severity[code]: message`, like compiler messages.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        write!(f, ": {}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+/// The default sink: logs each diagnostic at the level of its severity.
+pub fn log_diagnostics(diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        match diagnostic.severity {
+            Severity::Warning => warn!("{}", diagnostic),
+            Severity::Error => error!("{}", diagnostic),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let diagnostic = Diagnostic::warning("src/a.py", Some(3), DiagnosticCode::UnterminatedBlock, "missing @endrst");
+        assert_eq!(diagnostic.to_string(), "src/a.py:3: warning[unterminated-block]: missing @endrst");
+        let diagnostic = Diagnostic::error("b.rst", None, DiagnosticCode::ProcessFailed, "unreadable");
+        assert_eq!(diagnostic.to_string(), "b.rst: error[process-failed]: unreadable");
+    }
+
+    #[test]
+    fn test_serialize() {
+        let diagnostic = Diagnostic::warning("a.rst", Some(1), DiagnosticCode::SelfLink, "links to itself");
+        assert_eq!(
+            serde_json::to_value(&diagnostic).unwrap(),
+            serde_json::json!({"severity": "warning", "file": "a.rst", "line": 1, "message": "links to itself", "code": "self-link"})
+        );
+
+        let details = ParseWarning::ContentTruncated { directive_id: "R1".to_string(), original_bytes: 2048 };
+        let diagnostic = Diagnostic::warning("a.rst", Some(1), DiagnosticCode::ContentTruncated, "truncated").with_details(details);
+        assert_eq!(
+            serde_json::to_value(&diagnostic).unwrap()["details"],
+            serde_json::json!({"kind": "content-truncated", "directive_id": "R1", "original_bytes": 2048})
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Differences between two sets of aggregated outputs, e.g. for release notes listing the
+//! requirements added, removed or changed since a baseline.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use serde::Serialize;
+use crate::aggregator::{Aggregator, DirectiveOutput};
+use crate::error::RstParserError;
+
+/// The part of a directive that changed, see [`FieldChange`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "field", content = "key", rename_all = "snake_case")]
+pub enum ChangedField {
+    Name,
+    Arguments,
+    /// An option that is not a link field.
+    Option(String),
+    Content,
+    /// A link field or its backlink field, e.g. `links` or `links_back`.
+    Link(String),
+}
+
+/// A changed field of a directive, with its old and new value; `None` if the option or link
+/// field is missing on that side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    #[serde(flatten)]
+    pub field: ChangedField,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// A directive present on both sides whose fields differ.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DirectiveChange {
+    pub id: String,
+    /// The changed fields, sorted by field.
+    pub changes: Vec<FieldChange>,
+}
+
+/// Where a directive is written, for [`DirectiveMove`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Location {
+    pub source_file: String,
+    pub line_number: Option<usize>,
+}
+
+/// A directive present on both sides whose fields are unchanged, but whose source file or line
+/// number differ.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DirectiveMove {
+    pub id: String,
+    pub old: Location,
+    pub new: Location,
+}
+
+/// Differences between an old and a new set of outputs, matched by directive ID. All lists are
+/// sorted by ID. A directive whose fields changed is only listed in `changed`, even if it moved
+/// as well.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct OutputDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<DirectiveChange>,
+    pub moved: Vec<DirectiveMove>,
+}
+
+impl OutputDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() && self.moved.is_empty()
+    }
+}
+
+/// Compares the outputs written to `old_dir` and `new_dir`, see [`diff_outputs`].
+pub fn diff_output_dirs(old_dir: &Path, new_dir: &Path, link_fields: &[String]) -> Result<OutputDiff, RstParserError> {
+    Ok(diff_outputs(&Aggregator::load_outputs(old_dir)?, &Aggregator::load_outputs(new_dir)?, link_fields))
+}
+
+/// Compares two sets of outputs by directive ID. Option keys in `link_fields`, e.g. `links` and
+/// `links_back`, are reported as [`ChangedField::Link`] changes. If an ID occurs several times
+/// on one side, its last occurrence is compared.
+pub fn diff_outputs(old: &[DirectiveOutput], new: &[DirectiveOutput], link_fields: &[String]) -> OutputDiff {
+    let old_by_id: BTreeMap<&str, &DirectiveOutput> = old.iter().map(|output| (output.id.as_str(), output)).collect();
+    let new_by_id: BTreeMap<&str, &DirectiveOutput> = new.iter().map(|output| (output.id.as_str(), output)).collect();
+
+    let mut diff = OutputDiff {
+        added: new_by_id.keys().filter(|id| !old_by_id.contains_key(*id)).map(|id| id.to_string()).collect(),
+        removed: old_by_id.keys().filter(|id| !new_by_id.contains_key(*id)).map(|id| id.to_string()).collect(),
+        ..OutputDiff::default()
+    };
+    for (id, old_output) in &old_by_id {
+        let Some(new_output) = new_by_id.get(id) else {
+            continue;
+        };
+        let changes = field_changes(old_output, new_output, link_fields);
+        if !changes.is_empty() {
+            diff.changed.push(DirectiveChange { id: id.to_string(), changes });
+        } else if (&old_output.source_file, old_output.line_number) != (&new_output.source_file, new_output.line_number) {
+            diff.moved.push(DirectiveMove { id: id.to_string(), old: location(old_output), new: location(new_output) });
+        }
+    }
+    diff
+}
+
+fn location(output: &DirectiveOutput) -> Location {
+    Location { source_file: output.source_file.clone(), line_number: output.line_number }
+}
+
+fn field_changes(old: &DirectiveOutput, new: &DirectiveOutput, link_fields: &[String]) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    let mut compare = |field: ChangedField, old_value: Option<&String>, new_value: Option<&String>| {
+        if old_value != new_value {
+            changes.push(FieldChange { field, old: old_value.cloned(), new: new_value.cloned() });
+        }
+    };
+    compare(ChangedField::Name, Some(&old.name), Some(&new.name));
+    compare(ChangedField::Arguments, Some(&old.arguments), Some(&new.arguments));
+    let keys: BTreeSet<&String> = old.options.keys().chain(new.options.keys()).collect();
+    for key in keys {
+        let field = if link_fields.contains(key) { ChangedField::Link(key.clone()) } else { ChangedField::Option(key.clone()) };
+        compare(field, old.options.get(key), new.options.get(key));
+    }
+    compare(ChangedField::Content, Some(&old.content), Some(&new.content));
+    changes.sort_by(|a, b| a.field.cmp(&b.field));
+    changes
+}
+
+/// A human-readable summary of `diff`, one line per added, removed, changed or moved directive,
+/// with the changed fields indented below their directive.
+pub fn format_diff(diff: &OutputDiff) -> String {
+    let mut summary = format!(
+        "{} added, {} removed, {} changed, {} moved\n",
+        diff.added.len(), diff.removed.len(), diff.changed.len(), diff.moved.len(),
+    );
+    for id in &diff.added {
+        summary.push_str(&format!("+ {}\n", id));
+    }
+    for id in &diff.removed {
+        summary.push_str(&format!("- {}\n", id));
+    }
+    for change in &diff.changed {
+        summary.push_str(&format!("~ {}\n", change.id));
+        for field_change in &change.changes {
+            let field = match &field_change.field {
+                ChangedField::Name => "name".to_string(),
+                ChangedField::Arguments => "arguments".to_string(),
+                ChangedField::Option(key) => format!("option {}", key),
+                ChangedField::Content => "content".to_string(),
+                ChangedField::Link(key) => format!("link {}", key),
+            };
+            let value = |value: &Option<String>| value.as_deref().map_or("(none)".to_string(), |value| format!("{:?}", value));
+            summary.push_str(&format!("    {}: {} -> {}\n", field, value(&field_change.old), value(&field_change.new)));
+        }
+    }
+    for moved in &diff.moved {
+        let location = |location: &Location| match location.line_number {
+            Some(line) => format!("{}:{}", location.source_file, line),
+            None => location.source_file.clone(),
+        };
+        summary.push_str(&format!("> {}: {} -> {}\n", moved.id, location(&moved.old), location(&moved.new)));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::{DirectiveWithSource, GroupBy};
+    use crate::parser::Directive;
+    use tempfile::tempdir;
+
+    fn dws(id: &str, source_file: &str, line: usize, options: &[(&str, &str)], content: &str) -> DirectiveWithSource {
+        let mut directive = Directive::new("req").with_option("id", id).with_content(content);
+        for (key, value) in options {
+            directive = directive.with_option(key, value);
+        }
+        DirectiveWithSource {
+            directive,
+            source_file: source_file.to_string(),
+            line_number: Some(line),
+            id: id.to_string(),
+            file_metadata: None,
+            depth: 0,
+            byte_range: None,
+            source_byte_range: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_output_dirs() {
+        let (old_dir, new_dir) = (tempdir().unwrap(), tempdir().unwrap());
+        Aggregator::new(old_dir.path(), GroupBy::All).aggregate_to_json(vec![
+            dws("R1", "reqs.rst", 1, &[("status", "open")], "Unchanged."),
+            dws("R2", "reqs.rst", 5, &[("status", "open"), ("links", "R1")], "Old text."),
+            dws("R3", "reqs.rst", 9, &[], "Moved."),
+            dws("R4", "old.rst", 1, &[], "Removed."),
+        ]).unwrap();
+        Aggregator::new(new_dir.path(), GroupBy::DirectiveName).aggregate_to_json(vec![
+            dws("R1", "reqs.rst", 1, &[("status", "open")], "Unchanged."),
+            dws("R2", "reqs.rst", 5, &[("status", "done"), ("links", "R3")], "New text."),
+            dws("R3", "moved.rst", 2, &[], "Moved."),
+            dws("R5", "new.rst", 1, &[], "Added."),
+        ]).unwrap();
+
+        let diff = diff_output_dirs(old_dir.path(), new_dir.path(), &["links".to_string(), "links_back".to_string()]).unwrap();
+        assert_eq!(diff.added, vec!["R5"]);
+        assert_eq!(diff.removed, vec!["R4"]);
+        assert_eq!(diff.changed, vec![DirectiveChange {
+            id: "R2".to_string(),
+            changes: vec![
+                FieldChange { field: ChangedField::Option("status".to_string()), old: Some("open".to_string()), new: Some("done".to_string()) },
+                FieldChange { field: ChangedField::Content, old: Some("Old text.".to_string()), new: Some("New text.".to_string()) },
+                FieldChange { field: ChangedField::Link("links".to_string()), old: Some("R1".to_string()), new: Some("R3".to_string()) },
+            ],
+        }]);
+        assert_eq!(diff.moved, vec![DirectiveMove {
+            id: "R3".to_string(),
+            old: Location { source_file: "reqs.rst".to_string(), line_number: Some(9) },
+            new: Location { source_file: "moved.rst".to_string(), line_number: Some(2) },
+        }]);
+
+        assert_eq!(format_diff(&diff), "1 added, 1 removed, 1 changed, 1 moved\n\
+            + R5\n\
+            - R4\n\
+            ~ R2\n    option status: \"open\" -> \"done\"\n    content: \"Old text.\" -> \"New text.\"\n    link links: \"R1\" -> \"R3\"\n\
+            > R3: reqs.rst:9 -> moved.rst:2\n");
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(json["changed"][0]["changes"][0], serde_json::json!({ "field": "option", "key": "status", "old": "open", "new": "done" }));
+    }
+
+    #[test]
+    fn test_identical_outputs_have_an_empty_diff() {
+        let outputs: Vec<DirectiveOutput> = [dws("R1", "reqs.rst", 1, &[], "Text.")].iter().map(DirectiveOutput::from).collect();
+        assert!(diff_outputs(&outputs, &outputs, &[]).is_empty());
+        assert_eq!(format_diff(&OutputDiff::default()), "0 added, 0 removed, 0 changed, 0 moved\n");
+    }
+}
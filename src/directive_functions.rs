@@ -1,24 +1,102 @@
 use crate::aggregator::DirectiveWithSource;
-use crate::link_data::{LinkConfig, LinkGraph};
+use crate::link_data::{LinkConfig, LinkGraph, LinkNodeData};
+use crate::diagnostics::{Diagnostic, DiagnosticCode, Severity};
 use std::collections::HashMap; // Removed HashSet
+use log::debug;
+use crate::timing::{format_duration, stage_span, Timer};
+use std::ops::Deref;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
+/// Directives keyed by canonical file path and ID, stored as `D`.
+pub type DirectivesMapOf<D> = HashMap<PathBuf, HashMap<String, D>>;
+
 // Type alias for the main directive storage, to be passed to functions.
-pub type AllDirectivesMap = HashMap<PathBuf, HashMap<String, Arc<Mutex<DirectiveWithSource>>>>;
+pub type AllDirectivesMap = DirectivesMapOf<Arc<Mutex<DirectiveWithSource>>>;
+
+/// Directive storage without locks, for one-shot runs where nothing is shared between
+/// threads. Avoids an allocation and a lock per directive compared to `AllDirectivesMap`.
+pub type PlainDirectivesMap = DirectivesMapOf<DirectiveWithSource>;
+
+/// A directive as stored in a directive map: either the plain value or an `Arc<Mutex<_>>`
+/// shared with the watch mode. Both can be read from several threads at once.
+pub trait StoredDirective: Sync {
+    /// Borrows the directive, locking it if needed.
+    fn read(&self) -> impl Deref<Target = DirectiveWithSource> + '_;
+}
+
+impl StoredDirective for DirectiveWithSource {
+    fn read(&self) -> impl Deref<Target = DirectiveWithSource> + '_ {
+        self
+    }
+}
+
+impl StoredDirective for Arc<Mutex<DirectiveWithSource>> {
+    fn read(&self) -> impl Deref<Target = DirectiveWithSource> + '_ {
+        self.lock().unwrap()
+    }
+}
+
+/// Lookups into a directive map that directive functions may use, independent of how the
+/// map stores its directives.
+pub trait DirectiveLookup {
+    /// A copy of the directive with ID `id`, if any file contains it.
+    fn directive(&self, id: &str) -> Option<DirectiveWithSource>;
+}
+
+impl<D: StoredDirective> DirectiveLookup for DirectivesMapOf<D> {
+    fn directive(&self, id: &str) -> Option<DirectiveWithSource> {
+        find_directive_by_id(self, id).map(|stored| stored.read().clone())
+    }
+}
+
+/// Looks up a directive by its ID across all files in the map. Scans the files one by one;
+/// for many lookups into the same map, build an `index_by_id` once instead.
+pub fn find_directive_by_id<'a, D>(
+    map: &'a DirectivesMapOf<D>,
+    id: &str,
+) -> Option<&'a D> {
+    map.values().find_map(|file_map| file_map.get(id))
+}
+
+/// Maps every ID of the map to its directive, for repeated lookups by ID. If several files
+/// hold a directive with the same ID, the index holds one of them.
+pub fn index_by_id<D>(map: &DirectivesMapOf<D>) -> HashMap<&str, &D> {
+    map.values()
+        .flat_map(|file_map| file_map.iter())
+        .map(|(id, stored)| (id.as_str(), stored))
+        .collect()
+}
+
+/// Splits a link option value like `"a, b"` into its target IDs.
+fn parse_link_targets(value: &str) -> Vec<String> {
+    value.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+}
+
+/// Whether the link options of `new_directive` differ from the outgoing links recorded for
+/// it in `old_node`. If they do not, re-applying the functions would rebuild identical edges.
+pub fn changed_link_options(old_node: &LinkNodeData, new_directive: &DirectiveWithSource, link_config: &LinkConfig) -> bool {
+    link_config.link_types.iter().any(|link_type| {
+        let new_targets = new_directive.directive.options.get(&link_type.name).map(|value| parse_link_targets(value)).unwrap_or_default();
+        let old_targets = old_node.outgoing_links.get(&link_type.name).map_or(&[][..], Vec::as_slice);
+        new_targets != old_targets
+    })
+}
 
 /// Trait for functions that can be applied to directives.
 pub trait DirectiveFunction: Send + Sync {
     fn name(&self) -> &str;
 
-    /// Applies the function's logic.
+    /// Applies the function's logic. Problems that do not stop the function are pushed to
+    /// `diagnostics`; an `Err` is reported as an error diagnostic of the directive.
     fn apply(
         &self,
         directive_id: &str,
         directive_data: &DirectiveWithSource,
-        all_directives_map: &AllDirectivesMap,
+        all_directives_map: &dyn DirectiveLookup,
         link_graph: &mut LinkGraph,
         link_config: &LinkConfig,
+        diagnostics: &mut Vec<Diagnostic>,
     ) -> Result<(), String>;
 }
 
@@ -34,31 +112,25 @@ impl DirectiveFunction for BacklinkFunction {
         &self,
         directive_id: &str,
         directive_data: &DirectiveWithSource,
-        _all_directives_map: &AllDirectivesMap, // Not directly used for now
+        _all_directives_map: &dyn DirectiveLookup, // Not directly used for now
         link_graph: &mut LinkGraph,
         link_config: &LinkConfig,
+        diagnostics: &mut Vec<Diagnostic>,
     ) -> Result<(), String> {
         let directive_options = &directive_data.directive.options;
-        // Stores (field_name_of_link, source_directive_id, Vec<target_directive_ids>)
+        // Stores (field_name_of_link, backlink_field_name, Vec<target_directive_ids>)
         let mut links_to_process: Vec<(String, String, Vec<String>)> = Vec::new();
 
         // --- Pass 1: Collect all link information and ensure all involved nodes exist ---
         for link_type_cfg in &link_config.link_types {
             if let Some(target_ids_str) = directive_options.get(&link_type_cfg.name) {
-                if target_ids_str.is_empty() {
-                    continue;
-                }
-                let current_target_ids: Vec<String> = target_ids_str
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect();
+                let current_target_ids = parse_link_targets(target_ids_str);
 
                 if !current_target_ids.is_empty() {
                     // Add to list for processing in Pass 3
                     links_to_process.push((
                         link_type_cfg.name.clone(),
-                        directive_id.to_string(),
+                        link_type_cfg.backlink_name(),
                         current_target_ids.clone(),
                     ));
                     
@@ -79,16 +151,15 @@ impl DirectiveFunction for BacklinkFunction {
         if let Some(source_node_data) = link_graph.get_mut(directive_id) {
             source_node_data.outgoing_links.clear();
         } else {
-            // This should not happen if Pass 1 worked, but as a safeguard:
-            eprintln!("Error: Source node '{}' not found in link_graph for clearing outgoing links. Inconsistency.", directive_id);
+            // No link fields on this directive, so Pass 1 did not create a node and there is nothing to clear.
+            debug!("No link graph node for '{}', no outgoing links to clear.", directive_id);
             // If it doesn't exist, there's nothing to clear, but it implies an issue.
         }
         
         // --- Pass 3: Process collected links to update graph edges (outgoing and incoming) ---
-        for (field_name, source_id_str, target_ids_vec) in links_to_process {
-            // Update outgoing links for the source_id_str
-            // source_id_str here is always the current directive_id
-            if let Some(source_node_data) = link_graph.get_mut(&source_id_str) {
+        for (field_name, backlink_field_name, target_ids_vec) in links_to_process {
+            // Update outgoing links of the current directive
+            if let Some(source_node_data) = link_graph.get_mut(directive_id) {
                 source_node_data
                     .outgoing_links
                     .entry(field_name.clone()) // field_name is the original link field, e.g., "derives"
@@ -98,19 +169,28 @@ impl DirectiveFunction for BacklinkFunction {
 
             // Update incoming links for each target_id in target_ids_vec
             for target_id in target_ids_vec {
-                if target_id == source_id_str { 
-                    eprintln!("Warning: Directive '{}' in file '{}' has a self-referential link in field '{}'.", source_id_str, directive_data.source_file, field_name);
+                if target_id == directive_id {
+                    diagnostics.push(directive_diagnostic(
+                        Severity::Warning,
+                        directive_data,
+                        DiagnosticCode::SelfLink,
+                        format!("Directive '{}' has a self-referential link in field '{}'.", directive_id, field_name),
+                    ));
                     continue;
                 }
                 if let Some(target_node_data) = link_graph.get_mut(&target_id) {
-                    let backlink_field_name = format!("{}_back", field_name);
-                    let incoming_for_field = target_node_data.incoming_links.entry(backlink_field_name).or_default();
-                    if !incoming_for_field.contains(&source_id_str) {
-                        incoming_for_field.push(source_id_str.clone());
+                    let incoming_for_field = target_node_data.incoming_links.entry(backlink_field_name.clone()).or_default();
+                    if !incoming_for_field.iter().any(|id| id == directive_id) {
+                        incoming_for_field.push(directive_id.to_string());
                     }
                 } else {
                     // This should ideally not be reached if Pass 1 correctly ensures all nodes exist.
-                    eprintln!("Error: Target node '{}' not found in link_graph when trying to add incoming link from '{}' (field: {}). Inconsistency.", target_id, source_id_str, field_name);
+                    diagnostics.push(directive_diagnostic(
+                        Severity::Error,
+                        directive_data,
+                        DiagnosticCode::FunctionFailed,
+                        format!("Target node '{}' not found in link_graph when trying to add incoming link from '{}' (field: {}). Inconsistency.", target_id, directive_id, field_name),
+                    ));
                 }
             }
         }
@@ -118,6 +198,11 @@ impl DirectiveFunction for BacklinkFunction {
     }
 }
 
+/// A diagnostic located at `directive`'s source file and line.
+fn directive_diagnostic(severity: Severity, directive: &DirectiveWithSource, code: DiagnosticCode, message: String) -> Diagnostic {
+    Diagnostic { severity, file: PathBuf::from(&directive.source_file), line: directive.line_number, message, code, details: None }
+}
+
 pub struct FunctionApplicator {
     functions: Vec<Box<dyn DirectiveFunction>>,
     link_config: Arc<LinkConfig>,
@@ -125,18 +210,28 @@ pub struct FunctionApplicator {
 
 impl FunctionApplicator {
     pub fn new(link_config: Arc<LinkConfig>) -> Self {
-        let mut functions: Vec<Box<dyn DirectiveFunction>> = Vec::new();
-        functions.push(Box::new(BacklinkFunction));
+        let functions: Vec<Box<dyn DirectiveFunction>> = vec![Box::new(BacklinkFunction)];
         Self { functions, link_config }
     }
 
+    /// Only resolve the link types named in `link_type_names`. Options of the other link types
+    /// are left as they are and get no backlinks. Names that are not configured are ignored.
+    pub fn with_link_types(mut self, link_type_names: &[String]) -> Self {
+        let mut link_config = (*self.link_config).clone();
+        link_config.link_types.retain(|link_type| link_type_names.contains(&link_type.name));
+        self.link_config = Arc::new(link_config);
+        self
+    }
+
+    /// Applies all registered functions to one directive and returns their diagnostics.
     pub fn apply_to_directive(
         &self,
         directive_id: &str,
         directive_data: &DirectiveWithSource,
-        all_directives_map: &AllDirectivesMap,
+        all_directives_map: &dyn DirectiveLookup,
         link_graph: &mut LinkGraph,
-    ) {
+    ) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
         for function in &self.functions {
             if let Err(e) = function.apply(
                 directive_id,
@@ -144,22 +239,35 @@ impl FunctionApplicator {
                 all_directives_map,
                 link_graph,
                 &self.link_config,
+                &mut diagnostics,
             ) {
-                eprintln!(
-                    "Error applying function '{}' to directive '{}': {}",
-                    function.name(),
-                    directive_id,
-                    e
-                );
+                diagnostics.push(directive_diagnostic(
+                    Severity::Error,
+                    directive_data,
+                    DiagnosticCode::FunctionFailed,
+                    format!("Error applying function '{}' to directive '{}': {}", function.name(), directive_id, e),
+                ));
             }
         }
+        diagnostics
+    }
+
+    /// Whether `directive`'s link options differ from the outgoing links recorded for its ID in
+    /// `link_graph`, see [`changed_link_options`]. Directives without a node have no links.
+    pub fn has_changed_links(&self, directive: &DirectiveWithSource, link_graph: &LinkGraph) -> bool {
+        let old_node = link_graph.get(&directive.id).cloned().unwrap_or_default();
+        changed_link_options(&old_node, directive, &self.link_config)
     }
 
-    pub fn apply_to_all(
+    /// Rebuilds `link_graph` from all directives and returns the diagnostics of all functions.
+    /// Works on both `AllDirectivesMap` and the lock-free `PlainDirectivesMap`.
+    pub fn apply_to_all<D: StoredDirective>(
         &self,
-        current_directives_map: &AllDirectivesMap,
+        current_directives_map: &DirectivesMapOf<D>,
         link_graph: &mut LinkGraph,
-    ) {
+    ) -> Vec<Diagnostic> {
+        let timer = Timer::new("apply_to_all");
+        let span = stage_span!("apply_to_all", directives);
         // Clear all incoming links before full reprocessing.
         // Outgoing links are cleared per-directive within BacklinkFunction::apply (Pass 2).
         for node_data in link_graph.values_mut() {
@@ -176,15 +284,19 @@ impl FunctionApplicator {
         link_graph.retain(|id, _| valid_directive_ids.contains(id));
 
 
+        let mut diagnostics = Vec::new();
         for file_directives in current_directives_map.values() {
-            for (id, directive_arc) in file_directives.iter() {
-                let directive_data_guard = directive_arc.lock().unwrap();
+            for (id, stored_directive) in file_directives.iter() {
+                let directive_data_guard = stored_directive.read();
                 // Ensure node for current directive exists before applying (important if it has no outgoing links but might get incoming)
                 // This is now handled in Pass 1 of BacklinkFunction::apply
                 // link_graph.entry(id.clone()).or_default(); 
-                self.apply_to_directive(id, &directive_data_guard, current_directives_map, link_graph);
+                diagnostics.extend(self.apply_to_directive(id, &directive_data_guard, current_directives_map, link_graph));
             }
         }
+        span.record_count("directives", valid_directive_ids.len());
+        debug!("apply_to_all directives={} duration={}", valid_directive_ids.len(), format_duration(timer.elapsed()));
+        diagnostics
     }
 
     /// Applies all registered functions to a specific subset of directives.
@@ -195,9 +307,10 @@ impl FunctionApplicator {
     pub fn apply_to_subset(
         &self,
         directives_to_process: &[Arc<Mutex<DirectiveWithSource>>],
-        all_directives_map: &AllDirectivesMap, // Full map for contextual lookups by functions
+        all_directives_map: &dyn DirectiveLookup, // Full map for contextual lookups by functions
         link_graph: &mut LinkGraph,
-    ) {
+    ) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
         for directive_arc in directives_to_process {
             let directive_data_guard = directive_arc.lock().unwrap();
             // apply_to_directive will call each function's apply method.
@@ -205,12 +318,131 @@ impl FunctionApplicator {
             // 1. Ensure the node for directive_data_guard.id exists.
             // 2. Clear its old outgoing links.
             // 3. Rebuild its outgoing links and update incoming links on its targets.
-            self.apply_to_directive(
+            diagnostics.extend(self.apply_to_directive(
                 &directive_data_guard.id,
                 &directive_data_guard,
                 all_directives_map,
                 link_graph,
-            );
+            ));
         }
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link_data::LinkTypeConfig;
+    use crate::parser::Directive;
+
+    fn new_dws(id: &str, file: &str) -> DirectiveWithSource {
+        DirectiveWithSource {
+            directive: Directive::new("req"),
+            source_file: file.to_string(),
+            line_number: Some(1),
+            id: id.to_string(),
+            file_metadata: None,
+            depth: 0,
+            byte_range: None,
+            source_byte_range: None,
+        }
+    }
+
+    #[test]
+    fn test_find_directive_by_id() {
+        let mut map: AllDirectivesMap = HashMap::new();
+        for (id, file) in [("a", "file1.rst"), ("b", "file1.rst"), ("c", "file2.rst")] {
+            map.entry(PathBuf::from(file))
+                .or_default()
+                .insert(id.to_string(), Arc::new(Mutex::new(new_dws(id, file))));
+        }
+
+        let found = find_directive_by_id(&map, "c").expect("directive 'c' should be found");
+        assert_eq!(found.lock().unwrap().source_file, "file2.rst");
+        assert!(find_directive_by_id(&map, "b").is_some());
+        assert!(find_directive_by_id(&map, "missing").is_none());
+        assert!(find_directive_by_id(&AllDirectivesMap::new(), "a").is_none());
+
+        let index = index_by_id(&map);
+        assert_eq!(index.len(), 3);
+        assert_eq!(index["a"].lock().unwrap().source_file, "file1.rst");
+        assert!(Arc::ptr_eq(index["c"], found));
+        assert!(!index.contains_key("missing"));
+    }
+
+    #[test]
+    fn test_changed_link_options() {
+        let link_config = LinkConfig {
+            link_types: vec![LinkTypeConfig::new("links"), LinkTypeConfig::new("tests")],
+            ..Default::default()
+        };
+        let mut old_node = LinkNodeData::default();
+        old_node.outgoing_links.insert("links".to_string(), vec!["a".to_string(), "b".to_string()]);
+
+        let mut directive = new_dws("x", "file.rst");
+        directive.directive.options.insert("links".to_string(), "a, b".to_string());
+        directive.directive.options.insert("status".to_string(), "open".to_string());
+        assert!(!changed_link_options(&old_node, &directive, &link_config), "whitespace and other options do not matter");
+
+        directive.directive.options.insert("tests".to_string(), "".to_string());
+        assert!(!changed_link_options(&old_node, &directive, &link_config), "an empty link option has no targets");
+
+        directive.directive.options.insert("links".to_string(), "a".to_string());
+        assert!(changed_link_options(&old_node, &directive, &link_config));
+
+        directive.directive.options.shift_remove("links");
+        assert!(changed_link_options(&old_node, &directive, &link_config));
+        assert!(!changed_link_options(&LinkNodeData::default(), &directive, &link_config));
+    }
+
+    #[test]
+    fn test_backlink_function_uses_custom_suffix() {
+        let link_config = LinkConfig {
+            link_types: vec![LinkTypeConfig { custom_backlink_suffix: Some("_by".to_string()), ..LinkTypeConfig::new("tests") }],
+            ..Default::default()
+        };
+        let mut source = new_dws("source", "file.rst");
+        source.directive.options.insert("tests".to_string(), "target".to_string());
+        let mut link_graph = LinkGraph::new();
+        BacklinkFunction.apply("source", &source, &AllDirectivesMap::new(), &mut link_graph, &link_config, &mut Vec::new()).unwrap();
+        assert_eq!(link_graph["target"].incoming_links["tests_by"], vec!["source".to_string()]);
+    }
+
+    #[test]
+    fn test_with_link_types_only_resolves_selected_types() {
+        let link_config = LinkConfig {
+            link_types: vec![LinkTypeConfig::new("derives"), LinkTypeConfig::new("tests")],
+            ..Default::default()
+        };
+        let mut source = new_dws("source", "file.rst");
+        source.directive.options.insert("derives".to_string(), "parent".to_string());
+        source.directive.options.insert("tests".to_string(), "check".to_string());
+        let mut map: AllDirectivesMap = HashMap::new();
+        let file_map = map.entry(PathBuf::from("file.rst")).or_default();
+        for dws in [source, new_dws("parent", "file.rst"), new_dws("check", "file.rst")] {
+            file_map.insert(dws.id.clone(), Arc::new(Mutex::new(dws)));
+        }
+
+        let mut link_graph = LinkGraph::new();
+        FunctionApplicator::new(Arc::new(link_config))
+            .with_link_types(&["derives".to_string()])
+            .apply_to_all(&map, &mut link_graph);
+        assert_eq!(link_graph["parent"].incoming_links["derives_back"], vec!["source".to_string()]);
+        assert!(link_graph.get("check").is_none_or(|node| node.incoming_links.is_empty()));
+        assert!(!link_graph["source"].outgoing_links.contains_key("tests"));
+    }
+
+    #[test]
+    fn test_apply_to_all_reports_self_links() {
+        let link_config = LinkConfig { link_types: vec![LinkTypeConfig::new("links")], ..Default::default() };
+        let mut looped = new_dws("loop", "file.rst");
+        looped.line_number = Some(7);
+        looped.directive.options.insert("links".to_string(), "loop".to_string());
+        let mut map: AllDirectivesMap = HashMap::new();
+        map.entry(PathBuf::from("file.rst")).or_default().insert("loop".to_string(), Arc::new(Mutex::new(looped)));
+
+        let diagnostics = FunctionApplicator::new(Arc::new(link_config)).apply_to_all(&map, &mut LinkGraph::new());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].code, diagnostics[0].file.as_path(), diagnostics[0].line), (DiagnosticCode::SelfLink, std::path::Path::new("file.rst"), Some(7)));
     }
 }
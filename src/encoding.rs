@@ -0,0 +1,58 @@
+//! Decoding of source files that are not UTF-8, with the encodings of `encoding_rs`.
+
+use std::io;
+
+/// Text encoding used to decode source files, see `Processor::with_encoding`. Look encodings
+/// up by their WHATWG label with `Encoding::for_label`, e.g. `b"utf-16le"` or `b"windows-1252"`
+/// (which `latin1` and `iso-8859-1` also name), or use the statics such as `encoding_rs::UTF_16LE`.
+pub use encoding_rs::Encoding;
+
+/// Decodes `bytes` with the encoding announced by a leading byte order mark, or with
+/// `encoding` if there is none. The byte order mark is not part of the result.
+/// Fails with `io::ErrorKind::InvalidData` on byte sequences invalid in that encoding, and
+/// for the `replacement` encoding that some labels of unsupported encodings map to.
+pub fn decode(bytes: &[u8], encoding: &'static Encoding) -> io::Result<String> {
+    let (encoding, bytes) = match Encoding::for_bom(bytes) {
+        Some((bom_encoding, bom_length)) => (bom_encoding, &bytes[bom_length..]),
+        None => (encoding, bytes),
+    };
+    if encoding == encoding_rs::REPLACEMENT {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Unsupported encoding"));
+    }
+    encoding
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .map(|text| text.into_owned())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Text is not valid {}", encoding.name())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("é".as_bytes(), UTF_8).unwrap(), "é");
+        assert_eq!(decode(&[0xE9], WINDOWS_1252).unwrap(), "é");
+        assert_eq!(decode(&[0x00, 0x61, 0x00, 0xE9], UTF_16BE).unwrap(), "aé");
+        // The byte order mark overrides the configured encoding
+        assert_eq!(decode(&[0xFF, 0xFE, 0x61, 0x00], WINDOWS_1252).unwrap(), "a");
+        assert_eq!(decode(&[0xEF, 0xBB, 0xBF, 0x61], UTF_16LE).unwrap(), "a");
+
+        assert_eq!(decode(&[0xE9], UTF_8).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(decode(&[0x61, 0x00, 0x62], UTF_16LE).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(decode(&[0x00, 0xD8], UTF_16LE).is_err(), "unpaired surrogate");
+    }
+
+    #[test]
+    fn test_decode_windows_1252_by_label() {
+        // 0x80..0x9F are printable characters in windows-1252, not ISO-8859-1 control characters
+        for label in ["windows-1252", "latin1", "ISO-8859-1"] {
+            let encoding = Encoding::for_label(label.as_bytes()).unwrap();
+            assert_eq!(decode(&[0x93, 0x61, 0x94, 0x20, 0x80, 0x96], encoding).unwrap(), "\u{201C}a\u{201D} \u{20AC}\u{2013}", "{}", label);
+        }
+        assert!(Encoding::for_label(b"no-such-encoding").is_none());
+        let replacement = Encoding::for_label(b"iso-2022-kr").unwrap();
+        assert_eq!(decode(b"text", replacement).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,92 @@
+//! The error type of the public API, for callers that want to handle all failures of the
+//! library with one type.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use crate::aggregator::AggregatorError;
+use crate::diagnostics::{Diagnostic, DiagnosticCode};
+
+/// Error returned by the fallible functions of the processor, aggregator, file walker, link
+/// configuration and watch mode. The type is `Send` and `Sync`, so it also converts into
+/// `Box<dyn Error + Send + Sync>`.
+#[derive(Debug, Error)]
+pub enum RstParserError {
+    /// Reading `path` failed, or it could not be canonicalized, e.g. because it does not exist.
+    #[error("Failed to read {}: {source}", path.display())]
+    Io { path: PathBuf, #[source] source: io::Error },
+    /// Directives of `file` could not be parsed.
+    #[error("{}{}: {message}", file.display(), line.map(|line| format!(":{}", line)).unwrap_or_default())]
+    Parse { file: PathBuf, line: Option<usize>, message: String },
+    /// No RST could be extracted from `file`, e.g. because it is not valid text in the
+    /// configured encoding.
+    #[error("Failed to extract RST from {}: {source}", file.display())]
+    Extraction { file: PathBuf, #[source] source: io::Error },
+    /// The configuration file at `path` is malformed.
+    #[error("Invalid configuration in {}: {message}", path.display())]
+    Config { path: PathBuf, message: String },
+    /// Directives could not be written to or read back from output files. `AggregatorError`
+    /// tells why, e.g. a template error or a cycle in the topological order.
+    #[error("{0}")]
+    Aggregation(#[from] AggregatorError),
+    /// The link configuration is inconsistent, e.g. two link types share a backlink field.
+    #[error("{0}")]
+    LinkValidation(String),
+    /// Several files failed, e.g. during a watch mode initial scan, in input order.
+    #[error("{} errors occurred:\n{}", .0.len(), join_messages(.0))]
+    Multiple(Vec<RstParserError>),
+}
+
+impl RstParserError {
+    /// The file the error is about, if it is about a single one.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            RstParserError::Io { path, .. } | RstParserError::Config { path, .. } => Some(path),
+            RstParserError::Parse { file, .. } | RstParserError::Extraction { file, .. } => Some(file),
+            RstParserError::Aggregation(_) | RstParserError::LinkValidation(_) | RstParserError::Multiple(_) => None,
+        }
+    }
+
+    /// The error as a diagnostic of the file that could not be processed.
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let line = match self {
+            RstParserError::Parse { line, .. } => *line,
+            _ => None,
+        };
+        Diagnostic::error(self.path().unwrap_or(Path::new("")), line, DiagnosticCode::ProcessFailed, self.to_string())
+    }
+}
+
+fn join_messages(errors: &[RstParserError]) -> String {
+    errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn test_converts_into_boxed_errors() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<RstParserError>();
+
+        let err = RstParserError::Io { path: PathBuf::from("a.rst"), source: io::Error::other("denied") };
+        assert_eq!(err.source().unwrap().to_string(), "denied");
+        let boxed: Box<dyn Error + Send + Sync> = err.into();
+        assert_eq!(boxed.to_string(), "Failed to read a.rst: denied");
+        let boxed: Box<dyn Error> = RstParserError::LinkValidation("collision".to_string()).into();
+        assert_eq!(boxed.to_string(), "collision");
+    }
+
+    #[test]
+    fn test_display() {
+        let parse = |line| RstParserError::Parse { file: PathBuf::from("a.rst"), line, message: "bad".to_string() };
+        assert_eq!(parse(Some(3)).to_string(), "a.rst:3: bad");
+        assert_eq!(parse(None).to_string(), "a.rst: bad");
+        assert_eq!(parse(Some(3)).to_diagnostic().line, Some(3));
+        let multiple = RstParserError::Multiple(vec![parse(Some(1)), RstParserError::LinkValidation("collision".to_string())]);
+        assert_eq!(multiple.to_string(), "2 errors occurred:\na.rst:1: bad\ncollision");
+        assert!(multiple.path().is_none());
+    }
+}
@@ -1,5 +1,8 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::ffi::OsStr;
+use crate::diagnostics::{log_diagnostics, Diagnostic, DiagnosticCode};
+use crate::timing::{format_duration, stage_span, Timer};
+use log::debug;
 
 // Helper function to uniformly dedent lines
 fn dedent_lines(lines: Vec<String>) -> String {
@@ -38,10 +41,10 @@ fn dedent_lines(lines: Vec<String>) -> String {
         return String::new();
     }
     // Remove empty lines from the beginning and end of the result
-    while processed_lines.first().map_or(false, |line| line.trim().is_empty()) {
+    while processed_lines.first().is_some_and(|line| line.trim().is_empty()) {
         processed_lines.remove(0);
     }
-    while processed_lines.last().map_or(false, |line| line.trim().is_empty()) {
+    while processed_lines.last().is_some_and(|line| line.trim().is_empty()) {
         processed_lines.pop();
     }
     
@@ -57,6 +60,622 @@ fn dedent_lines(lines: Vec<String>) -> String {
     result
 }
 
+/// Options controlling how RST blocks are extracted from source files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractorConfig {
+    /// Treat truly empty lines without comment prefix inside a C++ `@rst` block as empty RST
+    /// lines (default). This applies to any number of consecutive empty lines, not just a
+    /// single one, as blocks were extracted before the option existed. When `false`, the first
+    /// such line terminates the block like any other non-comment line and the block is discarded.
+    pub lenient_blank_lines: bool,
+    /// Language tag of Markdown fenced code blocks that contain RST (default `rst`),
+    /// compared case-insensitively with the first word of the info string.
+    pub markdown_language: String,
+    /// In `.rst` files that contain `.. @rst` / `.. @endrst` comment lines, only use the
+    /// regions between them. Files without these markers are used as a whole either way.
+    pub rst_markers: bool,
+    /// Number of spaces stripped after the `///`, `//!` or `//` of a C++ comment line (default `1`);
+    /// lines with fewer spaces lose all of them. Indentation beyond it is kept, up to the
+    /// indentation common to all lines of the block, which is removed.
+    pub cpp_prefix_spaces: usize,
+}
+
+impl Default for ExtractorConfig {
+    fn default() -> Self {
+        ExtractorConfig {
+            lenient_blank_lines: true,
+            markdown_language: "rst".to_string(),
+            rst_markers: false,
+            cpp_prefix_spaces: 1,
+        }
+    }
+}
+
+/// Strips a leading `///`, `//!` or `//` comment marker and up to `spaces` following spaces.
+fn strip_cpp_comment_prefix(text: &str, spaces: usize) -> Option<&str> {
+    let rest = text.strip_prefix("///")
+        .or_else(|| text.strip_prefix("//!"))
+        .or_else(|| text.strip_prefix("//"))?;
+    Some(strip_spaces(rest, spaces))
+}
+
+/// Strips up to `spaces` leading spaces.
+fn strip_spaces(text: &str, spaces: usize) -> &str {
+    let stripped = text.bytes().take(spaces).take_while(|&b| b == b' ').count();
+    &text[stripped..]
+}
+
+/// Splits a line of a `/* ... */` comment at its closing `*/`, if any: the text before it,
+/// and whether the comment ends on this line.
+fn split_block_comment_end(text: &str) -> (&str, bool) {
+    match text.find("*/") {
+        Some(end) => (&text[..end], true),
+        None => (text, false),
+    }
+}
+
+/// Opens a `/*`, `/**` or `/*!` comment at the start of `trimmed_line`: the text after the
+/// opening marker and up to `spaces` spaces, and whether the comment ends on the same line.
+fn open_cpp_block_comment(trimmed_line: &str, spaces: usize) -> Option<(&str, bool)> {
+    let (text, closed) = split_block_comment_end(trimmed_line.strip_prefix("/*")?);
+    let text = text.strip_prefix(['*', '!']).unwrap_or(text);
+    Some((strip_spaces(text, spaces), closed))
+}
+
+/// Finds a `//` comment that follows code on the same line, ignoring `//` inside string
+/// and character literals. Returns the comment starting at its `//`.
+fn trailing_cpp_comment(line: &str) -> Option<&str> {
+    let bytes = line.as_bytes();
+    let mut in_literal: Option<u8> = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        match (in_literal, bytes[i]) {
+            (Some(_), b'\\') => i += 1, // Skip the escaped character
+            (Some(quote), b) if b == quote => in_literal = None,
+            (None, b'"') | (None, b'\'') => in_literal = Some(bytes[i]),
+            (None, b'/') if bytes.get(i + 1) == Some(&b'/') => return Some(&line[i..]),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// An opening Markdown code fence: its indentation, fence character, fence length and info string.
+fn markdown_fence_open(line: &str) -> Option<(usize, u8, usize, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let rest = &line[indent..];
+    let fence_char = *rest.as_bytes().first().filter(|c| **c == b'`' || **c == b'~')?;
+    let fence_len = rest.bytes().take_while(|c| *c == fence_char).count();
+    let info = rest[fence_len..].trim();
+    if fence_len < 3 || (fence_char == b'`' && info.contains('`')) {
+        return None;
+    }
+    Some((indent, fence_char, fence_len, info))
+}
+
+/// Whether `line` closes a code fence opened with at least `fence_len` `fence_char`s.
+fn is_markdown_fence_close(line: &str, fence_char: u8, fence_len: usize) -> bool {
+    let rest = line.trim();
+    rest.len() >= fence_len && rest.bytes().all(|c| c == fence_char)
+}
+
+/// What precedes the current position of `python_docstrings`, as far as docstrings care.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PythonContext {
+    /// Start of a statement: start of the file, after a complete line, `;`, or the `:` of a
+    /// `def` or `class` header.
+    StatementStart,
+    /// Inside an expression, e.g. after `=`, `(` or a name.
+    Expression,
+}
+
+/// Whether `word` is a Python string prefix such as `r` or `rb`. `f` strings are left out,
+/// as they are not docstrings.
+fn is_python_string_prefix(word: &str) -> bool {
+    matches!(word.to_ascii_lowercase().as_str(), "r" | "u" | "b" | "br" | "rb")
+}
+
+/// One-based number of the line of `content` that contains the byte at `offset`.
+fn line_at(content: &str, offset: usize) -> usize {
+    content.as_bytes()[..offset].iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+/// Records an unterminated block that opens at `line`. The file is filled in by
+/// `RstExtractor::extract_from_file_with_diagnostics`.
+fn unterminated(diagnostics: &mut Vec<Diagnostic>, line: usize, message: impl Into<String>) {
+    diagnostics.push(Diagnostic::warning(PathBuf::new(), Some(line), DiagnosticCode::UnterminatedBlock, message));
+}
+
+/// The byte offsets and contents of the triple-quoted strings of `content` that start a
+/// statement. Tracks the last significant token outside of strings and comments, and the
+/// bracket depth, to tell docstrings from triple-quoted strings inside expressions.
+fn python_docstrings<'a>(content: &'a str, diagnostics: &mut Vec<Diagnostic>) -> Vec<(usize, &'a str)> {
+    let bytes = content.as_bytes();
+    let mut docstrings = Vec::new();
+    let mut context = PythonContext::StatementStart;
+    let mut bracket_depth = 0usize;
+    let mut logical_line_start = 0;
+    // Context before the word that ends at `word_end`, for string prefixes like `r"""`
+    let mut word_start_context = context;
+    let mut word_start = 0;
+    let mut word_end = usize::MAX;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let byte = bytes[i];
+        match byte {
+            b'#' => {
+                i = content[i..].find('\n').map_or(bytes.len(), |pos| i + pos);
+                continue;
+            }
+            b'"' | b'\'' => {
+                let quote = &content[i..i + 1];
+                let triple = quote.repeat(3);
+                let opener_context = if word_end == i && is_python_string_prefix(&content[word_start..i]) { word_start_context } else { context };
+                if content[i..].starts_with(&triple) {
+                    let body_start = i + 3;
+                    let Some(body_len) = content[body_start..].find(&triple) else {
+                        if opener_context == PythonContext::StatementStart && bracket_depth == 0 {
+                            unterminated(diagnostics, line_at(content, i), "Unterminated Python docstring.");
+                        }
+                        break;
+                    };
+                    if opener_context == PythonContext::StatementStart && bracket_depth == 0 {
+                        docstrings.push((body_start, &content[body_start..body_start + body_len]));
+                    }
+                    i = body_start + body_len + 3;
+                } else {
+                    // Single-quoted string: skip to its closing quote on the same line
+                    let mut j = i + 1;
+                    while j < bytes.len() && bytes[j] != byte && bytes[j] != b'\n' {
+                        j += if bytes[j] == b'\\' { 2 } else { 1 };
+                    }
+                    i = (j + 1).min(bytes.len());
+                }
+                context = PythonContext::Expression;
+                continue;
+            }
+            b'(' | b'[' | b'{' => {
+                bracket_depth += 1;
+                context = PythonContext::Expression;
+            }
+            b')' | b']' | b'}' => {
+                bracket_depth = bracket_depth.saturating_sub(1);
+                context = PythonContext::Expression;
+            }
+            b'\n' => {
+                let continued = content[logical_line_start..i].trim_end().ends_with('\\');
+                if bracket_depth == 0 && !continued {
+                    context = PythonContext::StatementStart;
+                    logical_line_start = i + 1;
+                }
+            }
+            b';' if bracket_depth == 0 => {
+                context = PythonContext::StatementStart;
+                logical_line_start = i + 1;
+            }
+            b':' if bracket_depth == 0 => {
+                let header = content[logical_line_start..i].trim_start();
+                let is_definition = ["def ", "class ", "async def "].iter().any(|keyword| header.starts_with(keyword));
+                context = if is_definition { PythonContext::StatementStart } else { PythonContext::Expression };
+            }
+            b' ' | b'\t' | b'\r' => {}
+            _ if byte.is_ascii_alphanumeric() || byte == b'_' => {
+                if word_end != i {
+                    word_start = i;
+                    word_start_context = context;
+                }
+                word_end = i + 1;
+                context = PythonContext::Expression;
+            }
+            _ => context = PythonContext::Expression,
+        }
+        i += 1;
+    }
+    docstrings
+}
+
+/// Extensions of the files `RstExtractor::extract_from_file` extracts RST from. Files with other
+/// extensions yield no RST.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["rst", "py", "cpp", "h", "hpp", "cxx", "hxx", "cc", "hh", "md", "markdown", "html", "htm", "xml"];
+
+pub struct RstExtractor;
+
+impl RstExtractor {
+    /// Extract RST content from a file based on its extension
+    pub fn extract_from_file<P: AsRef<Path>>(file_path: P, content: &str) -> String {
+        Self::extract_from_file_with_config(file_path, content, &ExtractorConfig::default())
+    }
+
+    /// Same as `extract_from_file`, but with explicit extraction options.
+    pub fn extract_from_file_with_config<P: AsRef<Path>>(file_path: P, content: &str, config: &ExtractorConfig) -> String {
+        let (rst, diagnostics) = Self::extract_from_file_with_diagnostics(file_path, content, config);
+        log_diagnostics(&diagnostics);
+        rst
+    }
+
+    /// Same as `extract_from_file_with_config`, but returns the problems found in `content`,
+    /// such as unterminated blocks, instead of logging them.
+    pub fn extract_from_file_with_diagnostics<P: AsRef<Path>>(file_path: P, content: &str, config: &ExtractorConfig) -> (String, Vec<Diagnostic>) {
+        let file_path = file_path.as_ref();
+        let timer = Timer::new("extract");
+        let span = stage_span!("extract", file, rst_bytes);
+        span.record_display("file", file_path.display());
+        let mut diagnostics = Vec::new();
+        
+        let rst = match file_path.extension().and_then(OsStr::to_str) {
+            Some("cpp") | Some("h") | Some("hpp") | Some("cxx") | Some("hxx") | Some("cc") | Some("hh") => Self::cpp_blocks(content, config, &mut diagnostics),
+            Some("py") => Self::python_blocks(content, &mut diagnostics),
+            Some("md") | Some("markdown") => Self::markdown_blocks(content, config, &mut diagnostics),
+            Some("html") | Some("htm") | Some("xml") => Self::html_blocks(content, &mut diagnostics),
+            Some("rst") if config.rst_markers => Self::marked_rst_blocks(content, &mut diagnostics),
+            Some("rst") => content.to_string(), // For .rst files, use the content as is
+            _ => {
+                // eprint!("Unsupported file type for RST extraction: {:?}", file_path.extension());
+                String::new() // Or return content.to_string() if unknown types should pass through
+            }
+        };
+        span.record_count("rst_bytes", rst.len());
+        debug!("extract file={} rst_bytes={} duration={}", file_path.display(), rst.len(), format_duration(timer.elapsed()));
+        (rst, diagnostics.into_iter().map(|diagnostic| diagnostic.in_file(file_path)).collect())
+    }
+
+    /// Runs `extract` and logs its diagnostics, for the extractors that take no file path.
+    fn with_logged_diagnostics(extract: impl FnOnce(&mut Vec<Diagnostic>) -> String) -> String {
+        let mut diagnostics = Vec::new();
+        let rst = extract(&mut diagnostics);
+        let diagnostics: Vec<Diagnostic> = diagnostics.into_iter().map(|diagnostic| diagnostic.in_file(Path::new("<input>"))).collect();
+        log_diagnostics(&diagnostics);
+        rst
+    }
+
+    /// Extract RST between `@rst` and `@endrst` from Python docstrings: triple-quoted strings
+    /// that form a statement of their own, such as module, class and function docstrings.
+    /// Triple-quoted strings inside expressions (`x = """..."""`, `@decorator("""...""")`)
+    /// are skipped.
+    pub fn extract_from_python(content: &str) -> String {
+        Self::with_logged_diagnostics(|diagnostics| Self::python_blocks(content, diagnostics))
+    }
+
+    fn python_blocks(content: &str, diagnostics: &mut Vec<Diagnostic>) -> String {
+        let mut extracted_blocks = Vec::new();
+        for (doc_offset, doc_content) in python_docstrings(content, diagnostics) {
+            if let Some(unterminated_offset) = Self::extract_from_python_docstring(doc_content, &mut extracted_blocks) {
+                unterminated(diagnostics, line_at(content, doc_offset + unterminated_offset), "Unterminated RST block in Python docstring (missing @endrst).");
+            }
+        }
+        extracted_blocks.join("\n\n")
+    }
+
+    /// Appends the `@rst` blocks of one docstring to `extracted_blocks`. Returns the offset
+    /// of an `@rst` marker without matching `@endrst`, if there is one.
+    fn extract_from_python_docstring(doc_content: &str, extracted_blocks: &mut Vec<String>) -> Option<usize> {
+        const RST_START_MARKER: &str = "@rst";
+        const RST_END_MARKER: &str = "@endrst";
+
+        let mut rst_search_offset_in_doc = 0;
+        while rst_search_offset_in_doc < doc_content.len() {
+            if let Some(rst_start_rel) = doc_content[rst_search_offset_in_doc..].find(RST_START_MARKER) {
+                let rst_content_actual_start = rst_search_offset_in_doc + rst_start_rel + RST_START_MARKER.len();
+                if let Some(rst_end_rel) = doc_content[rst_content_actual_start..].find(RST_END_MARKER) {
+                    let rst_content_actual_end = rst_content_actual_start + rst_end_rel;
+                    let block_content_raw = &doc_content[rst_content_actual_start..rst_content_actual_end];
+                    
+                    let mut processed_block_str = block_content_raw;
+
+                    // Check for trailing newline (and potential following spaces on that line)
+                    // This needs to be done *after* leading newline is stripped if both are present.
+                    if processed_block_str.ends_with('\n') {
+                        processed_block_str = &processed_block_str[..processed_block_str.len() -1];
+                        if processed_block_str.ends_with('\r') { // Handle \r\n specifically
+                            processed_block_str = &processed_block_str[..processed_block_str.len() -1];
+                        }
+                    } else if processed_block_str.ends_with("\r\n") {
+                         processed_block_str = &processed_block_str[..processed_block_str.len() -2];
+                    }
+                    
+                    // After stripping optional newlines, if processed_block_str is empty,
+                    // it means the original block was like "@rst\n@endrst" or "@rst @endrst" or "@rst@endrst"
+                    if processed_block_str.is_empty() {
+                        // If original block_content_raw was just newlines, it should be a block with one empty line.
+                        // If block_content_raw was empty or just whitespace, it's an empty block.
+                        if block_content_raw.trim().is_empty() && !block_content_raw.is_empty() { // e.g. @rst \n @endrst
+                            extracted_blocks.push(dedent_lines(vec![String::new()]));
+                        } else { // e.g. @rst@endrst or @rst   @endrst
+                            extracted_blocks.push(String::new());
+                        }
+                    } else {
+                        let lines_vec: Vec<String> = processed_block_str.lines().map(String::from).collect();
+                        extracted_blocks.push(dedent_lines(lines_vec));
+                    }
+                    rst_search_offset_in_doc = rst_content_actual_end + RST_END_MARKER.len();
+                } else {
+                    return Some(rst_search_offset_in_doc + rst_start_rel); // Missing @endrst in this doc_content
+                }
+            } else {
+                break; // No more @rst in this doc_content
+            }
+        }
+        None
+    }
+
+    /// Extract the regions between `.. @rst` and `.. @endrst` comment lines of an RST file,
+    /// joined with blank lines. Without any `.. @rst` line the whole content is returned.
+    pub fn extract_from_marked_rst(content: &str) -> String {
+        Self::with_logged_diagnostics(|diagnostics| Self::marked_rst_blocks(content, diagnostics))
+    }
+
+    fn marked_rst_blocks(content: &str, diagnostics: &mut Vec<Diagnostic>) -> String {
+        const RST_START_MARKER: &str = ".. @rst";
+        const RST_END_MARKER: &str = ".. @endrst";
+
+        if !content.lines().any(|line| line.trim() == RST_START_MARKER) {
+            return content.to_string();
+        }
+        let mut extracted_blocks = Vec::new();
+        let mut lines = content.lines().enumerate();
+        while let Some((line_index, line)) = lines.next() {
+            if line.trim() != RST_START_MARKER {
+                continue;
+            }
+            let mut block_lines = Vec::new();
+            let mut closed = false;
+            for (_, block_line) in lines.by_ref() {
+                if block_line.trim() == RST_END_MARKER {
+                    closed = true;
+                    break;
+                }
+                block_lines.push(block_line);
+            }
+            if !closed {
+                unterminated(diagnostics, line_index + 1, "Unterminated RST block in RST file (missing .. @endrst).");
+                break;
+            }
+            extracted_blocks.push(block_lines.join("\n").trim_matches('\n').to_string());
+        }
+        extracted_blocks.join("\n\n")
+    }
+
+    /// Extract the content of fenced ```` ```rst ```` (or `~~~rst`) code blocks from Markdown.
+    /// Blocks are joined with blank lines, like the blocks of the other extractors.
+    pub fn extract_from_markdown(content: &str) -> String {
+        Self::extract_from_markdown_with_config(content, &ExtractorConfig::default())
+    }
+
+    /// Same as `extract_from_markdown`, but with explicit extraction options.
+    pub fn extract_from_markdown_with_config(content: &str, config: &ExtractorConfig) -> String {
+        Self::with_logged_diagnostics(|diagnostics| Self::markdown_blocks(content, config, diagnostics))
+    }
+
+    fn markdown_blocks(content: &str, config: &ExtractorConfig, diagnostics: &mut Vec<Diagnostic>) -> String {
+        let mut extracted_blocks = Vec::new();
+        let mut lines = content.lines().enumerate();
+
+        while let Some((line_index, line)) = lines.next() {
+            let Some((indent, fence_char, fence_len, info)) = markdown_fence_open(line) else {
+                continue;
+            };
+            let is_rst = info.split_whitespace().next().is_some_and(|language| language.eq_ignore_ascii_case(&config.markdown_language));
+            let mut block_lines = Vec::new();
+            let mut closed = false;
+            for (_, block_line) in lines.by_ref() {
+                if is_markdown_fence_close(block_line, fence_char, fence_len) {
+                    closed = true;
+                    break;
+                }
+                // Content lines lose up to the fence's own indentation, e.g. inside list items
+                let line_indent = block_line.len() - block_line.trim_start_matches(' ').len();
+                block_lines.push(block_line[line_indent.min(indent)..].to_string());
+            }
+            if !closed {
+                if is_rst {
+                    unterminated(diagnostics, line_index + 1, "Unterminated RST code fence in Markdown.");
+                }
+                break;
+            }
+            if is_rst {
+                extracted_blocks.push(dedent_lines(block_lines));
+            }
+        }
+        extracted_blocks.join("\n\n")
+    }
+
+    /// Extract RST between `@rst` and `@endrst` inside `<!-- ... -->` comments of HTML or XML.
+    /// A block may continue over several consecutive comments; text outside comments is ignored.
+    pub fn extract_from_html(content: &str) -> String {
+        Self::with_logged_diagnostics(|diagnostics| Self::html_blocks(content, diagnostics))
+    }
+
+    fn html_blocks(content: &str, diagnostics: &mut Vec<Diagnostic>) -> String {
+        const COMMENT_START: &str = "<!--";
+        const COMMENT_END: &str = "-->";
+        const RST_START_MARKER: &str = "@rst";
+        const RST_END_MARKER: &str = "@endrst";
+
+        let mut extracted_blocks = Vec::new();
+        let mut current_block_lines: Option<Vec<String>> = None;
+        let mut current_block_line = 0;
+        let mut search_offset = 0;
+
+        while let Some(comment_start_rel) = content[search_offset..].find(COMMENT_START) {
+            let body_start = search_offset + comment_start_rel + COMMENT_START.len();
+            let Some(body_len) = content[body_start..].find(COMMENT_END) else {
+                unterminated(diagnostics, line_at(content, body_start - COMMENT_START.len()), "Unterminated HTML comment.");
+                break;
+            };
+            let mut rest = &content[body_start..body_start + body_len];
+            search_offset = body_start + body_len + COMMENT_END.len();
+            if current_block_lines.is_some() {
+                // The line break after `<!--` only separates the comment from its opening
+                rest = rest.strip_prefix("\r\n").or_else(|| rest.strip_prefix('\n')).unwrap_or(rest);
+            }
+
+            loop {
+                match current_block_lines.as_mut() {
+                    None => {
+                        let Some(start_pos) = rest.find(RST_START_MARKER) else { break };
+                        current_block_line = line_at(content, rest.as_ptr() as usize - content.as_ptr() as usize + start_pos);
+                        rest = &rest[start_pos + RST_START_MARKER.len()..];
+                        current_block_lines = Some(Vec::new());
+                    }
+                    Some(block_lines) => match rest.find(RST_END_MARKER) {
+                        Some(end_pos) => {
+                            block_lines.extend(rest[..end_pos].lines().map(|line| line.trim_end().to_string()));
+                            extracted_blocks.push(dedent_lines(std::mem::take(block_lines)));
+                            current_block_lines = None;
+                            rest = &rest[end_pos + RST_END_MARKER.len()..];
+                        }
+                        None => {
+                            block_lines.extend(rest.lines().map(|line| line.trim_end().to_string()));
+                            break; // The block continues in the next comment
+                        }
+                    },
+                }
+            }
+        }
+        if current_block_lines.is_some() {
+            unterminated(diagnostics, current_block_line, "Unterminated RST block in HTML comments (missing @endrst).");
+        }
+        extracted_blocks.join("\n\n")
+    }
+
+    pub fn extract_from_cpp(content: &str) -> String {
+        Self::extract_from_cpp_with_config(content, &ExtractorConfig::default())
+    }
+
+    /// Same as `extract_from_cpp`, but with explicit extraction options.
+    pub fn extract_from_cpp_with_config(content: &str, config: &ExtractorConfig) -> String {
+        Self::with_logged_diagnostics(|diagnostics| Self::cpp_blocks(content, config, diagnostics))
+    }
+
+    fn cpp_blocks(content: &str, config: &ExtractorConfig, diagnostics: &mut Vec<Diagnostic>) -> String {
+        let mut extracted_blocks = Vec::new();
+        let mut current_block_lines: Vec<String> = Vec::new();
+        let mut in_rst_block = false;
+        let mut block_start_line = 0;
+        // Inside a `/* ... */` comment spanning several lines: whether its lines start with a
+        // `*` that is stripped like a `///`, decided by its second line.
+        let mut block_comment: Option<Option<bool>> = None;
+
+        const RST_START_MARKER: &str = "@rst";
+        const RST_END_MARKER: &str = "@endrst";
+
+        for (line_index, line) in content.lines().enumerate() {
+            let trimmed_line = line.trim_start();
+            let mut comment_content: Option<String> = None;
+
+            if let Some(starred) = &mut block_comment {
+                let (text, closed) = split_block_comment_end(line);
+                let text = if *starred.get_or_insert_with(|| text.trim_start().starts_with('*')) {
+                    let text = text.trim_start();
+                    text.strip_prefix('*').map_or(text, |rest| strip_spaces(rest, config.cpp_prefix_spaces))
+                } else {
+                    // Kept with its indentation, which `dedent_lines` removes for the whole block
+                    text
+                };
+                comment_content = Some(text.to_string());
+                if closed {
+                    block_comment = None;
+                }
+            } else if let Some((text, closed)) = open_cpp_block_comment(trimmed_line, config.cpp_prefix_spaces) {
+                comment_content = Some(text.to_string());
+                if !closed {
+                    block_comment = Some(None);
+                }
+            } else if let Some(rest) = strip_cpp_comment_prefix(trimmed_line, config.cpp_prefix_spaces) {
+                comment_content = Some(rest.to_string());
+            } else if let Some(rest) = trailing_cpp_comment(line).and_then(|comment| strip_cpp_comment_prefix(comment, config.cpp_prefix_spaces)) {
+                // A comment after code only counts if it opens or closes a block, e.g.
+                // `int x = 1; // @rst` or `int y = 2; // @endrst`.
+                let is_marker = if in_rst_block {
+                    rest.contains(RST_END_MARKER)
+                } else {
+                    rest.trim_start().starts_with(RST_START_MARKER)
+                };
+                if is_marker {
+                    comment_content = Some(rest.to_string());
+                }
+            }
+
+            if in_rst_block {
+                if let Some(text_in_comment) = comment_content.take() { // text_in_comment is the String from the comment line
+                    // Check if this line terminates the RST block
+                    if let Some(end_marker_pos) = text_in_comment.find(RST_END_MARKER) {
+                        // This line contains @endrst.
+                        let content_before_end_marker = text_in_comment[..end_marker_pos].trim_end();
+                        if !content_before_end_marker.is_empty() {
+                            current_block_lines.push(content_before_end_marker.to_string());
+                        }
+
+                        // Finalize current block
+                        if !current_block_lines.is_empty() {
+                            extracted_blocks.push(dedent_lines(std::mem::take(&mut current_block_lines)));
+                        }
+                        in_rst_block = false;
+                    } else {
+                        // Line is a comment and part of the RST block content
+                        current_block_lines.push(text_in_comment);
+                    }
+                } else {
+                    // Non-comment line or empty line breaks the RST block
+                    if line.trim().is_empty() && !config.lenient_blank_lines {
+                        unterminated(diagnostics, block_start_line, format!("Unterminated RST block in C++ content, broken by empty line {}", line_index + 1));
+                        current_block_lines.clear();
+                        in_rst_block = false;
+                    } else if line.trim().is_empty() && !current_block_lines.is_empty() {
+                         // Preserve empty lines within a block if they are truly empty
+                        current_block_lines.push(String::new());
+                    } else if !line.trim().is_empty() {
+                        unterminated(diagnostics, block_start_line, format!("Unterminated RST block in C++ content, broken by non-comment line {}: '{}'", line_index + 1, line));
+                        current_block_lines.clear();
+                        in_rst_block = false;
+                    } else if line.trim().is_empty() && current_block_lines.is_empty() && in_rst_block {
+                        // If we are in a block, and it's an empty line, and we have no content yet,
+                        // this could be the optional newline after @rst. Add it.
+                        current_block_lines.push(String::new());
+                    }
+                }
+            } else {
+                if let Some(text_after_comment_marker) = comment_content.take() {
+                    let potential_rst_line_content = text_after_comment_marker.trim_start(); // Trim spaces like "   @rst"
+                    if let Some(after_start_marker) = potential_rst_line_content.strip_prefix(RST_START_MARKER) {
+                        in_rst_block = true;
+                        block_start_line = line_index + 1;
+                        
+                        let mut content_on_rst_line = after_start_marker.to_string();
+                        if let Some(stripped) = content_on_rst_line.strip_prefix(' ') {
+                            content_on_rst_line = stripped.to_string();
+                        }
+                        
+                        // Check for @endrst on the same line
+                        if let Some(end_marker_pos) = content_on_rst_line.find(RST_END_MARKER) {
+                            let single_line_rst = content_on_rst_line[..end_marker_pos].trim_end_matches(' ').to_string();
+                            if !single_line_rst.is_empty() {
+                                extracted_blocks.push(single_line_rst);
+                            } else if content_on_rst_line[..end_marker_pos].is_empty() && end_marker_pos == 0 {
+                                extracted_blocks.push(String::new()); 
+                            }
+                            in_rst_block = false; 
+                        } else {
+                            // Content on the @rst line, after @rst and optional space
+                            if !content_on_rst_line.is_empty() {
+                                current_block_lines.push(content_on_rst_line);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if in_rst_block {
+            unterminated(diagnostics, block_start_line, "Unterminated RST block at end of C++ content.");
+            // current_block_lines.clear(); // As per test expectations for unterminated blocks
+        }
+        extracted_blocks.join("\n\n")
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -168,6 +787,102 @@ def some_function():
         );
     }
 
+    #[test]
+    fn test_cpp_prefix_spaces_keep_relative_indentation() {
+        // Two spaces after `///`, with a nested list and a code block
+        let cpp_content = "///  @rst\n///  .. note::\n///\n///     * Item\n///\n///       * Nested item\n///\n///     ::\n///\n///         int x = 1;\n///  @endrst\n";
+        let expected = ".. note::\n\n   * Item\n\n     * Nested item\n\n   ::\n\n       int x = 1;";
+
+        for cpp_prefix_spaces in [0, 1, 2] {
+            let config = ExtractorConfig { cpp_prefix_spaces, ..ExtractorConfig::default() };
+            assert_eq!(RstExtractor::extract_from_cpp_with_config(cpp_content, &config), expected, "cpp_prefix_spaces = {}", cpp_prefix_spaces);
+        }
+        assert_eq!(strip_cpp_comment_prefix("///   text", 2), Some(" text"));
+        assert_eq!(strip_cpp_comment_prefix("//text", 2), Some("text"));
+    }
+
+    #[test]
+    fn test_qt_style_line_comments_in_cpp() {
+        let cpp_content = "//! Brief description.\n//! @rst\n//! .. req:: Qt style\n//!    :id: R1\n//!\n//!    Content.\n//! @endrst\nvoid f();\n";
+        assert_eq!(RstExtractor::extract_from_cpp(cpp_content), ".. req:: Qt style\n   :id: R1\n\n   Content.");
+        assert_eq!(strip_cpp_comment_prefix("//!  text", 1), Some(" text"));
+    }
+
+    #[test]
+    fn test_block_comments_in_cpp() {
+        let single_line = "int x;\n/*! @rst .. note:: Inline @endrst */\nint y;\n";
+        assert_eq!(RstExtractor::extract_from_cpp(single_line), ".. note:: Inline");
+
+        let plain = "/*! @rst\n    .. req:: Block\n       :id: R2\n\n       Content.\n    @endrst\n*/\nvoid g();\n";
+        assert_eq!(RstExtractor::extract_from_cpp(plain), ".. req:: Block\n   :id: R2\n\n   Content.");
+
+        // Leading `*`s are stripped like `///`; a bullet list keeps its own `*`
+        let starred = "/**\n * @rst\n * .. note::\n *\n *    * Item\n * @endrst\n */\nvoid h();\n";
+        assert_eq!(RstExtractor::extract_from_cpp(starred), ".. note::\n\n   * Item");
+
+        // `//` inside a block comment does not start a line comment
+        let url = "/*! see http://example.com\n    @rst\n    Text.\n    @endrst */\n";
+        assert_eq!(RstExtractor::extract_from_cpp(url), "Text.");
+    }
+
+    #[test]
+    fn test_blank_line_without_comment_prefix_in_cpp() {
+        let cpp_content = "/// @rst\n/// .. directive1::\n///    :option1: value1\n\n///    Content after a blank line.\n/// @endrst\n";
+
+        let expected = ".. directive1::\n   :option1: value1\n\n   Content after a blank line.";
+        assert_eq!(
+            RstExtractor::extract_from_cpp(cpp_content),
+            expected,
+            "C++ lenient blank line failed"
+        );
+
+        let strict = ExtractorConfig { lenient_blank_lines: false, ..ExtractorConfig::default() };
+        assert_eq!(
+            RstExtractor::extract_from_cpp_with_config(cpp_content, &strict),
+            "",
+            "C++ strict blank line failed"
+        );
+
+        // A run of empty lines is tolerated as a whole
+        let cpp_content = "/// @rst\n/// .. directive1::\n\n\n///    Content after two blank lines.\n/// @endrst\n";
+        assert_eq!(
+            RstExtractor::extract_from_cpp(cpp_content),
+            ".. directive1::\n\n\n   Content after two blank lines.",
+            "C++ lenient blank lines failed"
+        );
+        assert_eq!(RstExtractor::extract_from_cpp_with_config(cpp_content, &strict), "", "C++ strict blank lines failed");
+    }
+
+    #[test]
+    fn test_extract_from_cpp_markers_after_code() {
+        assert_eq!(
+            RstExtractor::extract_from_cpp("int x = 1; // @rst content @endrst\n"),
+            "content",
+            "C++ single-line block after code failed"
+        );
+
+        let cpp_content = "void f(); /// @rst\n/// .. note::\n///\n///    Text.\n/// @endrst\n";
+        assert_eq!(
+            RstExtractor::extract_from_cpp(cpp_content),
+            ".. note::\n\n   Text.",
+            "C++ block start after code failed"
+        );
+
+        let cpp_content = "/// @rst\n/// Block content.\nint y = 2; // @endrst\n";
+        assert_eq!(
+            RstExtractor::extract_from_cpp(cpp_content),
+            "Block content.",
+            "C++ block end after code failed"
+        );
+
+        // Markers inside string literals, or in trailing comments that are not markers, are ignored
+        assert_eq!(
+            RstExtractor::extract_from_cpp("const char* s = \"// @rst\"; // note\nint z = 3; // @rst x @endrst\n"),
+            "x",
+            "C++ marker in string literal failed"
+        );
+    }
+
     #[test]
     fn test_multiple_rst_blocks_in_python() {
         let py_content = r#"
@@ -432,6 +1147,37 @@ stuff
         );
     }
 
+    #[test]
+    fn test_python_triple_quotes_in_expressions_are_not_docstrings() {
+        let content = r#"
+x = """
+@rst
+Assignment
+@endrst
+"""
+
+@decorator("""@rst
+Decorator argument
+@endrst""")
+def documented(value: int, other=(1, 2)) -> str:
+    r"""
+    @rst
+    Function docstring
+    @endrst
+    """
+    call(lambda: """@rst
+Lambda
+@endrst""", 'a """ in a string')
+
+"""
+@rst
+Module level
+@endrst
+"""
+"#;
+        assert_eq!(RstExtractor::extract_from_python(content), "Function docstring\n\nModule level");
+    }
+
     #[test]
     fn test_python_empty_and_no_rst() {
         let expected = "";
@@ -479,199 +1225,139 @@ Block one with newlines
         let expected = "Block one with newlines";
          assert_eq!(RstExtractor::extract_from_python(content), expected, "Python RST with optional newlines");
     }
-}
 
-pub struct RstExtractor;
+    #[test]
+    fn test_extract_from_markdown_single_fence() {
+        let content = r#"# Requirements
 
-impl RstExtractor {
-    /// Extract RST content from a file based on its extension
-    pub fn extract_from_file<P: AsRef<Path>>(file_path: P, content: &str) -> String {
-        let file_path = file_path.as_ref();
-        
-        match file_path.extension().and_then(OsStr::to_str) {
-            Some("cpp") | Some("h") | Some("hpp") | Some("cxx") | Some("hxx") | Some("cc") | Some("hh") => Self::extract_from_cpp(content),
-            Some("py") => Self::extract_from_python(content),
-            Some("rst") => content.to_string(), // For .rst files, use the content as is
-            _ => {
-                // eprint!("Unsupported file type for RST extraction: {:?}", file_path.extension());
-                String::new() // Or return content.to_string() if unknown types should pass through
-            }
-        }
+Some prose.
+
+```rst
+.. req:: Parse Markdown
+   :id: REQ-1
+
+   Content.
+```
+
+More prose.
+"#;
+        let expected = ".. req:: Parse Markdown\n   :id: REQ-1\n\n   Content.";
+        assert_eq!(RstExtractor::extract_from_markdown(content), expected);
+        assert_eq!(RstExtractor::extract_from_file("README.md", content), expected);
     }
 
-    pub fn extract_from_python(content: &str) -> String {
-        let mut extracted_blocks = Vec::new();
-        let mut search_offset = 0;
+    #[test]
+    fn test_extract_from_markdown_multiple_fences() {
+        let content = r#"
+~~~ rst
+.. note:: First
+~~~
 
-        const TRIPLE_DOUBLE_QUOTE: &str = "\"\"\"";
-        const TRIPLE_SINGLE_QUOTE: &str = "'''";
-        const RST_START_MARKER: &str = "@rst";
-        const RST_END_MARKER: &str = "@endrst";
+1. A list item with a nested block:
 
-        while search_offset < content.len() {
-            let q1_start = content[search_offset..].find(TRIPLE_DOUBLE_QUOTE);
-            let q3_start = content[search_offset..].find(TRIPLE_SINGLE_QUOTE);
+   ````RST title="ignored"
+   .. note:: Second
 
-            let (doc_start_marker, doc_start_rel) = match (q1_start, q3_start) {
-                (Some(s1), Some(s3)) => {
-                    if s1 <= s3 { (TRIPLE_DOUBLE_QUOTE, s1) } else { (TRIPLE_SINGLE_QUOTE, s3) }
-                }
-                (Some(s1), None) => (TRIPLE_DOUBLE_QUOTE, s1),
-                (None, Some(s3)) => (TRIPLE_SINGLE_QUOTE, s3),
-                (None, None) => break, // No more docstrings
-            };
-            
-            let doc_start_abs = search_offset + doc_start_rel;
-            let doc_content_start_abs = doc_start_abs + doc_start_marker.len();
-
-            if let Some(doc_end_rel) = content[doc_content_start_abs..].find(doc_start_marker) {
-                let doc_end_abs = doc_content_start_abs + doc_end_rel;
-                let doc_content = &content[doc_content_start_abs..doc_end_abs];
-                search_offset = doc_end_abs + doc_start_marker.len();
-
-                let mut rst_search_offset_in_doc = 0;
-                while rst_search_offset_in_doc < doc_content.len() {
-                    if let Some(rst_start_rel) = doc_content[rst_search_offset_in_doc..].find(RST_START_MARKER) {
-                        let rst_content_actual_start = rst_search_offset_in_doc + rst_start_rel + RST_START_MARKER.len();
-                        if let Some(rst_end_rel) = doc_content[rst_content_actual_start..].find(RST_END_MARKER) {
-                            let rst_content_actual_end = rst_content_actual_start + rst_end_rel;
-                            let block_content_raw = &doc_content[rst_content_actual_start..rst_content_actual_end];
-                            
-                            let mut processed_block_str = block_content_raw;
-
-                            // Check for trailing newline (and potential following spaces on that line)
-                            // This needs to be done *after* leading newline is stripped if both are present.
-                            if processed_block_str.ends_with('\n') {
-                                processed_block_str = &processed_block_str[..processed_block_str.len() -1];
-                                if processed_block_str.ends_with('\r') { // Handle \r\n specifically
-                                    processed_block_str = &processed_block_str[..processed_block_str.len() -1];
-                                }
-                            } else if processed_block_str.ends_with("\r\n") {
-                                 processed_block_str = &processed_block_str[..processed_block_str.len() -2];
-                            }
-                            
-                            // After stripping optional newlines, if processed_block_str is empty,
-                            // it means the original block was like "@rst\n@endrst" or "@rst @endrst" or "@rst@endrst"
-                            if processed_block_str.is_empty() {
-                                // If original block_content_raw was just newlines, it should be a block with one empty line.
-                                // If block_content_raw was empty or just whitespace, it's an empty block.
-                                if block_content_raw.trim().is_empty() && !block_content_raw.is_empty() { // e.g. @rst \n @endrst
-                                    extracted_blocks.push(dedent_lines(vec![String::new()]));
-                                } else { // e.g. @rst@endrst or @rst   @endrst
-                                    extracted_blocks.push(String::new());
-                                }
-                            } else {
-                                let lines_vec: Vec<String> = processed_block_str.lines().map(String::from).collect();
-                                extracted_blocks.push(dedent_lines(lines_vec));
-                            }
-                            rst_search_offset_in_doc = rst_content_actual_end + RST_END_MARKER.len();
-                        } else {
-                            eprintln!("Warning: Unterminated RST block in Python docstring (missing @endrst).");
-                            break; // Missing @endrst in this doc_content
-                        }
-                    } else {
-                        break; // No more @rst in this doc_content
-                    }
-                }
-            } else {
-                eprintln!("Warning: Unterminated Python docstring.");
-                break; // Unterminated docstring
-            }
-        }
-        extracted_blocks.join("\n\n")
+      Nested content.
+   ````
+"#;
+        let expected = ".. note:: First\n\n.. note:: Second\n\n   Nested content.";
+        assert_eq!(RstExtractor::extract_from_markdown(content), expected);
     }
 
-    pub fn extract_from_cpp(content: &str) -> String {
-        let mut extracted_blocks = Vec::new();
-        let mut current_block_lines: Vec<String> = Vec::new();
-        let mut in_rst_block = false;
+    #[test]
+    fn test_extract_from_markdown_ignores_other_fences() {
+        let content = r#"
+```python
+# .. note:: Not RST
+```
+
+````markdown
+```rst
+.. note:: Only an example
+```
+````
+
+```
+.. note:: No language
+```
+"#;
+        assert_eq!(RstExtractor::extract_from_markdown(content), "");
 
-        const RST_START_MARKER: &str = "@rst";
-        const RST_END_MARKER: &str = "@endrst";
+        let config = ExtractorConfig { markdown_language: "python".to_string(), ..ExtractorConfig::default() };
+        assert_eq!(RstExtractor::extract_from_markdown_with_config(content, &config), "# .. note:: Not RST");
+    }
 
-        for line in content.lines() {
-            let trimmed_line = line.trim_start();
-            let mut comment_content: Option<String> = None;
+    #[test]
+    fn test_extract_from_html() {
+        let content = r#"<html>
+<body>
+  <!-- Generated API docs -->
+  <!--
+    @rst
+    .. function:: parse(text)
+       :module: rstparser
 
-            if trimmed_line.starts_with("/// ") {
-                comment_content = Some(trimmed_line["/// ".len()..].to_string());
-            } else if trimmed_line.starts_with("///") { // No space after marker
-                comment_content = Some(trimmed_line["///".len()..].to_string());
-            } else if trimmed_line.starts_with("// ") {
-                comment_content = Some(trimmed_line["// ".len()..].to_string());
-            } else if trimmed_line.starts_with("//") { // No space after marker
-                comment_content = Some(trimmed_line["//".len()..].to_string());
-            }
+       Parses the text.
+    @endrst
+  -->
+  <p>Not RST: @rst .. note:: outside @endrst</p>
+  <!-- @rst .. note:: Inline @endrst -->
+</body>
+</html>
+"#;
+        let expected = ".. function:: parse(text)\n   :module: rstparser\n\n   Parses the text.\n\n.. note:: Inline";
+        assert_eq!(RstExtractor::extract_from_html(content), expected);
+        assert_eq!(RstExtractor::extract_from_file("api.html", content), expected);
+        assert_eq!(RstExtractor::extract_from_file("api.xml", content), expected);
+    }
 
-            if in_rst_block {
-                if let Some(text_in_comment) = comment_content.take() { // text_in_comment is the String from the comment line
-                    // Check if this line terminates the RST block
-                    if let Some(end_marker_pos) = text_in_comment.find(RST_END_MARKER) {
-                        // This line contains @endrst.
-                        let content_before_end_marker = text_in_comment[..end_marker_pos].trim_end();
-                        if !content_before_end_marker.is_empty() {
-                            current_block_lines.push(content_before_end_marker.to_string());
-                        }
+    #[test]
+    fn test_extract_from_html_block_spanning_comments() {
+        let content = "<!-- @rst\n.. note:: Split\n-->\n<div>ignored</div>\n<!--\n\n   Continued.\n@endrst -->\n<!-- @rst never closed -->";
+        assert_eq!(RstExtractor::extract_from_html(content), ".. note:: Split\n\n   Continued.");
+    }
 
-                        // Finalize current block
-                        if !current_block_lines.is_empty() {
-                            extracted_blocks.push(dedent_lines(current_block_lines.drain(..).collect::<Vec<String>>()));
-                        }
-                        in_rst_block = false;
-                    } else {
-                        // Line is a comment and part of the RST block content
-                        current_block_lines.push(text_in_comment);
-                    }
-                } else {
-                    // Non-comment line or empty line breaks the RST block
-                    if line.trim().is_empty() && !current_block_lines.is_empty() {
-                         // Preserve empty lines within a block if they are truly empty
-                        current_block_lines.push(String::new());
-                    } else if !line.trim().is_empty() {
-                        eprintln!("Warning: Unterminated RST block in C++ content, broken by non-comment line: '{}'", line);
-                        current_block_lines.clear();
-                        in_rst_block = false;
-                    } else if line.trim().is_empty() && current_block_lines.is_empty() && in_rst_block {
-                        // If we are in a block, and it's an empty line, and we have no content yet,
-                        // this could be the optional newline after @rst. Add it.
-                        current_block_lines.push(String::new());
-                    }
-                }
-            } else {
-                if let Some(text_after_comment_marker) = comment_content.take() {
-                    let potential_rst_line_content = text_after_comment_marker.trim_start(); // Trim spaces like "   @rst"
-                    if potential_rst_line_content.starts_with(RST_START_MARKER) {
-                        in_rst_block = true;
-                        
-                        let mut content_on_rst_line = potential_rst_line_content[RST_START_MARKER.len()..].to_string();
-                        if content_on_rst_line.starts_with(' ') {
-                            content_on_rst_line = content_on_rst_line[1..].to_string();
-                        }
-                        
-                        // Check for @endrst on the same line
-                        if let Some(end_marker_pos) = content_on_rst_line.find(RST_END_MARKER) {
-                            let single_line_rst = content_on_rst_line[..end_marker_pos].trim_end_matches(' ').to_string();
-                            if !single_line_rst.is_empty() {
-                                extracted_blocks.push(single_line_rst);
-                            } else if content_on_rst_line[..end_marker_pos].is_empty() && end_marker_pos == 0 {
-                                extracted_blocks.push(String::new()); 
-                            }
-                            in_rst_block = false; 
-                        } else {
-                            // Content on the @rst line, after @rst and optional space
-                            if !content_on_rst_line.is_empty() {
-                                current_block_lines.push(content_on_rst_line);
-                            }
-                        }
-                    }
-                }
-            }
-        }
+    #[test]
+    fn test_extract_from_marked_rst() {
+        let content = "Intro\n\n.. note:: Unmarked\n\n.. @rst\n\n.. req:: First\n   :id: r1\n\n.. @endrst\n\nMiddle\n\n.. @rst\n.. req:: Second\n.. @endrst\n";
+        let config = ExtractorConfig { rst_markers: true, ..ExtractorConfig::default() };
+        assert_eq!(
+            RstExtractor::extract_from_file_with_config("doc.rst", content, &config),
+            ".. req:: First\n   :id: r1\n\n.. req:: Second"
+        );
+        assert_eq!(RstExtractor::extract_from_file_with_config("doc.rst", content, &ExtractorConfig::default()), content);
 
-        if in_rst_block {
-            eprintln!("Warning: Unterminated RST block at end of C++ content.");
-            // current_block_lines.clear(); // As per test expectations for unterminated blocks
-        }
-        extracted_blocks.join("\n\n")
+        let unmarked = ".. note:: Whole file\n";
+        assert_eq!(RstExtractor::extract_from_file_with_config("doc.rst", unmarked, &config), unmarked);
+        assert_eq!(RstExtractor::extract_from_marked_rst(".. @rst\n.. req:: Open\n"), "");
     }
+
+    #[test]
+    fn test_unterminated_blocks_are_reported_with_file_and_line() {
+        let config = ExtractorConfig::default();
+        let cpp = "int x;\n/// @rst\n/// .. req:: Closed\n/// @endrst\n\n/// @rst\n/// .. req:: Open\n";
+        let (rst, diagnostics) = RstExtractor::extract_from_file_with_diagnostics("src/lib.cpp", cpp, &config);
+        assert_eq!(rst, ".. req:: Closed");
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::warning("src/lib.cpp", Some(6), DiagnosticCode::UnterminatedBlock, "Unterminated RST block at end of C++ content.")]
+        );
+
+        let python = "def f():\n    \"\"\"\n    Text\n\n    @rst\n    .. req:: Open\n    \"\"\"\n";
+        let (_, diagnostics) = RstExtractor::extract_from_file_with_diagnostics("mod.py", python, &config);
+        assert_eq!((diagnostics[0].file.as_path(), diagnostics[0].line), (Path::new("mod.py"), Some(5)));
+
+        let markdown = "# Title\n\n```rst\n.. req:: Open\n";
+        let (_, diagnostics) = RstExtractor::extract_from_file_with_diagnostics("README.md", markdown, &config);
+        assert_eq!(diagnostics[0].line, Some(3));
+
+        let html = "<p>x</p>\n<!-- @rst\n.. req:: Open -->\n";
+        let (_, diagnostics) = RstExtractor::extract_from_file_with_diagnostics("page.html", html, &config);
+        assert_eq!(diagnostics[0].line, Some(2));
+
+        let (_, diagnostics) = RstExtractor::extract_from_file_with_diagnostics("ok.cpp", "/// @rst\n/// x\n/// @endrst\n", &config);
+        assert!(diagnostics.is_empty());
+    }
+
 }
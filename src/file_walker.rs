@@ -1,12 +1,27 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
-use std::error::Error;
 use std::ffi::OsStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::SystemTime;
+use crate::error::RstParserError;
+use crate::extractor::SUPPORTED_EXTENSIONS;
+use crate::vfs::{default_file_provider, FileMetadata, FileProvider};
 
 /// A struct to configure file walking options
 pub struct FileWalker {
     extensions: Vec<String>,
     max_depth: Option<usize>,
+    modified_after: Option<SystemTime>,
+    modified_before: Option<SystemTime>,
+    skip_hidden: bool,
+    file_provider: Arc<dyn FileProvider>,
+}
+
+impl Default for FileWalker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl FileWalker {
@@ -15,9 +30,23 @@ impl FileWalker {
         FileWalker {
             extensions: vec!["rst".to_string(), "cpp".to_string(), "py".to_string()], // Default to .rst, .cpp, and .py files
             max_depth: None,                     // No depth limit by default
+            modified_after: None,
+            modified_before: None,
+            skip_hidden: false,
+            file_provider: default_file_provider(),
         }
     }
 
+    /// A walker for documentation projects: every extension the extractor supports
+    /// (`extractor::SUPPORTED_EXTENSIONS`, e.g. `.rst`, `.py`, `.cpp`, `.h`, `.md`), skipping
+    /// hidden files and directories. `new()` only looks for `.rst`, `.cpp` and `.py` files,
+    /// including hidden ones.
+    pub fn new_with_defaults_for_rst() -> Self {
+        FileWalker::new()
+            .with_extensions(SUPPORTED_EXTENSIONS.iter().map(|extension| extension.to_string()).collect())
+            .with_skip_hidden(true)
+    }
+
     /// Set the file extensions to filter by
     pub fn with_extensions(mut self, extensions: Vec<String>) -> Self {
         self.extensions = extensions;
@@ -30,42 +59,237 @@ impl FileWalker {
         self
     }
 
+    /// Only return files last modified strictly after `time`
+    pub fn with_modified_after(mut self, time: SystemTime) -> Self {
+        self.modified_after = Some(time);
+        self
+    }
+
+    /// Only return files last modified strictly before `time`
+    pub fn with_modified_before(mut self, time: SystemTime) -> Self {
+        self.modified_before = Some(time);
+        self
+    }
+
+    /// Skip files and directories whose name starts with `.`, such as `.git`, below the root
+    pub fn with_skip_hidden(mut self, skip_hidden: bool) -> Self {
+        self.skip_hidden = skip_hidden;
+        self
+    }
+
+    /// List and inspect files through `file_provider` instead of the local file system
+    pub fn with_file_provider(mut self, file_provider: Arc<dyn FileProvider>) -> Self {
+        self.file_provider = file_provider;
+        self
+    }
+
+    /// Check whether `path` has one of the configured extensions (a leading `.` is ignored).
+    pub fn matches_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|ext| self.extensions.iter().any(|e| e.trim_start_matches('.') == ext))
+    }
+
+    /// Check the modification time window, if any. Files whose mtime cannot be read are excluded.
+    fn matches_modified_window(&self, metadata: &FileMetadata) -> bool {
+        if self.modified_after.is_none() && self.modified_before.is_none() {
+            return true;
+        }
+        let Some(modified) = metadata.modified else {
+            return false;
+        };
+        self.modified_after.is_none_or(|after| modified > after)
+            && self.modified_before.is_none_or(|before| modified < before)
+    }
+
     /// Walk the directory and find files with the specified extensions
-    pub fn find_files<P: AsRef<Path>>(&self, root_dir: P) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    pub fn find_files<P: AsRef<Path>>(&self, root_dir: P) -> Result<Vec<PathBuf>, RstParserError> {
         let mut files = Vec::new();
-        let mut walker = WalkDir::new(root_dir);
-        
-        // Apply max depth if specified
-        if let Some(depth) = self.max_depth {
-            walker = walker.max_depth(depth);
-        }
+        self.walk(root_dir.as_ref(), 0, &mut files);
+        Ok(files)
+    }
 
-        for entry in walker.into_iter().filter_map(Result::ok) {
-            let path = entry.path();
-            
-            // Skip directories
-            if path.is_dir() {
-                continue;
-            }
-            
+    /// Collect the matching files at or below `path`, which is `depth` levels below the root.
+    /// Entries that cannot be read are skipped, and symbolic links to directories below the
+    /// root are not followed.
+    fn walk(&self, path: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+        let Ok(metadata) = self.file_provider.metadata(path) else {
+            return;
+        };
+        if !metadata.is_dir {
             // Check if the file has one of the specified extensions
-            if let Some(ext) = path.extension().and_then(OsStr::to_str) {
-                if self.extensions.iter().any(|e| e == ext) {
-                    files.push(path.to_path_buf());
-                }
+            if self.matches_extension(path) && self.matches_modified_window(&metadata) {
+                files.push(path.to_path_buf());
+            }
+            return;
+        }
+        if (depth > 0 && metadata.is_symlink) || self.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return;
+        }
+        let Ok(entries) = self.file_provider.list(path) else {
+            return;
+        };
+        for entry in entries {
+            let is_hidden = entry.file_name().and_then(OsStr::to_str).is_some_and(|name| name.starts_with('.'));
+            if !(self.skip_hidden && is_hidden) {
+                self.walk(&entry, depth + 1, files);
             }
         }
-        
-        Ok(files)
     }
 }
 
+/// Whether paths are compared case-insensitively: 0 until probed or set, otherwise 1 (no) or
+/// 2 (yes).
+static CASE_INSENSITIVE_PATHS: AtomicU8 = AtomicU8::new(0);
+
+/// Whether `normalize_path` lowercases paths. Unless set with `set_case_insensitive_paths`,
+/// this is probed once on the file system of the current directory, or of the temporary
+/// directory if that is inconclusive. Without the `fs` feature paths are case-sensitive.
+pub fn case_insensitive_paths() -> bool {
+    if CASE_INSENSITIVE_PATHS.load(Ordering::Relaxed) == 0 {
+        let probed = 1 + u8::from(probe_case_insensitive_paths());
+        // A concurrent `set_case_insensitive_paths` wins over the probe
+        let _ = CASE_INSENSITIVE_PATHS.compare_exchange(0, probed, Ordering::Relaxed, Ordering::Relaxed);
+    }
+    CASE_INSENSITIVE_PATHS.load(Ordering::Relaxed) == 2
+}
+
+/// Overrides the probed `case_insensitive_paths`, e.g. when the files live on a volume that
+/// compares names differently than the one of the current directory.
+pub fn set_case_insensitive_paths(case_insensitive: bool) {
+    CASE_INSENSITIVE_PATHS.store(1 + u8::from(case_insensitive), Ordering::Relaxed);
+}
+
+#[cfg(feature = "fs")]
+fn probe_case_insensitive_paths() -> bool {
+    [std::env::current_dir().ok(), Some(std::env::temp_dir())]
+        .into_iter()
+        .flatten()
+        .filter_map(|dir| std::fs::canonicalize(dir).ok())
+        .find_map(|dir| probe_case_insensitive_dir(&dir))
+        .unwrap_or(false)
+}
+
+#[cfg(not(feature = "fs"))]
+fn probe_case_insensitive_paths() -> bool {
+    false
+}
+
+/// Whether the file system finds `dir`, or the nearest of its ancestors with letters in its
+/// name, under its name with the case of the letters swapped. `None` if no ancestor can tell,
+/// e.g. because the swapped name exists as an entry of its own.
+#[cfg(feature = "fs")]
+fn probe_case_insensitive_dir(dir: &Path) -> Option<bool> {
+    dir.ancestors().find_map(|ancestor| {
+        let parent = ancestor.parent()?;
+        let name = ancestor.file_name()?.to_str()?;
+        let swapped: String = name.chars()
+            .map(|c| if c.is_ascii_lowercase() { c.to_ascii_uppercase() } else { c.to_ascii_lowercase() })
+            .collect();
+        if swapped == name {
+            return None;
+        }
+        let swapped_is_entry = std::fs::read_dir(parent).ok()?.flatten().any(|entry| entry.file_name() == swapped.as_str());
+        (!swapped_is_entry).then(|| parent.join(&swapped).exists())
+    })
+}
+
+/// The key under which a canonical `path` is stored in directive maps and compared with other
+/// paths. Where paths are case-insensitive (`case_insensitive_paths`) the whole path is
+/// lowercased, so that `test.rst` and `TEST.RST` share one entry; elsewhere the path is used as
+/// is. Keys are only for lookups: files are read, and reported in `source_file` and IDs, under
+/// their canonical path.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(path_str) if case_insensitive_paths() => PathBuf::from(path_str.to_lowercase()),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// `fs::canonicalize` followed by `normalize_path`.
+#[cfg(feature = "fs")]
+pub fn canonicalize_normalized(path: &Path) -> std::io::Result<PathBuf> {
+    std::fs::canonicalize(path).map(|canonical_path| normalize_path(&canonical_path))
+}
+
+/// The canonical path of `path`, falling back to canonicalizing the nearest ancestor that still
+/// exists (or the current directory) and appending the rest when the path itself is gone, e.g.
+/// for a deleted file. Without any existing ancestor `path` is returned as is.
+#[cfg(feature = "fs")]
+pub fn canonicalize_lenient(path: &Path) -> PathBuf {
+    if let Ok(canonical_path) = std::fs::canonicalize(path) {
+        return canonical_path;
+    }
+    for ancestor in path.ancestors().skip(1) {
+        let existing = if ancestor.as_os_str().is_empty() { Path::new(".") } else { ancestor };
+        if let Ok(canonical_ancestor) = std::fs::canonicalize(existing) {
+            let missing_part = path.strip_prefix(ancestor).unwrap_or(path);
+            return canonical_ancestor.join(missing_part);
+        }
+    }
+    path.to_path_buf()
+}
+
+/// Counts files per extension (without the leading `.`), sorted by extension. Files without
+/// an extension are counted under an empty string.
+pub fn count_files_by_extension(files: &[PathBuf]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for file in files {
+        let extension = file.extension().and_then(OsStr::to_str).unwrap_or_default().to_string();
+        *counts.entry(extension).or_insert(0) += 1;
+    }
+    counts
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs::{self, File};
     use std::io::Write;
+    use std::time::Duration;
     use tempfile::tempdir;
+    use crate::vfs::InMemoryFs;
+
+    #[test]
+    fn test_normalize_path() {
+        let path = Path::new("/Docs/Test.rst");
+        let expected = if case_insensitive_paths() { "/docs/test.rst" } else { "/Docs/Test.rst" };
+        assert_eq!(normalize_path(path), PathBuf::from(expected));
+    }
+
+    #[test]
+    fn test_probe_case_insensitive_dir() {
+        let temp_dir = tempdir().unwrap();
+        let dir = fs::canonicalize(temp_dir.path()).unwrap().join("Probe");
+        fs::create_dir(&dir).unwrap();
+        let case_insensitive = dir.with_file_name("pROBE").exists();
+        assert_eq!(probe_case_insensitive_dir(&dir), Some(case_insensitive));
+        // With both names present the directory cannot tell, so its parents are probed
+        if !case_insensitive {
+            fs::create_dir(dir.with_file_name("pROBE")).unwrap();
+            assert_eq!(probe_case_insensitive_dir(&dir), Some(false));
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_lenient() {
+        let temp_dir = tempdir().unwrap();
+        let canonical_dir = fs::canonicalize(temp_dir.path()).unwrap();
+        fs::write(temp_dir.path().join("kept.rst"), "").unwrap();
+        assert_eq!(canonicalize_lenient(&temp_dir.path().join("kept.rst")), canonical_dir.join("kept.rst"));
+        assert_eq!(canonicalize_lenient(&temp_dir.path().join("gone/deleted.rst")), canonical_dir.join("gone/deleted.rst"));
+        assert_eq!(canonicalize_lenient(Path::new("deleted.rst")), std::env::current_dir().unwrap().canonicalize().unwrap().join("deleted.rst"));
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    #[test]
+    fn test_differently_cased_names_share_one_key() {
+        let temp_dir = tempdir().unwrap();
+        fs::write(temp_dir.path().join("test.rst"), ".. req::\n").unwrap();
+        let lower = canonicalize_normalized(&temp_dir.path().join("test.rst")).unwrap();
+        let upper = canonicalize_normalized(&temp_dir.path().join("TEST.RST")).unwrap();
+        assert_eq!(lower, upper);
+    }
 
     #[test]
     fn test_find_rst_files() {
@@ -113,4 +337,94 @@ mod tests {
         assert!(files.contains(&file1_path));
         assert!(!files.contains(&file3_path));
     }
+
+    #[test]
+    fn test_modified_time_window() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path();
+
+        let old_path = temp_path.join("old.rst");
+        let new_path = temp_path.join("new.rst");
+        let old_file = File::create(&old_path).unwrap();
+        let new_file = File::create(&new_path).unwrap();
+
+        let now = SystemTime::now();
+        let day = Duration::from_secs(24 * 60 * 60);
+        old_file.set_modified(now - 3 * day).unwrap();
+        new_file.set_modified(now - Duration::from_secs(60)).unwrap();
+
+        let files = FileWalker::new().with_modified_after(now - day).find_files(temp_path).unwrap();
+        assert_eq!(files, vec![new_path.clone()]);
+
+        let files = FileWalker::new().with_modified_before(now - day).find_files(temp_path).unwrap();
+        assert_eq!(files, vec![old_path.clone()]);
+
+        // Both bounds combined with the extension filter
+        let files = FileWalker::new()
+            .with_extensions(vec!["txt".to_string()])
+            .with_modified_after(now - 4 * day)
+            .find_files(temp_path)
+            .unwrap();
+        assert!(files.is_empty());
+        let files = FileWalker::new()
+            .with_modified_after(now - 4 * day)
+            .with_modified_before(now - 2 * day)
+            .find_files(temp_path)
+            .unwrap();
+        assert_eq!(files, vec![old_path]);
+    }
+
+    #[test]
+    fn test_find_files_in_memory() {
+        let provider = Arc::new(InMemoryFs::new()
+            .with_file("/docs/index.rst", "")
+            .with_file("/docs/notes.txt", "")
+            .with_file("/docs/api/lib.cpp", "")
+            .with_file("/docs/api/deep/inner.py", "")
+            .with_file("/docs/.cache/old.rst", ""));
+        let now = SystemTime::now();
+        provider.write_file_modified_at("/docs/api/recent.rst", "", now);
+
+        let mut files = FileWalker::new().with_file_provider(provider.clone()).find_files("/docs").unwrap();
+        files.sort();
+        assert_eq!(files, ["/docs/.cache/old.rst", "/docs/api/deep/inner.py", "/docs/api/lib.cpp", "/docs/api/recent.rst", "/docs/index.rst"].map(PathBuf::from));
+
+        let walker = FileWalker::new().with_file_provider(provider.clone()).with_skip_hidden(true).with_max_depth(2);
+        assert_eq!(walker.find_files("/docs").unwrap(), ["/docs/api/lib.cpp", "/docs/api/recent.rst", "/docs/index.rst"].map(PathBuf::from));
+
+        // Files without a modification time are outside every window
+        let walker = FileWalker::new().with_file_provider(provider).with_modified_after(now - Duration::from_secs(60));
+        assert_eq!(walker.find_files("/docs").unwrap(), vec![PathBuf::from("/docs/api/recent.rst")]);
+    }
+
+    #[test]
+    fn test_new_with_defaults_for_rst() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path();
+        fs::create_dir(temp_path.join(".git")).unwrap();
+        fs::create_dir(temp_path.join("include")).unwrap();
+        for file in ["index.rst", "include/api.h", "include/api.hpp", "guide.md", ".hidden.rst", ".git/notes.rst", "notes.txt"] {
+            File::create(temp_path.join(file)).unwrap();
+        }
+
+        let mut files = FileWalker::new_with_defaults_for_rst().find_files(temp_path).unwrap();
+        files.sort();
+        let expected: Vec<PathBuf> = ["guide.md", "include/api.h", "include/api.hpp", "index.rst"].iter().map(|file| temp_path.join(file)).collect();
+        assert_eq!(files, expected);
+        assert_eq!(FileWalker::new_with_defaults_for_rst().extensions, SUPPORTED_EXTENSIONS);
+
+        // `new()` keeps its extensions and includes hidden files
+        let mut files = FileWalker::new().find_files(temp_path).unwrap();
+        files.sort();
+        assert_eq!(files, vec![temp_path.join(".git/notes.rst"), temp_path.join(".hidden.rst"), temp_path.join("index.rst")]);
+    }
+
+    #[test]
+    fn test_count_files_by_extension() {
+        let files: Vec<PathBuf> = ["a.rst", "b/c.rst", "d.cpp", "Makefile"].iter().map(PathBuf::from).collect();
+        let counts = count_files_by_extension(&files);
+        assert_eq!(counts.into_iter().collect::<Vec<_>>(), vec![
+            ("".to_string(), 1), ("cpp".to_string(), 1), ("rst".to_string(), 2),
+        ]);
+    }
 }
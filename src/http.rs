@@ -0,0 +1,213 @@
+//! Serves the directives and link graph of a watched tree over HTTP, for `rstparser serve`.
+//! Enabled with the `http` feature. All responses are JSON:
+//!
+//! - `GET /directives`: the directives as written to the outputs, backlinks included, sorted
+//!   by ID, source file and line.
+//! - `GET /directives/{id}`: the first of these directives with the (percent-encoded) ID, or
+//!   status 404.
+//! - `GET /links`: the link graph, from directive ID to its `outgoing_links` and `incoming_links`.
+//! - `GET /summary`: the number of directives and files, and the directive counts by name.
+//!
+//! Requests are answered from a `Snapshot` of the state, which the watch loop replaces after
+//! every change with `HttpServer::publish`. The lock on it is only held to clone an `Arc`, so
+//! every response is consistent and slow clients never delay the watch loop.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use log::{debug, error};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+use crate::aggregator::{count_directives_by_name, Aggregator, DirectiveOutput};
+use crate::directive_functions::{DirectivesMapOf, StoredDirective};
+use crate::error::RstParserError;
+use crate::link_data::LinkGraph;
+
+/// Default address of `rstparser serve`.
+pub const DEFAULT_BIND_ADDRESS: &str = "127.0.0.1:8080";
+
+/// What `GET /summary` returns.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Summary {
+    pub directive_count: usize,
+    pub file_count: usize,
+    pub directives_by_name: BTreeMap<String, usize>,
+}
+
+/// An immutable copy of the served state.
+#[derive(Debug, Default, Serialize)]
+pub struct Snapshot {
+    pub directives: Vec<DirectiveOutput>,
+    pub link_graph: LinkGraph,
+    pub summary: Summary,
+}
+
+impl Snapshot {
+    /// Copies the directives of `directives_map` as `aggregator` would write them, with the
+    /// backlinks of `link_graph`. Fails only if `with_topo_order` finds a cycle.
+    pub fn new<D: StoredDirective>(aggregator: &Aggregator, directives_map: &DirectivesMapOf<D>, link_graph: &LinkGraph) -> Result<Self, RstParserError> {
+        let mut directives: Vec<DirectiveOutput> = aggregator.aggregate_to_map(directives_map, link_graph)?
+            .into_values()
+            .flatten()
+            .collect();
+        directives.sort_by(|a, b| (&a.id, &a.source_file, a.line_number).cmp(&(&b.id, &b.source_file, b.line_number)));
+        let directives_by_name = count_directives_by_name(directives_map);
+        let summary = Summary {
+            directive_count: directives_by_name.values().sum(),
+            file_count: directives_map.len(),
+            directives_by_name,
+        };
+        Ok(Snapshot { directives, link_graph: link_graph.clone(), summary })
+    }
+
+    /// The status code and JSON body answering `method` on `url`.
+    pub fn respond(&self, method: &Method, url: &str) -> (u16, Value) {
+        if *method != Method::Get {
+            return (405, json!({ "error": format!("Method {} not allowed", method) }));
+        }
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+        match path.trim_end_matches('/') {
+            "/directives" => (200, to_json(&self.directives)),
+            "/links" => (200, to_json(&self.link_graph)),
+            "/summary" => (200, to_json(&self.summary)),
+            other => match other.strip_prefix("/directives/").map(percent_decode) {
+                Some(id) => match self.directives.iter().find(|directive| directive.id == id) {
+                    Some(directive) => (200, to_json(directive)),
+                    None => (404, json!({ "error": format!("No directive with ID '{}'", id) })),
+                },
+                None => (404, json!({ "error": format!("Not found: {}", path) })),
+            },
+        }
+    }
+}
+
+/// An HTTP server answering requests from the latest published `Snapshot`.
+pub struct HttpServer {
+    server: Arc<Server>,
+    snapshot: Arc<RwLock<Arc<Snapshot>>>,
+}
+
+impl HttpServer {
+    /// Listens on `address`, e.g. `127.0.0.1:8080`; port 0 picks a free port. Until the first
+    /// `publish`, an empty snapshot is served.
+    pub fn bind(address: &str) -> io::Result<Self> {
+        let server = Server::http(address).map_err(io::Error::other)?;
+        Ok(HttpServer { server: Arc::new(server), snapshot: Arc::new(RwLock::new(Arc::new(Snapshot::default()))) })
+    }
+
+    /// The address the server listens on.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Serves `snapshot` from now on. Requests being answered keep the snapshot they started with.
+    pub fn publish(&self, snapshot: Snapshot) {
+        *self.snapshot.write().unwrap() = Arc::new(snapshot);
+    }
+
+    /// Answers requests on a background thread until the process exits.
+    pub fn spawn(&self) -> JoinHandle<()> {
+        let server = Arc::clone(&self.server);
+        let snapshot = Arc::clone(&self.snapshot);
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let current = Arc::clone(&snapshot.read().unwrap());
+                answer(request, &current);
+            }
+        })
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).expect("snapshots are always serializable")
+}
+
+fn answer(request: Request, snapshot: &Snapshot) {
+    let (status, body) = snapshot.respond(request.method(), request.url());
+    debug!("{} {} -> {}", request.method(), request.url(), status);
+    let content_type = Header::from_bytes("Content-Type", "application/json").expect("valid header");
+    let response = Response::from_string(body.to_string()).with_status_code(status).with_header(content_type);
+    if let Err(err) = request.respond(response) {
+        error!("Error sending HTTP response: {}", err);
+    }
+}
+
+/// Decodes `%XX` escapes in a URL path segment; invalid escapes are kept as they are.
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| segment.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::aggregator::GroupBy;
+    use crate::directive_functions::{FunctionApplicator, PlainDirectivesMap};
+    use crate::link_data::{LinkConfig, LinkTypeConfig};
+    use crate::processor::Processor;
+
+    fn snapshot() -> Snapshot {
+        let text = ".. req::\n   :id: REQ 1\n\n.. spec::\n   :id: S1\n   :derives: REQ 1\n";
+        let (directives, _) = Processor::new(vec!["req".to_string(), "spec".to_string()]).process_content(text.to_string(), "/docs/a.rst").unwrap();
+        let mut directives_map = PlainDirectivesMap::new();
+        for dws in directives {
+            directives_map.entry(PathBuf::from(&dws.source_file)).or_default().insert(dws.id.clone(), dws);
+        }
+        let link_config = LinkConfig { link_types: vec![LinkTypeConfig::new("derives")], ..Default::default() };
+        let mut link_graph = LinkGraph::new();
+        FunctionApplicator::new(Arc::new(link_config)).apply_to_all(&directives_map, &mut link_graph);
+        Snapshot::new(&Aggregator::new("out", GroupBy::DirectiveName), &directives_map, &link_graph).unwrap()
+    }
+
+    #[test]
+    fn test_respond() {
+        let snapshot = snapshot();
+
+        let (status, directives) = snapshot.respond(&Method::Get, "/directives");
+        assert_eq!(status, 200);
+        let ids: Vec<&str> = directives.as_array().unwrap().iter().map(|directive| directive["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["REQ 1", "S1"]);
+        assert_eq!(directives[0]["options"]["derives_back"], "S1");
+
+        let (status, directive) = snapshot.respond(&Method::Get, "/directives/REQ%201?pretty");
+        assert_eq!((status, directive["name"].as_str()), (200, Some("req")));
+        assert_eq!(snapshot.respond(&Method::Get, "/directives/missing").0, 404);
+
+        let (status, links) = snapshot.respond(&Method::Get, "/links/");
+        assert_eq!((status, &links["S1"]["outgoing_links"]["derives"]), (200, &json!(["REQ 1"])));
+        let (status, summary) = snapshot.respond(&Method::Get, "/summary");
+        assert_eq!((status, summary), (200, json!({ "directive_count": 2, "file_count": 1, "directives_by_name": { "req": 1, "spec": 1 } })));
+
+        assert_eq!(snapshot.respond(&Method::Get, "/other").0, 404);
+        assert_eq!(snapshot.respond(&Method::Post, "/directives").0, 405);
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("REQ%201%2Fa"), "REQ 1/a");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%C3%A4"), "%zzä");
+    }
+}
@@ -6,10 +6,44 @@ pub mod timing;
 pub mod extractor;
 pub mod link_data; // Added for link processing structures
 pub mod directive_functions; // Added for directive function processing
+#[cfg(feature = "fs")]
+pub mod watcher;
+#[cfg(feature = "fs")]
+pub mod validation;
+pub mod query;
+pub mod store;
+pub mod encoding;
+pub mod diagnostics;
+pub mod rst_renderer;
+pub mod error;
+#[cfg(feature = "fs")]
+pub mod pipeline;
+#[cfg(feature = "fs")]
+pub mod diff;
+pub mod vfs;
+#[cfg(feature = "fs")]
+pub mod rpc;
+#[cfg(feature = "python")]
+pub mod bindings;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 // Re-export commonly used types for convenience
-pub use parser::Directive;
-pub use aggregator::{DirectiveWithSource, GroupBy};
+pub use parser::{Directive, ParseOptions};
+pub use aggregator::{DirectiveOutput, DirectiveWithSource, GroupBy, LineEnding, OutputFormat};
 pub use file_walker::FileWalker;
 pub use processor::Processor;
-pub use extractor::RstExtractor;
+pub use encoding::Encoding;
+pub use diagnostics::{Diagnostic, ParseWarning, Severity};
+pub use error::RstParserError;
+#[cfg(feature = "fs")]
+pub use pipeline::{Pipeline, PipelineResult};
+pub use store::DirectiveStore;
+pub use extractor::{ExtractorConfig, RstExtractor};
+#[cfg(feature = "fs")]
+pub use watcher::{WatchEvent, WatchSession};
+pub use vfs::{FileProvider, InMemoryFs};
+#[cfg(feature = "fs")]
+pub use vfs::RealFs;
@@ -1,26 +1,70 @@
-use serde::Deserialize;
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+#[cfg(feature = "fs")]
+use std::path::PathBuf;
+#[cfg(feature = "fs")]
+use crate::error::RstParserError;
 
 /// Represents the configuration for a single type of link field.
 /// Loaded from `rstparser_links.toml`.
 #[derive(Deserialize, Debug, Clone)]
 pub struct LinkTypeConfig {
     pub name: String,
+    /// Appended to `name` to form the backlink field on link targets (default `_back`).
+    #[serde(default)]
+    pub custom_backlink_suffix: Option<String>,
     // Placeholder for future enhancements, e.g.:
-    // pub custom_backlink_suffix: Option<String>,
     // pub presentation_hint: Option<String>,
 }
 
+/// Suffix of backlink fields for link types without a `custom_backlink_suffix`.
+pub const DEFAULT_BACKLINK_SUFFIX: &str = "_back";
+
+impl LinkTypeConfig {
+    /// A link type named `name` with the default backlink suffix.
+    pub fn new(name: &str) -> Self {
+        LinkTypeConfig { name: name.to_string(), custom_backlink_suffix: None }
+    }
+
+    /// The field under which link targets list the directives linking to them, e.g. `derives_back`.
+    pub fn backlink_name(&self) -> String {
+        format!("{}{}", self.name, self.custom_backlink_suffix.as_deref().unwrap_or(DEFAULT_BACKLINK_SUFFIX))
+    }
+}
+
 /// Represents the overall link configuration loaded from the TOML file.
 #[derive(Deserialize, Debug, Clone, Default)]
 pub struct LinkConfig {
     #[serde(rename = "links", default)]
     pub link_types: Vec<LinkTypeConfig>,
+    /// Canonical directive name -> alternative names that are matched as the same directive.
+    #[serde(default)]
+    pub aliases: HashMap<String, Vec<String>>,
+}
+
+impl LinkConfig {
+    /// Checks that no two link types produce the same backlink field, and that no backlink
+    /// field is also the name of a link type, since their links would silently be merged.
+    pub fn check_backlink_collisions(&self) -> Result<(), String> {
+        let mut backlink_owners: HashMap<String, &str> = HashMap::new();
+        for link_type in &self.link_types {
+            let backlink_name = link_type.backlink_name();
+            if let Some(other) = backlink_owners.insert(backlink_name.clone(), &link_type.name) {
+                return Err(format!("Link types '{}' and '{}' both produce the backlink field '{}'", other, link_type.name, backlink_name));
+            }
+        }
+        for link_type in &self.link_types {
+            if let Some(owner) = backlink_owners.get(&link_type.name) {
+                return Err(format!("The backlink field of link type '{}' collides with the link type '{}'; rename it or set a custom_backlink_suffix", owner, link_type.name));
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Data stored for each directive in the LinkGraph.
 /// Tracks both outgoing links (from this directive) and incoming links (to this directive).
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct LinkNodeData {
     /// Key: Original link field name (e.g., "derives", "tests").
     /// Value: List of target directive instance IDs.
@@ -38,44 +82,99 @@ pub type LinkGraph = HashMap<String, LinkNodeData>;
 
 /// Loads link configuration from the specified TOML file path.
 /// If the file does not exist, it returns a default (empty) LinkConfig.
-/// Errors during reading or parsing will be propagated.
-pub fn load_link_config(path: &str) -> Result<LinkConfig, Box<dyn std::error::Error>> {
+/// Errors during reading (`Io`) or parsing (`Config`), and colliding backlink fields
+/// (`LinkValidation`), will be propagated.
+#[cfg(feature = "fs")]
+pub fn load_link_config(path: &str) -> Result<LinkConfig, RstParserError> {
     match std::fs::read_to_string(path) {
         Ok(contents) => {
-            let config: LinkConfig = toml::from_str(&contents)?;
+            let config: LinkConfig = toml::from_str(&contents)
+                .map_err(|e| RstParserError::Config { path: PathBuf::from(path), message: e.to_string() })?;
+            config.check_backlink_collisions().map_err(RstParserError::LinkValidation)?;
             Ok(config)
         }
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
             Ok(LinkConfig::default()) // Return default config if file not found
         }
-        Err(e) => Err(Box::new(e)), // Propagate other errors
+        Err(source) => Err(RstParserError::Io { path: PathBuf::from(path), source }), // Propagate other errors
     }
 }
 
+/// Option keys that look like link fields, from the `(key, value)` options of all directives:
+/// some value names a directive in `known_ids`, or lists several comma-separated ID-like
+/// tokens (letters, digits, `-`, `_`, `.`). The `id` option itself is never a link field.
+pub fn link_field_candidates<'a>(options: impl IntoIterator<Item = (&'a str, &'a str)>, known_ids: &HashSet<String>) -> HashSet<String> {
+    let is_id_like = |token: &str| !token.is_empty() && token.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    let mut candidates = HashSet::new();
+    for (key, value) in options {
+        if key == "id" || candidates.contains(key) {
+            continue;
+        }
+        let tokens: Vec<&str> = value.split(',').map(str::trim).filter(|token| !token.is_empty()).collect();
+        let names_known_id = tokens.iter().any(|token| known_ids.contains(*token));
+        let is_id_list = tokens.len() > 1 && tokens.iter().all(|token| is_id_like(token));
+        if names_known_id || is_id_list {
+            candidates.insert(key.to_string());
+        }
+    }
+    candidates
+}
+
+/// Generates a commented starter `rstparser_links.toml` with a `[[links]]` entry for each of
+/// `observed_option_keys` (e.g. from `link_field_candidates`), in sorted order.
+pub fn generate_link_config_template(observed_option_keys: &HashSet<String>) -> String {
+    let mut template = String::from(
+        "# Link configuration for rstparser.\n\
+         #\n\
+         # Each [[links]] entry names a directive option whose value is a comma-separated list of\n\
+         # directive IDs, e.g. `:derives: REQ-1, REQ-2`. The linked directives get a backlink option\n\
+         # `<name>_back` listing the directives that link to them; `custom_backlink_suffix` replaces\n\
+         # the `_back` suffix.\n\
+         #\n\
+         # Alternative directive names can be matched as one directive with aliases:\n\
+         # [aliases]\n\
+         # req = [\"requirement\"]\n",
+    );
+    let keys: BTreeSet<&String> = observed_option_keys.iter().collect();
+    if keys.is_empty() {
+        template.push_str("\n# No link-like options were found. Example entry:\n# [[links]]\n# name = \"derives\"\n");
+    }
+    for key in keys {
+        template.push_str(&format!("\n[[links]]\nname = {}\n# custom_backlink_suffix = \"{}\"\n", toml::Value::String(key.clone()), DEFAULT_BACKLINK_SUFFIX));
+    }
+    template
+}
+
+/// Controls what `remove_links_for_ids` removes besides the given IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalMode {
+    /// Only remove the given IDs.
+    IdsOnly,
+    /// Also remove nodes left without any incoming or outgoing links, unless they belong to
+    /// a directive that still exists.
+    IdsAndOrphans,
+}
+
 /// Removes all link information associated with the given `ids_to_remove`.
 /// This involves:
 /// 1. Removing these IDs from the `incoming_links` of any nodes they previously linked to.
 /// 2. Removing the entries for `ids_to_remove` themselves from the graph.
-pub fn remove_links_for_ids(graph: &mut LinkGraph, ids_to_remove: &HashSet<String>) {
-    // Phase 1: Collect information about which incoming links to update.
-    // Store as (target_id, backlink_field_name, id_of_source_to_remove_from_target's_incoming_list)
-    let mut incoming_link_updates_to_make: Vec<(String, String, String)> = Vec::new();
+/// 3. With `RemovalMode::IdsAndOrphans`, removing nodes that are now isolated and not in
+///    `known_ids` (the IDs of directives that are still alive).
+pub fn remove_links_for_ids(graph: &mut LinkGraph, ids_to_remove: &HashSet<String>, mode: RemovalMode, known_ids: &HashSet<String>) {
+    // Phase 1: Collect the targets whose incoming links mention a removed ID.
+    // Store as (target_id, id_of_source_to_remove_from_target's_incoming_lists)
+    let mut incoming_link_updates_to_make: Vec<(String, String)> = Vec::new();
 
     for removed_id in ids_to_remove {
         if let Some(removed_node_data) = graph.get(removed_id) {
-            // For each outgoing link from the node being removed,
-            // find the target and record that this `removed_id` should be
-            // removed from the target's incoming links.
-            for (link_field_name, target_ids) in &removed_node_data.outgoing_links {
-                let backlink_field_name = format!("{}_back", link_field_name);
+            // All outgoing links of a removed node go away, so it can be dropped from every
+            // incoming list of its targets, whatever the backlink field is called.
+            for target_ids in removed_node_data.outgoing_links.values() {
                 for target_id in target_ids {
                     // Only update if the target itself is not being removed in this batch.
                     if !ids_to_remove.contains(target_id) {
-                        incoming_link_updates_to_make.push((
-                            target_id.clone(),
-                            backlink_field_name.clone(),
-                            removed_id.clone(),
-                        ));
+                        incoming_link_updates_to_make.push((target_id.clone(), removed_id.clone()));
                     }
                 }
             }
@@ -83,14 +182,12 @@ pub fn remove_links_for_ids(graph: &mut LinkGraph, ids_to_remove: &HashSet<Strin
     }
 
     // Perform the updates to incoming links.
-    for (target_id, backlink_field_name, source_to_remove) in incoming_link_updates_to_make {
+    for (target_id, source_to_remove) in incoming_link_updates_to_make {
         if let Some(target_node_data) = graph.get_mut(&target_id) {
-            if let Some(incoming_sources) = target_node_data.incoming_links.get_mut(&backlink_field_name) {
+            for incoming_sources in target_node_data.incoming_links.values_mut() {
                 incoming_sources.retain(|id| *id != source_to_remove);
-                if incoming_sources.is_empty() {
-                    target_node_data.incoming_links.remove(&backlink_field_name);
-                }
             }
+            target_node_data.incoming_links.retain(|_, incoming_sources| !incoming_sources.is_empty());
         }
     }
 
@@ -100,4 +197,337 @@ pub fn remove_links_for_ids(graph: &mut LinkGraph, ids_to_remove: &HashSet<Strin
     for id_to_remove in ids_to_remove {
         graph.remove(id_to_remove);
     }
+
+    // Phase 3: Drop nodes that no longer take part in any link.
+    if mode == RemovalMode::IdsAndOrphans {
+        graph.retain(|id, node_data| {
+            known_ids.contains(id)
+                || !node_data.outgoing_links.is_empty()
+                || !node_data.incoming_links.is_empty()
+        });
+    }
+}
+
+/// Merges `other` into `into`, e.g. to combine graphs built independently for different
+/// shards of a tree. Outgoing and incoming links of nodes present in both graphs are unioned
+/// per field; ID lists keep their order and are deduplicated.
+pub fn merge_link_graphs(into: &mut LinkGraph, other: LinkGraph) {
+    fn merge_fields(into: &mut HashMap<String, Vec<String>>, other: HashMap<String, Vec<String>>) {
+        for (field_name, ids) in other {
+            let merged_ids = into.entry(field_name).or_default();
+            for id in ids {
+                if !merged_ids.contains(&id) {
+                    merged_ids.push(id);
+                }
+            }
+        }
+    }
+
+    for (id, node_data) in other {
+        let merged_node = into.entry(id).or_default();
+        merge_fields(&mut merged_node.outgoing_links, node_data.outgoing_links);
+        merge_fields(&mut merged_node.incoming_links, node_data.incoming_links);
+    }
+}
+
+/// Orders `all_ids` so that every directive comes after the directives it links to via
+/// `field` (e.g. `depends_on`), keeping the input order wherever links allow it. Links to IDs
+/// outside `all_ids` and self-links are ignored. If the links contain a cycle, returns the IDs
+/// that cannot be ordered because they lie on a cycle or between cycles, in input order.
+pub fn topo_order(graph: &LinkGraph, field: &str, all_ids: &[String]) -> Result<Vec<String>, Vec<String>> {
+    let index_of: HashMap<&str, usize> = all_ids.iter().enumerate().map(|(index, id)| (id.as_str(), index)).collect();
+    // dependencies[i]: indices i links to; dependents[i]: indices linking to i
+    let mut dependencies: Vec<HashSet<usize>> = vec![HashSet::new(); all_ids.len()];
+    let mut dependents: Vec<HashSet<usize>> = vec![HashSet::new(); all_ids.len()];
+    for (index, id) in all_ids.iter().enumerate() {
+        let targets = graph.get(id).and_then(|node_data| node_data.outgoing_links.get(field));
+        for target_index in targets.into_iter().flatten().filter_map(|target| index_of.get(target.as_str())) {
+            if *target_index != index {
+                dependencies[index].insert(*target_index);
+                dependents[*target_index].insert(index);
+            }
+        }
+    }
+
+    let mut missing_count: Vec<usize> = dependencies.iter().map(HashSet::len).collect();
+    let mut ready: BTreeSet<usize> = (0..all_ids.len()).filter(|index| missing_count[*index] == 0).collect();
+    let mut ordered = Vec::with_capacity(all_ids.len());
+    while let Some(index) = ready.pop_first() {
+        ordered.push(index);
+        for dependent in &dependents[index] {
+            missing_count[*dependent] -= 1;
+            if missing_count[*dependent] == 0 {
+                ready.insert(*dependent);
+            }
+        }
+    }
+    if ordered.len() == all_ids.len() {
+        return Ok(ordered.into_iter().map(|index| all_ids[index].clone()).collect());
+    }
+
+    // Also strip directives that merely depend on a cycle, i.e. have no unordered dependents
+    let mut unordered: HashSet<usize> = (0..all_ids.len()).filter(|index| missing_count[*index] > 0).collect();
+    loop {
+        let leaves: Vec<usize> = unordered.iter()
+            .copied()
+            .filter(|index| dependents[*index].iter().all(|dependent| !unordered.contains(dependent)))
+            .collect();
+        if leaves.is_empty() {
+            break;
+        }
+        for leaf in leaves {
+            unordered.remove(&leaf);
+        }
+    }
+    let mut involved: Vec<usize> = unordered.into_iter().collect();
+    involved.sort();
+    Err(involved.into_iter().map(|index| all_ids[index].clone()).collect())
+}
+
+/// The sets of directives connected by links in either direction, found with union-find.
+/// Every node of `graph` and every ID it links to belongs to exactly one component. IDs are
+/// sorted within each component; components are sorted by size, largest first, and then by
+/// their first ID.
+pub fn connected_components(graph: &LinkGraph) -> Vec<Vec<String>> {
+    fn find(parents: &mut [usize], mut index: usize) -> usize {
+        while parents[index] != index {
+            parents[index] = parents[parents[index]]; // Path halving
+            index = parents[index];
+        }
+        index
+    }
+
+    fn linked_ids(node_data: &LinkNodeData) -> impl Iterator<Item = &String> {
+        node_data.outgoing_links.values().chain(node_data.incoming_links.values()).flatten()
+    }
+
+    let mut all_ids: BTreeSet<&str> = graph.keys().map(String::as_str).collect();
+    all_ids.extend(graph.values().flat_map(linked_ids).map(String::as_str));
+    let index_of: HashMap<&str, usize> = all_ids.iter().enumerate().map(|(index, id)| (*id, index)).collect();
+
+    // The root of each set is its smallest index, i.e. the first ID of the component
+    let mut parents: Vec<usize> = (0..all_ids.len()).collect();
+    for (id, node_data) in graph {
+        for linked_id in linked_ids(node_data) {
+            let root = find(&mut parents, index_of[id.as_str()]);
+            let linked_root = find(&mut parents, index_of[linked_id.as_str()]);
+            parents[root.max(linked_root)] = root.min(linked_root);
+        }
+    }
+
+    let mut components: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for (index, id) in all_ids.iter().enumerate() {
+        let root = find(&mut parents, index);
+        components.entry(root).or_default().push(id.to_string());
+    }
+    let mut components: Vec<Vec<String>> = components.into_values().collect();
+    components.sort_by_key(|component| std::cmp::Reverse(component.len())); // Stable, so ties stay ordered by first ID
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(graph: &mut LinkGraph, source: &str, target: &str) {
+        graph.entry(source.to_string()).or_default()
+            .outgoing_links.entry("links".to_string()).or_default().push(target.to_string());
+        graph.entry(target.to_string()).or_default()
+            .incoming_links.entry("links_back".to_string()).or_default().push(source.to_string());
+    }
+
+    fn ids(values: &[&str]) -> HashSet<String> {
+        values.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn chain_graph() -> LinkGraph {
+        // a -> b -> c, plus a linkless node for the still existing directive d
+        let mut graph = LinkGraph::new();
+        link(&mut graph, "a", "b");
+        link(&mut graph, "b", "c");
+        graph.insert("d".to_string(), LinkNodeData::default());
+        graph
+    }
+
+    #[test]
+    fn test_remove_links_ids_only() {
+        let mut graph = chain_graph();
+        remove_links_for_ids(&mut graph, &ids(&["a", "b"]), RemovalMode::IdsOnly, &ids(&["d"]));
+
+        let mut remaining: Vec<&String> = graph.keys().collect();
+        remaining.sort();
+        assert_eq!(remaining, vec!["c", "d"]);
+        assert!(graph["c"].incoming_links.is_empty()); // Stale backlink from b was cleaned up
+    }
+
+    #[test]
+    fn test_remove_links_ids_and_orphans() {
+        let mut graph = chain_graph();
+        remove_links_for_ids(&mut graph, &ids(&["a", "b"]), RemovalMode::IdsAndOrphans, &ids(&["d"]));
+
+        // c lost its only link and is not a known directive; d is isolated but still alive.
+        let remaining: Vec<&String> = graph.keys().collect();
+        assert_eq!(remaining, vec!["d"]);
+    }
+
+    #[test]
+    fn test_remove_links_keeps_nodes_with_remaining_links() {
+        let mut graph = chain_graph();
+        link(&mut graph, "e", "c");
+        remove_links_for_ids(&mut graph, &ids(&["b"]), RemovalMode::IdsAndOrphans, &HashSet::new());
+
+        assert!(!graph.contains_key("b"));
+        assert!(!graph.contains_key("d"));
+        assert!(graph["a"].outgoing_links.contains_key("links")); // Dangling link to b stays on the source
+        assert_eq!(graph["c"].incoming_links["links_back"], vec!["e".to_string()]);
+    }
+
+    #[test]
+    fn test_merge_link_graphs() {
+        // Shard 1 saw a -> c, shard 2 saw b -> c and a -> d; both know c's backlinks only partially.
+        let mut merged = LinkGraph::new();
+        link(&mut merged, "a", "c");
+        let mut other = LinkGraph::new();
+        link(&mut other, "b", "c");
+        link(&mut other, "a", "d");
+        link(&mut other, "a", "c"); // Edge known to both shards
+
+        merge_link_graphs(&mut merged, other);
+
+        let mut nodes: Vec<&String> = merged.keys().collect();
+        nodes.sort();
+        assert_eq!(nodes, vec!["a", "b", "c", "d"]);
+        assert_eq!(merged["a"].outgoing_links["links"], vec!["c".to_string(), "d".to_string()]);
+        assert_eq!(merged["b"].outgoing_links["links"], vec!["c".to_string()]);
+        assert_eq!(merged["c"].incoming_links["links_back"], vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(merged["d"].incoming_links["links_back"], vec!["a".to_string()]);
+    }
+
+    fn id_list(values: &[&str]) -> Vec<String> {
+        values.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_topo_order_linear_chain() {
+        // c links to (depends on) b, b depends on a; d and e are unconstrained
+        let mut graph = LinkGraph::new();
+        link(&mut graph, "c", "b");
+        link(&mut graph, "b", "a");
+        link(&mut graph, "e", "outside");
+
+        let ordered = topo_order(&graph, "links", &id_list(&["d", "c", "b", "a", "e"])).unwrap();
+        assert_eq!(ordered, id_list(&["d", "a", "b", "c", "e"]));
+        assert_eq!(topo_order(&graph, "other_field", &id_list(&["c", "b", "a"])).unwrap(), id_list(&["c", "b", "a"]));
+    }
+
+    #[test]
+    fn test_topo_order_cycle() {
+        // a -> b -> c -> a is a cycle, d only depends on it and e is independent
+        let mut graph = LinkGraph::new();
+        link(&mut graph, "a", "b");
+        link(&mut graph, "b", "c");
+        link(&mut graph, "c", "a");
+        link(&mut graph, "d", "a");
+
+        let cycle = topo_order(&graph, "links", &id_list(&["e", "d", "c", "b", "a"])).unwrap_err();
+        assert_eq!(cycle, id_list(&["c", "b", "a"]));
+    }
+
+    #[test]
+    fn test_backlink_collisions() {
+        let config: LinkConfig = toml::from_str("[[links]]\nname = \"derive\"\ncustom_backlink_suffix = \"s_back\"\n\n[[links]]\nname = \"derives\"\n").unwrap();
+        assert_eq!(config.link_types[0].backlink_name(), "derives_back");
+        let err = config.check_backlink_collisions().unwrap_err();
+        assert!(err.contains("'derive' and 'derives'"), "{}", err);
+
+        let config: LinkConfig = toml::from_str("[[links]]\nname = \"tests\"\ncustom_backlink_suffix = \"_by\"\n\n[[links]]\nname = \"tests_by\"\n").unwrap();
+        assert!(config.check_backlink_collisions().is_err());
+
+        let config: LinkConfig = toml::from_str("[[links]]\nname = \"derive\"\n\n[[links]]\nname = \"derives\"\n").unwrap();
+        assert!(config.check_backlink_collisions().is_ok());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rstparser_links.toml");
+        std::fs::write(&path, "[[links]]\nname = \"a\"\ncustom_backlink_suffix = \"_x\"\n\n[[links]]\nname = \"a\"\ncustom_backlink_suffix = \"_x\"\n").unwrap();
+        assert!(matches!(load_link_config(path.to_str().unwrap()), Err(RstParserError::LinkValidation(_))));
+
+        std::fs::write(&path, "[[links]\n").unwrap();
+        assert!(matches!(load_link_config(path.to_str().unwrap()), Err(RstParserError::Config { path: error_path, .. }) if error_path == path));
+    }
+
+    #[test]
+    fn test_backlink_field_colliding_with_link_type() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rstparser_links.toml");
+        for config in ["[[links]]\nname = \"derives\"\n\n[[links]]\nname = \"derives_back\"\n", "[[links]]\nname = \"derives_back\"\n\n[[links]]\nname = \"derives\"\n"] {
+            std::fs::write(&path, config).unwrap();
+            match load_link_config(path.to_str().unwrap()) {
+                Err(RstParserError::LinkValidation(message)) => assert!(message.contains("link type 'derives' collides with the link type 'derives_back'"), "{}", message),
+                other => panic!("expected a LinkValidation error, got {:?}", other),
+            }
+        }
+
+        std::fs::write(&path, "[[links]]\nname = \"derives\"\ncustom_backlink_suffix = \"_by\"\n\n[[links]]\nname = \"derives_back\"\n").unwrap();
+        assert!(load_link_config(path.to_str().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_connected_components_fully_connected() {
+        let mut graph = LinkGraph::new();
+        link(&mut graph, "c", "a");
+        link(&mut graph, "a", "b");
+        link(&mut graph, "b", "c");
+        link(&mut graph, "d", "b");
+        assert_eq!(connected_components(&graph), vec![vec!["a", "b", "c", "d"]]);
+    }
+
+    #[test]
+    fn test_connected_components_isolated_nodes() {
+        let mut graph = LinkGraph::new();
+        for id in ["z", "x", "y"] {
+            graph.insert(id.to_string(), LinkNodeData::default());
+        }
+        assert_eq!(connected_components(&graph), vec![vec!["x"], vec!["y"], vec!["z"]]);
+        assert!(connected_components(&LinkGraph::new()).is_empty());
+    }
+
+    #[test]
+    fn test_connected_components_two_clusters() {
+        // Links count in both directions: e and f only share the target g
+        let mut graph = chain_graph();
+        link(&mut graph, "e", "g");
+        link(&mut graph, "f", "g");
+        link(&mut graph, "h", "g");
+        graph.remove("d");
+        let components = connected_components(&graph);
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0], vec!["e", "f", "g", "h"]);
+        assert_eq!(components[1], vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_link_field_candidates() {
+        let options = [
+            ("id", "REQ-1"),
+            ("status", "open"),
+            ("derives", "REQ-1"),
+            ("tests", "T_1, T_2"),
+            ("title", "Parse the input, quickly"),
+            ("owner", "me"),
+        ];
+        assert_eq!(link_field_candidates(options, &ids(&["REQ-1"])), ids(&["derives", "tests"]));
+    }
+
+    #[test]
+    fn test_generate_link_config_template_is_valid_config() {
+        let template = generate_link_config_template(&ids(&["tests", "derives", "needs \"quotes\""]));
+        let config: LinkConfig = toml::from_str(&template).unwrap();
+        let names: Vec<&str> = config.link_types.iter().map(|link_type| link_type.name.as_str()).collect();
+        assert_eq!(names, ["derives", "needs \"quotes\"", "tests"]);
+        assert!(config.link_types.iter().all(|link_type| link_type.custom_backlink_suffix.is_none()));
+
+        let empty: LinkConfig = toml::from_str(&generate_link_config_template(&HashSet::new())).unwrap();
+        assert!(empty.link_types.is_empty());
+    }
+
 }
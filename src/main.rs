@@ -1,59 +1,445 @@
-mod parser;
-mod file_walker;
-mod aggregator;
-mod processor;
-mod extractor;
-mod link_data;
-mod directive_functions;
-
-// rstparser crate's own modules (if main.rs is treated as part of the crate)
-// If main.rs is a binary using rstparser as a library, these would be:
-// use rstparser::file_walker; etc.
-// For now, assuming main.rs can access sibling modules directly or via `crate::`
-use crate::file_walker::FileWalker;
-use crate::processor::Processor;
-use crate::aggregator::{Aggregator, GroupBy, DirectiveWithSource};
-use crate::link_data::{load_link_config, LinkConfig, LinkGraph, remove_links_for_ids}; // Added remove_links_for_ids
-use crate::directive_functions::FunctionApplicator; // Added
-
-use std::collections::{HashMap, HashSet}; // Added HashSet
-use std::path::PathBuf;
+// main.rs is a binary using the rstparser library crate.
+use rstparser::file_walker::{count_files_by_extension, normalize_path, FileWalker};
+use rstparser::processor::{DEFAULT_FRONT_MATTER_FENCE, DEFAULT_QUICK_SCAN_MARKER, Processor};
+use rstparser::parser::ParseOptions;
+use rstparser::extractor::ExtractorConfig;
+use rstparser::timing::{Timer, TimingRegistry, TimingReport};
+use rstparser::aggregator::{count_directives_by_name, format_counts_table, format_largest_directives, Aggregator, DirectiveWithSource, GroupBy, LineEnding, OutputFormat};
+use rstparser::link_data::{generate_link_config_template, link_field_candidates, load_link_config, LinkConfig};
+use rstparser::directive_functions::FunctionApplicator;
+use rstparser::diagnostics::log_diagnostics;
+use rstparser::pipeline::{Pipeline, PipelineResult};
+use rstparser::error::RstParserError;
+#[cfg(feature = "http")]
+use rstparser::http::{HttpServer, Snapshot};
+use rstparser::rpc::RpcServer;
+use rstparser::watcher::WatchSession;
+use rstparser::query::{format_results_table, DirectiveQuery, Predicate};
+use rstparser::store::DirectiveStore;
+use rstparser::diff::{diff_output_dirs, format_diff};
+use rstparser::validation::{
+    check_required_directive_present, find_dangling_links, find_missing_required_options,
+    validate_files, ValidationProblem, ValidationRule,
+};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
 use std::process;
-use std::sync::{Arc, Mutex};
-use clap::{Parser, ValueEnum};
-use notify::{RecommendedWatcher, RecursiveMode, Watcher, event::EventKind};
-use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use log::{debug, error, info, warn, LevelFilter, Log, Metadata, Record};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::sync::mpsc::{channel, Receiver};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Directory to search for RST files
-    #[arg(short, long, default_value = ".")]
+    #[arg(short, long, default_value = ".", global = true, env = "RSTPARSER_DIR")]
     dir: String,
 
-    /// File extensions to search (comma-separated)
-    #[arg(short, long, default_value = "rst,py,cpp")]
-    extensions: String,
+    /// File extensions to search (comma-separated). Without it, all extensions the extractor
+    /// supports are searched, skipping hidden files and directories
+    #[arg(short, long, global = true, env = "RSTPARSER_EXTENSIONS")]
+    extensions: Option<String>,
 
     /// Directive names to search for (comma-separated)
-    #[arg(short = 'D', long)]
-    directives: String,
+    #[arg(short = 'D', long, global = true, value_parser = parse_directive_list, env = "RSTPARSER_DIRECTIVES")]
+    directives: Option<String>,
+
+    /// Read more directive names from this file, one per line; `#` starts a comment
+    #[arg(long, value_name = "PATH", global = true)]
+    directives_file: Option<PathBuf>,
 
-    /// Output directory for JSON files
-    #[arg(short, long, default_value = "output")]
+    /// Output directory for JSON files, or `-` to write the JSON to stdout (one array with
+    /// `--group-by all`, NDJSON otherwise)
+    #[arg(short, long, default_value = "output", env = "RSTPARSER_OUTPUT")]
     output: String,
 
     /// How to group directives in output files
-    #[arg(short, long, value_enum, default_value_t = GroupByArg::DirectiveName)]
+    #[arg(short, long, value_enum, default_value_t = GroupByArg::DirectiveName, env = "RSTPARSER_GROUP_BY")]
     group_by: GroupByArg,
 
+    /// Link configuration file (also `--links-config`); its `[defaults]` table can set `dir`,
+    /// `directives`, `output`, `extensions` and `group_by` for options given neither here nor in
+    /// the environment. A missing file means no link types and no defaults
+    #[arg(long, visible_alias = "links-config", value_name = "PATH", default_value = DEFAULT_LINK_CONFIG_PATH, global = true, env = "RSTPARSER_LINK_CONFIG")]
+    link_config: PathBuf,
+
+    /// Only resolve these link types of the link configuration (comma-separated); options of
+    /// the other link types are written as they are, without backlinks
+    #[arg(long, value_name = "NAMES", value_delimiter = ',', global = true)]
+    link_types: Option<Vec<String>>,
+
+    /// Format of the output files
+    #[arg(long, value_enum, default_value_t = OutputFormatArg::Json, conflicts_with = "template")]
+    output_format: OutputFormatArg,
+
+    /// Leave directives with this option value (e.g. `index=false`) out of the output; they
+    /// still count as link targets and sources
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    exclude_when: Option<(String, String)>,
+
+    /// Line ending of the output files
+    #[arg(long, value_enum, default_value_t = LineEndingArg::Lf)]
+    line_ending: LineEndingArg,
+
+    /// Start each output file with a UTF-8 byte order mark
+    #[arg(long, default_value_t = false)]
+    bom: bool,
+
+    /// Render each output file with this Tera template instead of writing plain JSON.
+    /// `directive.json.tera` produces `.json` files.
+    #[arg(long, value_name = "PATH")]
+    template: Option<PathBuf>,
+
+    /// With `--group-by all`, write directives after the directives they link to via this
+    /// link field (e.g. `depends_on`)
+    #[arg(long, value_name = "FIELD")]
+    topo_order: Option<String>,
+
+    /// Add the nesting depth of each directive (0 for top-level directives) to the output
+    #[arg(long, default_value_t = false)]
+    include_depth: bool,
+
+    /// Add the first paragraph of each directive's content to the output as `summary`
+    #[arg(long, default_value_t = false)]
+    include_summary: bool,
+
+    /// Add the content of each directive rendered as HTML (paragraphs, bullet lists, bold and
+    /// italic text) to the output as `content_html`
+    #[arg(long, default_value_t = false)]
+    render_content_html: bool,
+
+    /// Also write an `objects.json` inventory mapping each directive ID to its name, source file and anchor
+    #[arg(long, default_value_t = false)]
+    objects_inventory: bool,
+
+    /// Also write `_components.json`, listing the sets of directives connected by links
+    /// (in either direction), largest first
+    #[arg(long, default_value_t = false)]
+    output_components: bool,
+
+    /// Remove this leading directory (e.g. `docs`) from the `source_file` of the written directives
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["since", "changed_files"])]
+    strip_prefix: Option<PathBuf>,
+
+    /// In `.rst` files with `.. @rst` / `.. @endrst` comment lines, only process the regions
+    /// between them
+    #[arg(long, default_value_t = false, global = true)]
+    rst_markers: bool,
+
+    /// Number of spaces to strip after the `///` or `//` of C++ comment lines
+    #[arg(long, value_name = "N", default_value_t = 1, global = true)]
+    cpp_prefix_spaces: usize,
+
     /// Maximum directory depth to search
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     max_depth: Option<usize>,
 
     /// Enable file watching mode
-    #[arg(short, long, default_value_t = false)]
+    #[arg(short, long, default_value_t = false, conflicts_with = "check")]
     watch: bool,
+
+    /// Minimum delay in milliseconds between processing consecutive events for the same file in watch mode.
+    /// Doubles (up to 5000 ms) while events keep arriving faster than that.
+    #[arg(long, default_value_t = 100)]
+    watch_delay_ms: u64,
+
+    /// In watch mode, collect file events until none arrived for this many milliseconds
+    /// (at most 5000 ms) and process them as one batch, e.g. for the files of a `git checkout`
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    batch_window_ms: u64,
+
+    /// Answer JSON-RPC requests for the directives of buffers and files on stdin and stdout,
+    /// e.g. for an editor plugin (see "Editor integration" in the README)
+    #[arg(long, default_value_t = false, conflicts_with_all = ["watch", "check"])]
+    serve: bool,
+
+    /// Inline files referenced by `.. include::` before parsing
+    #[arg(long, default_value_t = false, global = true)]
+    expand_includes: bool,
+
+    /// Lowercase option keys when parsing (e.g. `:Status:` becomes `status`)
+    #[arg(long, default_value_t = false, global = true)]
+    normalize_option_keys: bool,
+
+    /// Only recognize directives starting at column 0; indented ones are treated as content
+    #[arg(long, default_value_t = false, global = true)]
+    top_level_only: bool,
+
+    /// Keep comment lines (`.. text`) between directive options as a `comments` list
+    #[arg(long, default_value_t = false, global = true)]
+    keep_option_comments: bool,
+
+    /// Separator joining the values of an option given several times, e.g. `:see:` twice
+    #[arg(long, value_name = "SEP", default_value = ", ", global = true)]
+    repeated_option_separator: String,
+
+    /// Print a table with the number of directives per directive name after aggregation
+    #[arg(long, default_value_t = false)]
+    summary: bool,
+
+    /// Print the percentage of processed files to stderr while processing
+    #[arg(long, default_value_t = false, global = true)]
+    progress: bool,
+
+    /// Print how long walking, parsing, applying functions and aggregation took, the slowest
+    /// files and the directives with the most content (not in watch mode)
+    #[arg(long, default_value_t = false)]
+    stats: bool,
+
+    /// Skip source files whose first 8 KB do not contain the marker (default `@rst`).
+    /// `.rst` and Markdown files are always processed.
+    #[arg(long, value_name = "MARKER", num_args = 0..=1, default_missing_value = DEFAULT_QUICK_SCAN_MARKER, global = true)]
+    quick_scan: Option<String>,
+
+    /// Attach a TOML front-matter block enclosed by FENCE lines (default `+++`) at the start
+    /// of a file to all of its directives as `file_metadata`
+    #[arg(long, value_name = "FENCE", num_args = 0..=1, default_missing_value = DEFAULT_FRONT_MATTER_FENCE, global = true)]
+    front_matter: Option<String>,
+
+    /// Truncate directive content longer than this many bytes, with a warning
+    #[arg(long, value_name = "N", global = true)]
+    max_directive_content_bytes: Option<usize>,
+
+    /// Only match directives qualified with this Sphinx domain, e.g. `c` for `.. c:function::`
+    #[arg(long, value_name = "NAME", global = true)]
+    domain: Option<String>,
+
+    /// Warn about every processed file that does not contain this directive (comma-separated for several)
+    #[arg(long, value_name = "DIRECTIVE", global = true)]
+    require_directive: Option<String>,
+
+    /// Require options on directives, as `directive:option` pairs (comma-separated for several)
+    #[arg(long, value_name = "DIRECTIVE:OPTION", global = true)]
+    require_option: Option<String>,
+
+    /// Exit with status 2 if validation problems were found
+    #[arg(long, default_value_t = false, global = true)]
+    strict: bool,
+
+    /// CI mode: print validation problems as JSON to stdout and exit with 0 (clean),
+    /// 1 (operational error) or 2 (validation problems)
+    #[arg(long, default_value_t = false, global = true)]
+    check: bool,
+
+    /// Only reprocess the files changed since this git ref (`git diff --name-only`) or untracked,
+    /// and merge their directives with those of the other files already in the output directory
+    #[arg(long, value_name = "REF", conflicts_with_all = ["watch", "changed_files"])]
+    since: Option<String>,
+
+    /// Like `--since`, but read the changed files from this file, one path relative to `--dir` per line
+    #[arg(long, value_name = "FILE", conflicts_with = "watch")]
+    changed_files: Option<PathBuf>,
+
+    /// Print what would be processed and written, without writing anything
+    #[arg(long, default_value_t = false, conflicts_with = "watch")]
+    dry_run: bool,
+
+    /// Do not write any JSON output files
+    #[arg(long, default_value_t = false, conflicts_with = "watch")]
+    no_output: bool,
+
+    /// Whether links to unknown IDs fail `--check` and `validate`
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = ArgAction::Set, global = true)]
+    fail_on_dangling_links: bool,
+
+    /// Whether IDs used by several directives fail `--check` and `validate`
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = ArgAction::Set, global = true)]
+    fail_on_duplicate_ids: bool,
+
+    /// Whether missing required directives or options fail `--check` and `validate`
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = ArgAction::Set, global = true)]
+    fail_on_missing_required: bool,
+
+    /// Only log errors
+    #[arg(short, long, default_value_t = false, conflicts_with = "verbose", global = true)]
+    quiet: bool,
+
+    /// Log more details (-v for debug, -vv for trace)
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Format of log messages written to stderr
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, global = true)]
+    log_format: LogFormat,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the directives matching all given filters, without writing any files
+    Query(QueryArgs),
+    /// Write a starter link configuration with the options of the found directives that look
+    /// like link fields
+    GenerateConfig(GenerateConfigArgs),
+    /// Compare the JSON outputs of two runs: added, removed, changed and moved directives
+    Diff(DiffArgs),
+    /// Validate only the given files, e.g. the staged files of a pre-commit hook, against the
+    /// outputs of a previous full run. Exits with 2 if these files have validation problems
+    Validate(ValidateArgs),
+    /// Watch the directory like --watch and serve its directives, links and a summary as JSON
+    /// over HTTP (`GET /directives`, `/directives/{id}`, `/links`, `/summary`)
+    #[cfg(feature = "http")]
+    Serve(ServeArgs),
+}
+
+#[cfg(feature = "http")]
+#[derive(Args, Debug)]
+struct ServeArgs {
+    /// Address and port to listen on; port 0 picks a free port
+    #[arg(long, value_name = "ADDR", default_value = rstparser::http::DEFAULT_BIND_ADDRESS)]
+    bind: String,
+}
+
+#[derive(Args, Debug)]
+struct ValidateArgs {
+    /// Files to parse and validate; their directives replace those in the baseline
+    #[arg(long, value_name = "FILE", num_args = 1.., required = true)]
+    files: Vec<PathBuf>,
+
+    /// Output directory of a previous full run, read as the state of all other files
+    #[arg(long, value_name = "DIR")]
+    baseline: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct DiffArgs {
+    /// Output directory of the earlier run
+    old_output_dir: PathBuf,
+
+    /// Output directory of the later run
+    new_output_dir: PathBuf,
+
+    /// Print the differences as JSON instead of a summary
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct GenerateConfigArgs {
+    /// Write the configuration to this file instead of stdout
+    #[arg(long, value_name = "PATH")]
+    file: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct QueryArgs {
+    /// Only directives with this name; it does not need to be listed in --directives
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Option condition, `key=value`, `key!=value` or `has:key` (repeatable)
+    #[arg(long = "where", value_name = "CONDITION")]
+    conditions: Vec<String>,
+
+    /// Only directives from source files matching this glob (`*`, `**`, `?`); without a
+    /// leading `/` it may match the end of the path, e.g. `src/module_x/**`
+    #[arg(long, value_name = "GLOB")]
+    path: Option<String>,
+
+    /// Only directives with links, or only with links of this link field
+    #[arg(long, value_name = "FIELD", num_args = 0..=1, default_missing_value = "", conflicts_with = "not_linked")]
+    linked: Option<String>,
+
+    /// Only directives without links, or without links of this link field
+    #[arg(long, value_name = "FIELD", num_args = 0..=1, default_missing_value = "")]
+    not_linked: Option<String>,
+
+    /// Print the matching directives as JSON instead of a table
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+impl QueryArgs {
+    fn to_query(&self) -> Result<DirectiveQuery, String> {
+        let link_field = |field: &String| Some(field.clone()).filter(|field| !field.is_empty());
+        let mut query = DirectiveQuery::new();
+        if let Some(name) = &self.name {
+            query = query.with_predicate(Predicate::Name(name.clone()));
+        }
+        for condition in &self.conditions {
+            query = query.with_predicate(Predicate::parse_option_condition(condition)?);
+        }
+        if let Some(pattern) = &self.path {
+            query = query.with_predicate(Predicate::SourceGlob(pattern.clone()));
+        }
+        if let Some(field) = &self.linked {
+            query = query.with_predicate(Predicate::Linked(link_field(field)));
+        }
+        if let Some(field) = &self.not_linked {
+            query = query.with_predicate(Predicate::NotLinked(link_field(field)));
+        }
+        Ok(query)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    /// Plain messages, prefixed with the level for anything but info
+    Text,
+    /// One JSON object per log event
+    Json,
+}
+
+/// Writes log events to stderr in the format selected on the command line.
+struct CliLogger {
+    level: LevelFilter,
+    format: LogFormat,
+}
+
+impl CliLogger {
+    /// Installs the logger, with the level derived from `-q` and `-v`.
+    fn init(quiet: bool, verbose: u8, format: LogFormat) {
+        let level = match (quiet, verbose) {
+            (true, _) => LevelFilter::Error,
+            (false, 0) => LevelFilter::Info,
+            (false, 1) => LevelFilter::Debug,
+            (false, _) => LevelFilter::Trace,
+        };
+        if log::set_boxed_logger(Box::new(CliLogger { level, format })).is_ok() {
+            log::set_max_level(level);
+        }
+    }
+
+    fn format_record(&self, record: &Record) -> String {
+        match self.format {
+            LogFormat::Text if record.level() == log::Level::Info => record.args().to_string(),
+            LogFormat::Text => format!("{}: {}", record.level(), record.args()),
+            LogFormat::Json => {
+                let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or_default();
+                serde_json::json!({
+                    "timestamp": timestamp,
+                    "level": record.level().as_str(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                })
+                .to_string()
+            }
+        }
+    }
+}
+
+impl Log for CliLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", self.format_record(record));
+        }
+    }
+
+    fn flush(&self) {}
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
@@ -63,6 +449,149 @@ enum GroupByArg {
     SourceFile,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormatArg {
+    Json,
+    Csv,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum LineEndingArg {
+    Lf,
+    Crlf,
+}
+
+/// Link configuration file used unless `--link-config` names another one.
+const DEFAULT_LINK_CONFIG_PATH: &str = "rstparser_links.toml";
+
+/// The `[defaults]` table of the link configuration file.
+#[derive(Debug, Default, Deserialize)]
+struct ConfigDefaults {
+    dir: Option<String>,
+    directives: Option<String>,
+    output: Option<String>,
+    extensions: Option<String>,
+    group_by: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    defaults: ConfigDefaults,
+}
+
+/// Parses a `KEY=VALUE` argument.
+fn parse_key_value(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.trim().to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{}'", value)),
+    }
+}
+
+/// Reads the `[defaults]` table of the configuration file at `path`; a missing file has none.
+fn load_config_defaults(path: &Path) -> Result<ConfigDefaults, String> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => toml::from_str::<ConfigFile>(&contents)
+            .map(|config_file| config_file.defaults)
+            .map_err(|e| e.to_string()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ConfigDefaults::default()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+impl Cli {
+    /// Replaces the options that were neither given on the command line nor in the environment
+    /// with the configuration file's `defaults`, so that the precedence is command line >
+    /// environment > configuration file > built-in default.
+    fn apply_config_defaults(&mut self, matches: &ArgMatches, defaults: ConfigDefaults) -> Result<(), String> {
+        let is_unset = |id: &str| matches!(matches.value_source(id), None | Some(ValueSource::DefaultValue));
+        if let Some(dir) = defaults.dir && is_unset("dir") {
+            self.dir = dir;
+        }
+        if let Some(directives) = defaults.directives && is_unset("directives") {
+            self.directives = Some(parse_directive_list(&directives).map_err(|e| format!("Invalid directives in [defaults]: {}", e))?);
+        }
+        if let Some(output) = defaults.output && is_unset("output") {
+            self.output = output;
+        }
+        if let Some(extensions) = defaults.extensions && is_unset("extensions") {
+            self.extensions = Some(extensions);
+        }
+        if let Some(group_by) = defaults.group_by && is_unset("group_by") {
+            self.group_by = GroupByArg::from_str(&group_by, true).map_err(|e| format!("Invalid group_by in [defaults]: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Whether problems of this rule fail `--check`, according to the `--fail-on-*` toggles.
+    fn fails_on(&self, rule: ValidationRule) -> bool {
+        match rule {
+            ValidationRule::DanglingLink => self.fail_on_dangling_links,
+            ValidationRule::DuplicateId => self.fail_on_duplicate_ids,
+            ValidationRule::MissingRequiredOption | ValidationRule::MissingRequiredDirective => self.fail_on_missing_required,
+        }
+    }
+}
+
+/// Parses `--require-option` values like `req:status,spec:owner` into directive name -> option names.
+fn parse_required_options(value: &str) -> Result<HashMap<String, Vec<String>>, String> {
+    let mut required: HashMap<String, Vec<String>> = HashMap::new();
+    for pair in value.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        match pair.split_once(':') {
+            Some((directive, option)) if !directive.trim().is_empty() && !option.trim().is_empty() => {
+                required.entry(directive.trim().to_string()).or_default().push(option.trim().to_string());
+            }
+            _ => return Err(format!("Invalid --require-option value '{}', expected DIRECTIVE:OPTION", pair)),
+        }
+    }
+    Ok(required)
+}
+
+/// Validates a `--directives` value, which must name at least one directive.
+fn parse_directive_list(value: &str) -> Result<String, String> {
+    if value.split(',').all(|name| name.trim().is_empty()) {
+        return Err("expected at least one directive name".to_string());
+    }
+    Ok(value.to_string())
+}
+
+/// The directive names given with `--directives` and in the `--directives-file`, without
+/// duplicates, in the order given.
+fn directive_names(directives: Option<&str>, directives_file: Option<&Path>) -> Result<Vec<String>, String> {
+    let file_content = match directives_file {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| format!("cannot read directives file {}: {}", path.display(), e))?,
+        None => String::new(),
+    };
+    let from_option = directives.into_iter().flat_map(|names| names.split(','));
+    let from_file = file_content.lines().map(|line| line.split('#').next().unwrap_or(""));
+
+    let mut names: Vec<String> = Vec::new();
+    for name in from_option.chain(from_file).map(str::trim).filter(|name| !name.is_empty()) {
+        if !names.iter().any(|existing| existing == name) {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+impl From<OutputFormatArg> for OutputFormat {
+    fn from(arg: OutputFormatArg) -> Self {
+        match arg {
+            OutputFormatArg::Json => OutputFormat::Json,
+            OutputFormatArg::Csv => OutputFormat::Csv,
+        }
+    }
+}
+
+impl From<LineEndingArg> for LineEnding {
+    fn from(arg: LineEndingArg) -> Self {
+        match arg {
+            LineEndingArg::Lf => LineEnding::Lf,
+            LineEndingArg::Crlf => LineEnding::CrLf,
+        }
+    }
+}
+
 impl From<GroupByArg> for GroupBy {
     fn from(arg: GroupByArg) -> Self {
         match arg {
@@ -73,360 +602,729 @@ impl From<GroupByArg> for GroupBy {
     }
 }
 
+/// Runs `pipeline`, logs its diagnostics and exits if it fails.
+fn run_pipeline(pipeline: Pipeline) -> PipelineResult {
+    match pipeline.run() {
+        Ok(result) => {
+            log_diagnostics(&result.diagnostics);
+            result
+        }
+        Err(RstParserError::Aggregation(err)) => {
+            error!("Error writing JSON files: {}", err);
+            process::exit(1);
+        }
+        Err(err) => {
+            error!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+/// Files changed since `git_ref` according to `git diff --name-only`, plus the untracked files
+/// that are not ignored, relative to `dir`.
+fn git_changed_files(dir: &str, git_ref: &str) -> Result<Vec<PathBuf>, String> {
+    let mut changed = git_file_list(dir, &["diff", "--name-only", "--relative", git_ref, "--"])?;
+    changed.extend(git_file_list(dir, &["ls-files", "--others", "--exclude-standard", "--"])?);
+    Ok(changed)
+}
+
+/// Runs git in `dir` and returns the paths it prints, one per line.
+fn git_file_list(dir: &str, args: &[&str]) -> Result<Vec<PathBuf>, String> {
+    let output = process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("Cannot run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().filter(|line| !line.is_empty()).map(PathBuf::from).collect())
+}
+
+/// Reads a changed-file list with one path per line, ignoring blank lines.
+fn read_changed_files(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let list = std::fs::read_to_string(path).map_err(|e| format!("Cannot read changed-file list {}: {}", path.display(), e))?;
+    Ok(list.lines().map(str::trim).filter(|line| !line.is_empty()).map(PathBuf::from).collect())
+}
+
+/// The changed files selected by `--since` or `--changed-files`, as canonical paths below
+/// `dir` normalized with `normalize_path`. Deleted files are included, so that their old directives are dropped. Exits on errors.
+fn changed_files(cli: &Cli) -> Option<HashSet<PathBuf>> {
+    let changed = match (&cli.since, &cli.changed_files) {
+        (Some(git_ref), _) => git_changed_files(&cli.dir, git_ref),
+        (None, Some(list_path)) => read_changed_files(list_path),
+        (None, None) => return None,
+    };
+    let canonical_dir = std::fs::canonicalize(&cli.dir);
+    match (changed, canonical_dir) {
+        (Ok(changed), Ok(canonical_dir)) => Some(changed.iter().map(|file| normalize_path(&canonical_dir.join(file))).collect()),
+        (Err(e), _) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+        (_, Err(e)) => {
+            error!("Cannot resolve directory {}: {}", cli.dir, e);
+            process::exit(1);
+        }
+    }
+}
+
+/// `--output` value that writes the JSON to stdout instead of an output directory.
+const STDOUT_OUTPUT: &str = "-";
+
+/// Number of matched files listed by `--dry-run`.
+const DRY_RUN_SAMPLE_SIZE: usize = 10;
+
+/// Number of directives listed by `--stats`, largest first.
+const LARGEST_DIRECTIVES_SHOWN: usize = 5;
+
+/// Formats the `--dry-run` report: matched files per extension, a sample of their paths
+/// (relative to `root`), directive counts per name and the output files that would be written.
+fn format_dry_run_report(root: &Path, files: &[PathBuf], directive_counts: &BTreeMap<String, usize>, output_files: &[PathBuf]) -> String {
+    let mut report = format!("Files matched: {}\n", files.len());
+    let extension_counts = count_files_by_extension(files);
+    let extension_width = extension_counts.keys().map(String::len).max().unwrap_or(0);
+    for (extension, count) in &extension_counts {
+        report.push_str(&format!("  {:<extension_width$}  {}\n", extension, count));
+    }
+
+    let mut sample: Vec<String> = files.iter()
+        .map(|file| file.strip_prefix(root).unwrap_or(file).to_string_lossy().replace('\\', "/"))
+        .collect();
+    sample.sort();
+    report.push_str("Sample of matched files:\n");
+    for file in sample.iter().take(DRY_RUN_SAMPLE_SIZE) {
+        report.push_str(&format!("  {}\n", file));
+    }
+    if sample.len() > DRY_RUN_SAMPLE_SIZE {
+        report.push_str(&format!("  ... and {} more\n", sample.len() - DRY_RUN_SAMPLE_SIZE));
+    }
+
+    report.push_str("Directives that would be extracted:\n");
+    report.push_str(&format_counts_table(directive_counts));
+
+    report.push_str("Output files that would be written:\n");
+    for file in output_files {
+        let action = if file.exists() { "overwrite" } else { "create" };
+        report.push_str(&format!("  {:<9}  {}\n", action, file.display()));
+    }
+    report
+}
+
+/// Starts watching `dir` recursively; exits if that fails. Events are sent until the returned
+/// watcher is dropped.
+fn start_watcher(dir: &str) -> (RecommendedWatcher, Receiver<notify::Result<notify::Event>>) {
+    let (tx, rx) = channel();
+    let mut watcher = match RecommendedWatcher::new(tx, notify::Config::default()) {
+        Ok(w) => w,
+        Err(e) => {
+            error!("Error creating file watcher: {}", e);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = watcher.watch(PathBuf::from(dir).as_path(), RecursiveMode::Recursive) {
+        error!("Error watching path {}: {}", dir, e);
+        process::exit(1);
+    }
+    (watcher, rx)
+}
+
+/// Runs the `serve` subcommand: the watch loop of `--watch`, publishing a new snapshot to the
+/// HTTP server after the initial scan and after every change.
+#[cfg(feature = "http")]
+fn run_http_serve(serve_args: &ServeArgs, cli: &Cli, mut watch_session: WatchSession, rx: &Receiver<notify::Result<notify::Event>>) {
+    let server = match HttpServer::bind(&serve_args.bind) {
+        Ok(server) => server,
+        Err(e) => {
+            error!("Error listening on {}: {}", serve_args.bind, e);
+            process::exit(1);
+        }
+    };
+    let publish = |watch_session: &WatchSession| {
+        match Snapshot::new(watch_session.aggregator(), watch_session.directives_map(), watch_session.link_graph()) {
+            Ok(snapshot) => server.publish(snapshot),
+            Err(err) => error!("Error preparing the served directives: {}", err),
+        }
+    };
+
+    if let Err(err) = watch_session.initial_scan_with_buffered_events(&cli.dir, rx) {
+        error!("Error during initial scan: {}", err);
+        process::exit(1);
+    }
+    publish(&watch_session);
+    server.spawn();
+    match server.local_addr() {
+        Some(address) => info!("Serving directives of {} on http://{}. Press Ctrl+C to exit.", &cli.dir, address),
+        None => info!("Serving directives of {} on {}. Press Ctrl+C to exit.", &cli.dir, serve_args.bind),
+    }
+
+    let batch_window = Duration::from_millis(cli.batch_window_ms);
+    while let Some(watch_events) = watch_session.next_batch(rx, batch_window) {
+        if watch_session.handle_events(watch_events).has_changes() {
+            publish(&watch_session);
+        }
+    }
+    error!("Error receiving event: the file watcher stopped");
+}
+
+/// Runs the `query` subcommand: processes all files and prints the matching directives.
+fn run_query(query_args: &QueryArgs, pipeline: Pipeline) {
+    let query = match query_args.to_query() {
+        Ok(query) => query,
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    };
+    let result = run_pipeline(pipeline);
+    let store = DirectiveStore::from_map(&result.directives_map, result.link_graph);
+    let results = query.run_on_store(&store);
+    info!("{} matching directives", results.len());
+    if query_args.json {
+        match serde_json::to_string_pretty(&results) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                error!("Error serializing query results: {}", e);
+                process::exit(1);
+            }
+        }
+    } else {
+        print!("{}", format_results_table(&results));
+    }
+}
+
+fn run_generate_config(generate_args: &GenerateConfigArgs, pipeline: Pipeline) {
+    let result = run_pipeline(pipeline);
+    let directives: Vec<&DirectiveWithSource> = result.directives_map.values().flat_map(|file_map| file_map.values()).collect();
+    let known_ids: HashSet<String> = directives.iter().map(|dws| dws.id.clone()).collect();
+    let options = directives.iter().flat_map(|dws| dws.directive.options.iter().map(|(key, value)| (key.as_str(), value.as_str())));
+    let link_fields = link_field_candidates(options, &known_ids);
+    info!("Found {} option keys that look like link fields", link_fields.len());
+
+    let template = generate_link_config_template(&link_fields);
+    match &generate_args.file {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, template) {
+                error!("Error writing link configuration to {}: {}", path.display(), e);
+                process::exit(1);
+            }
+            info!("Wrote link configuration to {}", path.display());
+        }
+        None => print!("{}", template),
+    }
+}
+
+/// Runs the `validate` subcommand: the given files are validated against the baseline and
+/// their problems reported like those of a full run, as JSON with `--check`. Exits with 2 if
+/// a problem fails according to the `--fail-on-*` toggles.
+fn run_validate(
+    validate_args: &ValidateArgs,
+    cli: &Cli,
+    link_config: &LinkConfig,
+    processor: &Processor,
+    function_applicator: &FunctionApplicator,
+    required_options: &HashMap<String, Vec<String>>,
+) {
+    if !validate_args.baseline.is_dir() {
+        warn!("Baseline directory '{}' does not exist; all links to other files will look dangling.", validate_args.baseline.display());
+    }
+    let backlink_fields: Vec<String> = link_config.link_types.iter().map(|link_type| link_type.backlink_name()).collect();
+    let baseline = match Aggregator::new(&validate_args.baseline, GroupBy::All).load_output_directives(&backlink_fields) {
+        Ok(baseline) => baseline,
+        Err(err) => {
+            error!("Error reading the baseline from {}: {}", validate_args.baseline.display(), err);
+            process::exit(1);
+        }
+    };
+    let problems = match validate_files(&validate_args.files, baseline, processor, function_applicator, required_options) {
+        Ok(problems) => problems,
+        Err(err) => {
+            error!("Error validating files: {}", err);
+            process::exit(1);
+        }
+    };
+    if cli.check {
+        match serde_json::to_string_pretty(&problems) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                error!("Error serializing validation problems: {}", e);
+                process::exit(1);
+            }
+        }
+    } else {
+        log_problems(&problems);
+    }
+    let failing_count = problems.iter().filter(|problem| cli.fails_on(problem.rule)).count();
+    if failing_count > 0 {
+        error!("{} validation problem(s) found in {} file(s)", failing_count, validate_args.files.len());
+        process::exit(2);
+    }
+    info!("No validation problems found in {} file(s)", validate_args.files.len());
+}
+
+/// Logs each problem as a warning, located by file and line.
+fn log_problems(problems: &[ValidationProblem]) {
+    for problem in problems {
+        match problem.line {
+            Some(line) => warn!("{}:{}: {}", problem.file, line, problem.message),
+            None => warn!("{}: {}", problem.file, problem.message),
+        }
+    }
+}
+
+fn run_diff(diff_args: &DiffArgs, link_config: &LinkConfig) {
+    let link_fields: Vec<String> = link_config.link_types.iter()
+        .flat_map(|link_type| [link_type.name.clone(), link_type.backlink_name()])
+        .collect();
+    let diff = match diff_output_dirs(&diff_args.old_output_dir, &diff_args.new_output_dir, &link_fields) {
+        Ok(diff) => diff,
+        Err(e) => {
+            error!("Error reading outputs to compare: {}", e);
+            process::exit(1);
+        }
+    };
+    if diff_args.json {
+        match serde_json::to_string_pretty(&diff) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                error!("Error serializing differences: {}", e);
+                process::exit(1);
+            }
+        }
+    } else {
+        print!("{}", format_diff(&diff));
+    }
+}
+
 fn main() {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    CliLogger::init(cli.quiet, cli.verbose, cli.log_format);
 
-    let link_config_path = "rstparser_links.toml";
-    let link_config = match load_link_config(link_config_path) {
+    let link_config_path = cli.link_config.to_string_lossy().into_owned();
+    match load_config_defaults(&cli.link_config).and_then(|defaults| cli.apply_config_defaults(&matches, defaults)) {
+        Ok(()) => {}
+        Err(e) if cli.check => {
+            error!("Could not apply defaults from '{}': {}", link_config_path, e);
+            process::exit(1);
+        }
+        Err(e) => warn!("Could not apply defaults from '{}': {}", link_config_path, e),
+    }
+    let link_config = match load_link_config(&link_config_path) {
         Ok(cfg) => {
-            println!("Successfully loaded link configuration from '{}'. Found {} link types.", link_config_path, cfg.link_types.len());
+            info!("Successfully loaded link configuration from '{}'. Found {} link types.", link_config_path, cfg.link_types.len());
             Arc::new(cfg)
         }
+        Err(e) if cli.check => {
+            error!("Could not load link configuration from '{}': {}", link_config_path, e);
+            process::exit(1);
+        }
         Err(e) => {
-            eprintln!("Warning: Could not load link configuration from '{}': {}. Proceeding without link processing.", link_config_path, e);
+            warn!("Could not load link configuration from '{}': {}. Proceeding without link processing.", link_config_path, e);
             Arc::new(LinkConfig::default())
         }
     };
 
-    let function_applicator = FunctionApplicator::new(link_config.clone());
+    if let Some(Command::Diff(diff_args)) = &cli.command {
+        run_diff(diff_args, &link_config);
+        return;
+    }
 
-    let extensions: Vec<String> = cli.extensions.split(',').map(|s| s.trim().to_string()).collect();
-    let directives_to_find: Vec<String> = cli.directives.split(',').map(|s| s.trim().to_string()).collect();
+    let mut function_applicator = FunctionApplicator::new(link_config.clone());
+    if let Some(link_types) = &cli.link_types {
+        for name in link_types.iter().filter(|name| !link_config.link_types.iter().any(|link_type| &link_type.name == *name)) {
+            warn!("Link type '{}' from --link-types is not configured in '{}'", name, link_config_path);
+        }
+        function_applicator = function_applicator.with_link_types(link_types);
+    }
+
+    let mut directives_to_find = match directive_names(cli.directives.as_deref(), cli.directives_file.as_deref()) {
+        Ok(names) => names,
+        Err(e) => Cli::command().error(ErrorKind::Io, e).exit(),
+    };
+    if let Some(Command::Query(QueryArgs { name: Some(name), .. })) = &cli.command
+        && !directives_to_find.contains(name)
+    {
+        directives_to_find.push(name.clone());
+    }
+    let required_directives: Vec<String> = cli.require_directive.iter()
+        .flat_map(|names| names.split(','))
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    let required_options = match cli.require_option.as_deref().map(parse_required_options).transpose() {
+        Ok(required) => required.unwrap_or_default(),
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    };
+    for name in required_directives.iter().chain(required_options.keys()) {
+        if !directives_to_find.contains(name) {
+            directives_to_find.push(name.clone()); // Required directives must be parsed to be found
+        }
+    }
 
     if directives_to_find.is_empty() {
-        eprintln!("Error: At least one directive name must be specified.");
+        Cli::command()
+            .error(ErrorKind::MissingRequiredArgument, "at least one directive name must be given with --directives or --directives-file")
+            .exit();
+    }
+
+    let output_to_stdout = cli.output == STDOUT_OUTPUT;
+    if output_to_stdout && (cli.watch || cli.check) {
+        error!("--output {} cannot be combined with --watch or --check.", STDOUT_OUTPUT);
+        process::exit(1);
+    }
+
+    let incremental = cli.since.is_some() || cli.changed_files.is_some();
+    if incremental && (output_to_stdout || cli.template.is_some()) {
+        error!("--since and --changed-files need JSON outputs in an output directory to merge with.");
         process::exit(1);
     }
 
     let output_dir = PathBuf::from(&cli.output);
-    if !output_dir.exists() {
-        if let Err(e) = std::fs::create_dir_all(&output_dir) {
-            eprintln!("Error creating output directory {}: {}", output_dir.display(), e);
-            process::exit(1);
-        }
+    if !cli.no_output
+        && !output_to_stdout
+        && !cli.dry_run
+        && cli.command.is_none()
+        && !cli.serve
+        && !output_dir.exists()
+        && let Err(e) = std::fs::create_dir_all(&output_dir)
+    {
+        error!("Error creating output directory {}: {}", output_dir.display(), e);
+        process::exit(1);
     }
     
-    let walker = if let Some(depth) = cli.max_depth {
-        FileWalker::new().with_extensions(extensions.clone()).with_max_depth(depth)
-    } else {
-        FileWalker::new().with_extensions(extensions.clone())
+    let mut walker = match &cli.extensions {
+        Some(extensions) => FileWalker::new().with_extensions(extensions.split(',').map(|s| s.trim().to_string()).collect()),
+        None => FileWalker::new_with_defaults_for_rst(),
     };
+    if let Some(depth) = cli.max_depth {
+        walker = walker.with_max_depth(depth);
+    }
 
-    let processor = Processor::new(directives_to_find.clone());
-    let aggregator = Aggregator::new(output_dir.clone(), cli.group_by.into());
-
+    let parse_options = ParseOptions {
+        normalize_option_keys: cli.normalize_option_keys,
+        top_level_only: cli.top_level_only,
+        keep_option_comments: cli.keep_option_comments,
+        repeated_option_separator: Some(cli.repeated_option_separator.clone()),
+        ..ParseOptions::default()
+    };
+    let mut processor = Processor::new(directives_to_find.clone())
+        .with_aliases(link_config.aliases.clone())
+        .with_expand_includes(cli.expand_includes)
+        .with_parse_options(parse_options);
+    if let Some(marker) = &cli.quick_scan {
+        processor = processor.with_quick_scan_marker(marker);
+    }
+    if let Some(fence) = &cli.front_matter {
+        processor = processor.with_front_matter_fence(fence);
+    }
+    if let Some(max_bytes) = cli.max_directive_content_bytes {
+        processor = processor.with_directive_content_limit(max_bytes);
+    }
+    if let Some(domain) = &cli.domain {
+        processor = processor.with_domain(domain);
+    }
+    if cli.rst_markers || cli.cpp_prefix_spaces != 1 {
+        processor = processor.with_extractor_config(ExtractorConfig {
+            rst_markers: cli.rst_markers,
+            cpp_prefix_spaces: cli.cpp_prefix_spaces,
+            ..ExtractorConfig::default()
+        });
+    }
+    if cli.progress {
+        processor = processor.with_progress(Box::new(|completed, total| {
+            eprint!("\rProcessed {}/{} files ({}%)", completed, total, completed * 100 / total);
+            if completed == total {
+                eprintln!();
+            }
+        }));
+    }
+    let timing_registry = Arc::new(TimingRegistry::new());
+    if cli.stats {
+        processor = processor.with_timing_registry(Arc::clone(&timing_registry));
+    }
+    let mut aggregator = Aggregator::new(output_dir.clone(), cli.group_by.into()).with_depth(cli.include_depth)
+        .with_summary(cli.include_summary)
+        .with_rendered_content(cli.render_content_html)
+        .with_objects_inventory(cli.objects_inventory)
+        .with_components(cli.output_components)
+        .with_output_format(cli.output_format.into())
+        .with_line_ending(cli.line_ending.into())
+        .with_bom(cli.bom);
+    if let Some(template_path) = &cli.template {
+        aggregator = aggregator.with_template(template_path);
+    }
+    if let Some(field) = &cli.topo_order {
+        aggregator = aggregator.with_topo_order(field);
+    }
+    if let Some((key, value)) = &cli.exclude_when {
+        aggregator = aggregator.with_exclusion(key, value);
+    }
+    if let Some(prefix) = &cli.strip_prefix {
+        // Source files are canonical paths, so the prefix is made canonical as well if it exists
+        aggregator = aggregator.with_strip_prefix(std::fs::canonicalize(prefix).unwrap_or_else(|_| prefix.clone()));
+    }
 
-    if cli.watch {
-        println!("Watch mode enabled. Watching directory: {}. Press Ctrl+C to exit.", &cli.dir);
-        let (tx, rx) = channel();
-        let mut watcher = match RecommendedWatcher::new(tx, notify::Config::default()) {
-            Ok(w) => w,
-            Err(e) => {
-                eprintln!("Error creating file watcher: {}", e);
+    match &cli.command {
+        Some(Command::Query(query_args)) => {
+            run_query(query_args, Pipeline::new().dir(&cli.dir).walker(walker).processor(processor).function_applicator(function_applicator));
+            return;
+        }
+        Some(Command::GenerateConfig(generate_args)) => {
+            run_generate_config(generate_args, Pipeline::new().dir(&cli.dir).walker(walker).processor(processor));
+            return;
+        }
+        #[cfg(feature = "http")]
+        Some(Command::Serve(serve_args)) => {
+            if output_to_stdout {
+                error!("--output {} cannot be combined with serve.", STDOUT_OUTPUT);
                 process::exit(1);
             }
-        };
-        if let Err(e) = watcher.watch(PathBuf::from(&cli.dir).as_path(), RecursiveMode::Recursive) {
-            eprintln!("Error watching path {}: {}", &cli.dir, e);
+            let (_watcher, rx) = start_watcher(&cli.dir);
+            let watch_session = WatchSession::new(processor, function_applicator, aggregator, walker)
+                .with_watch_delay(Duration::from_millis(cli.watch_delay_ms));
+            run_http_serve(serve_args, &cli, watch_session, &rx);
+            return;
+        }
+        Some(Command::Validate(validate_args)) => {
+            run_validate(validate_args, &cli, &link_config, &processor, &function_applicator, &required_options);
+            return;
+        }
+        Some(Command::Diff(_)) | None => {}
+    }
+
+    if cli.serve {
+        info!("Serving JSON-RPC requests on stdin and stdout.");
+        if let Err(e) = RpcServer::new(processor).serve(io::stdin().lock(), io::stdout().lock()) {
+            error!("Error serving requests: {}", e);
             process::exit(1);
         }
+        return;
+    }
 
-        // --- Initial Scan Logic for Watch Mode ---
-        println!("Performing initial scan of '{}'...", &cli.dir);
-        let initial_files = match walker.find_files(&cli.dir) {
-            Ok(files) => files,
-            Err(err) => {
-                eprintln!("Error during initial file scan: {}", err);
-                process::exit(1);
-            }
-        };
-        println!("Initial scan found {} files to process.", initial_files.len());
-
-        let mut initial_processed_directives_map: HashMap<PathBuf, HashMap<String, Arc<Mutex<DirectiveWithSource>>>> = HashMap::new();
-        match processor.process_files_watch(initial_files) { // Assuming process_files_watch returns Vec<Arc<Mutex<Dws>>> per file or similar
-            Ok(processed_map_from_processor) => { // This needs to align with Processor's output for watch mode
-                for (file_path, directives_in_file_vec) in processed_map_from_processor {
-                     let canonical_file_path = match std::fs::canonicalize(&file_path) {
-                        Ok(p) => p,
-                        Err(e) => {
-                            eprintln!("Warning: Failed to canonicalize path during initial scan {}: {}", file_path.display(), e);
-                            file_path // Fallback
-                        }
-                    };
-                    let mut file_map = HashMap::new();
-                    for dws_arc in directives_in_file_vec {
-                        let dws_guard = dws_arc.lock().unwrap();
-                        file_map.insert(dws_guard.id.clone(), dws_arc.clone());
-                    }
-                    initial_processed_directives_map.insert(canonical_file_path, file_map);
+    if cli.watch {
+        info!("Watch mode enabled. Watching directory: {}. Press Ctrl+C to exit.", &cli.dir);
+        let (_watcher, rx) = start_watcher(&cli.dir);
+        let mut watch_session = WatchSession::new(processor, function_applicator, aggregator, walker)
+            .with_watch_delay(Duration::from_millis(cli.watch_delay_ms));
+        match watch_session.initial_scan_with_buffered_events(&cli.dir, &rx) {
+            Ok(summary) => {
+                info!("Initial aggregation complete. Wrote {} JSON files:", summary.output_files.len());
+                for file in summary.output_files { info!("  {}", file.display()); }
+                if cli.summary {
+                    print!("{}", format_counts_table(&count_directives_by_name(watch_session.directives_map())));
                 }
             }
             Err(err) => {
-                eprintln!("Error processing files during initial scan: {}", err);
-                process::exit(1);
-            }
-        }
-        
-        let current_directives_with_source = Arc::new(Mutex::new(initial_processed_directives_map));
-        
-        // --- Apply directive functions (Initial Scan for Watch Mode) ---
-        let mut link_graph_watch = LinkGraph::default();
-        println!("Applying directive functions (initial scan)...");
-        let directives_map_guard = current_directives_with_source.lock().unwrap();
-        function_applicator.apply_to_all(&directives_map_guard, &mut link_graph_watch);
-        drop(directives_map_guard); // Release lock
-        println!("Directive functions applied. Link graph has {} entries.", link_graph_watch.len());
-        let link_graph_arc_watch = Arc::new(Mutex::new(link_graph_watch));
-        // --- End of applying directive functions ---
-
-        let initial_directive_count = current_directives_with_source.lock().unwrap().values().map(|fm| fm.len()).sum::<usize>();
-        println!("Initial scan found {} directives.", initial_directive_count);
-        
-        match aggregator.aggregate_to_json_from_map_with_links(current_directives_with_source.clone(), link_graph_arc_watch.clone()) {
-            Ok(output_files) => {
-                println!("Initial aggregation complete. Wrote {} JSON files:", output_files.len());
-                for file in output_files { println!("  {}", file.display()); }
-            },
-            Err(err) => {
-                eprintln!("Error writing JSON files during initial aggregation: {}", err);
+                error!("Error during initial scan: {}", err);
                 process::exit(1);
             }
         }
 
         // Event loop for watch mode
-        loop {
-            match rx.recv() {
-                Ok(event_result) => match event_result {
-                    Ok(event) => {
-                        println!("File event: {:?}", event);
-                        let mut changed_anything_globally = false;
-                        let relevant_event_paths: Vec<PathBuf> = event.paths.iter().filter(|p| {
-                            !event.kind.is_remove() && extensions.iter().any(|ext| p.extension().map_or(false, |file_ext| file_ext == ext.trim_start_matches('.')))
-                        }).cloned().collect();
-                        
-                        let mut global_directives_map_guard = current_directives_with_source.lock().unwrap();
-                        let mut link_graph_guard = link_graph_arc_watch.lock().unwrap();
-                        
-                        let mut ids_to_clear_from_graph = HashSet::new(); // IDs whose links need to be removed before reprocessing
-                        let mut arcs_for_subset_application: Vec<Arc<Mutex<DirectiveWithSource>>> = Vec::new();
-                        let mut affected_ids_for_neighbor_scan = HashSet::new(); // IDs that were modified or removed, to find their neighbors
-
-                        match event.kind {
-                            EventKind::Create(_) | EventKind::Modify(_) => {
-                                if relevant_event_paths.is_empty() { continue; }
-                                println!("File(s) created/modified: {:?}", relevant_event_paths);
-                                for path_to_process_orig in &relevant_event_paths {
-                                    let canonical_path = match std::fs::canonicalize(path_to_process_orig) {
-                                        Ok(p) => p,
-                                        Err(e) => {
-                                            eprintln!("Warning: Failed to canonicalize path for event {}: {}", path_to_process_orig.display(), e);
-                                            path_to_process_orig.clone()
-                                        }
-                                    };
-
-                                    // Collect old IDs from this file to clear their links and find neighbors
-                                    if let Some(old_file_directives) = global_directives_map_guard.get(&canonical_path) {
-                                        for old_id in old_file_directives.keys() {
-                                            ids_to_clear_from_graph.insert(old_id.clone());
-                                            affected_ids_for_neighbor_scan.insert(old_id.clone());
-                                        }
-                                    }
-                                    
-                                    match processor.process_file_watch(&canonical_path) {
-                                        Ok(processed_directives_arcs_for_file) => {
-                                            let mut new_file_map = HashMap::new();
-                                            for dws_arc in processed_directives_arcs_for_file {
-                                                let dws_guard = dws_arc.lock().unwrap();
-                                                new_file_map.insert(dws_guard.id.clone(), dws_arc.clone());
-                                                arcs_for_subset_application.push(dws_arc.clone()); 
-                                                ids_to_clear_from_graph.insert(dws_guard.id.clone()); // Also clear new IDs in case they existed before with different content
-                                                affected_ids_for_neighbor_scan.insert(dws_guard.id.clone());
-                                            }
-                                            global_directives_map_guard.insert(canonical_path.clone(), new_file_map);
-                                            changed_anything_globally = true;
-                                            println!("  Updated/added directives for {}", canonical_path.display());
-                                        }
-                                        Err(e) => eprintln!("  Error processing file {}: {}", canonical_path.display(), e),
-                                    }
-                                }
-                            }
-                            EventKind::Remove(_) => {
-                                println!("Path(s) removed: {:?}", event.paths);
-                                for removed_path_item_orig in &event.paths {
-                                    let path_key_candidate = match std::fs::canonicalize(removed_path_item_orig) {
-                                        Ok(p) => p,
-                                        Err(_) => removed_path_item_orig.clone(), 
-                                    };
-                                    
-                                    let keys_to_remove_from_map: Vec<PathBuf> = global_directives_map_guard.keys()
-                                        .filter(|k| **k == path_key_candidate || k.starts_with(&path_key_candidate))
-                                        .cloned()
-                                        .collect();
-                                    
-                                    for key_to_remove in keys_to_remove_from_map {
-                                        if let Some(removed_file_directives) = global_directives_map_guard.remove(&key_to_remove) {
-                                            for id in removed_file_directives.keys() {
-                                                ids_to_clear_from_graph.insert(id.clone());
-                                                affected_ids_for_neighbor_scan.insert(id.clone());
-                                            }
-                                            println!("  Removed directives from cache for {}", key_to_remove.display());
-                                            changed_anything_globally = true;
-                                        }
-                                    }
-                                }
-                            }
-                            _ => {}
-                        }
-
-                        if changed_anything_globally {
-                            // Find neighbors of affected IDs (those that linked TO or were targeted BY affected_ids_for_neighbor_scan)
-                            // This scan must happen BEFORE clearing links from the graph.
-                            let mut neighbor_arcs_to_reprocess: HashMap<String, Arc<Mutex<DirectiveWithSource>>> = HashMap::new();
-                            if !affected_ids_for_neighbor_scan.is_empty() {
-                                println!("Scanning for neighbors of {} affected/removed IDs...", affected_ids_for_neighbor_scan.len());
-                                for (source_id, node_data) in link_graph_guard.iter() {
-                                    // Check if this source_id is one of the directly affected ones (already in arcs_for_subset_application or to be removed)
-                                    // If not, check its links.
-                                    if !affected_ids_for_neighbor_scan.contains(source_id) {
-                                        for targets in node_data.outgoing_links.values() {
-                                            if targets.iter().any(|target_id| affected_ids_for_neighbor_scan.contains(target_id)) {
-                                                // This source_id links to an affected ID. It needs reprocessing.
-                                                // Find its Arc<Mutex<Dws>> from global_directives_map_guard
-                                                for file_map in global_directives_map_guard.values() {
-                                                    if let Some(arc) = file_map.get(source_id) {
-                                                        neighbor_arcs_to_reprocess.insert(source_id.clone(), arc.clone());
-                                                        break;
-                                                    }
-                                                }
-                                                break; // Found a reason to reprocess this source_id, move to next in graph
-                                            }
-                                        }
-                                    }
-                                }
-                                // Also, directives that were targets of affected_ids_for_neighbor_scan might need reprocessing
-                                // if their incoming links are their only reason for being in the graph or having certain data.
-                                // However, apply_to_subset on the sources should update their incoming links.
-                                // The main concern is if a neighbor's *only* connection was to a now-deleted/changed node.
-                                // The `remove_links_for_ids` and subsequent `apply_to_subset` should handle this.
-                            }
-                            
-                            // Add collected neighbors to the main list for subset application, avoiding duplicates
-                            for (id, arc) in neighbor_arcs_to_reprocess {
-                                if !arcs_for_subset_application.iter().any(|a| a.lock().unwrap().id == id) {
-                                    arcs_for_subset_application.push(arc);
-                                }
-                            }
-
-
-                            if !ids_to_clear_from_graph.is_empty() {
-                                println!("Clearing links for {} directive IDs from graph...", ids_to_clear_from_graph.len());
-                                remove_links_for_ids(&mut link_graph_guard, &ids_to_clear_from_graph);
-                            }
-
-                            if !arcs_for_subset_application.is_empty() {
-                                println!("Re-applying directive functions to {} directives (modified + neighbors)...", arcs_for_subset_application.len());
-                                function_applicator.apply_to_subset(&arcs_for_subset_application, &global_directives_map_guard, &mut link_graph_guard);
-                            }
-                            
-                            // Final cleanup: remove any LinkGraph nodes for directives that no longer exist in global_directives_map_guard
-                            let mut still_valid_directive_ids = HashSet::new();
-                            for file_directives in global_directives_map_guard.values() {
-                                for id in file_directives.keys() {
-                                    still_valid_directive_ids.insert(id.clone());
-                                }
-                            }
-                            link_graph_guard.retain(|id, _| still_valid_directive_ids.contains(id));
-                            println!("Directive functions updated. Link graph has {} entries.", link_graph_guard.len());
-                        }
-                        
-                        drop(link_graph_guard); 
-                        drop(global_directives_map_guard); // Release before aggregator
-
-                        if changed_anything_globally {
-                            let final_directive_count = current_directives_with_source.lock().unwrap().values().map(|fm| fm.len()).sum::<usize>();
-                            println!("Re-aggregating {} total directives...", final_directive_count);
-                            match aggregator.aggregate_to_json_from_map_with_links(current_directives_with_source.clone(), link_graph_arc_watch.clone()) {
-                                Ok(output_files) => {
-                                    println!("Aggregation complete. Wrote {} JSON files:", output_files.len());
-                                    for file in output_files { println!("  {}", file.display()); }
-                                },
-                                Err(err) => eprintln!("Error writing JSON files after event: {}", err),
-                            }
-                        }
-                    }
-                    Err(e) => eprintln!("Watch error: {:?}", e),
-                },
-                Err(e) => {
-                    eprintln!("Error receiving event: {}", e);
-                    break; // Exit loop on channel receive error
+        let batch_window = Duration::from_millis(cli.batch_window_ms);
+        while let Some(watch_events) = watch_session.next_batch(&rx, batch_window) {
+            let summary = watch_session.handle_events(watch_events);
+            if summary.has_changes() && !summary.output_files.is_empty() {
+                info!("Aggregation complete. Wrote {} JSON files:", summary.output_files.len());
+                for file in summary.output_files { info!("  {}", file.display()); }
+                if cli.summary {
+                    print!("{}", format_counts_table(&count_directives_by_name(watch_session.directives_map())));
                 }
             }
         }
+        error!("Error receiving event: the file watcher stopped");
 
     } else { // Non-watch mode
-        let files = match walker.find_files(&cli.dir) {
-            Ok(f) => f,
-            Err(err) => {
-                eprintln!("Error finding files: {}", err);
-                process::exit(1);
+        let mut pipeline = Pipeline::new().dir(&cli.dir).walker(walker).processor(processor).function_applicator(function_applicator);
+        let mut unchanged_count = None;
+        if let Some(changed_files) = changed_files(&cli) {
+            let backlink_fields: Vec<String> = link_config.link_types.iter().map(|link_type| link_type.backlink_name()).collect();
+            match aggregator.load_output_directives(&backlink_fields) {
+                Ok(previous) => {
+                    let unchanged: Vec<DirectiveWithSource> = previous.into_iter()
+                        .filter(|dws| !changed_files.contains(&normalize_path(Path::new(&dws.source_file))))
+                        .collect();
+                    unchanged_count = Some(unchanged.len());
+                    pipeline = pipeline.only_files(changed_files).additional_directives(unchanged);
+                }
+                Err(err) => {
+                    error!("Error reading previous output from {}: {}", output_dir.display(), err);
+                    process::exit(1);
+                }
             }
-        };
-        println!("Found {} files to process", files.len());
+        }
 
-        // In non-watch mode, Processor returns Vec<DirectiveWithSource>
-        // We need to convert this to HashMap<PathBuf, HashMap<String, Arc<Mutex<DirectiveWithSource>>>>
-        // for FunctionApplicator and the new aggregator method.
-        let directives_vec = match processor.process_files(files) { // process_files returns Vec<Dws>
-            Ok(directives) => directives,
-            Err(err) => {
-                eprintln!("Error processing files: {}", err);
-                process::exit(1);
+        // Without files to write, the aggregator is kept for planning or writing to stdout
+        let writes_files = !cli.no_output && !output_to_stdout && !cli.dry_run;
+        let (pipeline, aggregator) = if writes_files { (pipeline.aggregator(aggregator), None) } else { (pipeline, Some(aggregator)) };
+        let result = run_pipeline(pipeline);
+        let mut timing_report = TimingReport::new();
+        for (phase, duration) in &result.phase_timings {
+            timing_report.record_phase(phase, *duration);
+        }
+        if let Some(unchanged_count) = unchanged_count {
+            info!("Processed {} changed files, keeping {} directives of unchanged files", result.files.len(), unchanged_count);
+        }
+        debug!("Directive functions applied. Link graph has {} entries.", result.link_graph.len());
+
+        let total_directives_found = result.directives_map.values().map(|fm| fm.len()).sum::<usize>();
+        info!("Found {} directives", total_directives_found);
+        for name in &result.missing_directives {
+            warn!("No '{}' directive was found in any file; is the name misspelled?", name);
+        }
+
+        if let Some(aggregator) = &aggregator {
+            if cli.dry_run {
+                match aggregator.plan_output_files(&result.directives_map, &result.link_graph) {
+                    Ok(output_files) => print!("{}", format_dry_run_report(
+                        Path::new(&cli.dir),
+                        &result.files,
+                        &count_directives_by_name(&result.directives_map),
+                        &output_files,
+                    )),
+                    Err(err) => {
+                        error!("Error planning JSON files: {}", err);
+                        process::exit(1);
+                    }
+                }
+                return;
             }
-        };
-        
-        let mut directives_map_for_processing: HashMap<PathBuf, HashMap<String, Arc<Mutex<DirectiveWithSource>>>> = HashMap::new();
-        for dws_val in directives_vec { // dws_val is DirectiveWithSource, not Arc<Mutex<Dws>>
-            let file_path_buf = PathBuf::from(&dws_val.source_file);
-            // Canonicalize paths for consistency, though less critical in non-watch mode if IDs are stable
-            let canonical_file_path = match std::fs::canonicalize(&file_path_buf) {
-                Ok(p) => p,
+            if output_to_stdout && !cli.no_output {
+                let timer = Timer::new("aggregate");
+                if let Err(err) = aggregator.write_map_to_writer(&result.directives_map, &result.link_graph, std::io::stdout().lock()) {
+                    error!("Error writing JSON to stdout: {}", err);
+                    process::exit(1);
+                }
+                timing_report.record_phase("aggregate", timer.elapsed());
+            }
+        } else {
+            info!("Successfully wrote {} JSON files:", result.written_files.len());
+            for file in &result.written_files { info!("  {}", file.display()); }
+        }
+        if cli.summary {
+            let table = format_counts_table(&count_directives_by_name(&result.directives_map));
+            // Keep stdout pure JSON when the directives are written there
+            if output_to_stdout { eprint!("{}", table) } else { print!("{}", table) }
+        }
+        if cli.stats {
+            timing_report.add_registry(&timing_registry);
+            let stats = format!("{}{}", timing_report, format_largest_directives(&result.directives_map, LARGEST_DIRECTIVES_SHOWN));
+            if output_to_stdout { eprint!("{}", stats) } else { print!("{}", stats) }
+        }
+
+        let mut problems = result.duplicate_ids;
+        problems.extend(find_dangling_links(&result.directives_map, &result.link_graph));
+        problems.extend(find_missing_required_options(&result.directives_map, &required_options));
+        for name in &required_directives {
+            for file in check_required_directive_present(&result.directives_map, name, &result.files) {
+                problems.push(ValidationProblem {
+                    file: file.to_string_lossy().into_owned(),
+                    line: None,
+                    rule: ValidationRule::MissingRequiredDirective,
+                    message: format!("File does not contain the required '{}' directive", name),
+                });
+            }
+        }
+        problems.sort();
+
+        if cli.check {
+            match serde_json::to_string_pretty(&problems) {
+                Ok(json) => println!("{}", json),
                 Err(e) => {
-                    eprintln!("Warning: Failed to canonicalize path in non-watch mode {}: {}", file_path_buf.display(), e);
-                    file_path_buf 
+                    error!("Error serializing validation problems: {}", e);
+                    process::exit(1);
                 }
-            };
-            
-            // Ensure dws_val.source_file is updated if canonicalized, and ID uses it
-            let mut dws_mut = dws_val; // Make it mutable to update source_file
-            dws_mut.source_file = canonical_file_path.to_string_lossy().into_owned();
-
-            let directive_id = dws_mut.id.clone(); // ID should already be generated by Processor
-
-            directives_map_for_processing
-                .entry(canonical_file_path)
-                .or_default()
-                .insert(directive_id, Arc::new(Mutex::new(dws_mut)));
-        }
-
-        // --- Apply directive functions (Non-Watch Mode) ---
-        let mut link_graph_non_watch = LinkGraph::default();
-        println!("Applying directive functions...");
-        function_applicator.apply_to_all(&directives_map_for_processing, &mut link_graph_non_watch);
-        println!("Directive functions applied. Link graph has {} entries.", link_graph_non_watch.len());
-        // --- End of applying directive functions ---
-
-        let total_directives_found = directives_map_for_processing.values().map(|fm| fm.len()).sum::<usize>();
-        println!("Found {} directives", total_directives_found);
-        
-        match aggregator.aggregate_map_to_json_with_links(&directives_map_for_processing, &link_graph_non_watch) {
-            Ok(output_files) => {
-                println!("Successfully wrote {} JSON files:", output_files.len());
-                for file in output_files { println!("  {}", file.display()); }
-            },
-            Err(err) => {
-                eprintln!("Error writing JSON files: {}", err);
-                process::exit(1);
+            }
+            let failing_count = problems.iter().filter(|problem| cli.fails_on(problem.rule)).count();
+            if failing_count > 0 {
+                error!("{} validation problem(s) found", failing_count);
+                process::exit(2);
+            }
+        } else {
+            log_problems(&problems);
+            if !problems.is_empty() && cli.strict {
+                error!("{} validation problem(s) found, failing because of --strict", problems.len());
+                process::exit(2);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_message(logger: &CliLogger, level: log::Level, message: &str) -> String {
+        logger.format_record(&Record::builder().level(level).target("rstparser::processor").args(format_args!("{}", message)).build())
+    }
+
+    #[test]
+    fn test_format_dry_run_report() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let existing_output = temp_dir.path().join("note.json");
+        std::fs::write(&existing_output, "[]").unwrap();
+        let new_output = temp_dir.path().join("req.json");
+
+        let root = Path::new("docs");
+        let files: Vec<PathBuf> = (0..12).map(|i| root.join(format!("part_{:02}.rst", i))).chain([root.join("src/lib.cpp")]).collect();
+        let counts = BTreeMap::from([("note".to_string(), 2), ("req".to_string(), 5)]);
+        let report = format_dry_run_report(root, &files, &counts, &[existing_output.clone(), new_output.clone()]);
+
+        let expected_files: String = (0..10).map(|i| format!("  part_{:02}.rst\n", i)).collect();
+        assert_eq!(report, format!(
+            "Files matched: 13\n  cpp  1\n  rst  12\nSample of matched files:\n{}  ... and 3 more\n\
+             Directives that would be extracted:\nDirective  Count\nnote           2\nreq            5\nTotal          7\n\
+             Output files that would be written:\n  overwrite  {}\n  create     {}\n",
+            expected_files, existing_output.display(), new_output.display(),
+        ));
+    }
+
+    #[test]
+    fn test_directive_names() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = temp_dir.path().join("directives.txt");
+        std::fs::write(&file, "# Requirements\nreq\n\n  spec  # trailing comment\n").unwrap();
+        assert_eq!(directive_names(None, Some(&file)).unwrap(), vec!["req", "spec"]);
+        assert_eq!(directive_names(Some("note, req"), Some(&file)).unwrap(), vec!["note", "req", "spec"]);
+
+        std::fs::write(&file, "req\r\n# comment\r\nspec\r\n").unwrap();
+        assert_eq!(directive_names(None, Some(&file)).unwrap(), vec!["req", "spec"]);
+
+        assert!(directive_names(None, Some(&temp_dir.path().join("missing.txt"))).is_err());
+        assert!(parse_directive_list(" , ").is_err());
+        assert!(Cli::try_parse_from(["rstparser", "-D", ""]).is_err());
+        assert!(Cli::try_parse_from(["rstparser", "-D", "req,"]).is_ok());
+    }
+
+    #[test]
+    fn test_parse_required_options() {
+        let required = parse_required_options("req:status, req:owner,spec:id").unwrap();
+        assert_eq!(required["req"], vec!["status".to_string(), "owner".to_string()]);
+        assert_eq!(required["spec"], vec!["id".to_string()]);
+        assert!(parse_required_options("req").is_err());
+        assert!(parse_required_options("req:").is_err());
+    }
+
+    #[test]
+    fn test_text_log_format() {
+        let logger = CliLogger { level: LevelFilter::Info, format: LogFormat::Text };
+        assert_eq!(format_message(&logger, log::Level::Info, "Found 3 files"), "Found 3 files");
+        assert_eq!(format_message(&logger, log::Level::Warn, "Include cycle"), "WARN: Include cycle");
+        assert!(!logger.enabled(&Metadata::builder().level(log::Level::Debug).build()));
+    }
+
+    #[test]
+    fn test_json_log_format() {
+        let logger = CliLogger { level: LevelFilter::Trace, format: LogFormat::Json };
+        let line = format_message(&logger, log::Level::Warn, "Include cycle");
+        let event: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(event["level"], "WARN");
+        assert_eq!(event["target"], "rstparser::processor");
+        assert_eq!(event["message"], "Include cycle");
+        assert!(event["timestamp"].as_f64().unwrap() > 0.0);
+    }
+}
@@ -1,12 +1,138 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Range;
+use indexmap::IndexMap;
+use log::debug;
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Directive {
     pub name: String,
     pub arguments: String,
-    pub options: HashMap<String, String>,
+    /// Options in the order they appear in the source
+    pub options: IndexMap<String, String>,
     pub content: String,
+    /// Comment lines between the options, without their `..`; only collected with
+    /// `ParseOptions::keep_option_comments`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<String>,
+}
+
+impl Directive {
+    /// A directive named `name` without arguments, options or content, to be completed with
+    /// the `with_*` methods.
+    pub fn new(name: &str) -> Self {
+        Directive {
+            name: name.to_string(),
+            arguments: String::new(),
+            options: IndexMap::new(),
+            content: String::new(),
+            comments: Vec::new(),
+        }
+    }
+
+    /// Set the arguments, i.e. the text after `::` on the directive's first line.
+    pub fn with_argument(mut self, arguments: &str) -> Self {
+        self.arguments = arguments.to_string();
+        self
+    }
+
+    /// Add an option, replacing any previous value for `key`.
+    pub fn with_option(mut self, key: &str, value: &str) -> Self {
+        self.options.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Set the content.
+    pub fn with_content(mut self, content: &str) -> Self {
+        self.content = content.to_string();
+        self
+    }
+
+    /// Options as `(key, value)` pairs sorted by key, for order-independent comparisons.
+    fn sorted_options(&self) -> Vec<(&String, &String)> {
+        let mut options: Vec<(&String, &String)> = self.options.iter().collect();
+        options.sort();
+        options
+    }
+}
+
+/// Number of characters of the content shown by `Display`.
+const CONTENT_PREVIEW_CHARS: usize = 30;
+
+/// A single line for logs: `.. name:: arguments (N options, "content preview...")`, with the
+/// content cut after `CONTENT_PREVIEW_CHARS` characters and left out if empty.
+impl fmt::Display for Directive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, ".. {}::", self.name)?;
+        if !self.arguments.is_empty() {
+            write!(f, " {}", self.arguments)?;
+        }
+        let plural = if self.options.len() == 1 { "" } else { "s" };
+        write!(f, " ({} option{}", self.options.len(), plural)?;
+        if !self.content.is_empty() {
+            let mut preview: String = self.content.chars().take(CONTENT_PREVIEW_CHARS).collect();
+            if preview.len() < self.content.len() {
+                preview.push_str("...");
+            }
+            write!(f, ", {:?}", preview)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Directives are ordered lexicographically by name, arguments, options (sorted by key), content
+/// and option comments.
+impl Ord for Directive {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name
+            .cmp(&other.name)
+            .then_with(|| self.arguments.cmp(&other.arguments))
+            .then_with(|| self.sorted_options().cmp(&other.sorted_options()))
+            .then_with(|| self.content.cmp(&other.content))
+            .then_with(|| self.comments.cmp(&other.comments))
+    }
+}
+
+impl PartialOrd for Directive {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Options controlling how directives are parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Strip trailing blank lines from directive content (default `true`).
+    /// Disable for directives like `code-block` where trailing blank lines are significant.
+    pub trim_content: bool,
+    /// Lowercase option keys (`:Status:` becomes `status`); values are kept verbatim (default `false`).
+    pub normalize_option_keys: bool,
+    /// Only recognize directives whose `..` starts at column `base_indent`, so indented
+    /// examples inside directive content are never picked up (default `false`).
+    pub top_level_only: bool,
+    /// Indentation of top-level directives, in spaces, used with `top_level_only` (default `0`).
+    pub base_indent: usize,
+    /// Collect comment lines (`.. text`) between the options into `Directive::comments` instead
+    /// of ending the options on them (default `false`).
+    pub keep_option_comments: bool,
+    /// Joins the values of an option key given several times, e.g. `:see:` on three lines,
+    /// in order with this separator (default `", "`, which link fields split like any other
+    /// comma-separated list). With `None`, the last value replaces the earlier ones.
+    pub repeated_option_separator: Option<String>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            trim_content: true,
+            normalize_option_keys: false,
+            top_level_only: false,
+            base_indent: 0,
+            keep_option_comments: false,
+            repeated_option_separator: Some(", ".to_string()),
+        }
+    }
 }
 
 /// Parses the body of a directive, given the text slice that starts immediately *after*
@@ -14,13 +140,27 @@ pub struct Directive {
 ///
 /// # Arguments
 /// * `text_after_marker` - The text slice beginning with the directive's arguments (if any)
-///                         on the first line, followed by options and content.
+///   on the first line, followed by options and content.
 /// * `directive_name` - The name of the directive being parsed.
+/// * `parse_options` - Options controlling how the body is parsed.
+///
+/// * `literal_zones` - The [`literal_zones`] of the text `text_after_marker` is taken from,
+///   which starts at byte `body_offset` of it. Directive lines in them do not end the content.
+///
+/// Also returns the length of the body: the bytes of `text_after_marker` up to the end of its
+/// last non-blank line that belongs to the directive, including the line break.
 fn parse_directive_body(
     text_after_marker: &str,
     directive_name: String,
-) -> Directive {
-    let mut options = HashMap::new();
+    parse_options: &ParseOptions,
+    literal_zones: &[Range<usize>],
+    body_offset: usize,
+) -> (Directive, usize) {
+    #[cfg(test)]
+    tests::PARSED_BODIES.with(|count| count.set(count.get() + 1));
+
+    let mut options: IndexMap<String, String> = IndexMap::new();
+    let mut comments = Vec::new();
     let mut content_lines = Vec::new();
     let mut in_options = true;
 
@@ -29,12 +169,12 @@ fn parse_directive_body(
         .find('\n')
         .map_or(text_after_marker.len(), |pos| pos);
     let arguments = text_after_marker[..first_line_end].trim().to_string();
+    let mut body_len = text_after_marker.len().min(first_line_end + 1);
 
     let mut block_indentation: Option<usize> = None;
 
     // Determine block_indentation from the first non-empty line after the argument line.
-    let mut temp_lines_iter = text_after_marker.lines().skip(1).peekable(); // Skip argument line
-    while let Some(line_str) = temp_lines_iter.next() {
+    for line_str in text_after_marker.lines().skip(1) { // Skip argument line
         let trimmed_line_for_indent_check = line_str.trim_start();
         if !trimmed_line_for_indent_check.is_empty() {
             block_indentation = Some(line_str.len() - trimmed_line_for_indent_check.len());
@@ -42,47 +182,76 @@ fn parse_directive_body(
         }
     }
 
-    let mut lines_iter = text_after_marker.lines().skip(1).peekable(); // Skip argument line
+    // Like `lines()`, but each line comes with the offsets of its start and of its end,
+    // including the line break
+    let mut lines_iter = text_after_marker
+        .split_inclusive('\n')
+        .scan(0, |line_end, line| {
+            let line_start = *line_end;
+            *line_end += line.len();
+            let line = line.strip_suffix('\n').map_or(line, |line| line.strip_suffix('\r').unwrap_or(line));
+            Some((line, line_start, *line_end))
+        })
+        .skip(1) // Skip argument line
+        .peekable();
 
-    while let Some(line_str) = lines_iter.next() {
+    while let Some((line_str, line_start, line_end)) = lines_iter.next() {
         let original_line_for_content = line_str.to_string();
         let current_indentation = line_str.len() - line_str.trim_start().len();
         let trimmed_line = line_str.trim();
 
         if in_options {
-            if trimmed_line.starts_with(':') {
+            if parse_options.keep_option_comments && !options.is_empty()
+                && let Some(comment) = option_comment(trimmed_line)
+            {
+                // Lines indented below the comment continue it
+                let mut comment_parts = vec![comment.to_string()];
+                body_len = line_end;
+                while let Some(&(next_line, _, next_line_end)) = lines_iter.peek() {
+                    let next_trimmed_line = next_line.trim();
+                    if next_trimmed_line.is_empty() || next_line.len() - next_line.trim_start().len() <= current_indentation {
+                        break;
+                    }
+                    comment_parts.push(next_trimmed_line.to_string());
+                    body_len = next_line_end;
+                    lines_iter.next();
+                }
+                comments.push(comment_parts.join("\n").trim_start().to_string());
+                continue;
+            }
+            if let Some(option_body) = trimmed_line.strip_prefix(':') {
                 let option_line_indentation = current_indentation;
-                let mut parts_iter = trimmed_line[1..].splitn(2, ':');
+                let mut parts_iter = option_body.splitn(2, ':');
                 if let (Some(key_str), Some(value_str)) = (parts_iter.next(), parts_iter.next()) {
-                    let key = key_str.trim().to_string();
+                    let key = if parse_options.normalize_option_keys {
+                        key_str.trim().to_lowercase()
+                    } else {
+                        key_str.trim().to_string()
+                    };
                     let mut value_parts = vec![value_str.trim_start().to_string()];
+                    body_len = line_end;
+
+                    while let Some(&(next_line_original, _, next_line_end)) = lines_iter.peek() {
+                        let next_line_indent = next_line_original.len()
+                            - next_line_original.trim_start().len();
+                        let next_trimmed_line = next_line_original.trim();
+
+                        // If the next line looks like a new option, stop collecting for current option's value
+                        if next_trimmed_line.strip_prefix(':').is_some_and(|rest| rest.contains(':')) {
+                            // Check if it's indented enough to be part of *this* directive's options,
+                            // or if it's less indented (could be a new directive or unrelated text)
+                            // For now, any new valid option format line terminates current option value.
+                            break;
+                        }
 
-                    loop {
-                        match lines_iter.peek() {
-                            Some(next_line_peek_str) => {
-                                let next_line_original = *next_line_peek_str;
-                                let next_line_indent = next_line_original.len()
-                                    - next_line_original.trim_start().len();
-                                let next_trimmed_line = next_line_original.trim();
-
-                                // If the next line looks like a new option, stop collecting for current option's value
-                                if next_trimmed_line.starts_with(':') && next_trimmed_line[1..].contains(':') {
-                                    // Check if it's indented enough to be part of *this* directive's options,
-                                    // or if it's less indented (could be a new directive or unrelated text)
-                                    // For now, any new valid option format line terminates current option value.
-                                    break;
-                                }
-
-                                if !next_trimmed_line.is_empty()
-                                    && next_line_indent > option_line_indentation
-                                {
-                                    value_parts.push(next_trimmed_line.to_string());
-                                    lines_iter.next(); 
-                                } else {
-                                    break; 
-                                }
-                            }
-                            None => break,
+                        if !next_trimmed_line.is_empty()
+                            && next_line_indent > option_line_indentation
+                        {
+                            value_parts.push(next_trimmed_line.to_string());
+                            body_len = next_line_end;
+                            lines_iter.next();
+                        } else {
+                            break; 
                         }
                     }
                     let final_value = if value_parts.len() > 1 && value_parts[0].is_empty() {
@@ -90,7 +259,15 @@ fn parse_directive_body(
                     } else {
                         value_parts.join("\n")
                     };
-                    options.insert(key, final_value);
+                    match (options.get_mut(&key), &parse_options.repeated_option_separator) {
+                        (Some(earlier_value), Some(separator)) => {
+                            earlier_value.push_str(separator);
+                            earlier_value.push_str(&final_value);
+                        }
+                        _ => {
+                            options.insert(key, final_value);
+                        }
+                    }
                     continue;
                 } else {
                     in_options = false;
@@ -103,16 +280,22 @@ fn parse_directive_body(
             }
         }
 
-        if trimmed_line.starts_with(".. ") && trimmed_line.contains("::") {
+        // Any directive line ends the content, except nested ones when only top-level
+        // directives are recognized, and literal text.
+        let is_nested_line = parse_options.top_level_only && current_indentation > parse_options.base_indent;
+        let is_literal_line = in_literal_zone(literal_zones, body_offset + line_start);
+        if trimmed_line.starts_with(".. ") && trimmed_line.contains("::") && !is_nested_line && !is_literal_line {
             break;
         }
 
-        let part_of_content_block = block_indentation.map_or(
-            true, 
+        let part_of_content_block = block_indentation.is_none_or(
             |indent| current_indentation >= indent || trimmed_line.is_empty(),
         );
 
         if part_of_content_block {
+            if !trimmed_line.is_empty() {
+                body_len = line_end;
+            }
             content_lines.push(original_line_for_content);
         } else if !trimmed_line.is_empty() {
             break;
@@ -144,19 +327,35 @@ fn parse_directive_body(
         })
         .collect();
 
-    while processed_content_lines
-        .last()
-        .map_or(false, |l| l.trim().is_empty())
-    {
-        processed_content_lines.pop();
+    if parse_options.trim_content {
+        while processed_content_lines
+            .last()
+            .is_some_and(|l| l.trim().is_empty())
+        {
+            processed_content_lines.pop();
+        }
     }
 
-    Directive {
+    let directive = Directive {
         name: directive_name,
         arguments,
         options,
         content: processed_content_lines.join("\n"),
+        comments,
+    };
+    (directive, body_len)
+}
+
+/// The text of `trimmed_line` if it is a comment: `..` followed by whitespace or nothing, but
+/// neither a directive nor a hyperlink target.
+fn option_comment(trimmed_line: &str) -> Option<&str> {
+    let text = trimmed_line.strip_prefix("..")?;
+    if text.is_empty() {
+        return Some(text);
     }
+    let text = text.strip_prefix(' ')?.trim();
+    let is_markup = text.starts_with('_') || text.contains("::");
+    (!is_markup).then_some(text)
 }
 
 // Helper function to check for valid directive name characters.
@@ -167,84 +366,566 @@ fn is_valid_directive_char_for_name(c: char) -> bool {
     // No space allowed here based on user feedback for strict RST.
 }
 
+/// Option under which the Sphinx domain of a domain-qualified directive is stored,
+/// e.g. `c` for `.. c:function::`.
+pub const DOMAIN_OPTION: &str = "_domain";
+
+/// Name of the synthetic directives for hyperlink targets such as `.. _REQ-123:`. The target
+/// name is in the arguments and the `id` option, the URL of an external target in the content.
+/// Search for this name like for any other directive to find the targets of a document.
+pub const TARGET_DIRECTIVE: &str = "_target";
+
+/// Byte ranges of the literal blocks of `text`: the lines indented below a paragraph that ends
+/// with `::` and a blank line, up to the first non-blank line that is not indented further than
+/// the paragraph. Their contents are literal, so `.. name::` lines in them are not directives
+/// and are skipped by all `parse_rst_*` functions. `.. name::` lines themselves never introduce
+/// a literal block. The ranges are sorted and run from the start of the first line of a block
+/// to the end of its last non-blank line, including the line break.
+pub fn literal_zones(text: &str) -> Vec<Range<usize>> {
+    let mut zones = Vec::new();
+    // Indentation of the paragraph introducing the open literal block, and the block so far
+    let mut open_zone: Option<(usize, Range<usize>)> = None;
+    // Indentation of a paragraph that ended with `::`, and whether a blank line followed it
+    let mut introducer: Option<(usize, bool)> = None;
+    let mut line_start = 0;
+    for raw_line in text.split_inclusive('\n') {
+        let line_range = line_start..line_start + raw_line.len();
+        line_start = line_range.end;
+        let line = raw_line.trim_end();
+        let trimmed = line.trim_start_matches(' ');
+        if trimmed.is_empty() {
+            if let Some((_, blank_seen)) = &mut introducer {
+                *blank_seen = true;
+            }
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        if let Some((paragraph_indent, zone)) = &mut open_zone {
+            if indent > *paragraph_indent {
+                zone.end = line_range.end;
+                continue;
+            }
+            zones.push(zone.clone());
+            open_zone = None;
+        }
+        if let Some((paragraph_indent, true)) = introducer.take()
+            && indent > paragraph_indent
+        {
+            open_zone = Some((paragraph_indent, line_range));
+            continue;
+        }
+        if trimmed.ends_with("::") && !trimmed.starts_with(".. ") {
+            introducer = Some((indent, false));
+        }
+    }
+    zones.extend(open_zone.map(|(_, zone)| zone));
+    zones
+}
+
+/// Whether byte offset `pos` is in one of the sorted `zones`.
+fn in_literal_zone(zones: &[Range<usize>], pos: usize) -> bool {
+    let next_zone = zones.partition_point(|zone| zone.end <= pos);
+    zones.get(next_zone).is_some_and(|zone| zone.contains(&pos))
+}
+
+/// A directive to search for: a plain name such as `"note"`, or a `(name, domain)` pair such as
+/// `("function", Some("c"))`, which matches the Sphinx domain directive `.. c:function::`.
+/// A target without a domain only matches the unqualified directive.
+pub trait DirectiveTarget {
+    /// The unqualified directive name.
+    fn name(&self) -> &str;
+    /// The Sphinx domain the directive must be qualified with, if any.
+    fn domain(&self) -> Option<&str>;
+
+    /// Whether a directive `name`, qualified with `domain`, is this target.
+    fn matches(&self, name: &str, domain: Option<&str>) -> bool {
+        self.name() == name && self.domain() == domain
+    }
+
+    /// Cheap pre-filter: whether `text` can contain this target at all.
+    fn may_occur_in(&self, text: &str) -> bool {
+        match self.name() {
+            "" => false,
+            TARGET_DIRECTIVE => text.contains(".. _"),
+            name => text.contains(name),
+        }
+    }
+}
+
+impl DirectiveTarget for &str {
+    fn name(&self) -> &str {
+        self
+    }
+
+    fn domain(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl DirectiveTarget for (&str, Option<&str>) {
+    fn name(&self) -> &str {
+        self.0
+    }
+
+    fn domain(&self) -> Option<&str> {
+        self.1
+    }
+}
+
+/// Matches every directive, with or without domain, and every hyperlink target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnyDirective;
+
+impl DirectiveTarget for AnyDirective {
+    fn name(&self) -> &str {
+        ""
+    }
+
+    fn domain(&self) -> Option<&str> {
+        None
+    }
+
+    fn matches(&self, _name: &str, _domain: Option<&str>) -> bool {
+        true
+    }
+
+    fn may_occur_in(&self, text: &str) -> bool {
+        text.contains(".. ")
+    }
+}
+
+/// Finds all directives of `text`, whatever their name, and its hyperlink targets as
+/// [`TARGET_DIRECTIVE`] directives, in source-document order with their line numbers.
+pub fn parse_rst_any(text: &str) -> Vec<(Directive, usize)> {
+    parse_rst_multiple(text, &[AnyDirective])
+}
+
+/// Finds the first `target_directive` in `text`, qualified with `domain` if given: with
+/// `domain = Some("c")`, `"function"` matches `.. c:function::` but not `.. function::`.
+/// The directive name is returned unqualified, with the domain in the [`DOMAIN_OPTION`] option.
+pub fn parse_rst_with_domain(text: &str, target_directive: &str, domain: Option<&str>) -> Option<(Directive, usize)> {
+    DirectiveIter::new(text, &[(target_directive, domain)], ParseOptions::default())
+        .next()
+        .map(|spanned| (spanned.directive, spanned.line_number))
+}
+
+/// What is malformed about a directive body, see [`ParseError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A line of the options starts with `:` but has no `:` closing the key, e.g. `:status open`.
+    MalformedOption,
+    /// Options and content lines are mixed without a blank line between them.
+    ContentInOptions,
+    /// A line of the directive block is indented less than its first line, but still more
+    /// than the directive marker.
+    InconsistentIndentation,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParseErrorKind::MalformedOption => "option line without a closing ':' after the key",
+            ParseErrorKind::ContentInOptions => "content mixed with options without a blank line",
+            ParseErrorKind::InconsistentIndentation => "line indented less than the start of the directive block",
+        })
+    }
+}
+
+/// A malformed directive body found by [`parse_rst_strict`], at a one-based line and column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub col: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Like [`parse_rst_with_domain`] without a domain, but fails instead of silently skipping or
+/// reinterpreting malformed lines of the found directive's body: a malformed option line,
+/// options mixed with content before the first blank line, or a line indented less than the
+/// first line of the block (but more than the marker), which would otherwise end the directive.
+pub fn parse_rst_strict(text: &str, target_directive: &str) -> Result<Option<(Directive, usize)>, ParseError> {
+    let Some(spanned) = DirectiveIter::new(text, &[target_directive], ParseOptions::default()).next() else {
+        return Ok(None);
+    };
+    if spanned.directive.name != TARGET_DIRECTIVE {
+        check_directive_block(text, spanned.byte_range.start, spanned.line_number)?;
+    }
+    Ok(Some((spanned.directive, spanned.line_number)))
+}
+
+/// Checks the body of the directive whose marker starts at byte `marker_start`, on line
+/// `marker_line`, for [`parse_rst_strict`]. The block ends before the first non-blank line that
+/// is not indented further than the marker.
+fn check_directive_block(text: &str, marker_start: usize, marker_line: usize) -> Result<(), ParseError> {
+    let line_start = text[..marker_start].rfind('\n').map_or(0, |pos| pos + 1);
+    let marker_column = marker_start - line_start;
+    let error = |line_index: usize, column: usize, kind: ParseErrorKind| ParseError { line: marker_line + line_index, col: column + 1, kind };
+
+    let mut block_indent: Option<usize> = None;
+    let mut in_options_region = true;
+    let (mut has_options, mut has_content) = (false, false);
+    // Indentation of the last option line, whose value may continue on deeper indented lines
+    let mut option_indent: Option<usize> = None;
+    for (line_index, line) in text[marker_start..].lines().enumerate().skip(1) {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            in_options_region = false;
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        if indent <= marker_column {
+            break;
+        }
+        let block_indent = *block_indent.get_or_insert(indent);
+        if indent < block_indent {
+            return Err(error(line_index, indent, ParseErrorKind::InconsistentIndentation));
+        }
+        if !in_options_region {
+            continue;
+        }
+        if option_indent.is_some_and(|option_indent| indent > option_indent) {
+            continue; // Continuation of an option value
+        }
+        if let Some(option_body) = trimmed.strip_prefix(':') {
+            if !option_body.contains(':') {
+                return Err(error(line_index, indent, ParseErrorKind::MalformedOption));
+            }
+            if has_content {
+                return Err(error(line_index, indent, ParseErrorKind::ContentInOptions));
+            }
+            has_options = true;
+            option_indent = Some(indent);
+        } else if has_options {
+            return Err(error(line_index, indent, ParseErrorKind::ContentInOptions));
+        } else {
+            has_content = true;
+        }
+    }
+    Ok(())
+}
+
 /// Parse a reStructuredText string and find all occurrences of any directive in the provided list.
 /// Performs a single pass over the text for efficiency.
-/// Returns a vector of all found directives with their line numbers, in the order they appear.
-pub fn parse_rst_multiple(text: &str, target_directives: &[&str]) -> Vec<(Directive, usize)> {
-    let mut found_directives_with_pos = Vec::new();
-    let mut current_pos = 0;
+/// Returns a vector of all found directives with their line numbers: the one-based number of
+/// the line holding the directive's `.. name::` marker. The result is always in
+/// source-document order. Targets are plain names or `(name, domain)` pairs, see [`DirectiveTarget`].
+pub fn parse_rst_multiple<T: DirectiveTarget>(text: &str, target_directives: &[T]) -> Vec<(Directive, usize)> {
+    parse_rst_multiple_with_options(text, target_directives, &ParseOptions::default())
+}
+
+/// Same as [`parse_rst_multiple`], but with explicit [`ParseOptions`].
+pub fn parse_rst_multiple_with_options<T: DirectiveTarget>(
+    text: &str,
+    target_directives: &[T],
+    parse_options: &ParseOptions,
+) -> Vec<(Directive, usize)> {
+    DirectiveIter::new(text, target_directives, parse_options.clone())
+        .map(|spanned| (spanned.directive, spanned.line_number))
+        .collect()
+}
+
+/// Same as [`parse_rst_multiple_with_options`], but additionally returns the nesting depth of
+/// each directive: the number of directives (of any name) it is indented inside, 0 at top level.
+pub fn parse_rst_multiple_with_depth<T: DirectiveTarget>(
+    text: &str,
+    target_directives: &[T],
+    parse_options: &ParseOptions,
+) -> Vec<(Directive, usize, usize)> {
+    DirectiveIter::new(text, target_directives, parse_options.clone())
+        .map(|spanned| (spanned.directive, spanned.line_number, spanned.depth))
+        .collect()
+}
+
+/// A directive found by [`parse_rst_spanned`], with where it is in the parsed text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpannedDirective {
+    pub directive: Directive,
+    /// One-based line of the `.. name::` marker.
+    pub line_number: usize,
+    /// Nesting depth, see [`parse_rst_multiple_with_depth`].
+    pub depth: usize,
+    /// Bytes of the directive block: from the `..` of its marker to the end of its last
+    /// non-blank line, including the line break. `&text[byte_range]` is the block as written,
+    /// e.g. to replace it in an editor.
+    pub byte_range: Range<usize>,
+}
+
+/// Same as [`parse_rst_multiple_with_depth`], but additionally returns the byte range of each
+/// directive block in `text`.
+pub fn parse_rst_spanned<T: DirectiveTarget>(
+    text: &str,
+    target_directives: &[T],
+    parse_options: &ParseOptions,
+) -> Vec<SpannedDirective> {
+    DirectiveIter::new(text, target_directives, parse_options.clone()).collect()
+}
+
+/// Lazily yields the directives of [`parse_rst_multiple`], in source-document order, with
+/// their line numbers. Each directive body is only parsed when the iterator reaches it, so
+/// stopping early (e.g. with `find` or `take`) skips the rest of the document.
+pub fn iter_directives<'a, T: DirectiveTarget>(text: &'a str, target_directives: &'a [T]) -> impl Iterator<Item = (Directive, usize)> + 'a {
+    DirectiveIter::new(text, target_directives, ParseOptions::default())
+        .map(|spanned| (spanned.directive, spanned.line_number))
+}
+
+/// Iterator behind [`iter_directives`] and the `parse_rst_*` functions, yielding each
+/// directive with its line number, nesting depth and byte range.
+struct DirectiveIter<'a, T> {
+    text: &'a str,
+    target_directives: &'a [T],
+    parse_options: ParseOptions,
+    /// Byte offset from which to search for the next marker.
+    pos: usize,
+    /// One-based line number at byte offset `line_pos`, so that line numbers are counted
+    /// incrementally: each marker adds the `\n`s between the previous marker and itself.
+    line_number: usize,
+    line_pos: usize,
+    /// Marker indentation of the directives enclosing the scan position, innermost last.
+    open_indents: Vec<usize>,
+    /// Start of the first line not yet taken into account for `open_indents`.
+    depth_pos: usize,
+    /// The [`literal_zones`] of `text`, computed when the first marker is found.
+    literal_zones: Option<Vec<Range<usize>>>,
+}
+
+impl<'a, T: DirectiveTarget> DirectiveIter<'a, T> {
+    fn new(text: &'a str, target_directives: &'a [T], parse_options: ParseOptions) -> Self {
+        DirectiveIter { text, target_directives, parse_options, pos: 0, line_number: 1, line_pos: 0, open_indents: Vec::new(), depth_pos: 0, literal_zones: None }
+    }
+
+    /// Updates `open_indents` with the lines up to the marker at `marker_start` and returns the
+    /// marker's depth. A non-blank line closes every directive indented at least as far, and
+    /// every `.. name::` line, targeted or not, opens one. A hyperlink target marker has no
+    /// body and does not open one (`opens_body`). Lines of literal blocks are ignored.
+    fn depth_of_marker(&mut self, marker_start: usize, opens_body: bool) -> usize {
+        let line_start = self.text[..marker_start].rfind('\n').map_or(0, |pos| pos + 1);
+        if line_start > self.depth_pos {
+            let literal_zones = self.literal_zones.as_deref().unwrap_or_default();
+            let mut next_line_start = self.depth_pos;
+            for raw_line in self.text[self.depth_pos..line_start].split_inclusive('\n') {
+                let current_line_start = next_line_start;
+                next_line_start += raw_line.len();
+                let line = raw_line.trim_end_matches(['\n', '\r']);
+                let trimmed = line.trim_start_matches(' ');
+                if trimmed.trim().is_empty() || in_literal_zone(literal_zones, current_line_start) {
+                    continue;
+                }
+                let indent = line.len() - trimmed.len();
+                self.open_indents.retain(|open_indent| *open_indent < indent);
+                if trimmed.starts_with(".. ") && trimmed.contains("::") {
+                    self.open_indents.push(indent);
+                }
+            }
+        }
+        let indent = marker_start - line_start;
+        self.open_indents.retain(|open_indent| *open_indent < indent);
+        let depth = self.open_indents.len();
+        if opens_body {
+            self.open_indents.push(indent);
+        }
+        self.depth_pos = self.text[marker_start..].find('\n').map_or(self.text.len(), |pos| marker_start + pos + 1);
+        depth
+    }
+}
+
+impl<T: DirectiveTarget> Iterator for DirectiveIter<'_, T> {
+    type Item = SpannedDirective;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(marker) = find_next_directive_marker(self.text, self.pos, self.target_directives) {
+            self.pos = marker.body_start;
+            if self.parse_options.top_level_only && marker_indent(self.text, marker.start) != Some(self.parse_options.base_indent) {
+                continue;
+            }
+            let literal_zones = self.literal_zones.get_or_insert_with(|| literal_zones(self.text));
+            if in_literal_zone(literal_zones, marker.start) {
+                debug!("Skipping `.. {}::` in a literal block at byte {}.", marker.name, marker.start);
+                continue;
+            }
+            self.line_number += self.text[self.line_pos..marker.start].matches('\n').count();
+            self.line_pos = marker.start;
+            let depth = self.depth_of_marker(marker.start, marker.target_url.is_none());
+            if let Some(url) = marker.target_url {
+                let directive = Directive::new(TARGET_DIRECTIVE).with_argument(marker.name).with_option("id", marker.name).with_content(url);
+                // The target's line, including its line break
+                let end = self.text[marker.body_start..].find('\n').map_or(marker.body_start, |pos| marker.body_start + pos + 1);
+                return Some(SpannedDirective { directive, line_number: self.line_number, depth, byte_range: marker.start..end });
+            }
+            let (mut directive, body_len) = parse_directive_body(
+                &self.text[marker.body_start..],
+                marker.name.to_string(),
+                &self.parse_options,
+                self.literal_zones.as_deref().unwrap_or_default(),
+                marker.body_start,
+            );
+            if let Some(domain) = marker.domain {
+                directive.options.insert(DOMAIN_OPTION.to_string(), domain.to_string());
+            }
+            let byte_range = marker.start..marker.body_start + body_len;
+            return Some(SpannedDirective { directive, line_number: self.line_number, depth, byte_range });
+        }
+        self.pos = self.text.len();
+        None
+    }
+}
+
+/// Quickly checks whether `text` contains at least one of the target directives,
+/// without parsing any directive bodies. Returns on the first hit.
+pub fn has_any_directive<T: DirectiveTarget>(text: &str, target_directives: &[T]) -> bool {
+    // Cheap pre-filter: a directive can only be present if its name occurs somewhere.
+    if !target_directives.iter().any(|target| target.may_occur_in(text)) {
+        return false;
+    }
+    let mut literal_block_zones = None;
+    let mut pos = 0;
+    while let Some(marker) = find_next_directive_marker(text, pos, target_directives) {
+        if !in_literal_zone(literal_block_zones.get_or_insert_with(|| literal_zones(text)), marker.start) {
+            return true;
+        }
+        pos = marker.body_start;
+    }
+    false
+}
+
+/// Position of a `.. name::` marker for one of the target directives.
+struct DirectiveMarker<'a> {
+    /// Byte offset of the leading ".. ".
+    start: usize,
+    /// The directive name, without surrounding spaces and without its domain.
+    name: &'a str,
+    /// The Sphinx domain of a `.. domain:name::` marker.
+    domain: Option<&'a str>,
+    /// For a hyperlink target, where `name` is the target name: its URL, empty for internal targets.
+    target_url: Option<&'a str>,
+    /// Byte offset just after the "::", or the end of the line of a hyperlink target.
+    body_start: usize,
+}
+
+/// Parses a hyperlink target line after its `.. _`, such as `REQ-123:` or
+/// `` `a: b`: https://example.com ``. Returns the target name and the URL, empty for internal
+/// targets. Anonymous targets (`.. __:`) have no name to refer to and are not returned.
+fn parse_hyperlink_target(rest_of_line: &str) -> Option<(&str, &str)> {
+    let (name, after_name) = match rest_of_line.strip_prefix('`') {
+        Some(quoted) => {
+            let end = quoted.find("`:")?;
+            (&quoted[..end], &quoted[end + 2..])
+        }
+        None => {
+            // The name ends at the first colon followed by whitespace or the end of the line
+            let end = rest_of_line.match_indices(':').map(|(pos, _)| pos)
+                .find(|&pos| rest_of_line[pos + 1..].chars().next().is_none_or(char::is_whitespace))?;
+            (&rest_of_line[..end], &rest_of_line[end + 1..])
+        }
+    };
+    let name = name.trim();
+    let is_separated = after_name.chars().next().is_none_or(char::is_whitespace);
+    (!name.is_empty() && name != "_" && is_separated).then(|| (name, after_name.trim()))
+}
+
+/// Column of the marker starting at `start`, or `None` if anything but spaces precedes it on its line.
+fn marker_indent(text: &str, start: usize) -> Option<usize> {
+    let line_start = text[..start].rfind('\n').map_or(0, |pos| pos + 1);
+    let prefix = &text[line_start..start];
+    prefix.bytes().all(|b| b == b' ').then_some(prefix.len())
+}
+
+/// Finds the next marker of a target directive starting at byte offset `from`.
+fn find_next_directive_marker<'a, T: DirectiveTarget>(text: &'a str, from: usize, target_directives: &[T]) -> Option<DirectiveMarker<'a>> {
+    let mut current_pos = from;
 
     while current_pos < text.len() {
         // Find the next potential directive start ".. " (must have a space)
-        if let Some(dots_space_offset) = text[current_pos..].find(".. ") {
-            let absolute_dots_space_start = current_pos + dots_space_offset;
-            let potential_directive_line_start = absolute_dots_space_start;
-            let name_search_start_abs = absolute_dots_space_start + 3; // Name starts after ".. "
-
-            // Minimum length for a directive: ".. a::" (6 chars)
-            if name_search_start_abs >= text.len() || absolute_dots_space_start + 6 > text.len() {
-                break;
-            }
+        let dots_space_offset = text[current_pos..].find(".. ")?;
+        let absolute_dots_space_start = current_pos + dots_space_offset;
+        let name_search_start_abs = absolute_dots_space_start + 3; // Name starts after ".. "
 
-            // Determine the end of the current line for searching "::"
-            let end_of_line_offset_from_name_start = text[name_search_start_abs..]
-                .find('\n')
-                .map_or(text.len() - name_search_start_abs, |pos| pos);
-
-            let line_search_slice = &text[name_search_start_abs..name_search_start_abs + end_of_line_offset_from_name_start];
-
-            if let Some(colon_colon_offset_in_slice) = line_search_slice.find("::") {
-                let absolute_colon_colon_start = name_search_start_abs + colon_colon_offset_in_slice;
-                let directive_name_candidate_str = &text[name_search_start_abs..absolute_colon_colon_start];
-                let trimmed_name = directive_name_candidate_str.trim(); // Trim spaces around the name
-
-                // Validate directive name characters (no spaces within the name itself)
-                let is_name_structurally_valid = !trimmed_name.is_empty() &&
-                    !trimmed_name.contains(' ') && // Ensure no internal spaces in the name
-                    trimmed_name.chars().all(is_valid_directive_char_for_name);
-
-                if is_name_structurally_valid && target_directives.contains(&trimmed_name) {
-                    let line_number = text[..potential_directive_line_start].matches('\n').count() + 1;
-                    let directive_body_start_index = absolute_colon_colon_start + 2; // After "::"
-
-                    if directive_body_start_index <= text.len() {
-                        let directive = parse_directive_body(
-                            &text[directive_body_start_index..],
-                            trimmed_name.to_string(),
-                        );
-                        found_directives_with_pos.push((potential_directive_line_start, directive, line_number));
-                    }
-                    current_pos = directive_body_start_index;
-                } else {
-                    // Invalid name, not a target, or malformed, but "::" was found after ".. ".
-                    // Advance past this "::" to avoid reprocessing.
-                    current_pos = absolute_colon_colon_start + 2;
-                }
-            } else {
-                // Found ".. " but no "::" on the same line after the name part.
-                // Advance past the ".. " to continue searching.
-                current_pos = name_search_start_abs; // which is absolute_dots_space_start + 3
+        // Minimum length for a directive: ".. a::" (6 chars)
+        if name_search_start_abs >= text.len() || absolute_dots_space_start + 6 > text.len() {
+            return None;
+        }
+
+        // Determine the end of the current line for searching "::"
+        let end_of_line_offset_from_name_start = text[name_search_start_abs..]
+            .find('\n')
+            .map_or(text.len() - name_search_start_abs, |pos| pos);
+
+        let line_search_slice = &text[name_search_start_abs..name_search_start_abs + end_of_line_offset_from_name_start];
+
+        if let Some(colon_colon_offset_in_slice) = line_search_slice.find("::") {
+            let absolute_colon_colon_start = name_search_start_abs + colon_colon_offset_in_slice;
+            let directive_name_candidate_str = &text[name_search_start_abs..absolute_colon_colon_start];
+            let trimmed_name = directive_name_candidate_str.trim(); // Trim spaces around the name
+            // A Sphinx domain directive is written `domain:name`
+            let (domain, unqualified_name) = match trimmed_name.split_once(':') {
+                Some((domain, name)) => (Some(domain), name),
+                None => (None, trimmed_name),
+            };
+
+            // Validate directive name characters (no spaces within the name itself)
+            let is_valid_name_part = |part: &str| !part.is_empty() &&
+                !part.contains(' ') && // Ensure no internal spaces in the name
+                part.chars().all(is_valid_directive_char_for_name);
+            let is_name_structurally_valid = is_valid_name_part(unqualified_name) && domain.is_none_or(is_valid_name_part);
+
+            let is_target = target_directives.iter().any(|target| target.matches(unqualified_name, domain));
+            if is_name_structurally_valid && is_target {
+                return Some(DirectiveMarker {
+                    start: absolute_dots_space_start,
+                    name: unqualified_name,
+                    domain,
+                    target_url: None,
+                    body_start: absolute_colon_colon_start + 2, // After "::"
+                });
             }
+            // Invalid name, not a target, or malformed, but "::" was found after ".. ".
+            // Advance past this "::" to avoid reprocessing.
+            current_pos = absolute_colon_colon_start + 2;
         } else {
-            // No more ".. " found
-            break;
+            // Found ".. " but no "::" on the same line after the name part. It may still be a
+            // hyperlink target, if it starts its line.
+            let line_end = name_search_start_abs + end_of_line_offset_from_name_start;
+            if let Some(rest_of_line) = line_search_slice.strip_prefix('_')
+                && marker_indent(text, absolute_dots_space_start).is_some()
+                && target_directives.iter().any(|target| target.matches(TARGET_DIRECTIVE, None))
+                && let Some((name, url)) = parse_hyperlink_target(rest_of_line.trim_end())
+            {
+                return Some(DirectiveMarker {
+                    start: absolute_dots_space_start,
+                    name,
+                    domain: None,
+                    target_url: Some(url),
+                    body_start: line_end,
+                });
+            }
+            // Advance past the ".. " to continue searching.
+            current_pos = name_search_start_abs; // which is absolute_dots_space_start + 3
         }
     }
-
-    found_directives_with_pos
-        .into_iter()
-        .map(|(_, directive, line_number)| (directive, line_number))
-        .collect()
+    None
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashMap;
+    use std::cell::Cell;
+    use indexmap::IndexMap;
+
+    thread_local! {
+        /// Number of directive bodies parsed on this thread, to check how much work lazy parsing does.
+        pub(super) static PARSED_BODIES: Cell<usize> = const { Cell::new(0) };
+    }
 
-    // Helper to create a HashMap for options easily in tests
-    fn opts(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    // Helper to create a IndexMap for options easily in tests
+    fn opts(pairs: &[(&str, &str)]) -> IndexMap<String, String> {
         pairs
             .iter()
             .map(|(k, v)| (k.to_string(), v.to_string()))
@@ -253,10 +934,10 @@ mod tests {
     
     // Test assertion helper for a single expected directive
     fn assert_single_directive_eq_props(
-        results: &Vec<(Directive, usize)>,
+        results: &[(Directive, usize)],
         expected_name: &str,
         expected_arguments: &str,
-        expected_options: &HashMap<String, String>,
+        expected_options: &IndexMap<String, String>,
         expected_content: &str,
         expected_line: Option<usize>,
     ) {
@@ -272,7 +953,7 @@ mod tests {
     }
 
     // Test assertion helper for expecting no directives
-    fn assert_no_directives_found(results: &Vec<(Directive, usize)>, directive_name_searched: &str) {
+    fn assert_no_directives_found(results: &[(Directive, usize)], directive_name_searched: &str) {
         assert!(results.is_empty(), "Expected no directives for '{}', found {} ({:?})", directive_name_searched, results.len(), results);
     }
 
@@ -297,6 +978,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_options_keep_source_order() {
+        let rst = ".. mydirective::\n   :zeta: 1\n   :alpha: 2\n   :mid: 3\n";
+        let results = parse_rst_multiple(rst, &["mydirective"]);
+        let keys: Vec<&str> = results[0].0.options.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["zeta", "alpha", "mid"]);
+    }
+
+    #[test]
+    fn test_line_numbers_are_one_based_marker_lines() {
+        let rst = ".. note:: First\n\n\n\n.. note:: After blank lines\n   :class: tip\n\n   Content\n\n\n.. outer::\n\n   .. note:: Nested\n\n.. note::\n";
+        let lines: Vec<usize> = parse_rst_multiple(rst, &["note"]).into_iter().map(|(_, line)| line).collect();
+        assert_eq!(lines, vec![1, 5, 13, 15]);
+
+        let crlf = rst.replace('\n', "\r\n");
+        let lines: Vec<usize> = parse_rst_multiple(&crlf, &["note"]).into_iter().map(|(_, line)| line).collect();
+        assert_eq!(lines, vec![1, 5, 13, 15]);
+
+        let leading_blank_lines = "\n\nText\n\n.. note:: Hi\n";
+        assert_eq!(parse_rst_multiple(leading_blank_lines, &["note"])[0].1, 5);
+        assert_eq!(parse_rst_with_domain(leading_blank_lines, "note", None).unwrap().1, 5);
+        assert_eq!(iter_directives(leading_blank_lines, &["note"]).next().unwrap().1, 5);
+    }
+
     #[test]
     fn test_directive_no_options() {
         let rst = r#"
@@ -309,7 +1014,7 @@ mod tests {
             &results,
             "mydirective",
             "",
-            &HashMap::new(),
+            &IndexMap::new(),
             "This is content without options.",
             Some(2),
         );
@@ -341,7 +1046,7 @@ mod tests {
             &results,
             "mydirective",
             "",
-            &HashMap::new(),
+            &IndexMap::new(),
             "",
             Some(1),
         );
@@ -355,7 +1060,7 @@ mod tests {
             &results,
             "mydirective",
             "",
-            &HashMap::new(),
+            &IndexMap::new(),
             "",
             Some(1),
         );
@@ -447,7 +1152,7 @@ mod tests {
             &results,
             "mydirective",
             "",
-            &HashMap::new(),
+            &IndexMap::new(),
             "Immediately starting content.\nMore content.",
             Some(2),
         );
@@ -487,7 +1192,7 @@ mod tests {
             &results,
             "mydirective",
             "",
-            &HashMap::new(), 
+            &IndexMap::new(), 
             ":option1 value1\n:option2: value2\n\nContent", // Content parsing is greedy
             Some(2),
         );
@@ -522,7 +1227,7 @@ mod tests {
             &results,
             "mydirective",
             "",
-            &HashMap::new(),
+            &IndexMap::new(),
             "Final content.",
             Some(1),
         );
@@ -798,7 +1503,7 @@ mod tests {
             &results,
             "mydirective",
             "just arguments",
-            &HashMap::new(),
+            &IndexMap::new(),
             "",
             Some(1),
         );
@@ -816,7 +1521,7 @@ mod tests {
             &results,
             "mydirective",
             "arguments here",
-            &HashMap::new(),
+            &IndexMap::new(),
             "Content without options.",
             Some(2),
         );
@@ -939,4 +1644,339 @@ Some text in between.
         assert_eq!(results[0].0.name, "realdir");
         assert_eq!(results[0].1, 2); // Line number of ".. realdir::"
     }
+
+    #[test]
+    fn test_trim_content_option() {
+        let rst = ".. code-block:: python\n\n   print('hi')\n\n   print('bye')\n\n\nAfter the directive.\n";
+
+        let trimmed = parse_rst_multiple(rst, &["code-block"]);
+        assert_eq!(trimmed[0].0.content, "print('hi')\n\nprint('bye')");
+
+        let untrimmed_options = ParseOptions { trim_content: false, ..Default::default() };
+        let untrimmed = parse_rst_multiple_with_options(rst, &["code-block"], &untrimmed_options);
+        assert_eq!(untrimmed.len(), 1);
+        assert_eq!(untrimmed[0].0.content, "print('hi')\n\nprint('bye')\n\n");
+        assert_eq!(untrimmed[0].0.arguments, "python");
+    }
+
+    #[test]
+    fn test_has_any_directive() {
+        let rst = "Intro mentioning mydirective in prose.\n\n.. note::\n   A note.\n\n..  other ::\n";
+        assert!(!has_any_directive(rst, &["mydirective"]));
+        assert!(has_any_directive(rst, &["mydirective", "note"]));
+        assert!(has_any_directive(rst, &["other"])); // Spaces around the name are allowed
+        assert!(!has_any_directive(rst, &["not"])); // Substring of "note" is not a match
+        assert!(!has_any_directive("", &["note"]));
+        assert!(!has_any_directive::<&str>(rst, &[]));
+    }
+
+    #[test]
+    fn test_repeated_option_keys_are_joined() {
+        let rst = ".. req:: Multi\n   :see: REQ-1\n   :status: open\n   :see: REQ-2\n   :see:\n      REQ-3\n\n   Content.\n";
+
+        let results = parse_rst_multiple(rst, &["req"]);
+        assert_eq!(results[0].0.options, opts(&[("see", "REQ-1, REQ-2, REQ-3"), ("status", "open")]));
+        assert_eq!(results[0].0.content, "Content.");
+
+        let custom = ParseOptions { repeated_option_separator: Some("\n".to_string()), ..Default::default() };
+        let results = parse_rst_multiple_with_options(rst, &["req"], &custom);
+        assert_eq!(results[0].0.options["see"], "REQ-1\nREQ-2\nREQ-3");
+
+        let last_wins = ParseOptions { repeated_option_separator: None, ..Default::default() };
+        let results = parse_rst_multiple_with_options(rst, &["req"], &last_wins);
+        assert_eq!(results[0].0.options["see"], "REQ-3");
+    }
+
+    #[test]
+    fn test_normalize_option_keys_option() {
+        let rst = ".. mydirective::\n   :Status: Open\n   :ID: Req-1\n";
+
+        let results = parse_rst_multiple(rst, &["mydirective"]);
+        assert_eq!(results[0].0.options, opts(&[("Status", "Open"), ("ID", "Req-1")]));
+
+        let normalizing = ParseOptions { normalize_option_keys: true, ..Default::default() };
+        let results = parse_rst_multiple_with_options(rst, &["mydirective"], &normalizing);
+        assert_eq!(results[0].0.options, opts(&[("status", "Open"), ("id", "Req-1")]));
+    }
+
+    #[test]
+    fn test_top_level_only_option() {
+        let rst = r#".. note:: Outer
+
+   Example usage:
+
+   .. note:: Nested example
+
+      Not a real note.
+
+.. note:: Second
+"#;
+
+        let results = parse_rst_multiple(rst, &["note"]);
+        assert_eq!(results.len(), 3);
+
+        let top_level = ParseOptions { top_level_only: true, ..Default::default() };
+        let results = parse_rst_multiple_with_options(rst, &["note"], &top_level);
+        let arguments: Vec<&str> = results.iter().map(|(d, _)| d.arguments.as_str()).collect();
+        assert_eq!(arguments, vec!["Outer", "Second"]);
+        assert_eq!(results[1].1, 9);
+        assert_eq!(results[0].0.content, "Example usage:\n\n.. note:: Nested example\n\n   Not a real note.");
+    }
+
+    #[test]
+    fn test_top_level_only_with_base_indent() {
+        // E.g. RST extracted from a docstring that was not dedented
+        let rst = "    .. note:: Top\n\n       .. note:: Nested\n\nText .. note:: inline\n";
+        let options = ParseOptions { top_level_only: true, base_indent: 4, ..Default::default() };
+        let results = parse_rst_multiple_with_options(rst, &["note"], &options);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0.arguments, "Top");
+    }
+
+    #[test]
+    fn test_directives_in_literal_blocks_are_skipped() {
+        let rst = r#"Write a note like this::
+
+   .. note:: Example
+
+      Not a real note.
+
+.. note:: Real
+
+   Nested example::
+
+      .. note:: Also an example
+
+   Still part of the real note.
+"#;
+
+        let results = parse_rst_multiple(rst, &["note"]);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0.arguments, "Real");
+        assert_eq!(results[0].1, 7);
+        assert_eq!(results[0].0.content, "Nested example::\n\n   .. note:: Also an example\n\nStill part of the real note.");
+        assert!(parse_rst_with_domain(rst, "note", None).is_some_and(|(d, _)| d.arguments == "Real"));
+        assert_eq!(parse_rst_multiple_with_depth(rst, &["note"], &ParseOptions::default())[0].2, 0);
+        assert!(!has_any_directive("Example::\n\n   .. note:: Example\n", &["note"]));
+
+        let zones = literal_zones(rst);
+        assert_eq!(zones.len(), 2);
+        assert!(rst[zones[0].clone()].starts_with("   .. note:: Example\n"));
+        assert_eq!(&rst[zones[1].clone()], "      .. note:: Also an example\n");
+    }
+
+    #[test]
+    fn test_literal_blocks_need_a_blank_line_and_an_indented_block() {
+        // No blank line after the `::` paragraph, no indented block, and a directive marker line
+        let rst = "Term::\n   .. note:: One\n\nText::\n\n.. note:: Two\n\n.. note:: Three\n\n   .. note:: Four\n";
+        let arguments: Vec<String> = parse_rst_multiple(rst, &["note"]).into_iter().map(|(d, _)| d.arguments).collect();
+        assert_eq!(arguments, vec!["One", "Two", "Three", "Four"]);
+        assert!(literal_zones(rst).is_empty());
+    }
+
+    #[test]
+    fn test_keep_option_comments_option() {
+        let rst = ".. req:: Title\n   :id: R1\n   .. TODO: agree on the status\n      with the reviewers\n   :status: open\n\n   Content.\n";
+
+        let results = parse_rst_multiple(rst, &["req"]);
+        assert_eq!(results[0].0.options, opts(&[("id", "R1")]));
+        assert!(results[0].0.comments.is_empty());
+
+        let keep_comments = ParseOptions { keep_option_comments: true, ..Default::default() };
+        let results = parse_rst_multiple_with_options(rst, &["req"], &keep_comments);
+        let directive = &results[0].0;
+        assert_eq!(directive.options, opts(&[("id", "R1"), ("status", "open")]));
+        assert_eq!(directive.comments, vec!["TODO: agree on the status\nwith the reviewers".to_string()]);
+        assert_eq!(directive.content, "Content.");
+    }
+
+    #[test]
+    fn test_parse_rst_strict_accepts_well_formed_directives() {
+        let rst = "Intro\n\n.. req:: Title\n   :id: R1\n   :links: R2,\n      R3\n\n   Content\n\n      Indented more.\n\nAfter.\n";
+        let (directive, line) = parse_rst_strict(rst, "req").unwrap().unwrap();
+        assert_eq!(line, 3);
+        assert_eq!(directive, parse_rst_multiple(rst, &["req"])[0].0);
+        assert_eq!(parse_rst_strict(rst, "note"), Ok(None));
+        assert!(parse_rst_strict(".. note::\n   Content right away.\n", "note").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_parse_rst_strict_errors() {
+        let error = |line, col, kind| Err(ParseError { line, col, kind });
+        assert_eq!(parse_rst_strict(".. req::\n   :id: R1\n   :status open\n", "req"), error(3, 4, ParseErrorKind::MalformedOption));
+        assert_eq!(parse_rst_strict("\n.. req::\n   :id: R1\n   Content without a blank line.\n", "req"), error(4, 4, ParseErrorKind::ContentInOptions));
+        assert_eq!(parse_rst_strict(".. req::\n   Content first.\n   :id: R1\n", "req"), error(3, 4, ParseErrorKind::ContentInOptions));
+        assert_eq!(parse_rst_strict(".. req::\n\n    Content.\n  Less indented.\n", "req"), error(4, 3, ParseErrorKind::InconsistentIndentation));
+        assert_eq!(
+            parse_rst_strict(".. req::\n   :status open\n", "req").unwrap_err().to_string(),
+            "2:4: option line without a closing ':' after the key",
+        );
+        // The same bodies are silently reinterpreted by the lenient parsers
+        assert_eq!(parse_rst_multiple(".. req::\n\n    Content.\n  Less indented.\n", &["req"])[0].0.content, "Content.");
+    }
+
+    #[test]
+    fn test_iter_directives_matches_parse_rst_multiple() {
+        let rst = ".. note:: First\n\n.. warning::\n   :level: high\n\n   Careful.\n\n.. note:: Second\n";
+        let targets = ["note", "warning"];
+        let lazy: Vec<(Directive, usize)> = iter_directives(rst, &targets).collect();
+        assert_eq!(lazy, parse_rst_multiple(rst, &targets));
+        assert_eq!(lazy.iter().map(|(_, line)| *line).collect::<Vec<_>>(), vec![1, 3, 8]);
+    }
+
+    #[test]
+    fn test_iter_directives_stops_early() {
+        let rst: String = (0..1000).map(|i| format!(".. note:: Note {}\n\n", i)).collect();
+        let targets = ["note"];
+
+        PARSED_BODIES.with(|count| count.set(0));
+        let first: Vec<(Directive, usize)> = iter_directives(&rst, &targets).take(1).collect();
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].0.arguments, "Note 0");
+        assert_eq!(PARSED_BODIES.with(Cell::get), 1);
+
+        let found = iter_directives(&rst, &targets).find(|(directive, _)| directive.arguments == "Note 2");
+        assert_eq!(found.map(|(_, line)| line), Some(5));
+        assert_eq!(PARSED_BODIES.with(Cell::get), 4);
+    }
+
+    #[test]
+    fn test_parse_rst_multiple_with_depth() {
+        let rst = r#".. note:: Outer
+
+   .. warning:: Nested
+
+      .. tip:: Nested twice
+
+   .. only:: html
+
+      .. tip:: Inside a non-target directive
+
+Paragraph.
+
+.. tip:: Top level again
+"#;
+        let depths: Vec<(String, usize)> = parse_rst_multiple_with_depth(rst, &["note", "warning", "tip"], &ParseOptions::default())
+            .into_iter()
+            .map(|(directive, _, depth)| (directive.arguments, depth))
+            .collect();
+        assert_eq!(depths, vec![
+            ("Outer".to_string(), 0),
+            ("Nested".to_string(), 1),
+            ("Nested twice".to_string(), 2),
+            ("Inside a non-target directive".to_string(), 2),
+            ("Top level again".to_string(), 0),
+        ]);
+    }
+
+    #[test]
+    fn test_byte_ranges_reconstruct_directive_blocks() {
+        let rst = "Intro.\n\n.. note:: Title\n   :class: tip\n   :links: a,\n      b\n\n   Content\n\n   More content\n\n\n.. _REQ-1:\n\n.. warning::\n\n   Careful.\n\n   .. tip:: Nested\n\n      Tip text.\n\nParagraph.\n.. tip::";
+        let blocks: Vec<&str> = parse_rst_spanned(rst, &["note", "warning", "tip", TARGET_DIRECTIVE], &ParseOptions::default())
+            .into_iter()
+            .map(|spanned| &rst[spanned.byte_range])
+            .collect();
+        assert_eq!(blocks, vec![
+            ".. note:: Title\n   :class: tip\n   :links: a,\n      b\n\n   Content\n\n   More content\n",
+            ".. _REQ-1:\n",
+            // A nested directive ends the content of the enclosing one
+            ".. warning::\n\n   Careful.\n",
+            ".. tip:: Nested\n\n      Tip text.\n",
+            ".. tip::",
+        ]);
+
+        let crlf = ".. note:: Title\r\n   :class: tip\r\n\r\n.. note::\r\n\r\n   Content\r\n";
+        let blocks: Vec<&str> = parse_rst_spanned(crlf, &["note"], &ParseOptions::default())
+            .into_iter()
+            .map(|spanned| &crlf[spanned.byte_range])
+            .collect();
+        assert_eq!(blocks, vec![".. note:: Title\r\n   :class: tip\r\n", ".. note::\r\n\r\n   Content\r\n"]);
+    }
+
+    #[test]
+    fn test_parse_rst_with_domain() {
+        let rst = ".. function:: plain()\n\n.. c:function:: int f(void)\n   :noindex:\n\n.. py:function:: g()\n";
+
+        let (directive, line) = parse_rst_with_domain(rst, "function", Some("c")).unwrap();
+        assert_eq!(line, 3);
+        assert_eq!(directive.name, "function");
+        assert_eq!(directive.arguments, "int f(void)");
+        assert_eq!(directive.options, opts(&[("noindex", ""), (DOMAIN_OPTION, "c")]));
+
+        assert!(parse_rst_with_domain(rst, "function", Some("cpp")).is_none());
+        assert!(parse_rst_with_domain(rst, "class", Some("c")).is_none());
+
+        // A target without a domain only matches the unqualified directive
+        let (directive, line) = parse_rst_with_domain(rst, "function", None).unwrap();
+        assert_eq!((directive.arguments.as_str(), line), ("plain()", 1));
+        assert!(!directive.options.contains_key(DOMAIN_OPTION));
+    }
+
+    #[test]
+    fn test_parse_rst_multiple_with_domain_targets() {
+        let rst = ".. py:class:: A\n\n.. note:: N\n\n.. class:: B\n";
+        let results = parse_rst_multiple(rst, &[("class", Some("py")), ("note", None)]);
+        let found: Vec<(&str, &str, Option<&String>)> = results.iter()
+            .map(|(directive, _)| (directive.name.as_str(), directive.arguments.as_str(), directive.options.get(DOMAIN_OPTION)))
+            .collect();
+        assert_eq!(found, vec![("class", "A", Some(&"py".to_string())), ("note", "N", None)]);
+        assert!(has_any_directive(rst, &[("class", Some("py"))]));
+        assert!(!has_any_directive(rst, &[("class", Some("c"))]));
+    }
+
+    #[test]
+    fn test_directive_builder() {
+        let built = Directive::new("note")
+            .with_argument("Title")
+            .with_option("class", "old")
+            .with_option("class", "tip")
+            .with_content("Body");
+        assert_eq!(built, Directive {
+            name: "note".to_string(),
+            arguments: "Title".to_string(),
+            options: opts(&[("class", "tip")]),
+            content: "Body".to_string(),
+            comments: Vec::new(),
+        });
+        assert_eq!(parse_rst_multiple(".. note:: Title\n   :class: tip\n\n   Body\n", &["note"])[0].0, built);
+    }
+
+    #[test]
+    fn test_directive_display() {
+        let directive = Directive::new("req")
+            .with_argument("Parse input")
+            .with_option("id", "REQ-1")
+            .with_option("status", "open")
+            .with_content("The parser reads directives.\nIt keeps their options.");
+        assert_eq!(format!("{}", directive), r#".. req:: Parse input (2 options, "The parser reads directives.\nI...")"#);
+        assert_eq!(format!("{}", Directive::new("note").with_option("class", "tip")), ".. note:: (1 option)");
+        assert_eq!(format!("{}", Directive::new("note").with_content("Short.")), r#".. note:: (0 options, "Short.")"#);
+    }
+
+    #[test]
+    fn test_hyperlink_target() {
+        let rst = "Intro\n\n.. _REQ-123:\n\n.. req:: First\n";
+        let results = parse_rst_any(rst);
+        assert_eq!(results, vec![
+            (Directive::new(TARGET_DIRECTIVE).with_argument("REQ-123").with_option("id", "REQ-123"), 3),
+            (Directive::new("req").with_argument("First"), 5),
+        ]);
+        assert_eq!(parse_rst_multiple(rst, &[TARGET_DIRECTIVE]).len(), 1);
+        assert!(parse_rst_multiple(rst, &["req"]).iter().all(|(directive, _)| directive.name == "req"));
+        assert!(has_any_directive(rst, &[TARGET_DIRECTIVE]));
+        assert!(!has_any_directive(".. req:: Only\n", &[TARGET_DIRECTIVE]));
+    }
+
+    #[test]
+    fn test_multi_word_and_external_hyperlink_targets() {
+        let rst = ".. _some target:\n.. _`a: b`:\n.. _Python: https://www.python.org\n.. __: https://anonymous.example\n";
+        let targets: Vec<(String, String, usize)> = parse_rst_any(rst).into_iter()
+            .map(|(directive, line)| (directive.arguments, directive.content, line))
+            .collect();
+        assert_eq!(targets, vec![
+            ("some target".to_string(), String::new(), 1),
+            ("a: b".to_string(), String::new(), 2),
+            ("Python".to_string(), "https://www.python.org".to_string(), 3),
+        ]);
+    }
 }
@@ -0,0 +1,279 @@
+//! End-to-end runs of the library: finding files, parsing their directives, applying the
+//! directive functions and writing the outputs, as the command line tool does without `--watch`.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use log::info;
+use crate::aggregator::{Aggregator, DirectiveWithSource, GroupBy};
+use crate::diagnostics::{log_diagnostics, Diagnostic};
+use crate::directive_functions::{FunctionApplicator, PlainDirectivesMap};
+use crate::error::RstParserError;
+use crate::file_walker::{canonicalize_normalized, normalize_path, FileWalker};
+use crate::link_data::{LinkConfig, LinkGraph};
+use crate::processor::Processor;
+use crate::timing::Timer;
+use crate::validation::{find_duplicate_ids, ValidationProblem};
+
+/// Builder for a one-shot run over a directory.
+///
+/// The simple settings (`extensions`, `directives`, `link_config`, `group_by`, `output`) cover
+/// the common case; `walker`, `processor`, `function_applicator` and `aggregator` replace the
+/// components built from them for full control. Outputs are only written if `output` or
+/// `aggregator` is set.
+///
+/// ```
+/// # let docs = tempfile::tempdir().unwrap();
+/// # std::fs::write(docs.path().join("index.rst"), ".. req::\n   :id: R1\n").unwrap();
+/// # let output = docs.path().join("out");
+/// use rstparser::Pipeline;
+///
+/// let result = Pipeline::new().dir(docs.path()).directives(vec!["req".to_string()]).output(&output).run().unwrap();
+/// assert_eq!(result.written_files, vec![output.join("req.json")]);
+/// ```
+pub struct Pipeline {
+    dir: PathBuf,
+    extensions: Option<Vec<String>>,
+    directives: Vec<String>,
+    link_config: Arc<LinkConfig>,
+    group_by: GroupBy,
+    output: Option<PathBuf>,
+    walker: Option<FileWalker>,
+    processor: Option<Processor>,
+    function_applicator: Option<FunctionApplicator>,
+    aggregator: Option<Aggregator>,
+    only_files: Option<HashSet<PathBuf>>,
+    additional_directives: Vec<DirectiveWithSource>,
+}
+
+/// Everything a `Pipeline` run produced.
+pub struct PipelineResult {
+    /// The files that were processed, as found by the file walker.
+    pub files: Vec<PathBuf>,
+    /// The directives by canonical file path and ID.
+    pub directives_map: PlainDirectivesMap,
+    pub link_graph: LinkGraph,
+    /// Diagnostics of processing the files and of the directive functions.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Directives whose ID is already used by another one. Only the last directive with an
+    /// ID of a file is kept in `directives_map`, so duplicates are detected before building it.
+    pub duplicate_ids: Vec<ValidationProblem>,
+    /// The requested directive names that occur neither in the processed files nor in the
+    /// additional directives, see `Processor::missing_directives`.
+    pub missing_directives: Vec<String>,
+    /// The output files that were written, empty if no output was configured.
+    pub written_files: Vec<PathBuf>,
+    /// How long each phase (`walk`, `parse`, `functions`, `aggregate`) took, in order.
+    pub phase_timings: Vec<(&'static str, Duration)>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pipeline {
+    /// A pipeline over the current directory with the default extensions, no directives and no links.
+    pub fn new() -> Self {
+        Pipeline {
+            dir: PathBuf::from("."),
+            extensions: None,
+            directives: Vec::new(),
+            link_config: Arc::new(LinkConfig::default()),
+            group_by: GroupBy::DirectiveName,
+            output: None,
+            walker: None,
+            processor: None,
+            function_applicator: None,
+            aggregator: None,
+            only_files: None,
+            additional_directives: Vec::new(),
+        }
+    }
+
+    /// Set the directory to search for files
+    pub fn dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Set the file extensions to process, see `FileWalker::with_extensions`
+    pub fn extensions(mut self, extensions: Vec<String>) -> Self {
+        self.extensions = Some(extensions);
+        self
+    }
+
+    /// Set the directive names to extract
+    pub fn directives(mut self, directives: Vec<String>) -> Self {
+        self.directives = directives;
+        self
+    }
+
+    /// Set the link types and directive aliases
+    pub fn link_config(mut self, link_config: LinkConfig) -> Self {
+        self.link_config = Arc::new(link_config);
+        self
+    }
+
+    /// Set how directives are grouped into output files
+    pub fn group_by(mut self, group_by: GroupBy) -> Self {
+        self.group_by = group_by;
+        self
+    }
+
+    /// Write the outputs to `output_dir`
+    pub fn output<P: AsRef<Path>>(mut self, output_dir: P) -> Self {
+        self.output = Some(output_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Use `walker` to find files, instead of one built from `extensions`
+    pub fn walker(mut self, walker: FileWalker) -> Self {
+        self.walker = Some(walker);
+        self
+    }
+
+    /// Use `processor` to parse files, instead of one built from `directives` and the aliases
+    /// of `link_config`
+    pub fn processor(mut self, processor: Processor) -> Self {
+        self.processor = Some(processor);
+        self
+    }
+
+    /// Use `function_applicator` to build the link graph, instead of one built from `link_config`
+    pub fn function_applicator(mut self, function_applicator: FunctionApplicator) -> Self {
+        self.function_applicator = Some(function_applicator);
+        self
+    }
+
+    /// Write the outputs with `aggregator`, instead of one built from `output` and `group_by`
+    pub fn aggregator(mut self, aggregator: Aggregator) -> Self {
+        self.aggregator = Some(aggregator);
+        self
+    }
+
+    /// Only process the found files whose canonical path is in `files`, compared after
+    /// `normalize_path`
+    pub fn only_files(mut self, files: HashSet<PathBuf>) -> Self {
+        self.only_files = Some(files);
+        self
+    }
+
+    /// Add directives that were not parsed in this run, e.g. those of unchanged files read
+    /// back with `Aggregator::load_output_directives`
+    pub fn additional_directives(mut self, directives: Vec<DirectiveWithSource>) -> Self {
+        self.additional_directives = directives;
+        self
+    }
+
+    /// Runs all phases. Fails if the files cannot be listed, if any file cannot be processed
+    /// (with `RstParserError::Multiple`, after logging the diagnostics of the other files) or
+    /// if the outputs cannot be written.
+    pub fn run(self) -> Result<PipelineResult, RstParserError> {
+        let mut phase_timings = Vec::new();
+
+        let timer = Timer::new("walk");
+        let walker = self.walker.unwrap_or_else(|| match self.extensions {
+            Some(extensions) => FileWalker::new().with_extensions(extensions),
+            None => FileWalker::new(),
+        });
+        let mut files = walker.find_files(&self.dir)?;
+        if let Some(only_files) = &self.only_files {
+            let only_files: HashSet<PathBuf> = only_files.iter().map(|file| normalize_path(file)).collect();
+            files.retain(|file| canonicalize_normalized(file).is_ok_and(|canonical| only_files.contains(&canonical)));
+        }
+        info!("Found {} files to process", files.len());
+        phase_timings.push(("walk", timer.elapsed()));
+
+        let timer = Timer::new("parse");
+        let processor = self.processor
+            .unwrap_or_else(|| Processor::new(self.directives).with_aliases(self.link_config.aliases.clone()));
+        let processed = processor.process_files_with_diagnostics(files.clone());
+        phase_timings.push(("parse", timer.elapsed()));
+        let mut diagnostics = processed.diagnostics;
+        if !processed.errors.is_empty() {
+            log_diagnostics(&diagnostics);
+            return Err(RstParserError::Multiple(processed.errors));
+        }
+
+        let mut missing_directives = processor.missing_directives();
+        missing_directives.retain(|name| !self.additional_directives.iter().any(|dws| dws.directive.name == *name));
+        let mut directives = processed.directives;
+        directives.extend(self.additional_directives);
+        let duplicate_ids = find_duplicate_ids(&directives);
+        let mut directives_map = PlainDirectivesMap::new();
+        for dws in directives {
+            // The processor stores canonical paths in `source_file`
+            directives_map.entry(PathBuf::from(&dws.source_file)).or_default().insert(dws.id.clone(), dws);
+        }
+
+        let timer = Timer::new("functions");
+        let function_applicator = self.function_applicator.unwrap_or_else(|| FunctionApplicator::new(self.link_config));
+        let mut link_graph = LinkGraph::new();
+        diagnostics.extend(function_applicator.apply_to_all(&directives_map, &mut link_graph));
+        phase_timings.push(("functions", timer.elapsed()));
+
+        let aggregator = self.aggregator.or_else(|| self.output.map(|output_dir| Aggregator::new(output_dir, self.group_by)));
+        let mut written_files = Vec::new();
+        if let Some(aggregator) = aggregator {
+            let timer = Timer::new("aggregate");
+            written_files = aggregator.aggregate_map_to_json_with_links(&directives_map, &link_graph)?;
+            phase_timings.push(("aggregate", timer.elapsed()));
+        }
+
+        Ok(PipelineResult { files, directives_map, link_graph, diagnostics, duplicate_ids, missing_directives, written_files, phase_timings })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link_data::LinkTypeConfig;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_run_links_and_writes_directives() {
+        let temp_dir = tempdir().unwrap();
+        let docs = temp_dir.path().join("docs");
+        fs::create_dir(&docs).unwrap();
+        fs::write(docs.join("a.rst"), ".. req::\n   :id: R1\n\n.. spec::\n   :id: S1\n   :derives: R1, R2\n").unwrap();
+        fs::write(docs.join("b.rst"), ".. req::\n   :id: R1\n").unwrap();
+        fs::write(docs.join("c.txt"), ".. req::\n   :id: R3\n").unwrap();
+        let link_config = LinkConfig {
+            link_types: vec![LinkTypeConfig { name: "derives".to_string(), custom_backlink_suffix: None }],
+            ..Default::default()
+        };
+
+        let result = Pipeline::new()
+            .dir(&docs)
+            .extensions(vec!["rst".to_string()])
+            .directives(vec!["req".to_string(), "spec".to_string(), "design".to_string()])
+            .link_config(link_config)
+            .group_by(GroupBy::All)
+            .output(temp_dir.path().join("out"))
+            .run()
+            .unwrap();
+
+        assert_eq!(result.files.len(), 2);
+        assert_eq!(result.directives_map.values().map(|file_map| file_map.len()).sum::<usize>(), 3);
+        assert_eq!(result.link_graph["R1"].incoming_links["derives_back"], vec!["S1".to_string()]);
+        assert_eq!(result.duplicate_ids.len(), 1);
+        assert_eq!(result.missing_directives, vec!["design"]);
+        assert_eq!(result.written_files, vec![temp_dir.path().join("out").join("all_directives.json")]);
+        let phases: Vec<&str> = result.phase_timings.iter().map(|(name, _)| *name).collect();
+        assert_eq!(phases, vec!["walk", "parse", "functions", "aggregate"]);
+    }
+
+    #[test]
+    fn test_run_without_output_writes_nothing() {
+        let temp_dir = tempdir().unwrap();
+        fs::write(temp_dir.path().join("a.rst"), ".. req::\n   :id: R1\n").unwrap();
+        let result = Pipeline::new().dir(temp_dir.path()).directives(vec!["req".to_string()]).run().unwrap();
+        assert_eq!(result.directives_map.len(), 1);
+        assert!(result.written_files.is_empty());
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+}
@@ -1,46 +1,300 @@
-use std::fs;
 use std::path::{Path, PathBuf};
-use std::error::Error;
+use crate::error::RstParserError;
+use crate::file_walker::normalize_path;
+#[cfg(feature = "fs")]
 use rayon::prelude::*;
-use crate::parser::parse_rst_multiple;
+use crate::parser::{has_any_directive, parse_rst_spanned, ParseOptions, SpannedDirective};
 use crate::aggregator::DirectiveWithSource; // DirectiveWithSource now has an `id` field
-use crate::extractor::RstExtractor;
+use crate::extractor::{ExtractorConfig, RstExtractor};
+use crate::encoding::{decode, Encoding};
+use crate::timing::{format_duration, stage_span, Timer, TimingRegistry};
+use crate::diagnostics::{log_diagnostics, Diagnostic, DiagnosticCode, ParseWarning};
+use crate::vfs::{default_file_provider, FileProvider};
 use std::sync::{Arc, Mutex}; // For watch mode return types
-use std::collections::HashMap; // For process_files_watch return type
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::collections::{HashMap, HashSet}; // For process_files_watch return type
+use std::time::Duration;
+use std::ffi::OsStr;
+use memchr::memmem;
+use log::debug;
+
+/// Directives found in a single file, wrapped for shared mutation in watch mode.
+pub type SharedDirectives = Vec<Arc<Mutex<DirectiveWithSource>>>;
+
+/// Called with `(completed, total)` each time `process_files` finishes a file.
+pub type ProgressCallback = Box<dyn Fn(usize, usize) + Send + Sync>;
+
+/// Time spent reading, extracting and parsing each file.
+pub type FileTimings = Vec<(PathBuf, Duration)>;
+
+/// Directives and diagnostics of one file, or why it could not be processed.
+type FileResult = Result<(Vec<DirectiveWithSource>, Vec<Diagnostic>), RstParserError>;
+
+/// Maximum nesting depth for `.. include::` expansion.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// Marker searched for by the quick scan unless another one is configured.
+pub const DEFAULT_QUICK_SCAN_MARKER: &str = "@rst";
+
+/// Number of bytes at the start of a file inspected by the quick scan.
+const QUICK_SCAN_BYTES: usize = 8 * 1024;
+
+/// Fence line enclosing TOML front-matter unless another one is configured.
+pub const DEFAULT_FRONT_MATTER_FENCE: &str = "+++";
+
+/// Everything `Processor::process_files_with_diagnostics` found, in input order.
+#[derive(Debug, Default)]
+pub struct ProcessedFiles {
+    pub directives: Vec<DirectiveWithSource>,
+    /// Time spent reading, extracting and parsing each input file.
+    pub timings: FileTimings,
+    /// Problems in files that could be processed, see `Processor::process_file_with_diagnostics`.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Files that could not be processed at all.
+    pub errors: Vec<RstParserError>,
+}
 
 /// A struct to process RST files and find directives
 pub struct Processor {
     target_directives: Vec<String>,
+    expand_includes: bool,
+    parse_options: ParseOptions,
+    quick_scan_marker: Option<String>,
+    extractor_config: ExtractorConfig,
+    canonical_names: HashMap<String, String>, // Alias -> canonical directive name
+    front_matter_fence: Option<String>,
+    directive_content_limit: Option<usize>,
+    domain: Option<String>,
+    encoding: Option<&'static Encoding>,
+    timing_registry: Option<Arc<TimingRegistry>>,
+    progress: Option<ProgressCallback>,
+    file_provider: Arc<dyn FileProvider>,
+    /// The directive names given to `new`, see `missing_directives`.
+    requested_directives: Vec<String>,
+    /// Canonical names of the directives found so far.
+    found_directives: Mutex<HashSet<String>>,
 }
 
 impl Processor {
     pub fn new(target_directives: Vec<String>) -> Self {
-        Processor { target_directives }
+        Processor {
+            requested_directives: target_directives.clone(),
+            found_directives: Mutex::new(HashSet::new()),
+            target_directives,
+            expand_includes: false,
+            parse_options: ParseOptions::default(),
+            quick_scan_marker: None,
+            extractor_config: ExtractorConfig::default(),
+            canonical_names: HashMap::new(),
+            front_matter_fence: None,
+            directive_content_limit: None,
+            domain: None,
+            encoding: None,
+            timing_registry: None,
+            progress: None,
+            file_provider: default_file_provider(),
+        }
+    }
+
+    /// Set the options used when parsing directives.
+    pub fn with_parse_options(mut self, parse_options: ParseOptions) -> Self {
+        self.parse_options = parse_options;
+        self
+    }
+
+    /// Inline the content of `.. include::` directives before parsing.
+    /// Paths are resolved relative to the including file. Line numbers of directives
+    /// then refer to the expanded text.
+    pub fn with_expand_includes(mut self, expand_includes: bool) -> Self {
+        self.expand_includes = expand_includes;
+        self
+    }
+
+    /// Also match alternative names of directives (canonical name -> aliases). Directives
+    /// found under an alias are reported under their canonical name. Only aliases of
+    /// directives that are searched for, by canonical name or by any alias, are used.
+    pub fn with_aliases(mut self, aliases: HashMap<String, Vec<String>>) -> Self {
+        for (canonical, alias_names) in aliases {
+            let is_target = self.target_directives.contains(&canonical)
+                || alias_names.iter().any(|alias| self.target_directives.contains(alias));
+            if !is_target {
+                continue;
+            }
+            for name in alias_names.iter().chain(std::iter::once(&canonical)) {
+                if !self.target_directives.contains(name) {
+                    self.target_directives.push(name.clone());
+                }
+            }
+            for alias in alias_names {
+                self.canonical_names.insert(alias, canonical.clone());
+            }
+        }
+        self
+    }
+
+    /// Set the options used when extracting RST blocks from source files.
+    pub fn with_extractor_config(mut self, extractor_config: ExtractorConfig) -> Self {
+        self.extractor_config = extractor_config;
+        self
+    }
+
+    /// Skip source files whose first 8 KB do not contain `marker` (e.g. `"@rst"`,
+    /// see `DEFAULT_QUICK_SCAN_MARKER`) without reading or extracting them further.
+    /// `.rst` and Markdown files carry no marker and are always processed in full.
+    pub fn with_quick_scan_marker(mut self, marker: &str) -> Self {
+        self.quick_scan_marker = Some(marker.to_string());
+        self
+    }
+
+    /// Read a front-matter block enclosed by two `fence` lines (e.g. `"+++"`, see
+    /// `DEFAULT_FRONT_MATTER_FENCE`) at the very start of each file, parse it as TOML and
+    /// attach it to every directive of that file as `file_metadata`. The block is blanked
+    /// out before parsing, so line numbers still refer to the original file.
+    pub fn with_front_matter_fence(mut self, fence: &str) -> Self {
+        self.front_matter_fence = Some(fence.to_string());
+        self
+    }
+
+    /// Truncate directive content longer than `max_bytes` (unlimited by default). The kept
+    /// content is followed by a marker line, and a `DiagnosticCode::ContentTruncated` diagnostic
+    /// with `ParseWarning::ContentTruncated` details is reported.
+    pub fn with_directive_content_limit(mut self, max_bytes: usize) -> Self {
+        self.directive_content_limit = Some(max_bytes);
+        self
+    }
+
+    /// Also record the per-file durations of `process_files` and `process_files_timed` in
+    /// `registry`, e.g. to report the slowest files of a run.
+    pub fn with_timing_registry(mut self, registry: Arc<TimingRegistry>) -> Self {
+        self.timing_registry = Some(registry);
+        self
+    }
+
+    /// Call `progress` with the number of completed files and the total each time
+    /// `process_files`, `process_files_timed` or `process_files_watch` finishes a file. It is called from the worker
+    /// threads, so completions may be reported out of order.
+    pub fn with_progress(mut self, progress: ProgressCallback) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Only match directives qualified with the Sphinx `domain`, e.g. `.. c:function::` for
+    /// the target `function` with domain `"c"`. The domain is kept in the `_domain` option.
+    pub fn with_domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    /// Decode files and their includes with `encoding` instead of requiring UTF-8. A byte
+    /// order mark at the start of a file selects the encoding it announces instead.
+    /// The quick scan (`with_quick_scan_marker`) is not applied to files read this way.
+    pub fn with_encoding(mut self, encoding: &'static Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Read files and their includes from `file_provider` instead of the local file system.
+    pub fn with_file_provider(mut self, file_provider: Arc<dyn FileProvider>) -> Self {
+        self.file_provider = file_provider;
+        self
+    }
+
+    /// The canonical path of `path` in the file provider.
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, RstParserError> {
+        self.file_provider.canonicalize(path)
+            .map_err(|source| RstParserError::Io { path: path.to_path_buf(), source })
+    }
+
+    /// The directive names given to `new` that no file processed so far contained, in the
+    /// given order, e.g. to warn about misspelled names. A name with aliases counts as found
+    /// when the directive occurred under any of its names.
+    pub fn missing_directives(&self) -> Vec<String> {
+        let found_directives = self.found_directives.lock().unwrap();
+        self.requested_directives.iter()
+            .filter(|name| {
+                let canonical = self.canonical_names.get(*name).unwrap_or(name);
+                !found_directives.contains(canonical)
+            })
+            .cloned()
+            .collect()
     }
 
     /// Process a single file, canonicalize its path, generate directive IDs, and find directives.
-    pub fn process_file<P: AsRef<Path>>(&self, file_path_ref: P) -> Result<Vec<DirectiveWithSource>, Box<dyn Error>> {
+    /// Diagnostics are logged; use `process_file_with_diagnostics` to get them instead.
+    pub fn process_file<P: AsRef<Path>>(&self, file_path_ref: P) -> Result<Vec<DirectiveWithSource>, RstParserError> {
+        let (directives, diagnostics) = self.process_file_with_diagnostics(file_path_ref)?;
+        log_diagnostics(&diagnostics);
+        Ok(directives)
+    }
+
+    /// Same as `process_file`, but returns the problems found in the file, e.g. unterminated
+    /// `@rst` blocks, invalid front-matter or unresolved includes, instead of logging them.
+    pub fn process_file_with_diagnostics<P: AsRef<Path>>(&self, file_path_ref: P) -> Result<(Vec<DirectiveWithSource>, Vec<Diagnostic>), RstParserError> {
         let original_path = file_path_ref.as_ref();
-        let canonical_file_path = match fs::canonicalize(original_path) {
-            Ok(p) => p,
-            Err(e) => {
-                // If canonicalization fails (e.g. file deleted during watch), return error or empty.
-                // For a direct call, failing might be better.
-                return Err(Box::new(std::io::Error::new(
-                    e.kind(),
-                    format!("Failed to canonicalize path {}: {}", original_path.display(), e)
-                )));
-            }
-        };
-        let canonical_source_file_str = canonical_file_path.to_string_lossy().to_string();
+        // Fails e.g. for files deleted during watch
+        let canonical_file_path = self.canonicalize(original_path)?;
+        if let Some(marker) = &self.quick_scan_marker
+            && self.encoding.is_none() // The scan compares raw bytes against the UTF-8 marker
+            && !matches!(canonical_file_path.extension().and_then(OsStr::to_str), Some("rst" | "md" | "markdown"))
+            && !file_head_contains(self.file_provider.as_ref(), &canonical_file_path, marker)
+                .map_err(|source| RstParserError::Io { path: canonical_file_path.clone(), source })?
+        {
+            return Ok((Vec::new(), Vec::new())); // Marker not found, skip extraction entirely
+        }
 
-        let content = fs::read_to_string(&canonical_file_path)?;
-        let rst_content = RstExtractor::extract_from_file(&canonical_file_path, &content);
+        let timer = Timer::new("process_file");
+        let span = stage_span!("process_file", file, directives);
+        span.record_display("file", canonical_file_path.display());
+        let content = read_source(self.file_provider.as_ref(), &canonical_file_path, self.encoding)?;
+        let processed = self.process_content(content, &canonical_file_path)?;
+        span.record_count("directives", processed.0.len());
+        debug!("process_file file={} directives={} duration={}", canonical_file_path.display(), processed.0.len(), format_duration(timer.elapsed()));
+        Ok(processed)
+    }
+
+    /// Find directives in `content` as if it had been read from `source_path`: the path's
+    /// extension selects the extractor, and the path is used as is for source files and
+    /// generated IDs. Nothing is read from the file provider except `.. include::` targets.
+    pub fn process_content<P: AsRef<Path>>(&self, mut content: String, source_path: P) -> Result<(Vec<DirectiveWithSource>, Vec<Diagnostic>), RstParserError> {
+        let canonical_file_path = source_path.as_ref().to_path_buf();
+        let canonical_source_file_str = canonical_file_path.to_string_lossy().to_string();
+        let mut diagnostics = Vec::new();
+        let mut file_metadata = None;
+        // Whether byte offsets into the parsed RST are also offsets into `content`
+        let mut parsed_text_is_source = canonical_file_path.extension().is_some_and(|extension| extension == "rst") && !self.extractor_config.rst_markers;
+        if let Some(fence) = &self.front_matter_fence
+            && let Some((front_matter, end)) = split_front_matter(&content, fence)
+        {
+            match toml::from_str::<toml::Table>(front_matter).map_err(|e| e.to_string())
+                .and_then(|table| serde_json::to_value(table).map_err(|e| e.to_string()))
+            {
+                Ok(value) => file_metadata = Some(value),
+                Err(e) => diagnostics.push(Diagnostic::warning(&canonical_file_path, Some(1), DiagnosticCode::InvalidFrontMatter, format!("Ignoring invalid front-matter: {}", e))),
+            }
+            let blanked_lines = "\n".repeat(content[..end].matches('\n').count());
+            content.replace_range(..end, &blanked_lines);
+            parsed_text_is_source = false;
+        }
+        let (mut rst_content, extract_diagnostics) = RstExtractor::extract_from_file_with_diagnostics(&canonical_file_path, &content, &self.extractor_config);
+        diagnostics.extend(extract_diagnostics);
+        if self.expand_includes {
+            let base_dir = canonical_file_path.parent().unwrap_or(Path::new(""));
+            let mut include_stack = vec![canonical_file_path.clone()];
+            let expanded = expand_includes(self.file_provider.as_ref(), &rst_content, base_dir, &mut include_stack, self.encoding, &mut diagnostics);
+            parsed_text_is_source &= expanded == rst_content;
+            rst_content = expanded;
+        }
         
-        let target_directives_refs: Vec<&str> = self.target_directives.iter().map(|s| s.as_str()).collect();
-        let directives_with_lines = parse_rst_multiple(&rst_content, &target_directives_refs);
+        let target_directives_refs: Vec<(&str, Option<&str>)> = self.target_directives.iter().map(|s| (s.as_str(), self.domain.as_deref())).collect();
+        if !has_any_directive(&rst_content, &target_directives_refs) {
+            return Ok((Vec::new(), diagnostics)); // Skip the full parse for files without any target directive
+        }
+        let spanned_directives = parse_rst_spanned(&rst_content, &target_directives_refs, &self.parse_options);
         
-        let directives_with_source = directives_with_lines.into_iter().map(|(directive, line_number)| { // Removed mut from directive
+        let directives_with_source: Vec<DirectiveWithSource> = spanned_directives.into_iter().map(|SpannedDirective { mut directive, line_number, depth, byte_range }| {
+            if let Some(canonical) = self.canonical_names.get(&directive.name) {
+                directive.name = canonical.clone();
+            }
             // Generate ID: use :id: option if present, otherwise fallback
             let id = directive.options.get("id")
                 .map(|id_val| id_val.trim().to_string())
@@ -54,7 +308,7 @@ impl Processor {
                 });
             
             // Ensure the :id: option is stored if it was used for the ID
-            if !directive.options.contains_key("id") && id.starts_with(&canonical_source_file_str) == false { // Heuristic: if id is not path-based, it was from :id:
+            if !directive.options.contains_key("id") && !id.starts_with(&canonical_source_file_str) { // Heuristic: if id is not path-based, it was from :id:
                  if let Some(opt_id) = directive.options.get("id") {
                     if opt_id.trim() == id {
                         // ID came from option, ensure it's stored as such if not already.
@@ -69,97 +323,263 @@ impl Processor {
             }
 
 
+            if let Some(max_bytes) = self.directive_content_limit
+                && directive.content.len() > max_bytes
+            {
+                let original_bytes = directive.content.len();
+                truncate_content(&mut directive.content, max_bytes);
+                diagnostics.push(Diagnostic::warning(
+                    &canonical_file_path,
+                    Some(line_number),
+                    DiagnosticCode::ContentTruncated,
+                    format!("Content of directive '{}' was truncated from {} bytes", id, original_bytes),
+                ).with_details(ParseWarning::ContentTruncated { directive_id: id.clone(), original_bytes }));
+            }
+
             DirectiveWithSource {
                 directive,
                 source_file: canonical_source_file_str.clone(),
                 line_number: Some(line_number), // line_number from parse_rst_multiple is usize, wrap in Some()
                 id, // Populate the new id field
+                file_metadata: file_metadata.clone(),
+                depth,
+                source_byte_range: parsed_text_is_source.then(|| byte_range.clone()),
+                byte_range: Some(byte_range),
             }
         }).collect();
-        
-        Ok(directives_with_source)
+
+        let mut found_directives = self.found_directives.lock().unwrap();
+        for dws in &directives_with_source {
+            if !found_directives.contains(&dws.directive.name) {
+                found_directives.insert(dws.directive.name.clone());
+            }
+        }
+        Ok((directives_with_source, diagnostics))
     }
 
     /// Process multiple files in parallel (for non-watch mode).
-    /// Returns a flat Vec of all found directives with populated IDs and canonical source_file.
-    pub fn process_files(&self, file_paths: Vec<PathBuf>) -> Result<Vec<DirectiveWithSource>, Box<dyn Error + Send + Sync>> {
-        let results: Vec<Result<Vec<DirectiveWithSource>, String>> = file_paths.par_iter()
+    /// Returns a flat Vec of all found directives with populated IDs and canonical source_file,
+    /// and the errors of the files that could not be processed, in input order.
+    pub fn process_files(&self, file_paths: Vec<PathBuf>) -> (Vec<DirectiveWithSource>, Vec<RstParserError>) {
+        let (all_directives, _, errors) = self.process_files_timed(file_paths);
+        (all_directives, errors)
+    }
+
+    /// Same as `process_files`, but additionally returns how long reading, extracting and
+    /// parsing took for each input file, in input order.
+    pub fn process_files_timed(&self, file_paths: Vec<PathBuf>) -> (Vec<DirectiveWithSource>, FileTimings, Vec<RstParserError>) {
+        let processed = self.process_files_with_diagnostics(file_paths);
+        log_diagnostics(&processed.diagnostics);
+        (processed.directives, processed.timings, processed.errors)
+    }
+
+    /// Same as `process_files_timed`, but returns the diagnostics of all files instead of
+    /// logging them.
+    pub fn process_files_with_diagnostics(&self, file_paths: Vec<PathBuf>) -> ProcessedFiles {
+        let completed = AtomicUsize::new(0);
+        #[cfg(feature = "fs")]
+        let file_paths_iter = file_paths.par_iter();
+        #[cfg(not(feature = "fs"))]
+        let file_paths_iter = file_paths.iter();
+        let results: Vec<(FileResult, Duration)> = file_paths_iter
             .map(|file_path| {
-                self.process_file(file_path)
-                    .map_err(|e| e.to_string()) // Convert error to String
+                let timer = Timer::new(&file_path.to_string_lossy());
+                let result = self.process_file_with_diagnostics(file_path);
+                let elapsed = timer.elapsed();
+                if let Some(registry) = &self.timing_registry {
+                    registry.record_file(file_path.clone(), elapsed);
+                }
+                if let Some(progress) = &self.progress {
+                    progress(completed.fetch_add(1, AtomicOrdering::Relaxed) + 1, file_paths.len());
+                }
+                (result, elapsed)
             })
             .collect();
         
-        let mut all_directives = Vec::new();
-        let mut errors_accumulator: Vec<String> = Vec::new();
-        
-        for result in results {
+        let mut processed = ProcessedFiles { timings: Vec::with_capacity(file_paths.len()), ..ProcessedFiles::default() };
+        for (file_path, (result, duration)) in file_paths.into_iter().zip(results) {
+            processed.timings.push((file_path, duration));
             match result {
-                Ok(directives) => all_directives.extend(directives),
-                Err(e_str) => errors_accumulator.push(e_str),
+                Ok((directives, diagnostics)) => {
+                    processed.directives.extend(directives);
+                    processed.diagnostics.extend(diagnostics);
+                }
+                Err(e) => processed.errors.push(e),
             }
         }
-        
-        if !errors_accumulator.is_empty() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Errors occurred while processing files: {}", errors_accumulator.join("\n"))
-            )));
-        }
-        Ok(all_directives)
+        processed
     }
 
     /// Process a single file for watch mode, returning Vec<Arc<Mutex<DirectiveWithSource>>>.
     /// Handles ID generation and path canonicalization.
-    pub fn process_file_watch<P: AsRef<Path>>(&self, file_path_ref: P) -> Result<Vec<Arc<Mutex<DirectiveWithSource>>>, Box<dyn Error>> {
+    pub fn process_file_watch<P: AsRef<Path>>(&self, file_path_ref: P) -> Result<Vec<Arc<Mutex<DirectiveWithSource>>>, RstParserError> {
         let directives = self.process_file(file_path_ref)?; // Reuses the updated process_file
         Ok(directives.into_iter().map(|dws| Arc::new(Mutex::new(dws))).collect())
     }
 
     /// Process multiple files for watch mode initial scan.
     /// Returns a map of canonical_path -> Vec<Arc<Mutex<DirectiveWithSource>>>.
-    pub fn process_files_watch(&self, file_paths: Vec<PathBuf>) -> Result<HashMap<PathBuf, Vec<Arc<Mutex<DirectiveWithSource>>>>, Box<dyn Error + Send + Sync>> {
-        let results: Vec<Result<(PathBuf, Vec<Arc<Mutex<DirectiveWithSource>>>), String>> = file_paths.par_iter()
+    /// Fails with `RstParserError::Multiple` holding the errors of all files that failed.
+    pub fn process_files_watch(&self, file_paths: Vec<PathBuf>) -> Result<HashMap<PathBuf, SharedDirectives>, RstParserError> {
+        let completed = AtomicUsize::new(0);
+        #[cfg(feature = "fs")]
+        let file_paths_iter = file_paths.par_iter();
+        #[cfg(not(feature = "fs"))]
+        let file_paths_iter = file_paths.iter();
+        let results: Vec<Result<(PathBuf, SharedDirectives), RstParserError>> = file_paths_iter
             .map(|file_path_orig| {
-                let canonical_file_path = match fs::canonicalize(file_path_orig) {
-                     Ok(p) => p,
-                     Err(e) => return Err(format!("Failed to canonicalize path {}: {}", file_path_orig.display(), e)),
-                };
-                match self.process_file_watch(&canonical_file_path) {
-                    Ok(arc_directives) => Ok((canonical_file_path, arc_directives)),
-                    Err(e) => Err(format!("Error processing file {}: {}", canonical_file_path.display(), e)),
+                let result = self.canonicalize(file_path_orig).and_then(|canonical_file_path| {
+                    let arc_directives = self.process_file_watch(&canonical_file_path)?;
+                    Ok((canonical_file_path, arc_directives))
+                });
+                if let Some(progress) = &self.progress {
+                    progress(completed.fetch_add(1, AtomicOrdering::Relaxed) + 1, file_paths.len());
                 }
+                result
             })
             .collect();
 
-        let mut processed_map: HashMap<PathBuf, Vec<Arc<Mutex<DirectiveWithSource>>>> = HashMap::new();
-        let mut errors_accumulator: Vec<String> = Vec::new();
+        let mut processed_map: HashMap<PathBuf, SharedDirectives> = HashMap::new();
+        let mut errors_accumulator: Vec<RstParserError> = Vec::new();
 
         for result in results {
             match result {
                 Ok((path, directives)) => {
                     processed_map.insert(path, directives);
                 }
-                Err(e_str) => errors_accumulator.push(e_str),
+                Err(e) => errors_accumulator.push(e),
             }
         }
 
         if !errors_accumulator.is_empty() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Errors occurred during initial watch scan: {}", errors_accumulator.join("\n"))
-            )));
+            return Err(RstParserError::Multiple(errors_accumulator));
         }
         Ok(processed_map)
     }
 }
 
+/// Cut `content` to at most `max_bytes` (at a character boundary) and append a marker line.
+fn truncate_content(content: &mut String, max_bytes: usize) {
+    let original_bytes = content.len();
+    let mut end = max_bytes;
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+    content.truncate(end);
+    content.push_str(&format!("\n[... content truncated, {} of {} bytes kept]", end, original_bytes));
+}
+
+/// Find a front-matter block opened by a `fence` line at the start of `content` and closed by
+/// the next `fence` line. Returns the text between the fences and the byte offset just past
+/// the closing fence line.
+fn split_front_matter<'a>(content: &'a str, fence: &str) -> Option<(&'a str, usize)> {
+    let mut lines = content.split_inclusive('\n');
+    let first_line = lines.next()?;
+    if first_line.trim_end() != fence {
+        return None;
+    }
+    let mut offset = first_line.len();
+    for line in lines {
+        if line.trim_end() == fence {
+            return Some((&content[first_line.len()..offset], offset + line.len()));
+        }
+        offset += line.len();
+    }
+    None // Unclosed fence, treat the file as having no front-matter
+}
+
+/// Check whether the first `QUICK_SCAN_BYTES` of the file contain `marker`.
+fn file_head_contains(file_provider: &dyn FileProvider, path: &Path, marker: &str) -> std::io::Result<bool> {
+    let head = file_provider.read_head(path, QUICK_SCAN_BYTES)?;
+    Ok(memmem::find(&head, marker.as_bytes()).is_some())
+}
+
+/// Reads a source file as UTF-8, or decodes it with `encoding` if one is configured.
+fn read_source(file_provider: &dyn FileProvider, path: &Path, encoding: Option<&'static Encoding>) -> Result<String, RstParserError> {
+    let bytes = file_provider.read(path).map_err(|source| RstParserError::Io { path: path.to_path_buf(), source })?;
+    let decoded = match encoding {
+        Some(encoding) => decode(&bytes, encoding),
+        None => String::from_utf8(bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+    };
+    decoded.map_err(|source| RstParserError::Extraction { file: path.to_path_buf(), source })
+}
+
+/// Replaces every `.. include:: path` line in `rst` with the content of the referenced file,
+/// indented like the include line. Included files are expanded recursively up to
+/// `MAX_INCLUDE_DEPTH`; `include_stack` holds the canonical paths currently being expanded
+/// and guards against cycles. Includes that cannot be resolved are left untouched. Problems
+/// are reported as diagnostics of the including file.
+fn expand_includes(file_provider: &dyn FileProvider, rst: &str, base_dir: &Path, include_stack: &mut Vec<PathBuf>, encoding: Option<&'static Encoding>, diagnostics: &mut Vec<Diagnostic>) -> String {
+    const INCLUDE_MARKER: &str = ".. include::";
+
+    let mut expanded = String::with_capacity(rst.len());
+    for line in rst.split_inclusive('\n') {
+        let trimmed_line = line.trim_start();
+        let include_target = trimmed_line
+            .strip_prefix(INCLUDE_MARKER)
+            .map(str::trim)
+            .filter(|target| !target.is_empty() && !target.starts_with('<')); // <...> refers to docutils' standard includes
+
+        let Some(include_target) = include_target else {
+            expanded.push_str(line);
+            continue;
+        };
+
+        let included_path = match file_provider.canonicalize(&base_dir.join(include_target)) {
+            Ok(p) => p,
+            Err(e) => {
+                include_problem(diagnostics, include_stack, format!("Could not resolve include '{}' relative to {}: {}", include_target, base_dir.display(), e));
+                expanded.push_str(line);
+                continue;
+            }
+        };
+        if include_stack.iter().any(|path| normalize_path(path) == normalize_path(&included_path)) {
+            include_problem(diagnostics, include_stack, format!("Include cycle detected for {}, skipping.", included_path.display()));
+            continue;
+        }
+        if include_stack.len() > MAX_INCLUDE_DEPTH {
+            include_problem(diagnostics, include_stack, format!("Maximum include depth ({}) exceeded at {}, skipping.", MAX_INCLUDE_DEPTH, included_path.display()));
+            continue;
+        }
+        let included_content = match read_source(file_provider, &included_path, encoding) {
+            Ok(c) => c,
+            Err(e) => {
+                include_problem(diagnostics, include_stack, format!("Could not read included file {}: {}", included_path.display(), e));
+                expanded.push_str(line);
+                continue;
+            }
+        };
+
+        include_stack.push(included_path.clone());
+        let included_base_dir = included_path.parent().unwrap_or(Path::new(""));
+        let included_rst = expand_includes(file_provider, &included_content, included_base_dir, include_stack, encoding, diagnostics);
+        include_stack.pop();
+
+        let indentation = &line[..line.len() - trimmed_line.len()];
+        for included_line in included_rst.lines() {
+            if !included_line.is_empty() {
+                expanded.push_str(indentation);
+            }
+            expanded.push_str(included_line);
+            expanded.push('\n');
+        }
+    }
+    expanded
+}
+
+/// Records a failed include of the file currently being expanded, the last of `include_stack`.
+fn include_problem(diagnostics: &mut Vec<Diagnostic>, include_stack: &[PathBuf], message: String) {
+    let file = include_stack.last().cloned().unwrap_or_default();
+    diagnostics.push(Diagnostic::warning(file, None, DiagnosticCode::IncludeFailed, message));
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs::File;
+    use std::fs::{self, File};
     use std::io::Write;
     use tempfile::tempdir;
+    use crate::vfs::InMemoryFs;
 
     #[test]
     fn test_process_file() {
@@ -208,11 +628,26 @@ mod tests {
     }
 
     #[test]
-    fn test_process_files() {
+    fn test_process_error_variants() {
         let temp_dir = tempdir().unwrap();
-        let file1_path = temp_dir.path().join("file1.rst");
-        let file2_path = temp_dir.path().join("file2.rst");
-        
+        let processor = Processor::new(vec!["req".to_string()]);
+
+        let missing = temp_dir.path().join("missing.rst");
+        let err = processor.process_file(&missing).unwrap_err();
+        assert!(matches!(&err, RstParserError::Io { path, source } if *path == missing && source.kind() == std::io::ErrorKind::NotFound));
+        assert_eq!(err.path(), Some(missing.as_path()));
+
+        let binary = temp_dir.path().join("binary.rst");
+        fs::write(&binary, [0xff, 0xfe, 0x00, 0x80]).unwrap();
+        assert!(matches!(processor.process_file(&binary), Err(RstParserError::Extraction { .. })));
+
+        let directory = temp_dir.path().join("dir.rst");
+        fs::create_dir(&directory).unwrap();
+        assert!(matches!(processor.process_file(&directory), Err(RstParserError::Io { path, .. }) if path.ends_with("dir.rst")));
+    }
+
+    #[test]
+    fn test_process_files() {
         let rst_content1 = r#".
 .. directive1::
    :id: d1f1
@@ -229,25 +664,415 @@ mod tests {
    :id: d1f2
 
    Content for directive1 in file2."#;
+        let provider = Arc::new(InMemoryFs::new().with_file("/docs/file1.rst", rst_content1).with_file("/docs/file2.rst", rst_content2));
         
-        File::create(&file1_path).unwrap().write_all(rst_content1.as_bytes()).unwrap();
-        File::create(&file2_path).unwrap().write_all(rst_content2.as_bytes()).unwrap();
-        
-        let processor = Processor::new(vec!["directive1".to_string(), "directive2".to_string()]);
-        let result_vec = processor.process_files(vec![file1_path.clone(), file2_path.clone()]).unwrap();
+        let processor = Processor::new(vec!["directive1".to_string(), "directive2".to_string()]).with_file_provider(provider);
+        let (result_vec, errors) = processor.process_files(vec![PathBuf::from("/docs/file1.rst"), PathBuf::from("/docs/file2.rst")]);
+        assert!(errors.is_empty());
         
         assert_eq!(result_vec.len(), 3);
         
         let d1f1_opt = result_vec.iter().find(|d| d.id == "d1f1");
         assert!(d1f1_opt.is_some());
-        assert_eq!(d1f1_opt.unwrap().source_file, fs::canonicalize(&file1_path).unwrap().to_string_lossy());
+        assert_eq!(d1f1_opt.unwrap().source_file, "/docs/file1.rst");
 
         let d2f2_opt = result_vec.iter().find(|d| d.id == "d2f2");
         assert!(d2f2_opt.is_some());
-        assert_eq!(d2f2_opt.unwrap().source_file, fs::canonicalize(&file2_path).unwrap().to_string_lossy());
+        assert_eq!(d2f2_opt.unwrap().source_file, "/docs/file2.rst");
 
         let d1f2_opt = result_vec.iter().find(|d| d.id == "d1f2");
         assert!(d1f2_opt.is_some());
-        assert_eq!(d1f2_opt.unwrap().source_file, fs::canonicalize(&file2_path).unwrap().to_string_lossy());
+        assert_eq!(d1f2_opt.unwrap().source_file, "/docs/file2.rst");
+    }
+
+    #[test]
+    fn test_process_file_expands_includes() {
+        let provider = Arc::new(InMemoryFs::new()
+            .with_file("/docs/a.rst", ".. directive1::\n   :id: from-a\n\n.. include:: parts/b.rst\n")
+            .with_file("/docs/parts/b.rst", ".. directive1::\n   :id: from-b\n\n   Content from b.\n"));
+        let file_a_path = Path::new("/docs/a.rst");
+
+        let processor = Processor::new(vec!["directive1".to_string()]).with_file_provider(provider.clone());
+        let without_includes = processor.process_file(file_a_path).unwrap();
+        assert_eq!(without_includes.len(), 1);
+
+        let processor = Processor::new(vec!["directive1".to_string()]).with_expand_includes(true).with_file_provider(provider);
+        let result = processor.process_file(file_a_path).unwrap();
+        assert_eq!(result.len(), 2);
+        let from_b = result.iter().find(|d| d.id == "from-b").unwrap();
+        assert_eq!(from_b.directive.content, "Content from b.");
+        assert_eq!(from_b.source_file, "/docs/a.rst");
+    }
+
+    #[test]
+    fn test_source_file_keeps_the_case_of_the_path() {
+        let provider = Arc::new(InMemoryFs::new().with_file("/Docs/Specs/Main.rst", ".. directive1::\n"));
+        let processor = Processor::new(vec!["directive1".to_string()]).with_file_provider(provider);
+        let result = processor.process_file(Path::new("/Docs/Specs/Main.rst")).unwrap();
+        assert_eq!(result[0].source_file, "/Docs/Specs/Main.rst");
+        assert_eq!(result[0].id, "/Docs/Specs/Main.rst:directive1:1");
+    }
+
+    #[test]
+    fn test_process_file_include_cycle_terminates() {
+        let provider = Arc::new(InMemoryFs::new()
+            .with_file("/docs/a.rst", ".. include:: b.rst\n\n.. directive1::\n   :id: in-a\n")
+            .with_file("/docs/b.rst", ".. include:: a.rst\n\n.. directive1::\n   :id: in-b\n"));
+
+        let processor = Processor::new(vec!["directive1".to_string()]).with_expand_includes(true).with_file_provider(provider);
+        let result = processor.process_file(Path::new("/docs/a.rst")).unwrap();
+        let mut ids: Vec<&str> = result.iter().map(|d| d.id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["in-a", "in-b"]);
+    }
+
+    #[test]
+    fn test_process_files_timed() {
+        let provider = Arc::new(InMemoryFs::new()
+            .with_file("/docs/file1.rst", ".. directive1::\n   :id: t1\n")
+            .with_file("/docs/file2.rst", "No directives here.\n"));
+        let (file1_path, file2_path) = (PathBuf::from("/docs/file1.rst"), PathBuf::from("/docs/file2.rst"));
+
+        let processor = Processor::new(vec!["directive1".to_string()]).with_file_provider(provider);
+        let (directives, timings, _) = processor.process_files_timed(vec![file1_path.clone(), file2_path.clone()]);
+
+        assert_eq!(directives.len(), 1);
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings[0].0, file1_path);
+        assert_eq!(timings[1].0, file2_path);
+        assert!(timings.iter().all(|(_, duration)| *duration > Duration::ZERO));
+    }
+
+    #[test]
+    fn test_quick_scan_marker() {
+        let provider = Arc::new(InMemoryFs::new()
+            .with_file("/src/with_marker.py", r#"
+def documented():
+    """
+    @rst
+    .. directive1::
+       :option1: value1
+
+       Content.
+    @endrst
+    """
+"#)
+            // Would be extracted, but the marker is spelled differently
+            .with_file("/src/without_marker.py", r#"
+def other():
+    """
+    @RST
+    .. directive1::
+    @endrst
+    """
+"#)
+            .with_file("/src/plain.rst", ".. directive1::\n\n   RST content.\n"));
+        let (with_marker, without_marker, rst_file) = (Path::new("/src/with_marker.py"), Path::new("/src/without_marker.py"), Path::new("/src/plain.rst"));
+
+        let processor = Processor::new(vec!["directive1".to_string()])
+            .with_quick_scan_marker(DEFAULT_QUICK_SCAN_MARKER)
+            .with_file_provider(provider.clone());
+
+        let found = processor.process_file(with_marker).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].directive.options.get("option1"), Some(&"value1".to_string()));
+        assert!(processor.process_file(without_marker).unwrap().is_empty());
+        // .rst files are never skipped by the quick scan
+        assert_eq!(processor.process_file(rst_file).unwrap().len(), 1);
+
+        let custom = Processor::new(vec!["directive1".to_string()]).with_quick_scan_marker("@RST").with_file_provider(provider);
+        assert!(custom.process_file(with_marker).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_quick_scan_matches_full_scan() {
+        let provider = Arc::new(InMemoryFs::new());
+        let mut file_paths = Vec::new();
+        for i in 0..10 {
+            let file_path = PathBuf::from(format!("/src/module_{}.cpp", i));
+            let content = if i % 3 == 0 {
+                format!("/// @rst\n/// .. directive1::\n///    :index: {}\n/// @endrst\nvoid f{}();\n", i, i)
+            } else {
+                format!("// plain comment\nvoid f{}();\n", i)
+            };
+            provider.write_file(&file_path, content);
+            file_paths.push(file_path);
+        }
+
+        let targets = vec!["directive1".to_string()];
+        let (mut full, _) = Processor::new(targets.clone()).with_file_provider(provider.clone()).process_files(file_paths.clone());
+        let (mut quick, _) = Processor::new(targets)
+            .with_quick_scan_marker(DEFAULT_QUICK_SCAN_MARKER)
+            .with_file_provider(provider)
+            .process_files(file_paths);
+        full.sort();
+        quick.sort();
+        assert_eq!(full.len(), 4);
+        assert_eq!(quick, full);
+    }
+
+    #[test]
+    fn test_aliases_are_reported_under_canonical_name() {
+        let provider = Arc::new(InMemoryFs::new()
+            .with_file("/docs/legacy.rst", ".. req::\n   :id: old-style\n\n.. requirement::\n\n   New style.\n\n.. rq::\n   :id: not-an-alias\n"));
+        let file_path = Path::new("/docs/legacy.rst");
+
+        let aliases = HashMap::from([("requirement".to_string(), vec!["req".to_string()])]);
+        let processor = Processor::new(vec!["requirement".to_string()]).with_aliases(aliases.clone()).with_file_provider(provider.clone());
+        let result = processor.process_file(file_path).unwrap();
+
+        assert_eq!(result.len(), 2);
+        assert!(result.iter().all(|dws| dws.directive.name == "requirement"));
+        assert_eq!(result[0].id, "old-style");
+        assert_eq!(result[1].id, "/docs/legacy.rst:requirement:4");
+
+        // Searching for the alias finds the canonical name as well
+        let by_alias = Processor::new(vec!["req".to_string()]).with_aliases(aliases).with_file_provider(provider).process_file(file_path).unwrap();
+        assert_eq!(by_alias, result);
+    }
+
+    #[test]
+    fn test_front_matter_attached_to_directives() {
+        let provider = Arc::new(InMemoryFs::new().with_file("/docs/spec.rst", r#"+++
+component = "parser"
+owners = ["alice", "bob"]
++++
+
+.. directive1::
+   :option1: value1
+
+.. directive1::
+"#));
+        let file_path = Path::new("/docs/spec.rst");
+
+        let result = Processor::new(vec!["directive1".to_string()])
+            .with_front_matter_fence(DEFAULT_FRONT_MATTER_FENCE)
+            .with_file_provider(provider.clone())
+            .process_file(file_path)
+            .unwrap();
+        assert_eq!(result.len(), 2);
+        let expected = serde_json::json!({ "component": "parser", "owners": ["alice", "bob"] });
+        assert!(result.iter().all(|dws| dws.file_metadata.as_ref() == Some(&expected)));
+        assert_eq!(result[0].line_number, Some(6)); // Line numbers still count the front-matter
+        assert_eq!(result[1].line_number, Some(9));
+
+        // Without a configured fence, nothing is attached
+        let result = Processor::new(vec!["directive1".to_string()]).with_file_provider(provider).process_file(file_path).unwrap();
+        assert!(result.iter().all(|dws| dws.file_metadata.is_none()));
+    }
+
+    #[test]
+    fn test_front_matter_custom_fence() {
+        let provider = Arc::new(InMemoryFs::new().with_file("/docs/spec.rst", "---\nstatus = \"draft\"\n---\n.. directive1::\n"));
+        let file_path = Path::new("/docs/spec.rst");
+
+        let processor = Processor::new(vec!["directive1".to_string()]).with_front_matter_fence("---").with_file_provider(provider.clone());
+        let result = processor.process_file(file_path).unwrap();
+        assert_eq!(result[0].file_metadata, Some(serde_json::json!({ "status": "draft" })));
+
+        // An opening fence that is never closed is not front-matter
+        provider.write_file(file_path, "---\nstatus = \"draft\"\n.. directive1::\n");
+        let result = processor.process_file(file_path).unwrap();
+        assert_eq!(result[0].file_metadata, None);
+    }
+
+    #[test]
+    fn test_directive_content_limit() {
+        let large_content = "   ".to_string() + &"x".repeat(1024 * 1024) + "\n";
+        let rst = format!(".. directive1::\n   :id: big\n\n{}\n.. directive1::\n   :id: small\n\n   Small content.\n", large_content);
+        let provider = Arc::new(InMemoryFs::new().with_file("/docs/large.rst", rst));
+        let file_path = Path::new("/docs/large.rst");
+
+        let processor = Processor::new(vec!["directive1".to_string()]).with_directive_content_limit(1000).with_file_provider(provider.clone());
+        let (directives, warnings) = processor.process_file_with_diagnostics(file_path).unwrap();
+        assert_eq!(directives.len(), 2);
+        assert_eq!(directives[0].directive.content, format!("{}\n[... content truncated, 1000 of {} bytes kept]", "x".repeat(1000), 1024 * 1024));
+        assert_eq!(directives[1].directive.content, "Small content.");
+        assert_eq!(
+            warnings,
+            vec![Diagnostic::warning(
+                "/docs/large.rst",
+                Some(1),
+                DiagnosticCode::ContentTruncated,
+                format!("Content of directive 'big' was truncated from {} bytes", 1024 * 1024),
+            ).with_details(ParseWarning::ContentTruncated { directive_id: "big".to_string(), original_bytes: 1024 * 1024 })]
+        );
+
+        // Unlimited by default
+        let (directives, warnings) = Processor::new(vec!["directive1".to_string()]).with_file_provider(provider).process_file_with_diagnostics(file_path).unwrap();
+        assert_eq!(directives[0].directive.content.len(), 1024 * 1024);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_process_files_with_diagnostics() {
+        let provider = Arc::new(InMemoryFs::new()
+            .with_file("/src/good.cpp", "/// @rst\n/// .. req:: Good\n/// @endrst\n")
+            .with_file("/src/open.cpp", "int x;\n\n/// @rst\n/// .. req:: Open\n"));
+        let (good, open, missing) = (PathBuf::from("/src/good.cpp"), PathBuf::from("/src/open.cpp"), PathBuf::from("/src/missing.rst"));
+
+        let processed = Processor::new(vec!["req".to_string()]).with_file_provider(provider).process_files_with_diagnostics(vec![good, open, missing.clone()]);
+        assert_eq!(processed.directives.len(), 1);
+        assert_eq!(processed.timings.len(), 3);
+        assert_eq!(processed.errors.len(), 1);
+        assert_eq!(processed.errors[0].to_diagnostic().file, missing);
+        assert_eq!(
+            processed.diagnostics,
+            vec![Diagnostic::warning(
+                "/src/open.cpp",
+                Some(3),
+                DiagnosticCode::UnterminatedBlock,
+                "Unterminated RST block at end of C++ content.",
+            )]
+        );
+    }
+
+    #[test]
+    fn test_truncate_content_at_char_boundary() {
+        let mut content = "aé".to_string(); // 'é' is two bytes
+        truncate_content(&mut content, 2);
+        assert_eq!(content, "a\n[... content truncated, 1 of 3 bytes kept]");
+    }
+
+    #[test]
+    fn test_process_content_without_file() {
+        let processor = Processor::new(vec!["directive1".to_string()]);
+        let content = "def f():\n    \"\"\"\n    @rst\n    .. directive1::\n    @endrst\n    \"\"\"\n".to_string();
+        let (directives, warnings) = processor.process_content(content, "does/not/exist.py").unwrap();
+        assert_eq!(directives.len(), 1);
+        assert_eq!(directives[0].source_file, "does/not/exist.py");
+        assert_eq!(directives[0].id, "does/not/exist.py:directive1:1");
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_byte_ranges_of_rst_and_extracted_directives() {
+        let processor = Processor::new(vec!["req".to_string()]);
+        let content = "Title\n=====\n\n.. req:: First\n   :id: R1\n\nText.\n";
+        let (directives, _) = processor.process_content(content.to_string(), "doc.rst").unwrap();
+        let source_range = directives[0].source_byte_range.clone().unwrap();
+        assert_eq!(&content[source_range], ".. req:: First\n   :id: R1\n");
+        assert_eq!(directives[0].byte_range, directives[0].source_byte_range);
+
+        // Extracted RST is no copy of the source, so only the range in the extracted text is known
+        let content = "/// @rst\n/// .. req:: First\n/// @endrst\nvoid f();\n".to_string();
+        let (directives, _) = processor.process_content(content, "lib.cpp").unwrap();
+        assert!(directives[0].byte_range.is_some());
+        assert_eq!(directives[0].source_byte_range, None);
+    }
+
+    #[test]
+    fn test_with_domain() {
+        let processor = Processor::new(vec!["function".to_string()]).with_domain("c");
+        let content = ".. function:: plain()\n\n.. c:function:: int f(void)\n".to_string();
+        let (directives, _) = processor.process_content(content, "api.rst").unwrap();
+        assert_eq!(directives.len(), 1);
+        assert_eq!(directives[0].directive.name, "function");
+        assert_eq!(directives[0].directive.options.get("_domain").map(String::as_str), Some("c"));
+        assert_eq!(directives[0].id, "api.rst:function:3");
+    }
+
+    #[test]
+    fn test_with_encoding_utf16le() {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(".. req:: Décodé\n   :id: R1\n".encode_utf16().flat_map(u16::to_le_bytes));
+        let provider = Arc::new(InMemoryFs::new().with_file("/docs/legacy.rst", bytes));
+        let file_path = Path::new("/docs/legacy.rst");
+
+        assert!(Processor::new(vec!["req".to_string()]).with_file_provider(provider.clone()).process_file(file_path).is_err(), "UTF-8 is still the default");
+
+        let processor = Processor::new(vec!["req".to_string()]).with_encoding(encoding_rs::UTF_16LE).with_file_provider(provider);
+        let directives = processor.process_file(file_path).unwrap();
+        assert_eq!(directives.len(), 1);
+        assert_eq!(directives[0].id, "R1");
+        assert_eq!(directives[0].directive.arguments, "Décodé");
+    }
+
+    #[test]
+    fn test_process_error_variants_in_memory() {
+        let provider = Arc::new(InMemoryFs::new()
+            .with_file("/docs/binary.rst", [0xff, 0xfe, 0x00, 0x80])
+            .with_file("/docs/dir.rst/index.rst", ""));
+        let processor = Processor::new(vec!["req".to_string()]).with_file_provider(provider);
+
+        let missing = PathBuf::from("/docs/missing.rst");
+        let err = processor.process_file(&missing).unwrap_err();
+        assert!(matches!(&err, RstParserError::Io { path, source } if *path == missing && source.kind() == std::io::ErrorKind::NotFound));
+        assert!(matches!(processor.process_file(Path::new("/docs/binary.rst")), Err(RstParserError::Extraction { .. })));
+        assert!(matches!(processor.process_file(Path::new("/docs/dir.rst")), Err(RstParserError::Io { path, .. }) if path == Path::new("/docs/dir.rst")));
+    }
+
+    #[test]
+    fn test_process_files_returns_errors_alongside_results() {
+        let provider = Arc::new(InMemoryFs::new().with_file("/docs/good.rst", ".. req::\n   :id: R1\n"));
+        let missing = PathBuf::from("/docs/missing.rst");
+
+        let (directives, errors) = Processor::new(vec!["req".to_string()])
+            .with_file_provider(provider)
+            .process_files(vec![missing.clone(), PathBuf::from("/docs/good.rst")]);
+        assert_eq!(directives.len(), 1);
+        assert_eq!(directives[0].id, "R1");
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], RstParserError::Io { path, .. } if *path == missing));
+    }
+
+    #[test]
+    fn test_missing_directives() {
+        let provider = Arc::new(InMemoryFs::new()
+            .with_file("/docs/a.rst", ".. req::\n   :id: R1\n")
+            .with_file("/docs/b.rst", ".. requirement::\n   :id: R2\n\n.. test::\n   :id: T1\n"));
+        let processor = Processor::new(vec!["req".to_string(), "design".to_string(), "test".to_string()]).with_file_provider(provider);
+        assert_eq!(processor.missing_directives(), vec!["req", "design", "test"]);
+
+        let (_, errors) = processor.process_files(vec![PathBuf::from("/docs/a.rst"), PathBuf::from("/docs/b.rst")]);
+        assert!(errors.is_empty());
+        assert_eq!(processor.missing_directives(), vec!["design"]);
+
+        // Requested by an alias, found under the canonical name
+        let provider = Arc::new(InMemoryFs::new().with_file("/docs/a.rst", ".. req::\n   :id: R1\n"));
+        let processor = Processor::new(vec!["requirement".to_string()])
+            .with_aliases(HashMap::from([("req".to_string(), vec!["requirement".to_string()])]))
+            .with_file_provider(provider);
+        processor.process_file("/docs/a.rst").unwrap();
+        assert!(processor.missing_directives().is_empty());
+    }
+
+    #[test]
+    fn test_process_file_with_rst_markers() {
+        let provider = Arc::new(InMemoryFs::new()
+            .with_file("/docs/doc.rst", ".. req:: Unmarked\n   :id: outside\n\n.. @rst\n\n.. req:: Marked\n   :id: inside\n\n.. @endrst\n"));
+        let file_path = Path::new("/docs/doc.rst");
+
+        let config = ExtractorConfig { rst_markers: true, ..ExtractorConfig::default() };
+        let processor = Processor::new(vec!["req".to_string()]).with_extractor_config(config).with_file_provider(provider.clone());
+        let ids: Vec<String> = processor.process_file(file_path).unwrap().into_iter().map(|dws| dws.id).collect();
+        assert_eq!(ids, vec!["inside"]);
+
+        let processor = Processor::new(vec!["req".to_string()]).with_file_provider(provider);
+        let ids: Vec<String> = processor.process_file(file_path).unwrap().into_iter().map(|dws| dws.id).collect();
+        assert_eq!(ids, vec!["outside", "inside"]);
+    }
+
+    #[test]
+    fn test_progress_called_once_per_file() {
+        let provider = Arc::new(InMemoryFs::new());
+        let files: Vec<PathBuf> = (0..5).map(|index| {
+            let path = PathBuf::from(format!("/docs/doc{}.rst", index));
+            provider.write_file(&path, ".. note:: Hi\n");
+            path
+        }).collect();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let max_completed = Arc::new(AtomicUsize::new(0));
+        let (calls_in_callback, max_in_callback) = (Arc::clone(&calls), Arc::clone(&max_completed));
+        let processor = Processor::new(vec!["note".to_string()]).with_file_provider(provider).with_progress(Box::new(move |completed, total| {
+            assert_eq!(total, 5);
+            calls_in_callback.fetch_add(1, AtomicOrdering::SeqCst);
+            max_in_callback.fetch_max(completed, AtomicOrdering::SeqCst);
+        }));
+        let (directives, errors) = processor.process_files(files);
+        assert_eq!((directives.len(), errors.len()), (5, 0));
+        assert_eq!(calls.load(AtomicOrdering::SeqCst), 5);
+        assert_eq!(max_completed.load(AtomicOrdering::SeqCst), 5);
     }
 }
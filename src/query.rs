@@ -0,0 +1,286 @@
+use crate::aggregator::DirectiveWithSource;
+use crate::directive_functions::{DirectivesMapOf, StoredDirective};
+use crate::link_data::LinkGraph;
+use crate::store::DirectiveStore;
+
+/// A single condition a directive has to satisfy to be selected by a [`DirectiveQuery`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Predicate {
+    /// The directive has this name.
+    Name(String),
+    /// The option is present with exactly this value.
+    OptionEquals(String, String),
+    /// The option is missing or has another value.
+    OptionNotEquals(String, String),
+    /// The option is present, with any value.
+    HasOption(String),
+    /// The source file matches this glob, see [`glob_matches_path`].
+    SourceGlob(String),
+    /// The directive has outgoing or incoming links, only via this link field if one is given.
+    /// Incoming links count under the link field name, without the `_back` suffix.
+    Linked(Option<String>),
+    /// The opposite of `Linked`.
+    NotLinked(Option<String>),
+}
+
+impl Predicate {
+    /// Parses an option condition: `key=value`, `key!=value` or `has:key`.
+    pub fn parse_option_condition(condition: &str) -> Result<Self, String> {
+        let condition = condition.trim();
+        let predicate = if let Some(key) = condition.strip_prefix("has:") {
+            Predicate::HasOption(key.trim().to_string())
+        } else if let Some((key, value)) = condition.split_once("!=") {
+            Predicate::OptionNotEquals(key.trim().to_string(), value.trim().to_string())
+        } else if let Some((key, value)) = condition.split_once('=') {
+            Predicate::OptionEquals(key.trim().to_string(), value.trim().to_string())
+        } else {
+            return Err(format!("Invalid option condition '{}', expected key=value, key!=value or has:key", condition));
+        };
+        match &predicate {
+            Predicate::HasOption(key) | Predicate::OptionEquals(key, _) | Predicate::OptionNotEquals(key, _) if key.is_empty() => {
+                Err(format!("Invalid option condition '{}', the option key is empty", condition))
+            }
+            _ => Ok(predicate),
+        }
+    }
+
+    pub fn matches(&self, dws: &DirectiveWithSource, link_graph: &LinkGraph) -> bool {
+        let options = &dws.directive.options;
+        match self {
+            Predicate::Name(name) => dws.directive.name == *name,
+            Predicate::OptionEquals(key, value) => options.get(key) == Some(value),
+            Predicate::OptionNotEquals(key, value) => options.get(key) != Some(value),
+            Predicate::HasOption(key) => options.contains_key(key),
+            Predicate::SourceGlob(pattern) => glob_matches_path(pattern, &dws.source_file),
+            Predicate::Linked(field) => is_linked(&dws.id, field.as_deref(), link_graph),
+            Predicate::NotLinked(field) => !is_linked(&dws.id, field.as_deref(), link_graph),
+        }
+    }
+}
+
+fn is_linked(id: &str, field: Option<&str>, link_graph: &LinkGraph) -> bool {
+    let Some(node_data) = link_graph.get(id) else {
+        return false;
+    };
+    let outgoing = node_data.outgoing_links.iter().map(|(field_name, ids)| (field_name.as_str(), ids));
+    let incoming = node_data.incoming_links.iter()
+        .map(|(field_name, ids)| (field_name.strip_suffix("_back").unwrap_or(field_name), ids));
+    outgoing.chain(incoming)
+        .any(|(field_name, ids)| !ids.is_empty() && field.is_none_or(|field| field == field_name))
+}
+
+/// Selects the directives that satisfy all of its predicates.
+#[derive(Debug, Clone, Default)]
+pub struct DirectiveQuery {
+    predicates: Vec<Predicate>,
+}
+
+impl DirectiveQuery {
+    pub fn new() -> Self {
+        DirectiveQuery::default()
+    }
+
+    /// Add a condition; a directive has to satisfy all of them.
+    pub fn with_predicate(mut self, predicate: Predicate) -> Self {
+        self.predicates.push(predicate);
+        self
+    }
+
+    pub fn matches(&self, dws: &DirectiveWithSource, link_graph: &LinkGraph) -> bool {
+        self.predicates.iter().all(|predicate| predicate.matches(dws, link_graph))
+    }
+
+    /// Returns copies of all matching directives, in source order.
+    pub fn run<D: StoredDirective>(&self, directives_map: &DirectivesMapOf<D>, link_graph: &LinkGraph) -> Vec<DirectiveWithSource> {
+        let mut results: Vec<DirectiveWithSource> = directives_map.values()
+            .flat_map(|file_map| file_map.values())
+            .map(|stored_directive| stored_directive.read())
+            .filter(|dws| self.matches(dws, link_graph))
+            .map(|dws| dws.clone())
+            .collect();
+        results.sort();
+        results
+    }
+
+    /// Same as `run`, but only looks at the directives the store's indexes select for the
+    /// first name or `key=value` predicate, if there is one.
+    pub fn run_on_store(&self, store: &DirectiveStore) -> Vec<DirectiveWithSource> {
+        let indexed = self.predicates.iter().find_map(|predicate| match predicate {
+            Predicate::Name(name) => Some(Box::new(store.by_name(name)) as Box<dyn Iterator<Item = _>>),
+            Predicate::OptionEquals(key, value) => Some(Box::new(store.with_option(key, value))),
+            _ => None,
+        });
+        let candidates = indexed.unwrap_or_else(|| Box::new(store.iter()));
+        let mut results: Vec<DirectiveWithSource> = candidates
+            .filter(|dws| self.matches(dws, store.link_graph()))
+            .cloned()
+            .collect();
+        results.sort();
+        results
+    }
+}
+
+/// Matches `path` against a glob where `*` matches within one path component, `**` matches
+/// any number of components and `?` matches one character. A pattern starting with `/` must
+/// match the whole path; any other pattern may also match a trailing part of it, so
+/// `src/module_x/**` matches every file below any `src/module_x` directory.
+pub fn glob_matches_path(pattern: &str, path: &str) -> bool {
+    let path = path.replace('\\', "/");
+    let pattern: Vec<char> = pattern.chars().collect();
+    if pattern.first() == Some(&'/') {
+        return glob_matches(&pattern, &path.chars().collect::<Vec<_>>());
+    }
+    let path: Vec<char> = path.chars().collect();
+    std::iter::once(0)
+        .chain(path.iter().enumerate().filter(|(_, c)| **c == '/').map(|(index, _)| index + 1))
+        .any(|start| glob_matches(&pattern, &path[start..]))
+}
+
+fn glob_matches(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        ['*', '*', '/', rest @ ..] => {
+            // `**/` matches zero or more whole components
+            glob_matches(rest, path)
+                || path.iter().enumerate().any(|(index, c)| *c == '/' && glob_matches(rest, &path[index + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=path.len()).any(|skip| glob_matches(rest, &path[skip..])),
+        ['*', rest @ ..] => {
+            let component_len = path.iter().position(|c| *c == '/').unwrap_or(path.len());
+            (0..=component_len).any(|skip| glob_matches(rest, &path[skip..]))
+        }
+        ['?', rest @ ..] => path.first().is_some_and(|c| *c != '/') && glob_matches(rest, &path[1..]),
+        [c, rest @ ..] => path.first() == Some(c) && glob_matches(rest, &path[1..]),
+    }
+}
+
+/// Formats query results as an aligned table with ID, directive name and location columns.
+pub fn format_results_table(results: &[DirectiveWithSource]) -> String {
+    let rows: Vec<[String; 3]> = results.iter()
+        .map(|dws| {
+            let location = match dws.line_number {
+                Some(line) => format!("{}:{}", dws.short_source_file(), line),
+                None => dws.short_source_file().to_string(),
+            };
+            [dws.display_id().to_string(), dws.directive.name.clone(), location]
+        })
+        .collect();
+    let header = ["ID".to_string(), "Directive".to_string(), "Location".to_string()];
+    let width = |column: usize| rows.iter().chain([&header]).map(|row| row[column].len()).max().unwrap_or(0);
+    let (id_width, name_width) = (width(0), width(1));
+
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        table.push_str(&format!("{:<id_width$}  {:<name_width$}  {}\n", row[0], row[1], row[2]));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directive_functions::AllDirectivesMap;
+    use crate::link_data::LinkNodeData;
+    use crate::testing::new_dws;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    fn sample() -> (AllDirectivesMap, LinkGraph) {
+        let directives = [
+            new_dws("req", "/repo/src/module_x/reqs.rst", 1, "REQ-1", &[("status", "open")]),
+            new_dws("req", "/repo/src/module_x/reqs.rst", 8, "REQ-2", &[("status", "done")]),
+            new_dws("req", "/repo/src/module_y/reqs.rst", 1, "REQ-3", &[("status", "open"), ("links", "REQ-1")]),
+            new_dws("note", "/repo/src/module_x/notes.rst", 3, "NOTE-1", &[]),
+        ];
+        let mut directives_map: AllDirectivesMap = HashMap::new();
+        for dws in directives {
+            directives_map.entry(PathBuf::from(&dws.source_file)).or_default()
+                .insert(dws.id.clone(), Arc::new(Mutex::new(dws)));
+        }
+        let mut link_graph = LinkGraph::new();
+        let mut source = LinkNodeData::default();
+        source.outgoing_links.insert("links".to_string(), vec!["REQ-1".to_string()]);
+        link_graph.insert("REQ-3".to_string(), source);
+        let mut target = LinkNodeData::default();
+        target.incoming_links.insert("links_back".to_string(), vec!["REQ-3".to_string()]);
+        link_graph.insert("REQ-1".to_string(), target);
+        (directives_map, link_graph)
+    }
+
+    /// The IDs `query` selects, checking that the indexed store lookup agrees with the full scan.
+    fn result_ids(query: &DirectiveQuery) -> Vec<String> {
+        let (directives_map, link_graph) = sample();
+        let results = query.run(&directives_map, &link_graph);
+        assert_eq!(query.run_on_store(&DirectiveStore::from_map(&directives_map, link_graph)), results);
+        results.into_iter().map(|dws| dws.id).collect()
+    }
+
+    #[test]
+    fn test_parse_option_condition() {
+        assert_eq!(Predicate::parse_option_condition("status=open"), Ok(Predicate::OptionEquals("status".to_string(), "open".to_string())));
+        assert_eq!(Predicate::parse_option_condition("status != open"), Ok(Predicate::OptionNotEquals("status".to_string(), "open".to_string())));
+        assert_eq!(Predicate::parse_option_condition("has:links"), Ok(Predicate::HasOption("links".to_string())));
+        assert!(Predicate::parse_option_condition("status").is_err());
+        assert!(Predicate::parse_option_condition("=open").is_err());
+    }
+
+    #[test]
+    fn test_query_by_name_and_options() {
+        let query = DirectiveQuery::new()
+            .with_predicate(Predicate::Name("req".to_string()))
+            .with_predicate(Predicate::parse_option_condition("status=open").unwrap());
+        assert_eq!(result_ids(&query), vec!["REQ-1", "REQ-3"]);
+
+        let query = DirectiveQuery::new().with_predicate(Predicate::parse_option_condition("status!=open").unwrap());
+        assert_eq!(result_ids(&query), vec!["NOTE-1", "REQ-2"]);
+
+        let query = DirectiveQuery::new().with_predicate(Predicate::HasOption("links".to_string()));
+        assert_eq!(result_ids(&query), vec!["REQ-3"]);
+    }
+
+    #[test]
+    fn test_query_by_source_glob() {
+        let query = DirectiveQuery::new().with_predicate(Predicate::SourceGlob("src/module_x/**".to_string()));
+        assert_eq!(result_ids(&query), vec!["NOTE-1", "REQ-1", "REQ-2"]);
+
+        let query = DirectiveQuery::new().with_predicate(Predicate::SourceGlob("notes.rst".to_string()));
+        assert_eq!(result_ids(&query), vec!["NOTE-1"]);
+    }
+
+    #[test]
+    fn test_query_by_links() {
+        let query = DirectiveQuery::new().with_predicate(Predicate::Linked(None));
+        assert_eq!(result_ids(&query), vec!["REQ-1", "REQ-3"]);
+
+        let query = DirectiveQuery::new().with_predicate(Predicate::Linked(Some("links".to_string())));
+        assert_eq!(result_ids(&query), vec!["REQ-1", "REQ-3"]);
+        let query = DirectiveQuery::new().with_predicate(Predicate::Linked(Some("tests".to_string())));
+        assert!(result_ids(&query).is_empty());
+
+        let query = DirectiveQuery::new()
+            .with_predicate(Predicate::Name("req".to_string()))
+            .with_predicate(Predicate::NotLinked(None));
+        assert_eq!(result_ids(&query), vec!["REQ-2"]);
+    }
+
+    #[test]
+    fn test_glob_matches_path() {
+        assert!(glob_matches_path("/repo/src/*.rst", "/repo/src/a.rst"));
+        assert!(!glob_matches_path("/repo/src/*.rst", "/repo/src/sub/a.rst"));
+        assert!(glob_matches_path("/repo/**/a.rst", "/repo/a.rst"));
+        assert!(glob_matches_path("/repo/**/a.rst", "/repo/src/sub/a.rst"));
+        assert!(glob_matches_path("sub/?.rst", "/repo/src/sub/a.rst"));
+        assert!(!glob_matches_path("rc/sub/a.rst", "/repo/src/sub/a.rst")); // Only whole components
+        assert!(!glob_matches_path("/src/**", "/repo/src/a.rst"));
+    }
+
+    #[test]
+    fn test_format_results_table() {
+        let results = vec![new_dws("req", "/repo/reqs.rst", 12, "REQ-1", &[]), new_dws("note", "/repo/notes.rst", 3, "/repo/notes.rst:note:3", &[])];
+        assert_eq!(
+            format_results_table(&results),
+            "ID      Directive  Location\nREQ-1   req        reqs.rst:12\nnote:3  note       notes.rst:3\n"
+        );
+    }
+}
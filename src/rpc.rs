@@ -0,0 +1,273 @@
+//! A small JSON-RPC 2.0 server for editor integration, run with `rstparser --serve`. It is no
+//! full language server, but uses the same framing: every message is a JSON object preceded by a
+//! `Content-Length: <bytes>` header and a blank line (`\r\n\r\n`), on stdin and stdout.
+//!
+//! Requests:
+//!
+//! - `parseBuffer` with `{"text": "...", "path": "src/lib.cpp"}` parses unsaved text as if it
+//!   were the file `path` (optional, default `buffer.rst`); its extension selects the extractor.
+//! - `getDirectives` with `{"path": "docs/index.rst"}` reads and parses a file.
+//! - `shutdown` without parameters answers `null` and stops the server; so does the end of input.
+//!
+//! Both parse requests answer `{"directives": [...], "diagnostics": [...]}`. Each directive has
+//! the fields of the JSON outputs plus its `byte_range` in the parsed RST and, for `.rst` files,
+//! `source_byte_range` in the file, each as `{"start": .., "end": ..}` or `null`. Messages without
+//! an `id` are notifications and get no response. Errors use the JSON-RPC codes: `-32700` for
+//! invalid JSON, `-32600` for an invalid request, `-32601` for unknown methods, `-32602` for
+//! invalid parameters and `-32603` for files that cannot be processed.
+
+use std::io::{self, BufRead, Read, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::aggregator::{DirectiveOutput, DirectiveWithSource};
+use crate::diagnostics::Diagnostic;
+use crate::processor::Processor;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Largest message body `read_frame` accepts.
+pub const MAX_FRAME_BYTES: usize = 64 * 1024 * 1024;
+
+/// Path assumed for `parseBuffer` requests without one.
+const DEFAULT_BUFFER_PATH: &str = "buffer.rst";
+
+#[derive(Deserialize)]
+struct ParseBufferParams {
+    text: String,
+    path: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct GetDirectivesParams {
+    path: PathBuf,
+}
+
+/// A directive in a response, with its spans.
+#[derive(Serialize)]
+struct SpannedOutput {
+    #[serde(flatten)]
+    output: DirectiveOutput,
+    byte_range: Option<Range<usize>>,
+    source_byte_range: Option<Range<usize>>,
+}
+
+#[derive(Serialize)]
+struct ParseResult {
+    directives: Vec<SpannedOutput>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl ParseResult {
+    fn new((directives, diagnostics): (Vec<DirectiveWithSource>, Vec<Diagnostic>)) -> Self {
+        let directives = directives.into_iter()
+            .map(|dws| SpannedOutput {
+                output: DirectiveOutput::from(&dws),
+                byte_range: dws.byte_range,
+                source_byte_range: dws.source_byte_range,
+            })
+            .collect();
+        ParseResult { directives, diagnostics }
+    }
+}
+
+/// A JSON-RPC error: its code and message.
+type RpcError = (i64, String);
+
+/// Answers requests with the directives `processor` finds, see the module documentation.
+pub struct RpcServer {
+    processor: Processor,
+}
+
+impl RpcServer {
+    pub fn new(processor: Processor) -> Self {
+        RpcServer { processor }
+    }
+
+    /// Answers framed requests from `input` on `output` until a `shutdown` request or the end
+    /// of `input`. Fails on I/O errors and malformed frames.
+    pub fn serve<R: BufRead, W: Write>(&self, mut input: R, mut output: W) -> io::Result<()> {
+        while let Some(body) = read_frame(&mut input)? {
+            let (response, shutdown) = self.handle_message(&body);
+            if let Some(response) = response {
+                write_frame(&mut output, &response)?;
+            }
+            if shutdown {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// The response to one message, `None` for notifications, and whether it asked the server
+    /// to shut down.
+    pub fn handle_message(&self, body: &[u8]) -> (Option<Value>, bool) {
+        let request: Value = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(err) => return (Some(error_response(Value::Null, (PARSE_ERROR, err.to_string()))), false),
+        };
+        let id = request.get("id").cloned();
+        let Some(method) = request.get("method").and_then(Value::as_str) else {
+            return (Some(error_response(id.unwrap_or(Value::Null), (INVALID_REQUEST, "Missing method".to_string()))), false);
+        };
+        let shutdown = method == "shutdown";
+        let result = self.call(method, request.get("params").cloned().unwrap_or(Value::Null));
+        let response = id.map(|id| match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => error_response(id, err),
+        });
+        (response, shutdown)
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let parsed = match method {
+            "parseBuffer" => {
+                let params: ParseBufferParams = parse_params(params)?;
+                let path = params.path.unwrap_or_else(|| PathBuf::from(DEFAULT_BUFFER_PATH));
+                self.processor.process_content(params.text, path)
+            }
+            "getDirectives" => {
+                let params: GetDirectivesParams = parse_params(params)?;
+                self.processor.process_file_with_diagnostics(params.path)
+            }
+            "shutdown" => return Ok(Value::Null),
+            _ => return Err((METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+        };
+        let result = parsed.map_err(|err| (INTERNAL_ERROR, err.to_string()))?;
+        serde_json::to_value(ParseResult::new(result)).map_err(|err| (INTERNAL_ERROR, err.to_string()))
+    }
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|err| (INVALID_PARAMS, err.to_string()))
+}
+
+fn error_response(id: Value, (code, message): RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// Reads the body of one `Content-Length` framed message, or `None` at the end of `input`.
+/// Other headers are ignored. Fails with `io::ErrorKind::InvalidData` if the announced length
+/// exceeds `MAX_FRAME_BYTES`, and with `UnexpectedEof` if `input` ends before the body does.
+pub fn read_frame<R: BufRead>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue; // Blank lines between messages
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.trim().eq_ignore_ascii_case("Content-Length")
+        {
+            let length = value.trim().parse::<usize>()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid Content-Length '{}'", value.trim())))?;
+            content_length = Some(length);
+        }
+    }
+    let content_length = content_length.unwrap_or_default();
+    if content_length > MAX_FRAME_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Content-Length {} exceeds the limit of {} bytes", content_length, MAX_FRAME_BYTES),
+        ));
+    }
+    // Grows with the data actually received instead of trusting the header up front
+    let mut body = Vec::new();
+    input.take(content_length as u64).read_to_end(&mut body)?;
+    if body.len() < content_length {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Message body ends before its Content-Length"));
+    }
+    Ok(Some(body))
+}
+
+/// Writes `message` with a `Content-Length` header and flushes `output`.
+pub fn write_frame<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = serde_json::to_vec(message)?;
+    write!(output, "Content-Length: {}\r\n\r\n", body.len())?;
+    output.write_all(&body)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn frame(message: Value) -> Vec<u8> {
+        let mut framed = Vec::new();
+        write_frame(&mut framed, &message).unwrap();
+        framed
+    }
+
+    fn responses(output: Vec<u8>) -> Vec<Value> {
+        let mut output = Cursor::new(output);
+        let mut responses = Vec::new();
+        while let Some(body) = read_frame(&mut output).unwrap() {
+            responses.push(serde_json::from_slice(&body).unwrap());
+        }
+        responses
+    }
+
+    #[test]
+    fn test_parse_buffer_request() {
+        let server = RpcServer::new(Processor::new(vec!["req".to_string()]));
+        let text = "Title\n=====\n\n.. req:: Parse\n   :id: REQ-1\n";
+        let input = frame(json!({ "jsonrpc": "2.0", "id": 1, "method": "parseBuffer", "params": { "text": text, "path": "docs/reqs.rst" } }));
+
+        let mut output = Vec::new();
+        server.serve(Cursor::new(input), &mut output).unwrap();
+        let responses = responses(output);
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0]["id"], 1);
+        let directive = &responses[0]["result"]["directives"][0];
+        assert_eq!(directive["id"], "REQ-1");
+        assert_eq!(directive["arguments"], "Parse");
+        assert_eq!(directive["line_number"], 4);
+        assert_eq!(directive["source_file"], "docs/reqs.rst");
+        assert_eq!(directive["source_byte_range"], json!({ "start": 13, "end": 42 }));
+        assert_eq!(responses[0]["result"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn test_errors_notifications_and_shutdown() {
+        let server = RpcServer::new(Processor::new(vec!["req".to_string()]));
+        let mut input = b"Content-Length: 5\r\n\r\n{oops".to_vec();
+        input.extend(frame(json!({ "jsonrpc": "2.0", "id": 2, "method": "format" })));
+        input.extend(frame(json!({ "jsonrpc": "2.0", "id": 3, "method": "getDirectives", "params": {} })));
+        input.extend(frame(json!({ "jsonrpc": "2.0", "id": 4, "method": "getDirectives", "params": { "path": "does/not/exist.rst" } })));
+        input.extend(frame(json!({ "jsonrpc": "2.0", "method": "parseBuffer", "params": { "text": "" } })));
+        input.extend(frame(json!({ "jsonrpc": "2.0", "id": 5, "method": "shutdown" })));
+        input.extend(frame(json!({ "jsonrpc": "2.0", "id": 6, "method": "shutdown" })));
+
+        let mut output = Vec::new();
+        server.serve(Cursor::new(input), &mut output).unwrap();
+        let responses = responses(output);
+        let codes: Vec<&Value> = responses.iter().map(|response| &response["error"]["code"]).collect();
+        assert_eq!(codes, vec![&json!(PARSE_ERROR), &json!(METHOD_NOT_FOUND), &json!(INVALID_PARAMS), &json!(INTERNAL_ERROR), &Value::Null]);
+        assert_eq!(responses[4], json!({ "jsonrpc": "2.0", "id": 5, "result": null }));
+    }
+
+    #[test]
+    fn test_read_frame_limits() {
+        let oversized = format!("Content-Length: {}\r\n\r\n{{}}", MAX_FRAME_BYTES + 1);
+        let err = read_frame(&mut Cursor::new(oversized)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("exceeds the limit"));
+
+        let truncated = b"Content-Length: 10\r\n\r\n{}".to_vec();
+        assert_eq!(read_frame(&mut Cursor::new(truncated)).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(read_frame(&mut Cursor::new(frame(json!({ "id": 1 })))).unwrap().unwrap(), br#"{"id":1}"#);
+    }
+}
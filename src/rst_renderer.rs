@@ -0,0 +1,112 @@
+//! A minimal RST to HTML renderer for directive content, used by
+//! `Aggregator::with_rendered_content`. It is no replacement for docutils: it only knows
+//! paragraphs, bullet lists (`*`, `-` or `+` items, continued by indented lines) and the inline
+//! markup `**strong**` and `*emphasis*`. Everything else is rendered as paragraph text, escaped.
+
+const BULLETS: [&str; 3] = ["* ", "- ", "+ "];
+
+/// Renders `rst` as HTML, one element per block of lines separated by blank lines, joined by
+/// newlines. Empty content renders as an empty string.
+pub fn render_html(rst: &str) -> String {
+    let mut blocks = Vec::new();
+    let mut block = Vec::new();
+    for line in rst.lines() {
+        if line.trim().is_empty() {
+            if !block.is_empty() {
+                blocks.push(render_block(&block));
+                block.clear();
+            }
+        } else {
+            block.push(line);
+        }
+    }
+    if !block.is_empty() {
+        blocks.push(render_block(&block));
+    }
+    blocks.join("\n")
+}
+
+fn bullet_item(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    BULLETS.iter().find_map(|bullet| trimmed.strip_prefix(bullet).or((trimmed == bullet.trim_end()).then_some("")))
+}
+
+/// A bullet list if the block starts with an item, otherwise a paragraph.
+fn render_block(lines: &[&str]) -> String {
+    if bullet_item(lines[0]).is_none() {
+        let text: Vec<&str> = lines.iter().map(|line| line.trim()).collect();
+        return format!("<p>{}</p>", render_inline(&text.join("\n")));
+    }
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let list_indent = indent(lines[0]);
+    let mut items: Vec<Vec<&str>> = Vec::new();
+    for line in lines {
+        match bullet_item(line) {
+            Some(item) if indent(line) <= list_indent => items.push(vec![item.trim()]),
+            _ => items.last_mut().expect("the block starts with an item").push(line.trim()),
+        }
+    }
+    let items: String = items.iter().map(|item| format!("<li>{}</li>", render_inline(&item.join(" ")))).collect();
+    format!("<ul>{}</ul>", items)
+}
+
+/// Escapes `text` and renders its `**strong**` and `*emphasis*` markup. As in RST, markup must
+/// not start before or end after whitespace, so `2 * 3 * 4` is kept as it is; unmatched
+/// markers are kept too.
+fn render_inline(text: &str) -> String {
+    let mut html = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('*') {
+        html.push_str(&escape(&rest[..start]));
+        let (marker, tag) = if rest[start..].starts_with("**") { ("**", "strong") } else { ("*", "em") };
+        let inner = &rest[start + marker.len()..];
+        match markup_end(inner, marker) {
+            Some(end) => {
+                html.push_str(&format!("<{tag}>{}</{tag}>", escape(&inner[..end])));
+                rest = &inner[end + marker.len()..];
+            }
+            None => {
+                html.push_str(marker);
+                rest = inner;
+            }
+        }
+    }
+    html.push_str(&escape(rest));
+    html
+}
+
+/// The position of the `marker` closing markup that starts at `inner`.
+fn markup_end(inner: &str, marker: &str) -> Option<usize> {
+    if inner.starts_with(char::is_whitespace) {
+        return None;
+    }
+    inner.match_indices(marker)
+        .map(|(end, _)| end)
+        .find(|&end| inner[..end].ends_with(|c: char| !c.is_whitespace() && c != '*'))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_blocks() {
+        assert_eq!(render_html("* item 1\n* item 2"), "<ul><li>item 1</li><li>item 2</li></ul>");
+        assert_eq!(
+            render_html("First line\nsecond line.\n\n- one\n  continued\n- two\n\nEnd"),
+            "<p>First line\nsecond line.</p>\n<ul><li>one continued</li><li>two</li></ul>\n<p>End</p>"
+        );
+        assert_eq!(render_html(""), "");
+    }
+
+    #[test]
+    fn test_render_inline_markup() {
+        assert_eq!(render_html("A **bold** and *italic* <word>"), "<p>A <strong>bold</strong> and <em>italic</em> &lt;word&gt;</p>");
+        assert_eq!(render_html("2 * 3 * 4 and *open"), "<p>2 * 3 * 4 and *open</p>");
+        assert_eq!(render_html("* **Must** work"), "<ul><li><strong>Must</strong> work</li></ul>");
+    }
+}
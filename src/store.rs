@@ -0,0 +1,249 @@
+//! Indexed lookups into processed directives.
+
+use std::collections::HashMap;
+use crate::aggregator::DirectiveWithSource;
+use crate::directive_functions::{DirectivesMapOf, StoredDirective};
+use crate::link_data::LinkGraph;
+
+/// Identifies a stored directive by source file, line and ID. The ID alone does not: duplicate
+/// IDs are reported by validation, and the store keeps every directive that has one.
+type DirectiveKey = (String, Option<usize>, String);
+
+fn key_of(dws: &DirectiveWithSource) -> DirectiveKey {
+    (dws.source_file.clone(), dws.line_number, dws.id.clone())
+}
+
+/// Processed directives with their link graph, indexed by ID, name, option value and source
+/// file. Lookups yield directives in insertion order; `new` inserts in source order.
+///
+/// A directive is identified by its source file, line and ID, so directives sharing an ID are
+/// all kept; inserting a directive at the same place with the same ID replaces the stored one.
+/// `insert` and `remove_file` keep the indexes up to date, so that the store can follow
+/// incremental changes; the link graph is replaced as a whole with `set_link_graph`.
+#[derive(Debug, Clone, Default)]
+pub struct DirectiveStore {
+    directives: HashMap<DirectiveKey, DirectiveWithSource>,
+    by_id: HashMap<String, Vec<DirectiveKey>>,
+    by_name: HashMap<String, Vec<DirectiveKey>>,
+    by_option: HashMap<(String, String), Vec<DirectiveKey>>,
+    by_file: HashMap<String, Vec<DirectiveKey>>,
+    /// (target ID, link type) -> IDs of the directives linking to the target
+    incoming: HashMap<(String, String), Vec<String>>,
+    link_graph: LinkGraph,
+}
+
+impl DirectiveStore {
+    pub fn new(mut directives: Vec<DirectiveWithSource>, link_graph: LinkGraph) -> Self {
+        directives.sort();
+        let mut store = DirectiveStore::default();
+        for dws in directives {
+            store.insert(dws);
+        }
+        store.set_link_graph(link_graph);
+        store
+    }
+
+    /// Builds a store from a directive map, like the one `FunctionApplicator::apply_to_all` takes.
+    pub fn from_map<D: StoredDirective>(directives_map: &DirectivesMapOf<D>, link_graph: LinkGraph) -> Self {
+        let directives = directives_map.values()
+            .flat_map(|file_map| file_map.values())
+            .map(|stored_directive| stored_directive.read().clone())
+            .collect();
+        Self::new(directives, link_graph)
+    }
+
+    /// Adds `dws`, replacing a stored directive with the same source file, line and ID.
+    pub fn insert(&mut self, dws: DirectiveWithSource) {
+        let key = key_of(&dws);
+        self.remove_key(&key);
+        self.by_id.entry(dws.id.clone()).or_default().push(key.clone());
+        self.by_name.entry(dws.directive.name.clone()).or_default().push(key.clone());
+        for (option, value) in &dws.directive.options {
+            self.by_option.entry((option.clone(), value.clone())).or_default().push(key.clone());
+        }
+        self.by_file.entry(dws.source_file.clone()).or_default().push(key.clone());
+        self.directives.insert(key, dws);
+    }
+
+    /// Removes all directives with ID `id` and returns them.
+    pub fn remove(&mut self, id: &str) -> Vec<DirectiveWithSource> {
+        let keys = self.by_id.get(id).cloned().unwrap_or_default();
+        keys.iter().filter_map(|key| self.remove_key(key)).collect()
+    }
+
+    /// Removes all directives of `source_file` and returns them, e.g. when the file changed or
+    /// was deleted in watch mode.
+    pub fn remove_file(&mut self, source_file: &str) -> Vec<DirectiveWithSource> {
+        let keys = self.by_file.get(source_file).cloned().unwrap_or_default();
+        keys.iter().filter_map(|key| self.remove_key(key)).collect()
+    }
+
+    fn remove_key(&mut self, key: &DirectiveKey) -> Option<DirectiveWithSource> {
+        let dws = self.directives.remove(key)?;
+        remove_from_index(&mut self.by_id, dws.id.clone(), key);
+        remove_from_index(&mut self.by_name, dws.directive.name.clone(), key);
+        for (option, value) in &dws.directive.options {
+            remove_from_index(&mut self.by_option, (option.clone(), value.clone()), key);
+        }
+        remove_from_index(&mut self.by_file, dws.source_file.clone(), key);
+        Some(dws)
+    }
+
+    /// Replaces the link graph used by `incoming` and by queries on links.
+    pub fn set_link_graph(&mut self, link_graph: LinkGraph) {
+        self.incoming.clear();
+        for (source_id, node_data) in &link_graph {
+            for (link_type, target_ids) in &node_data.outgoing_links {
+                for target_id in target_ids {
+                    self.incoming.entry((target_id.clone(), link_type.clone())).or_default().push(source_id.clone());
+                }
+            }
+        }
+        for source_ids in self.incoming.values_mut() {
+            source_ids.sort();
+        }
+        self.link_graph = link_graph;
+    }
+
+    pub fn link_graph(&self) -> &LinkGraph {
+        &self.link_graph
+    }
+
+    pub fn len(&self) -> usize {
+        self.directives.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.directives.is_empty()
+    }
+
+    /// The first inserted directive with ID `id`; see `get_all` for duplicate IDs.
+    pub fn get(&self, id: &str) -> Option<&DirectiveWithSource> {
+        self.get_all(id).next()
+    }
+
+    /// All directives with ID `id`.
+    pub fn get_all(&self, id: &str) -> impl Iterator<Item = &DirectiveWithSource> {
+        self.resolve(self.by_id.get(id))
+    }
+
+    /// All directives, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &DirectiveWithSource> {
+        self.directives.values()
+    }
+
+    /// Directives named `name`.
+    pub fn by_name(&self, name: &str) -> impl Iterator<Item = &DirectiveWithSource> {
+        self.resolve(self.by_name.get(name))
+    }
+
+    /// Directives whose option `key` is exactly `value`.
+    pub fn with_option(&self, key: &str, value: &str) -> impl Iterator<Item = &DirectiveWithSource> {
+        self.resolve(self.by_option.get(&(key.to_string(), value.to_string())))
+    }
+
+    /// Directives of `source_file`, which is compared like `DirectiveWithSource::source_file`.
+    pub fn in_file(&self, source_file: &str) -> impl Iterator<Item = &DirectiveWithSource> {
+        self.resolve(self.by_file.get(source_file))
+    }
+
+    /// Directives linking to `id` via the link type `link_type` (e.g. `derives`), sorted by ID.
+    /// Sources that are not stored, e.g. because their file was removed, are skipped.
+    pub fn incoming(&self, id: &str, link_type: &str) -> impl Iterator<Item = &DirectiveWithSource> {
+        self.incoming.get(&(id.to_string(), link_type.to_string()))
+            .into_iter()
+            .flatten()
+            .flat_map(|source_id| self.get_all(source_id))
+    }
+
+    fn resolve<'a>(&'a self, keys: Option<&'a Vec<DirectiveKey>>) -> impl Iterator<Item = &'a DirectiveWithSource> {
+        keys.into_iter().flatten().filter_map(|key| self.directives.get(key))
+    }
+}
+
+fn remove_from_index<K: std::hash::Hash + Eq>(index: &mut HashMap<K, Vec<DirectiveKey>>, key: K, directive: &DirectiveKey) {
+    if let Some(keys) = index.get_mut(&key) {
+        keys.retain(|indexed| indexed != directive);
+        if keys.is_empty() {
+            index.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link_data::LinkNodeData;
+    use crate::testing::new_dws;
+
+    fn ids<'a>(directives: impl Iterator<Item = &'a DirectiveWithSource>) -> Vec<&'a str> {
+        directives.map(|dws| dws.id.as_str()).collect()
+    }
+
+    fn sample() -> DirectiveStore {
+        let directives = vec![
+            new_dws("spec", "b.rst", 1, "S1", &[("derives", "R1"), ("status", "open")]),
+            new_dws("req", "a.rst", 5, "R2", &[("status", "open")]),
+            new_dws("req", "a.rst", 1, "R1", &[("status", "done")]),
+        ];
+        let mut link_graph = LinkGraph::new();
+        link_graph.insert("S1".to_string(), LinkNodeData {
+            outgoing_links: HashMap::from([("derives".to_string(), vec!["R1".to_string()])]),
+            incoming_links: HashMap::new(),
+        });
+        DirectiveStore::new(directives, link_graph)
+    }
+
+    #[test]
+    fn test_lookups() {
+        let store = sample();
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.get("R2").unwrap().line_number, Some(5));
+        assert!(store.get("missing").is_none());
+        assert_eq!(ids(store.by_name("req")), vec!["R1", "R2"]);
+        assert_eq!(ids(store.with_option("status", "open")), vec!["R2", "S1"]);
+        assert_eq!(ids(store.in_file("a.rst")), vec!["R1", "R2"]);
+        assert_eq!(ids(store.incoming("R1", "derives")), vec!["S1"]);
+        assert_eq!(ids(store.incoming("R1", "tests")), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_insert_and_remove_file_update_indexes() {
+        let mut store = sample();
+        store.insert(new_dws("req", "a.rst", 1, "R1", &[("status", "open")]));
+        assert_eq!(store.len(), 3);
+        assert_eq!(ids(store.with_option("status", "done")), Vec::<&str>::new());
+        assert_eq!(ids(store.with_option("status", "open")), vec!["R2", "S1", "R1"]);
+
+        let removed = store.remove_file("a.rst");
+        assert_eq!(ids(removed.iter()), vec!["R2", "R1"]);
+        assert_eq!(ids(store.by_name("req")), Vec::<&str>::new());
+        assert_eq!(ids(store.in_file("a.rst")), Vec::<&str>::new());
+        assert_eq!(ids(store.with_option("status", "open")), vec!["S1"]);
+
+        store.remove_file("b.rst");
+        assert!(store.is_empty());
+        assert!(store.by_id.is_empty() && store.by_name.is_empty() && store.by_option.is_empty() && store.by_file.is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_ids_are_kept() {
+        let mut store = sample();
+        store.insert(new_dws("req", "c.rst", 3, "R1", &[("status", "open")]));
+        assert_eq!(store.len(), 4);
+        assert_eq!(store.get("R1").unwrap().source_file, "a.rst");
+        let files: Vec<&str> = store.get_all("R1").map(|dws| dws.source_file.as_str()).collect();
+        assert_eq!(files, vec!["a.rst", "c.rst"]);
+        assert_eq!(ids(store.by_name("req")), vec!["R1", "R2", "R1"]);
+        assert_eq!(ids(store.with_option("status", "open")), vec!["R2", "S1", "R1"]);
+
+        let removed = store.remove_file("a.rst");
+        assert_eq!(ids(removed.iter()), vec!["R1", "R2"]);
+        assert_eq!(store.get("R1").unwrap().source_file, "c.rst");
+        assert_eq!(ids(store.in_file("c.rst")), vec!["R1"]);
+
+        store.insert(new_dws("req", "c.rst", 9, "R1", &[]));
+        assert_eq!(store.remove("R1").len(), 2);
+        assert_eq!(ids(store.iter()), vec!["S1"]);
+    }
+}
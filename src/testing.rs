@@ -0,0 +1,68 @@
+//! Helpers for tests of crates using rstparser, enabled with the `testing` feature.
+//!
+//! They run the processing pipeline on in-memory text, so tests need no temporary files and
+//! produce the same IDs and paths on every machine, or build directives directly.
+//!
+//! ```
+//! use rstparser::testing::{process_str, to_golden_json};
+//!
+//! let directives = process_str("/// @rst\n/// .. req:: Parse\n///    :id: REQ-1\n/// @endrst\n", "cpp", &["req"]);
+//! assert_eq!(directives[0].id, "REQ-1");
+//! assert_eq!(directives[0].source_file, "input.cpp");
+//! assert!(to_golden_json(&directives).contains("\"name\": \"req\""));
+//! ```
+
+use crate::aggregator::DirectiveWithSource;
+use crate::parser::Directive;
+use crate::processor::Processor;
+
+/// Finds the `targets` directives in `content`, extracted as for a file with extension `ext`
+/// (e.g. `"rst"`, `"py"`, `"cpp"`). The source file of the directives is `input.<ext>`.
+/// Panics if processing fails.
+pub fn process_str(content: &str, ext: &str, targets: &[&str]) -> Vec<DirectiveWithSource> {
+    let processor = Processor::new(targets.iter().map(|name| name.to_string()).collect());
+    process_str_with(&processor, content, ext)
+}
+
+/// Same as [`process_str`], but with a configured `Processor`.
+pub fn process_str_with(processor: &Processor, content: &str, ext: &str) -> Vec<DirectiveWithSource> {
+    let (directives, _) = processor
+        .process_content(content.to_string(), format!("input.{}", ext))
+        .unwrap_or_else(|e| panic!("processing input.{} failed: {}", ext, e));
+    directives
+}
+
+/// Serializes directives in source order as pretty-printed JSON, for comparison with golden files.
+pub fn to_golden_json(directives: &[DirectiveWithSource]) -> String {
+    let mut sorted = directives.to_vec();
+    sorted.sort();
+    serde_json::to_string_pretty(&sorted).expect("directives are always serializable")
+}
+
+/// A directive `name` with `id` and `options` at `file:line`, without arguments or content,
+/// e.g. to fill a `DirectiveStore` or run a `Query` without processing any text.
+pub fn new_dws(name: &str, file: &str, line: usize, id: &str, options: &[(&str, &str)]) -> DirectiveWithSource {
+    DirectiveWithSource {
+        directive: options.iter().fold(Directive::new(name), |directive, (key, value)| directive.with_option(key, value)),
+        source_file: file.to_string(),
+        line_number: Some(line),
+        id: id.to_string(),
+        file_metadata: None,
+        depth: 0,
+        byte_range: None,
+        source_byte_range: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_str_is_deterministic() {
+        let rst = ".. note:: First\n\n.. note:: Second\n";
+        let directives = process_str(rst, "rst", &["note"]);
+        assert_eq!(directives.iter().map(|dws| dws.id.as_str()).collect::<Vec<_>>(), vec!["input.rst:note:1", "input.rst:note:3"]);
+        assert_eq!(to_golden_json(&directives), to_golden_json(&process_str(rst, "rst", &["note"])));
+    }
+}
@@ -1,29 +1,39 @@
 use std::time::{Duration, Instant};
 use std::fmt;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use serde::Serialize;
 
-/// A simple struct to measure and report execution time
+/// A simple struct to measure and report execution time. On `wasm32-unknown-unknown`, which
+/// has no clock, timers always report zero.
 pub struct Timer {
-    start: Instant,
+    start: Option<Instant>,
     name: String,
 }
 
+/// The current time, or `None` where `Instant::now` would panic.
+fn now() -> Option<Instant> {
+    if cfg!(all(target_arch = "wasm32", target_os = "unknown")) { None } else { Some(Instant::now()) }
+}
+
 impl Timer {
     /// Create a new timer with the given name
     pub fn new(name: &str) -> Self {
         Timer {
-            start: Instant::now(),
+            start: now(),
             name: name.to_string(),
         }
     }
 
     /// Reset the timer
     pub fn reset(&mut self) {
-        self.start = Instant::now();
+        self.start = now();
     }
 
     /// Get the elapsed time since the timer was created or reset
     pub fn elapsed(&self) -> Duration {
-        self.start.elapsed()
+        self.start.map_or(Duration::ZERO, |start| start.elapsed())
     }
 
     /// Get the elapsed time in milliseconds
@@ -48,24 +58,265 @@ impl Timer {
     pub fn report(&self) {
         println!("{}", self);
     }
+
+    /// Stop the timer and return the elapsed time, without printing anything.
+    pub fn finish(self) -> Duration {
+        self.elapsed()
+    }
+}
+
+/// Function receiving a timer's name and measured duration.
+pub type ReportFn<'a> = Box<dyn FnMut(&str, Duration) + 'a>;
+
+/// Where a `ScopedTimer` reports its measurement.
+pub enum TimerSink<'a> {
+    /// Print `name: duration` to stdout, like `Timer::report`.
+    Println,
+    /// Log `name: duration` at debug level.
+    LogDebug,
+    /// Record a sample named like the timer.
+    Registry(&'a TimingRegistry),
+    /// Call a function with the name and the duration.
+    Custom(ReportFn<'a>),
+}
+
+/// A timer that reports to its `TimerSink` when it is dropped, so that early returns and `?`
+/// do not lose the measurement.
+pub struct ScopedTimer<'a> {
+    timer: Timer,
+    sink: TimerSink<'a>,
+}
+
+impl<'a> ScopedTimer<'a> {
+    pub fn new(name: &str, sink: TimerSink<'a>) -> Self {
+        ScopedTimer { timer: Timer::new(name), sink }
+    }
+}
+
+impl Drop for ScopedTimer<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.timer.elapsed();
+        match &mut self.sink {
+            TimerSink::Println => println!("{}: {}", self.timer.name, format_duration(elapsed)),
+            TimerSink::LogDebug => log::debug!("{}: {}", self.timer.name, format_duration(elapsed)),
+            TimerSink::Registry(registry) => registry.record(&self.timer.name, elapsed),
+            TimerSink::Custom(report) => report(&self.timer.name, elapsed),
+        }
+    }
 }
 
 impl fmt::Display for Timer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let elapsed = self.elapsed();
-        
-        if elapsed.as_secs() > 0 {
-            write!(f, "{}: {:.2} s", self.name, elapsed.as_secs_f64())
-        } else if elapsed.as_millis() > 0 {
-            write!(f, "{}: {:.2} ms", self.name, self.elapsed_ms())
-        } else if elapsed.as_micros() > 0 {
-            write!(f, "{}: {:.2} µs", self.name, self.elapsed_us())
-        } else {
-            write!(f, "{}: {:.2} ns", self.name, self.elapsed_ns())
+        write!(f, "{}: {}", self.name, format_duration(self.elapsed()))
+    }
+}
+
+/// Formats a duration in the largest unit that is at least 1, e.g. `12.34 ms`.
+pub fn format_duration(duration: Duration) -> String {
+    if duration.as_secs() > 0 {
+        format!("{:.2} s", duration.as_secs_f64())
+    } else if duration.as_millis() > 0 {
+        format!("{:.2} ms", duration.as_secs_f64() * 1000.0)
+    } else if duration.as_micros() > 0 {
+        format!("{:.2} µs", duration.as_secs_f64() * 1_000_000.0)
+    } else {
+        format!("{:.2} ns", duration.as_secs_f64() * 1_000_000_000.0)
+    }
+}
+
+/// Name of the samples recorded by `TimingRegistry::record_file`.
+pub const PARSE_FILE_SAMPLE: &str = "parse_file";
+
+/// Collects named duration samples and per-file durations from several threads, e.g. rayon
+/// workers, and summarizes them with `stats`.
+#[derive(Debug, Default)]
+pub struct TimingRegistry {
+    samples: Mutex<BTreeMap<String, Vec<Duration>>>,
+    file_timings: Mutex<Vec<(PathBuf, Duration)>>,
+}
+
+/// Summary of the samples recorded under one name. Durations are in milliseconds, and the
+/// percentiles use the nearest-rank method.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SampleStats {
+    pub name: String,
+    pub count: usize,
+    pub mean_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+}
+
+impl TimingRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one sample of `name`, e.g. `registry.record("parse_file", duration)`.
+    pub fn record(&self, name: &str, duration: Duration) {
+        self.samples.lock().unwrap().entry(name.to_string()).or_default().push(duration);
+    }
+
+    /// Record how long processing `path` took, also as a `parse_file` sample.
+    pub fn record_file(&self, path: PathBuf, duration: Duration) {
+        self.record(PARSE_FILE_SAMPLE, duration);
+        self.file_timings.lock().unwrap().push((path, duration));
+    }
+
+    /// Times the rest of the enclosing scope as a sample of `name`, see `time_scope!`.
+    pub fn scope(&self, name: &str) -> ScopedTimer<'_> {
+        ScopedTimer::new(name, TimerSink::Registry(self))
+    }
+
+    /// The recorded per-file durations, in the order they were recorded.
+    pub fn file_timings(&self) -> Vec<(PathBuf, Duration)> {
+        self.file_timings.lock().unwrap().clone()
+    }
+
+    /// Count, mean, min, max, p50 and p95 of the samples of each name, sorted by name.
+    pub fn stats(&self) -> Vec<SampleStats> {
+        let samples = self.samples.lock().unwrap();
+        samples.iter().filter(|(_, durations)| !durations.is_empty()).map(|(name, durations)| {
+            let mut sorted = durations.clone();
+            sorted.sort();
+            let percentile = |p: usize| sorted[(sorted.len() * p).div_ceil(100).max(1) - 1];
+            let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+            SampleStats {
+                name: name.clone(),
+                count: sorted.len(),
+                mean_ms: millis(sorted.iter().sum::<Duration>()) / sorted.len() as f64,
+                min_ms: millis(sorted[0]),
+                max_ms: millis(sorted[sorted.len() - 1]),
+                p50_ms: millis(percentile(50)),
+                p95_ms: millis(percentile(95)),
+            }
+        }).collect()
+    }
+
+    /// The `stats` as a table with one row per sample name.
+    pub fn format_stats_table(&self) -> String {
+        format_stats_table(&self.stats())
+    }
+
+    /// The `stats` as a JSON array.
+    pub fn stats_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&self.stats())
+    }
+}
+
+/// Renders sample statistics as a table like `format_counts_table`.
+fn format_stats_table(stats: &[SampleStats]) -> String {
+    let width = stats.iter().map(|sample| sample.name.len()).chain(["Name".len()]).max().unwrap_or(0);
+    let duration = |millis: f64| format_duration(Duration::from_secs_f64(millis / 1000.0));
+    let mut table = format!(
+        "{:<width$}  {:>6}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}\n",
+        "Name", "Count", "Mean", "Min", "Max", "p50", "p95", width = width
+    );
+    for sample in stats {
+        table.push_str(&format!(
+            "{:<width$}  {:>6}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}\n",
+            sample.name,
+            sample.count,
+            duration(sample.mean_ms),
+            duration(sample.min_ms),
+            duration(sample.max_ms),
+            duration(sample.p50_ms),
+            duration(sample.p95_ms),
+            width = width
+        ));
+    }
+    table
+}
+
+/// Durations of the phases of one run, plus the per-file parse times.
+#[derive(Debug, Default)]
+pub struct TimingReport {
+    phases: Vec<(String, Duration)>,
+    file_timings: Vec<(PathBuf, Duration)>,
+    sample_stats: Vec<SampleStats>,
+}
+
+impl TimingReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the duration of a phase; phases are reported in the order they were added.
+    pub fn record_phase(&mut self, name: &str, duration: Duration) {
+        self.phases.push((name.to_string(), duration));
+    }
+
+    /// Add the per-file durations and the sample statistics collected in `registry`.
+    pub fn add_registry(&mut self, registry: &TimingRegistry) {
+        self.file_timings.extend(registry.file_timings());
+        self.sample_stats.extend(registry.stats());
+    }
+
+    pub fn phases(&self) -> &[(String, Duration)] {
+        &self.phases
+    }
+
+    /// Sum of all phase durations.
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    /// The `n` files that took longest, slowest first.
+    pub fn slowest_files(&self, n: usize) -> Vec<&(PathBuf, Duration)> {
+        let mut files: Vec<&(PathBuf, Duration)> = self.file_timings.iter().collect();
+        files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        files.truncate(n);
+        files
+    }
+}
+
+/// Number of files listed under "Slowest files" in a `TimingReport`.
+const SLOWEST_FILES_SHOWN: usize = 5;
+
+impl fmt::Display for TimingReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.phases.iter().map(|(name, _)| name.len()).chain(["total".len()]).max().unwrap_or(0);
+        writeln!(f, "Timing:")?;
+        for (name, duration) in &self.phases {
+            writeln!(f, "  {:<width$}  {}", name, format_duration(*duration), width = width)?;
+        }
+        writeln!(f, "  {:<width$}  {}", "total", format_duration(self.total()), width = width)?;
+        if !self.sample_stats.is_empty() {
+            writeln!(f, "Samples:")?;
+            for line in format_stats_table(&self.sample_stats).lines() {
+                writeln!(f, "  {}", line)?;
+            }
         }
+        let slowest = self.slowest_files(SLOWEST_FILES_SHOWN);
+        if !slowest.is_empty() {
+            writeln!(f, "Slowest files:")?;
+            for (path, duration) in slowest {
+                writeln!(f, "  {:>10}  {}", format_duration(*duration), path.display())?;
+            }
+        }
+        Ok(())
     }
 }
 
+/// A macro to record the time until the end of the enclosing scope into a `TimingRegistry`
+/// as a sample of `name`:
+///
+/// ```
+/// let registry = rstparser::timing::TimingRegistry::new();
+/// {
+///     rstparser::time_scope!(registry, "parse");
+///     // ... perform operation ...
+/// }
+/// assert_eq!(registry.stats()[0].count, 1);
+/// ```
+#[macro_export]
+macro_rules! time_scope {
+    ($registry:expr, $name:expr) => {
+        let _time_scope_guard = $registry.scope($name);
+    };
+}
+
 /// A macro to time a block of code and print the result
 #[macro_export]
 macro_rules! time_it {
@@ -87,3 +338,180 @@ macro_rules! time_call {
         result
     }};
 }
+
+/// Like `time_it!`, but returns `(result, duration)` instead of printing anything
+#[macro_export]
+macro_rules! timed {
+    ($block:block) => {{
+        let timer = $crate::timing::Timer::new("");
+        let result = $block;
+        (result, timer.finish())
+    }};
+}
+
+/// Like `time_call!`, but returns `(result, duration)` instead of printing anything
+#[macro_export]
+macro_rules! timed_call {
+    ($func:ident, $($arg:expr),*) => {{
+        let timer = $crate::timing::Timer::new(stringify!($func));
+        let result = $func($($arg),*);
+        (result, timer.finish())
+    }};
+}
+
+/// A span around a processing stage, entered until dropped, see `stage_span!`. With the
+/// `tracing` feature it is a `tracing` span at debug level that records its `duration_ms`
+/// when it ends; without it, it does nothing.
+pub(crate) struct StageSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+    #[cfg(feature = "tracing")]
+    timer: Timer,
+}
+
+impl StageSpan {
+    #[cfg(feature = "tracing")]
+    pub(crate) fn enter(span: tracing::Span) -> Self {
+        StageSpan { span: span.entered(), timer: Timer::new("") }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) fn disabled() -> Self {
+        StageSpan {}
+    }
+
+    /// Records a count, e.g. of directives, as the span's `field`.
+    pub(crate) fn record_count(&self, field: &'static str, count: usize) {
+        #[cfg(feature = "tracing")]
+        self.span.record(field, count as u64);
+        #[cfg(not(feature = "tracing"))]
+        let _ = (field, count);
+    }
+
+    /// Records `value`, e.g. a file path, as the span's `field`.
+    pub(crate) fn record_display(&self, field: &'static str, value: impl fmt::Display) {
+        #[cfg(feature = "tracing")]
+        self.span.record(field, tracing::field::display(value));
+        #[cfg(not(feature = "tracing"))]
+        let _ = (field, value.to_string());
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for StageSpan {
+    fn drop(&mut self) {
+        self.span.record("duration_ms", self.timer.elapsed_ms());
+    }
+}
+
+/// Enters a `StageSpan` named `$name` with the fields to be recorded later with
+/// `StageSpan::record_count` and `StageSpan::record_display`, plus `duration_ms`.
+macro_rules! stage_span {
+    ($name:literal $(, $field:ident)* $(,)?) => {{
+        #[cfg(feature = "tracing")]
+        let span = $crate::timing::StageSpan::enter(
+            tracing::debug_span!($name, $($field = tracing::field::Empty,)* duration_ms = tracing::field::Empty),
+        );
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::timing::StageSpan::disabled();
+        span
+    }};
+}
+pub(crate) use stage_span;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(1500)), "1.50 s");
+        assert_eq!(format_duration(Duration::from_micros(2500)), "2.50 ms");
+        assert_eq!(format_duration(Duration::from_nanos(1500)), "1.50 µs");
+        assert_eq!(format_duration(Duration::from_nanos(15)), "15.00 ns");
+    }
+
+    #[test]
+    fn test_timing_report() {
+        let registry = TimingRegistry::new();
+        std::thread::scope(|scope| {
+            for (name, millis) in [("a.rst", 3), ("b.rst", 7), ("c.rst", 1)] {
+                let registry = &registry;
+                scope.spawn(move || registry.record_file(PathBuf::from(name), Duration::from_millis(millis)));
+            }
+        });
+
+        let mut report = TimingReport::new();
+        report.record_phase("walk", Duration::from_millis(2));
+        report.record_phase("parse", Duration::from_millis(11));
+        report.add_registry(&registry);
+        assert_eq!(report.total(), Duration::from_millis(13));
+        let slowest: Vec<&PathBuf> = report.slowest_files(2).into_iter().map(|(path, _)| path).collect();
+        assert_eq!(slowest, [&PathBuf::from("b.rst"), &PathBuf::from("a.rst")]);
+        assert_eq!(
+            report.to_string(),
+            "Timing:\n  walk   2.00 ms\n  parse  11.00 ms\n  total  13.00 ms\nSamples:\n\
+             \x20 Name         Count        Mean         Min         Max         p50         p95\n\
+             \x20 parse_file       3     3.67 ms     1.00 ms     7.00 ms     3.00 ms     7.00 ms\n\
+             Slowest files:\n     7.00 ms  b.rst\n     3.00 ms  a.rst\n     1.00 ms  c.rst\n"
+        );
+    }
+
+    #[test]
+    fn test_registry_stats() {
+        let registry = TimingRegistry::new();
+        for millis in 1..=20 {
+            registry.record("parse_file", Duration::from_millis(millis));
+        }
+        {
+            time_scope!(registry, "scope");
+        }
+
+        let stats = registry.stats();
+        assert_eq!(stats.iter().map(|sample| sample.name.as_str()).collect::<Vec<_>>(), ["parse_file", "scope"]);
+        let parse = &stats[0];
+        assert_eq!((parse.count, parse.min_ms, parse.max_ms), (20, 1.0, 20.0));
+        assert_eq!((parse.mean_ms, parse.p50_ms, parse.p95_ms), (10.5, 10.0, 19.0));
+        assert_eq!(stats[1].count, 1);
+
+        let json: serde_json::Value = serde_json::from_str(&registry.stats_json().unwrap()).unwrap();
+        assert_eq!(json[0]["p95_ms"], 19.0);
+        assert!(registry.format_stats_table().starts_with("Name         Count"));
+    }
+
+    fn checked_len(text: &str) -> Result<usize, String> {
+        if text.is_empty() { Err("empty".to_string()) } else { Ok(text.len()) }
+    }
+
+    #[test]
+    fn test_scoped_timer_reports_on_early_return() {
+        let reported: RefCell<Vec<(String, Duration)>> = RefCell::new(Vec::new());
+        let sink = || TimerSink::Custom(Box::new(|name: &str, duration| reported.borrow_mut().push((name.to_string(), duration))));
+        let measured = |text: &str| -> Result<usize, String> {
+            let _timer = ScopedTimer::new("measured", sink());
+            let len = checked_len(text)?;
+            Ok(len * 2)
+        };
+
+        assert_eq!(measured("abc"), Ok(6));
+        assert!(measured("").is_err());
+        let names: Vec<String> = reported.borrow().iter().map(|(name, _)| name.clone()).collect();
+        assert_eq!(names, ["measured", "measured"], "the early return should be reported as well");
+
+        let registry = TimingRegistry::new();
+        drop(ScopedTimer::new("registry", TimerSink::Registry(&registry)));
+        assert_eq!(registry.stats()[0].name, "registry");
+    }
+
+    #[test]
+    fn test_timed_macros_return_durations() {
+        let (value, duration) = timed!({ 40 + 2 });
+        assert_eq!(value, 42);
+        assert!(duration < Duration::from_secs(1));
+
+        let (len, _) = timed_call!(checked_len, "four");
+        assert_eq!(len, Ok(4));
+        assert!(Timer::new("finish").finish() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use serde::Serialize;
+use crate::aggregator::DirectiveWithSource;
+use crate::directive_functions::{find_directive_by_id, AllDirectivesMap, DirectivesMapOf, FunctionApplicator, StoredDirective};
+use crate::error::RstParserError;
+use crate::link_data::LinkGraph;
+use crate::file_walker::{canonicalize_lenient, canonicalize_normalized, normalize_path};
+use crate::processor::Processor;
+
+/// The kinds of problems reported by the validators in this module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ValidationRule {
+    /// A link option names an ID that no directive has.
+    DanglingLink,
+    /// Several directives share the same ID.
+    DuplicateId,
+    /// A directive lacks an option that is required for its directive name.
+    MissingRequiredOption,
+    /// A file lacks a directive that every file must contain.
+    MissingRequiredDirective,
+}
+
+/// A single validation problem, located by source file and (where known) line.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct ValidationProblem {
+    pub file: String,
+    pub line: Option<usize>,
+    pub rule: ValidationRule,
+    pub message: String,
+}
+
+impl ValidationProblem {
+    fn for_directive(dws: &DirectiveWithSource, rule: ValidationRule, message: String) -> Self {
+        ValidationProblem {
+            file: dws.source_file.clone(),
+            line: dws.line_number,
+            rule,
+            message,
+        }
+    }
+}
+
+/// Reports every link in `link_graph` whose target is not the ID of any directive in `directives_map`.
+pub fn find_dangling_links<D: StoredDirective>(directives_map: &DirectivesMapOf<D>, link_graph: &LinkGraph) -> Vec<ValidationProblem> {
+    let known_ids: HashSet<&String> = directives_map.values().flat_map(|file_map| file_map.keys()).collect();
+    let mut problems = Vec::new();
+    for (source_id, node_data) in link_graph {
+        let Some(stored_source) = find_directive_by_id(directives_map, source_id) else {
+            continue; // Targets of dangling links have nodes too, but no outgoing links
+        };
+        let source = stored_source.read();
+        for (link_field_name, target_ids) in &node_data.outgoing_links {
+            for target_id in target_ids.iter().filter(|target_id| !known_ids.contains(target_id)) {
+                problems.push(ValidationProblem::for_directive(
+                    &source,
+                    ValidationRule::DanglingLink,
+                    format!("'{}' links to unknown ID '{}' via '{}'", source_id, target_id, link_field_name),
+                ));
+            }
+        }
+    }
+    problems.sort();
+    problems
+}
+
+/// Reports every directive whose ID is also used by an earlier directive (in source order).
+/// Takes the flat processor output, since duplicates within one file collapse in an `AllDirectivesMap`.
+pub fn find_duplicate_ids(directives: &[DirectiveWithSource]) -> Vec<ValidationProblem> {
+    let mut sorted: Vec<&DirectiveWithSource> = directives.iter().collect();
+    sorted.sort();
+    let mut first_by_id: HashMap<&str, &DirectiveWithSource> = HashMap::new();
+    let mut problems = Vec::new();
+    for dws in sorted {
+        if let Some(first) = first_by_id.get(dws.id.as_str()) {
+            problems.push(ValidationProblem::for_directive(
+                dws,
+                ValidationRule::DuplicateId,
+                format!("ID '{}' is already used at {}:{}", dws.id, first.source_file, first.line_number.unwrap_or_default()),
+            ));
+        } else {
+            first_by_id.insert(&dws.id, dws);
+        }
+    }
+    problems
+}
+
+/// Reports directives lacking an option listed for their name in `required_options`
+/// (directive name -> option names).
+pub fn find_missing_required_options<D: StoredDirective>(directives_map: &DirectivesMapOf<D>, required_options: &HashMap<String, Vec<String>>) -> Vec<ValidationProblem> {
+    let mut problems = Vec::new();
+    for file_map in directives_map.values() {
+        for stored_directive in file_map.values() {
+            let dws = stored_directive.read();
+            let Some(options) = required_options.get(&dws.directive.name) else {
+                continue;
+            };
+            for option in options.iter().filter(|option| !dws.directive.options.contains_key(*option)) {
+                problems.push(ValidationProblem::for_directive(
+                    &dws,
+                    ValidationRule::MissingRequiredOption,
+                    format!("'{}' directive '{}' is missing the required option '{}'", dws.directive.name, dws.id, option),
+                ));
+            }
+        }
+    }
+    problems.sort();
+    problems
+}
+
+/// Returns the files from `files` that contain no `directive_name` directive in `directives_map`,
+/// in the order given. Paths are compared after canonicalization, and `normalize_path` on both
+/// sides, with the keys of the map.
+pub fn check_required_directive_present<D: StoredDirective>(directives_map: &DirectivesMapOf<D>, directive_name: &str, files: &[PathBuf]) -> Vec<PathBuf> {
+    let files_with_directive: HashSet<PathBuf> = directives_map
+        .iter()
+        .filter(|(_, file_map)| file_map.values().any(|stored_directive| stored_directive.read().directive.name == directive_name))
+        .map(|(file, _)| normalize_path(file))
+        .collect();
+    files
+        .iter()
+        .filter(|file| {
+            let file_key = canonicalize_normalized(file).unwrap_or_else(|_| normalize_path(file));
+            !files_with_directive.contains(&file_key)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Validates only `files`, e.g. the staged files of a pre-commit hook, against the state of the
+/// whole project in `baseline`, the directives of a previous full run (see
+/// `Aggregator::load_output_directives`). The baseline directives of `files` are replaced by
+/// the freshly parsed ones (files that no longer exist just lose theirs), links are resolved
+/// and the duplicate ID, dangling link and `required_options` checks run on the result. Only
+/// problems located in `files` are returned, so a link to an ID removed by one of them is not
+/// reported for the file containing the link. Fails if one of `files` cannot be processed.
+pub fn validate_files(
+    files: &[PathBuf],
+    baseline: Vec<DirectiveWithSource>,
+    processor: &Processor,
+    function_applicator: &FunctionApplicator,
+    required_options: &HashMap<String, Vec<String>>,
+) -> Result<Vec<ValidationProblem>, RstParserError> {
+    // Source files are canonical paths, in the baseline as in fresh results
+    let given_keys: HashSet<PathBuf> = files
+        .iter()
+        .map(|file| normalize_path(&canonicalize_lenient(file)))
+        .collect();
+    let is_given = |file: &str| given_keys.contains(&normalize_path(Path::new(file)));
+    let mut existing_keys = HashSet::new();
+    let existing_files: Vec<PathBuf> = files
+        .iter()
+        .filter_map(|file| std::fs::canonicalize(file).ok())
+        .filter(|canonical_file| existing_keys.insert(normalize_path(canonical_file)))
+        .collect();
+    let (fresh, errors) = processor.process_files(existing_files);
+    if let Some(err) = errors.into_iter().next() {
+        return Err(err);
+    }
+
+    let baseline: Vec<DirectiveWithSource> = baseline.into_iter().filter(|dws| !is_given(&dws.source_file)).collect();
+    let mut directives_map: AllDirectivesMap = HashMap::new();
+    for dws in &baseline {
+        directives_map
+            .entry(PathBuf::from(&dws.source_file))
+            .or_default()
+            .insert(dws.id.clone(), Arc::new(Mutex::new(dws.clone())));
+    }
+    let mut link_graph = LinkGraph::new();
+    function_applicator.apply_to_all(&directives_map, &mut link_graph);
+
+    // Only the links of the given files need resolving on top of the baseline
+    let mut fresh_directives = Vec::new();
+    for dws in &fresh {
+        let stored_directive = Arc::new(Mutex::new(dws.clone()));
+        directives_map
+            .entry(PathBuf::from(&dws.source_file))
+            .or_default()
+            .insert(dws.id.clone(), Arc::clone(&stored_directive));
+        fresh_directives.push(stored_directive);
+    }
+    function_applicator.apply_to_subset(&fresh_directives, &directives_map, &mut link_graph);
+
+    // A duplicate is reported for the given file, whichever of the two directives comes first
+    let mut problems = find_duplicate_ids(&fresh);
+    let baseline_by_id: HashMap<&str, &DirectiveWithSource> = baseline.iter().map(|dws| (dws.id.as_str(), dws)).collect();
+    for dws in &fresh {
+        if let Some(first) = baseline_by_id.get(dws.id.as_str()) {
+            problems.push(ValidationProblem::for_directive(
+                dws,
+                ValidationRule::DuplicateId,
+                format!("ID '{}' is already used at {}:{}", dws.id, first.source_file, first.line_number.unwrap_or_default()),
+            ));
+        }
+    }
+    problems.extend(find_dangling_links(&directives_map, &link_graph));
+    problems.extend(find_missing_required_options(&directives_map, required_options));
+    problems.retain(|problem| is_given(&problem.file));
+    problems.sort();
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directive_functions::{AllDirectivesMap, FunctionApplicator};
+    use crate::link_data::{LinkConfig, LinkTypeConfig};
+    use crate::processor::Processor;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    fn to_map(directives: Vec<DirectiveWithSource>) -> AllDirectivesMap {
+        let mut directives_map: AllDirectivesMap = HashMap::new();
+        for dws in directives {
+            directives_map
+                .entry(PathBuf::from(&dws.source_file))
+                .or_default()
+                .insert(dws.id.clone(), Arc::new(Mutex::new(dws)));
+        }
+        directives_map
+    }
+
+    #[test]
+    fn test_check_required_directive_present() {
+        let temp_dir = tempdir().unwrap();
+        let with_meta = temp_dir.path().join("with_meta.rst");
+        let without_meta = temp_dir.path().join("without_meta.rst");
+        std::fs::write(&with_meta, ".. module-meta::\n   :owner: team-a\n\n.. note::\n\n   Text.\n").unwrap();
+        std::fs::write(&without_meta, ".. note::\n\n   Only a note.\n").unwrap();
+        let files = vec![with_meta, without_meta.clone()];
+
+        let processor = Processor::new(vec!["module-meta".to_string(), "note".to_string()]);
+        let directives_map = to_map(processor.process_files(files.clone()).0);
+
+        assert_eq!(check_required_directive_present(&directives_map, "module-meta", &files), vec![without_meta]);
+        assert!(check_required_directive_present(&directives_map, "note", &files).is_empty());
+    }
+
+    #[test]
+    fn test_find_dangling_links() {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("reqs.rst");
+        std::fs::write(&file, ".. req::\n   :id: a\n\n.. req::\n   :id: b\n   :links: a, missing\n").unwrap();
+        let directives_map = to_map(Processor::new(vec!["req".to_string()]).process_file(&file).unwrap());
+        let link_config = LinkConfig {
+            link_types: vec![LinkTypeConfig::new("links")],
+            ..Default::default()
+        };
+        let mut link_graph = LinkGraph::new();
+        FunctionApplicator::new(Arc::new(link_config)).apply_to_all(&directives_map, &mut link_graph);
+
+        let problems = find_dangling_links(&directives_map, &link_graph);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].rule, ValidationRule::DanglingLink);
+        assert_eq!(problems[0].line, Some(4));
+        assert!(problems[0].message.contains("'missing'"));
+    }
+
+    #[test]
+    fn test_find_duplicate_ids() {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("reqs.rst");
+        std::fs::write(&file, ".. req::\n   :id: a\n\n.. req::\n   :id: a\n\n.. req::\n   :id: c\n").unwrap();
+        let directives = Processor::new(vec!["req".to_string()]).process_file(&file).unwrap();
+
+        let problems = find_duplicate_ids(&directives);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].rule, ValidationRule::DuplicateId);
+        assert_eq!(problems[0].line, Some(4));
+    }
+
+    #[test]
+    fn test_find_missing_required_options() {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("reqs.rst");
+        std::fs::write(&file, ".. req::\n   :id: a\n   :status: open\n\n.. req::\n   :id: b\n\n.. note::\n\n   No options.\n").unwrap();
+        let directives_map = to_map(Processor::new(vec!["req".to_string(), "note".to_string()]).process_file(&file).unwrap());
+        let required = HashMap::from([("req".to_string(), vec!["status".to_string()])]);
+
+        let problems = find_missing_required_options(&directives_map, &required);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].rule, ValidationRule::MissingRequiredOption);
+        assert_eq!(problems[0].line, Some(5));
+    }
+
+    #[test]
+    fn test_validate_files_reports_new_dangling_link_in_staged_file() {
+        let temp_dir = tempdir().unwrap();
+        let targets = temp_dir.path().join("targets.rst");
+        let staged = temp_dir.path().join("staged.rst");
+        let new_file = temp_dir.path().join("new.rst");
+        std::fs::write(&targets, ".. req::\n   :id: a\n\n.. req::\n   :id: old\n   :links: gone\n").unwrap();
+        std::fs::write(&staged, ".. req::\n   :id: b\n   :links: a\n").unwrap();
+        let processor = Processor::new(vec!["req".to_string()]);
+        let baseline = processor.process_files(vec![targets.clone(), staged.clone()]).0;
+        let link_config = LinkConfig { link_types: vec![LinkTypeConfig::new("links")], ..Default::default() };
+        let function_applicator = FunctionApplicator::new(Arc::new(link_config));
+        let required = HashMap::new();
+
+        // Links into unstaged files resolve against the baseline; the dangling link of the
+        // unstaged file is not reported
+        let problems = validate_files(std::slice::from_ref(&staged), baseline.clone(), &processor, &function_applicator, &required).unwrap();
+        assert!(problems.is_empty(), "unexpected problems: {:?}", problems);
+
+        std::fs::write(&staged, ".. req::\n   :id: b\n   :links: a, missing\n").unwrap();
+        std::fs::write(&new_file, ".. req::\n   :id: a\n").unwrap();
+        let problems = validate_files(&[staged.clone(), new_file], baseline.clone(), &processor, &function_applicator, &required).unwrap();
+        let rules: Vec<(ValidationRule, Option<usize>)> = problems.iter().map(|problem| (problem.rule, problem.line)).collect();
+        assert_eq!(rules.len(), 2);
+        assert!(rules.contains(&(ValidationRule::DanglingLink, Some(1))));
+        assert!(rules.contains(&(ValidationRule::DuplicateId, Some(1))));
+        assert!(problems.iter().any(|problem| problem.message.contains("'missing'")));
+
+        // A deleted staged file only loses its directives
+        std::fs::remove_file(&staged).unwrap();
+        assert!(validate_files(&[staged], baseline, &processor, &function_applicator, &required).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,291 @@
+//! The file system access of `FileWalker` and `Processor`, so that files can come from
+//! somewhere else than the local disk, e.g. an editor's unsaved buffers or a build sandbox.
+//!
+//! ```
+//! use std::sync::Arc;
+//! use rstparser::{FileWalker, Processor};
+//! use rstparser::vfs::InMemoryFs;
+//!
+//! let fs = Arc::new(InMemoryFs::new().with_file("/docs/index.rst", ".. req::\n   :id: REQ-1\n"));
+//! let files = FileWalker::new().with_file_provider(fs.clone()).find_files("/docs").unwrap();
+//! let (directives, errors) = Processor::new(vec!["req".to_string()]).with_file_provider(fs).process_files(files);
+//! assert!(errors.is_empty());
+//! assert_eq!(directives[0].id, "REQ-1");
+//! ```
+
+use std::collections::BTreeMap;
+#[cfg(feature = "fs")]
+use std::fs;
+use std::io;
+#[cfg(feature = "fs")]
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use crate::file_walker::normalize_path;
+
+/// What `FileProvider::metadata` reports about a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMetadata {
+    /// The path is a directory, or a symbolic link to one.
+    pub is_dir: bool,
+    /// The path itself is a symbolic link. Directories reached through one are not walked.
+    pub is_symlink: bool,
+    /// Time of the last modification, if known.
+    pub modified: Option<SystemTime>,
+}
+
+/// The file system operations used to find and read files. `RealFs` uses the local file
+/// system; `InMemoryFs` holds the files in memory.
+pub trait FileProvider: Send + Sync {
+    /// The entries of the directory `dir`, each as `dir` joined with the entry's name.
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// The contents of the file at `path`.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// The contents of the file at `path`, which must be valid UTF-8.
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        String::from_utf8(self.read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// At most the first `max_bytes` of the file at `path`.
+    fn read_head(&self, path: &Path, max_bytes: usize) -> io::Result<Vec<u8>> {
+        let mut contents = self.read(path)?;
+        contents.truncate(max_bytes);
+        Ok(contents)
+    }
+
+    /// The absolute path of `path` without `.` and `..` components; fails if nothing exists there.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+}
+
+/// The local file system, used unless another `FileProvider` is configured. Needs the `fs`
+/// feature.
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+#[cfg(feature = "fs")]
+impl FileProvider for RealFs {
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::read_dir(dir)?.map(|entry| entry.map(|entry| entry.path())).collect()
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn read_head(&self, path: &Path, max_bytes: usize) -> io::Result<Vec<u8>> {
+        let mut head = Vec::with_capacity(max_bytes);
+        fs::File::open(path)?.take(max_bytes as u64).read_to_end(&mut head)?;
+        Ok(head)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        let is_symlink = fs::symlink_metadata(path)?.file_type().is_symlink();
+        let metadata = fs::metadata(path)?;
+        Ok(FileMetadata { is_dir: metadata.is_dir(), is_symlink, modified: metadata.modified().ok() })
+    }
+}
+
+/// The provider of new `FileWalker`s and `Processor`s: `RealFs`, or an empty `InMemoryFs`
+/// without the `fs` feature.
+pub(crate) fn default_file_provider() -> Arc<dyn FileProvider> {
+    #[cfg(feature = "fs")]
+    return Arc::new(RealFs);
+    #[cfg(not(feature = "fs"))]
+    return Arc::new(InMemoryFs::new());
+}
+
+/// A file stored in an `InMemoryFs`.
+#[derive(Debug, Clone)]
+struct InMemoryFile {
+    /// The absolute path the file was first written under.
+    path: PathBuf,
+    contents: Vec<u8>,
+    modified: Option<SystemTime>,
+}
+
+/// Files held in memory, keyed by absolute path. Directories exist implicitly as the parents
+/// of the files; relative paths are taken relative to `/`. Like the local file system, paths
+/// are compared case-insensitively where `normalize_path` lowercases them, while listing and
+/// canonicalizing return the names the files were written under.
+/// Files can be added and replaced while the provider is in use, e.g. for an editor's buffers.
+#[derive(Debug, Default)]
+pub struct InMemoryFs {
+    files: RwLock<BTreeMap<PathBuf, InMemoryFile>>, // By `path_key`
+}
+
+impl InMemoryFs {
+    pub fn new() -> Self {
+        InMemoryFs::default()
+    }
+
+    /// Adds the file `path` with `contents`, see `write_file`.
+    pub fn with_file<P: AsRef<Path>>(self, path: P, contents: impl Into<Vec<u8>>) -> Self {
+        self.write_file(path, contents);
+        self
+    }
+
+    /// Adds the file `path` with `contents` and no modification time, or replaces its contents.
+    pub fn write_file<P: AsRef<Path>>(&self, path: P, contents: impl Into<Vec<u8>>) {
+        self.insert(path.as_ref(), contents.into(), None);
+    }
+
+    /// Adds or replaces the file `path`, last modified at `modified`.
+    pub fn write_file_modified_at<P: AsRef<Path>>(&self, path: P, contents: impl Into<Vec<u8>>, modified: SystemTime) {
+        self.insert(path.as_ref(), contents.into(), Some(modified));
+    }
+
+    /// Removes the file `path`; returns whether it existed.
+    pub fn remove_file<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.files.write().unwrap().remove(&path_key(path.as_ref())).is_some()
+    }
+
+    fn insert(&self, path: &Path, contents: Vec<u8>, modified: Option<SystemTime>) {
+        let mut files = self.files.write().unwrap();
+        let path = match files.get(&path_key(path)) {
+            Some(replaced) => replaced.path.clone(),
+            None => absolute_path(path),
+        };
+        files.insert(path_key(&path), InMemoryFile { path, contents, modified });
+    }
+
+    fn file(&self, path: &Path) -> io::Result<InMemoryFile> {
+        let key = path_key(path);
+        match self.files.read().unwrap().get(&key) {
+            Some(file) => Ok(file.clone()),
+            None if self.is_dir(&key) => Err(io::Error::new(io::ErrorKind::IsADirectory, format!("{} is a directory", path.display()))),
+            None => Err(not_found(path)),
+        }
+    }
+
+    /// Whether `key`, an absolute path, is the parent directory of any file.
+    fn is_dir(&self, key: &Path) -> bool {
+        self.files.read().unwrap().keys().any(|file_path| file_path != key && file_path.starts_with(key))
+    }
+}
+
+impl FileProvider for InMemoryFs {
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let key = path_key(dir);
+        let files = self.files.read().unwrap();
+        if files.contains_key(&key) {
+            return Err(io::Error::new(io::ErrorKind::NotADirectory, format!("{} is not a directory", dir.display())));
+        }
+        // The first component below `dir` of every file in it, each name once and in order
+        let depth = key.components().count();
+        let mut names: Vec<(&std::ffi::OsStr, &std::ffi::OsStr)> = files.iter()
+            .filter(|(file_key, _)| file_key.starts_with(&key))
+            .filter_map(|(file_key, file)| Some((file_key.components().nth(depth)?.as_os_str(), file.path.components().nth(depth)?.as_os_str())))
+            .collect();
+        names.dedup_by_key(|(key_name, _)| *key_name);
+        if names.is_empty() {
+            return Err(not_found(dir));
+        }
+        Ok(names.into_iter().map(|(_, name)| dir.join(name)).collect())
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        Ok(self.file(path)?.contents)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        // The file itself or, for a directory, the first file in it, whose keys sort right after
+        let key = path_key(path);
+        let files = self.files.read().unwrap();
+        files.range(key.clone()..)
+            .next()
+            .filter(|(file_key, _)| file_key.starts_with(&key))
+            .map(|(_, file)| file.path.components().take(key.components().count()).collect())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        let key = path_key(path);
+        if let Some(file) = self.files.read().unwrap().get(&key) {
+            return Ok(FileMetadata { is_dir: false, is_symlink: false, modified: file.modified });
+        }
+        if self.is_dir(&key) {
+            return Ok(FileMetadata { is_dir: true, is_symlink: false, modified: None });
+        }
+        Err(not_found(path))
+    }
+}
+
+/// `path` made absolute relative to `/`, with `.` and `..` resolved lexically.
+fn absolute_path(path: &Path) -> PathBuf {
+    let mut absolute = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => absolute.push(component.as_os_str()),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            Component::Normal(name) => absolute.push(name),
+        }
+    }
+    absolute
+}
+
+/// The key of `path` in `InMemoryFs::files`: its `absolute_path`, normalized with `normalize_path`.
+fn path_key(path: &Path) -> PathBuf {
+    normalize_path(&absolute_path(path))
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_memory_fs() {
+        let fs = InMemoryFs::new()
+            .with_file("/docs/index.rst", "Index")
+            .with_file("/docs/api/lib.rst", "API")
+            .with_file("docs/api/../guide.md", [0xff, 0xfe]);
+
+        assert_eq!(fs.list(Path::new("/docs")).unwrap(), vec![PathBuf::from("/docs/api"), PathBuf::from("/docs/guide.md"), PathBuf::from("/docs/index.rst")]);
+        assert_eq!(fs.list(Path::new("/docs/api/")).unwrap(), vec![PathBuf::from("/docs/api/lib.rst")]);
+        assert_eq!(fs.list(Path::new("/missing")).unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert!(fs.list(Path::new("/docs/index.rst")).is_err());
+
+        assert_eq!(fs.read_to_string(Path::new("/docs/./api/lib.rst")).unwrap(), "API");
+        assert_eq!(fs.read_to_string(Path::new("/docs/guide.md")).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs.read_head(Path::new("/docs/index.rst"), 3).unwrap(), b"Ind");
+        assert_eq!(fs.read(Path::new("/docs")).unwrap_err().kind(), io::ErrorKind::IsADirectory);
+
+        assert_eq!(fs.canonicalize(Path::new("docs/api/../index.rst")).unwrap(), PathBuf::from("/docs/index.rst"));
+        assert_eq!(fs.canonicalize(Path::new("/docs/api/")).unwrap(), PathBuf::from("/docs/api"));
+        assert!(fs.canonicalize(Path::new("/docs/missing.rst")).is_err());
+        assert!(fs.metadata(Path::new("/docs/api")).unwrap().is_dir);
+        assert!(!fs.metadata(Path::new("/docs/index.rst")).unwrap().is_dir);
+
+        fs.write_file("/docs/index.rst", "Changed");
+        assert_eq!(fs.read(Path::new("/docs/index.rst")).unwrap(), b"Changed");
+        assert!(fs.remove_file("/docs/index.rst"));
+        assert!(fs.read(Path::new("/docs/index.rst")).is_err());
+    }
+
+    #[test]
+    fn test_in_memory_fs_keeps_the_names_files_were_written_under() {
+        let fs = InMemoryFs::new().with_file("/Docs/Index.rst", "Index");
+        fs.write_file(normalize_path(Path::new("/Docs/Index.rst")), "Changed");
+
+        assert_eq!(fs.read(Path::new("/Docs/Index.rst")).unwrap(), b"Changed");
+        assert_eq!(fs.canonicalize(&normalize_path(Path::new("/Docs/Index.rst"))).unwrap(), PathBuf::from("/Docs/Index.rst"));
+        assert_eq!(fs.canonicalize(Path::new("/Docs")).unwrap(), PathBuf::from("/Docs"));
+        assert_eq!(fs.list(Path::new("/Docs")).unwrap(), vec![PathBuf::from("/Docs/Index.rst")]);
+    }
+}
@@ -0,0 +1,1097 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use notify::event::{EventKind, ModifyKind, RenameMode};
+use indexmap::IndexMap;
+use log::{debug, error, info};
+use crate::aggregator::{Aggregator, DirectiveWithSource};
+use crate::diagnostics::log_diagnostics;
+use crate::error::RstParserError;
+use crate::directive_functions::{index_by_id, AllDirectivesMap, FunctionApplicator};
+use crate::file_walker::{canonicalize_lenient, normalize_path, FileWalker};
+use crate::link_data::{remove_links_for_ids, LinkGraph, RemovalMode};
+use crate::processor::Processor;
+use crate::timing::{format_duration, stage_span, Timer};
+
+/// Upper bound for the per-file delay computed by `EventThrottle`.
+const MAX_WATCH_DELAY: Duration = Duration::from_millis(5000);
+
+/// Upper bound for how long `receive_batch` keeps collecting events while they keep arriving,
+/// unless the batch window itself is longer.
+const MAX_BATCH_DURATION: Duration = Duration::from_millis(5000);
+
+/// Per-file bookkeeping for `EventThrottle`.
+#[derive(Debug, Clone, Copy)]
+struct ThrottleState {
+    last_processed: Instant,
+    current_delay: Duration,
+}
+
+/// Throttles repeated file events in watch mode using exponential backoff.
+///
+/// An event for a file that arrives less than the current delay after the file was last
+/// processed has to wait out the rest of that window, and the window doubles for the next
+/// event (capped at `MAX_WATCH_DELAY`). Once a file has been stable for twice its current
+/// delay, the window resets to the base delay. `WatchSession` defers such files instead of
+/// waiting, so that other files are not held up.
+struct EventThrottle {
+    base_delay: Duration,
+    files: HashMap<PathBuf, ThrottleState>,
+}
+
+impl EventThrottle {
+    fn new(base_delay: Duration) -> Self {
+        EventThrottle {
+            base_delay,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Returns how long to wait before processing `path` at `now`, and advances the backoff.
+    fn required_wait(&mut self, path: &Path, now: Instant) -> Duration {
+        let Some(state) = self.files.get_mut(path) else {
+            return Duration::ZERO; // Never processed before
+        };
+        let elapsed = now.saturating_duration_since(state.last_processed);
+
+        if elapsed >= state.current_delay * 2 {
+            // File was stable long enough, start over with the base delay.
+            state.current_delay = self.base_delay;
+            return Duration::ZERO;
+        }
+        if elapsed >= state.current_delay {
+            return Duration::ZERO;
+        }
+
+        let wait = state.current_delay - elapsed;
+        state.current_delay = (state.current_delay * 2).min(MAX_WATCH_DELAY.max(self.base_delay));
+        wait
+    }
+
+    /// Records that `path` finished processing at `at`.
+    fn record_processed(&mut self, path: &Path, at: Instant) {
+        let base_delay = self.base_delay;
+        self.files
+            .entry(path.to_path_buf())
+            .and_modify(|state| state.last_processed = at)
+            .or_insert(ThrottleState { last_processed: at, current_delay: base_delay });
+    }
+
+    /// Drops any backoff state for `path`, e.g. after the file was removed.
+    fn forget(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+}
+
+/// The directive map key of `path`, see `canonicalize_lenient`. Remove and rename events
+/// report paths that are gone, possibly with whole directory trees.
+fn event_path_key(path: &Path) -> PathBuf {
+    normalize_path(&canonicalize_lenient(path))
+}
+
+/// A file system change relevant to watch mode, independent of the notification backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent {
+    /// A file or directory was created.
+    Created(PathBuf),
+    /// A file was written to or otherwise modified.
+    Modified(PathBuf),
+    /// A file or directory was removed.
+    Removed(PathBuf),
+    /// A file or directory was renamed, with both ends known.
+    Renamed { from: PathBuf, to: PathBuf },
+    /// A file or directory was renamed away from this path.
+    RenamedFrom(PathBuf),
+    /// A file or directory was renamed to this path.
+    RenamedTo(PathBuf),
+}
+
+impl WatchEvent {
+    /// Converts a `notify` event into watch events, one per affected path.
+    /// Renames of unknown direction are decided by whether the path still exists.
+    pub fn from_notify(event: &notify::Event) -> Vec<WatchEvent> {
+        let paths = event.paths.iter().cloned();
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(rename_mode)) => match (rename_mode, event.paths.as_slice()) {
+                (RenameMode::Both, [from, to]) => vec![WatchEvent::Renamed { from: from.clone(), to: to.clone() }],
+                (RenameMode::From, _) => paths.map(WatchEvent::RenamedFrom).collect(),
+                (RenameMode::To, _) => paths.map(WatchEvent::RenamedTo).collect(),
+                _ => paths
+                    .map(|path| if path.exists() { WatchEvent::RenamedTo(path) } else { WatchEvent::RenamedFrom(path) })
+                    .collect(),
+            },
+            EventKind::Create(_) => paths.map(WatchEvent::Created).collect(),
+            EventKind::Modify(_) => paths.map(WatchEvent::Modified).collect(),
+            EventKind::Remove(_) => paths.map(WatchEvent::Removed).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// File events collected for one batch, coalesced per path: only the last event for a path
+/// is kept, and events are applied in the order of each path's last event. Renames are split
+/// into their two ends, which is what `handle_event` does with them as well.
+#[derive(Debug, Default)]
+struct EventBatch {
+    events: IndexMap<PathBuf, WatchEvent>,
+}
+
+impl EventBatch {
+    fn add(&mut self, event: WatchEvent) {
+        let (path, event) = match event {
+            WatchEvent::Renamed { from, to } => {
+                self.add(WatchEvent::RenamedFrom(from));
+                (to.clone(), WatchEvent::RenamedTo(to))
+            }
+            WatchEvent::Created(ref path)
+            | WatchEvent::Modified(ref path)
+            | WatchEvent::Removed(ref path)
+            | WatchEvent::RenamedFrom(ref path)
+            | WatchEvent::RenamedTo(ref path) => (path.clone(), event),
+        };
+        self.events.shift_remove(&path);
+        self.events.insert(path, event);
+    }
+
+    /// Adds the events of a `notify` result, logging errors.
+    fn add_notify_result(&mut self, event_result: notify::Result<notify::Event>) {
+        match event_result {
+            Ok(event) => {
+                debug!("File event: {:?}", event);
+                for watch_event in WatchEvent::from_notify(&event) {
+                    self.add(watch_event);
+                }
+            }
+            Err(err) => error!("Watch error: {:?}", err),
+        }
+    }
+
+    fn into_events(self) -> Vec<WatchEvent> {
+        self.events.into_values().collect()
+    }
+}
+
+/// Waits for the next file event from `events`, then keeps collecting until no new event
+/// arrived for `window` (or `MAX_BATCH_DURATION` has passed), so that e.g. the hundreds of
+/// events of a `git checkout` are applied as one batch with `WatchSession::handle_events`.
+/// Events for the same path are coalesced into the last one. Returns `None` once the sender
+/// is gone and no events are left.
+pub fn receive_batch(events: &Receiver<notify::Result<notify::Event>>, window: Duration) -> Option<Vec<WatchEvent>> {
+    receive_batch_until(events, window, None)
+}
+
+/// Same as `receive_batch`, but returns an empty batch if no event arrived by `deadline`.
+pub fn receive_batch_until(
+    events: &Receiver<notify::Result<notify::Event>>,
+    window: Duration,
+    deadline: Option<Instant>,
+) -> Option<Vec<WatchEvent>> {
+    let first_event = match deadline {
+        Some(deadline) => match events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(event_result) => event_result,
+            Err(RecvTimeoutError::Timeout) => return Some(Vec::new()),
+            Err(RecvTimeoutError::Disconnected) => return None,
+        },
+        None => events.recv().ok()?,
+    };
+    let mut batch = EventBatch::default();
+    batch.add_notify_result(first_event);
+    let deadline = Instant::now() + MAX_BATCH_DURATION.max(window);
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match events.recv_timeout(window.min(remaining)) {
+            Ok(event_result) => batch.add_notify_result(event_result),
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+        }
+        if remaining.is_zero() {
+            break;
+        }
+    }
+    Some(batch.into_events())
+}
+
+/// What a call to `WatchSession::handle_event` or `WatchSession::initial_scan` changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeSummary {
+    /// Canonical paths of files whose directives were (re)parsed.
+    pub updated_files: Vec<PathBuf>,
+    /// Keys (see `WatchSession::directives_map`) of files whose directives were dropped from
+    /// the cache.
+    pub removed_files: Vec<PathBuf>,
+    /// JSON files written by the aggregator. Empty if nothing changed.
+    pub output_files: Vec<PathBuf>,
+}
+
+impl ChangeSummary {
+    /// Whether any cached directives changed, i.e. the outputs were re-aggregated.
+    pub fn has_changes(&self) -> bool {
+        !self.updated_files.is_empty() || !self.removed_files.is_empty()
+    }
+}
+
+/// Bookkeeping collected while applying a watch event, used to update the link graph afterwards.
+#[derive(Default)]
+struct PendingLinkUpdate {
+    changed: bool,
+    ids_to_clear_from_graph: HashSet<String>, // IDs whose links need to be removed before reprocessing
+    arcs_for_subset_application: Vec<Arc<Mutex<DirectiveWithSource>>>,
+    affected_ids_for_neighbor_scan: HashSet<String>, // IDs that were modified or removed, to find their neighbors
+}
+
+/// Keeps the directives of a watched tree and their link graph up to date as files change,
+/// and rewrites the aggregated outputs after every change.
+pub struct WatchSession {
+    processor: Processor,
+    function_applicator: FunctionApplicator,
+    aggregator: Aggregator,
+    file_walker: FileWalker,
+    event_throttle: EventThrottle,
+    deferred_files: HashMap<PathBuf, Instant>, // Canonical paths of throttled files by the time they are due
+    directives_map: AllDirectivesMap,
+    link_graph: LinkGraph,
+}
+
+impl WatchSession {
+    /// Create a session with an empty cache. `file_walker` decides which files are watched,
+    /// both for the initial scan and for files appearing later.
+    pub fn new(processor: Processor, function_applicator: FunctionApplicator, aggregator: Aggregator, file_walker: FileWalker) -> Self {
+        WatchSession {
+            processor,
+            function_applicator,
+            aggregator,
+            file_walker,
+            event_throttle: EventThrottle::new(Duration::ZERO),
+            deferred_files: HashMap::new(),
+            directives_map: AllDirectivesMap::new(),
+            link_graph: LinkGraph::new(),
+        }
+    }
+
+    /// Set the minimum delay between processing consecutive events for the same file.
+    /// Doubles (up to 5000 ms) while events keep arriving faster than that. Events arriving
+    /// earlier leave the file pending until its delay has passed; see `next_batch`.
+    pub fn with_watch_delay(mut self, delay: Duration) -> Self {
+        self.event_throttle = EventThrottle::new(delay);
+        self
+    }
+
+    /// When the first file left pending by the watch delay is due, if any.
+    pub fn next_deferred_deadline(&self) -> Option<Instant> {
+        self.deferred_files.values().min().copied()
+    }
+
+    /// Receives the next batch of events like `receive_batch`, but only waits until the first
+    /// pending file is due and returns an empty batch then. `handle_events` processes the due
+    /// files along with the batch.
+    pub fn next_batch(&self, events: &Receiver<notify::Result<notify::Event>>, window: Duration) -> Option<Vec<WatchEvent>> {
+        receive_batch_until(events, window, self.next_deferred_deadline())
+    }
+
+    /// The cached directives, keyed by canonical file path normalized with `normalize_path`, and
+    /// directive ID.
+    pub fn directives_map(&self) -> &AllDirectivesMap {
+        &self.directives_map
+    }
+
+    /// The link graph for the cached directives.
+    pub fn link_graph(&self) -> &LinkGraph {
+        &self.link_graph
+    }
+
+    /// The aggregator writing the outputs.
+    pub fn aggregator(&self) -> &Aggregator {
+        &self.aggregator
+    }
+
+    /// Processes all files below `root`, replacing the cache, builds the link graph and
+    /// writes the aggregated outputs.
+    pub fn initial_scan<P: AsRef<Path>>(&mut self, root: P) -> Result<ChangeSummary, RstParserError> {
+        let root = root.as_ref();
+        info!("Performing initial scan of '{}'...", root.display());
+        let initial_files = self.file_walker.find_files(root)?;
+        info!("Initial scan found {} files to process.", initial_files.len());
+
+        let processed_map = self.processor.process_files_watch(initial_files)?;
+        let mut updated_files: Vec<PathBuf> = processed_map.keys().cloned().collect();
+        updated_files.sort();
+        self.directives_map.clear();
+        for (file_path, directives_in_file_vec) in processed_map {
+            let mut file_map = HashMap::new();
+            for dws_arc in directives_in_file_vec {
+                let id = dws_arc.lock().unwrap().id.clone();
+                file_map.insert(id, dws_arc);
+            }
+            self.directives_map.insert(normalize_path(&file_path), file_map);
+        }
+
+        debug!("Applying directive functions (initial scan)...");
+        self.link_graph = LinkGraph::new();
+        log_diagnostics(&self.function_applicator.apply_to_all(&self.directives_map, &mut self.link_graph));
+        debug!("Directive functions applied. Link graph has {} entries.", self.link_graph.len());
+
+        let initial_directive_count = self.directives_map.values().map(|fm| fm.len()).sum::<usize>();
+        info!("Initial scan found {} directives.", initial_directive_count);
+
+        let output_files = self.aggregator.aggregate_map_to_json_with_links(&self.directives_map, &self.link_graph)?;
+        Ok(ChangeSummary { updated_files, removed_files: Vec::new(), output_files })
+    }
+
+    /// Runs `initial_scan`, then applies the events `events` received in the meantime, so that
+    /// changes made while the tree was being scanned are not lost. The watcher sending to
+    /// `events` has to be armed before calling this; the remaining events are left for the
+    /// caller's event loop. Repeated identical events are applied once. The summary lists the
+    /// files updated or removed by the scan and the events, and the outputs written last.
+    pub fn initial_scan_with_buffered_events<P: AsRef<Path>>(
+        &mut self,
+        root: P,
+        events: &Receiver<notify::Result<notify::Event>>,
+    ) -> Result<ChangeSummary, RstParserError> {
+        let mut summary = self.initial_scan(root)?;
+
+        let mut batch = EventBatch::default();
+        for event_result in events.try_iter() {
+            batch.add_notify_result(event_result);
+        }
+        let buffered_events = batch.into_events();
+        if buffered_events.is_empty() {
+            return Ok(summary);
+        }
+        info!("Applying {} file events received during the initial scan.", buffered_events.len());
+
+        let events_summary = self.handle_events(buffered_events);
+        summary.updated_files.extend(events_summary.updated_files);
+        summary.removed_files.extend(events_summary.removed_files);
+        if !events_summary.output_files.is_empty() {
+            summary.output_files = events_summary.output_files;
+        }
+        summary.updated_files.sort();
+        summary.updated_files.dedup();
+        summary.removed_files.sort();
+        summary.removed_files.dedup();
+        Ok(summary)
+    }
+
+    /// Applies a single file event to the cache and link graph. If anything changed, the
+    /// outputs are re-aggregated; errors while writing them are reported and leave
+    /// `output_files` empty.
+    pub fn handle_event(&mut self, event: WatchEvent) -> ChangeSummary {
+        self.handle_events(vec![event])
+    }
+
+    /// Applies a batch of file events, e.g. from `next_batch`, in order, then updates the
+    /// link graph and re-aggregates the outputs once for the whole batch, see `handle_event`.
+    /// Pending files whose watch delay has passed are processed first.
+    pub fn handle_events(&mut self, events: Vec<WatchEvent>) -> ChangeSummary {
+        let timer = Timer::new("handle_events");
+        let event_count = events.len();
+        let span = stage_span!("handle_events", events, affected_directives);
+        span.record_count("events", event_count);
+        let mut pending = PendingLinkUpdate::default();
+        let mut summary = ChangeSummary::default();
+
+        let now = Instant::now();
+        let mut due_files: Vec<(Instant, PathBuf)> = self.deferred_files.iter()
+            .filter(|(_, due)| **due <= now)
+            .map(|(path, due)| (*due, path.clone()))
+            .collect();
+        due_files.sort();
+        for (_, path) in due_files {
+            self.deferred_files.remove(&path);
+            self.process_path(&path, &mut pending, &mut summary);
+        }
+        for event in events {
+            let event_span = stage_span!("watch_event", event, affected_directives);
+            event_span.record_display("event", format_args!("{:?}", event));
+            let affected_before = pending.affected_ids_for_neighbor_scan.len();
+            self.apply_event(event, &mut pending, &mut summary);
+            event_span.record_count("affected_directives", pending.affected_ids_for_neighbor_scan.len() - affected_before);
+        }
+        // A file processed more than once in the batch, or removed after it was processed,
+        // left directives behind that are no longer cached
+        pending.arcs_for_subset_application.retain(|arc| {
+            let dws = arc.lock().unwrap();
+            self.directives_map.get(&normalize_path(Path::new(&dws.source_file)))
+                .and_then(|file_directives| file_directives.get(&dws.id))
+                .is_some_and(|cached| Arc::ptr_eq(cached, arc))
+        });
+
+        let affected_directives = pending.affected_ids_for_neighbor_scan.len();
+        span.record_count("affected_directives", affected_directives);
+        if pending.changed {
+            self.update_link_graph(pending);
+            let final_directive_count = self.directives_map.values().map(|fm| fm.len()).sum::<usize>();
+            debug!("Re-aggregating {} total directives...", final_directive_count);
+            match self.aggregator.aggregate_map_to_json_with_links(&self.directives_map, &self.link_graph) {
+                Ok(output_files) => summary.output_files = output_files,
+                Err(err) => error!("Error writing JSON files after event: {}", err),
+            }
+        }
+        debug!("handle_events events={} affected_directives={} duration={}", event_count, affected_directives, format_duration(timer.elapsed()));
+        summary
+    }
+
+    /// Applies one file event to the cache, collecting what the link graph update needs.
+    fn apply_event(&mut self, event: WatchEvent, pending: &mut PendingLinkUpdate, summary: &mut ChangeSummary) {
+        debug!("Applying {:?}", event);
+        match event {
+            WatchEvent::Created(path) | WatchEvent::RenamedTo(path) => {
+                if path.is_dir() {
+                    self.process_new_directory(&path, pending, summary);
+                } else {
+                    self.reprocess_path(&path, pending, summary);
+                }
+            }
+            WatchEvent::Modified(path) => self.reprocess_path(&path, pending, summary),
+            WatchEvent::Removed(path) => {
+                info!("Path removed: {}", path.display());
+                self.remove_cached_path(&path, pending, summary);
+            }
+            WatchEvent::RenamedFrom(path) => {
+                self.remove_cached_path(&path, pending, summary);
+            }
+            WatchEvent::Renamed { from, to } => {
+                info!("Path renamed: {} -> {}", from.display(), to.display());
+                // Some backends report the `From` and `To` halves before `Both`; if those were
+                // already applied there is nothing left to do.
+                let from_was_cached = self.remove_cached_path(&from, pending, summary);
+                if from_was_cached || !self.directives_map.contains_key(&event_path_key(&to)) {
+                    if to.is_dir() {
+                        self.process_new_directory(&to, pending, summary);
+                    } else {
+                        self.reprocess_path(&to, pending, summary);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Processes every matching file below a directory that appeared in the watched tree,
+    /// as if each had been reported by its own Create event. Notify only reports the
+    /// directory itself when a whole tree is copied or moved in.
+    fn process_new_directory(&mut self, dir: &Path, pending: &mut PendingLinkUpdate, summary: &mut ChangeSummary) {
+        info!("Directory created: {}", dir.display());
+        match self.file_walker.find_files(dir) {
+            Ok(files) => {
+                for file in files {
+                    self.reprocess_path(&file, pending, summary);
+                }
+            }
+            Err(e) => error!("Error scanning directory {}: {}", dir.display(), e),
+        }
+    }
+
+    /// Re-parses a created or modified file and replaces its cached directives.
+    fn reprocess_path(&mut self, path_to_process_orig: &Path, pending: &mut PendingLinkUpdate, summary: &mut ChangeSummary) {
+        if !self.file_walker.matches_extension(path_to_process_orig) || !path_to_process_orig.is_file() {
+            return; // Not watched, or a transient file that is already gone again
+        }
+        info!("File created/modified: {}", path_to_process_orig.display());
+        let canonical_path = match std::fs::canonicalize(path_to_process_orig) {
+            Ok(canonical_path) => canonical_path,
+            Err(e) => {
+                error!("Error processing file {}: {}", path_to_process_orig.display(), e);
+                return;
+            }
+        };
+        if self.deferred_files.contains_key(&canonical_path) {
+            debug!("{} is already pending, its latest content is read when it is due", canonical_path.display());
+            return;
+        }
+        let wait = self.event_throttle.required_wait(&normalize_path(&canonical_path), Instant::now());
+        if !wait.is_zero() {
+            debug!("Delaying processing of {} by {} ms", canonical_path.display(), wait.as_millis());
+            self.deferred_files.insert(canonical_path, Instant::now() + wait);
+            return;
+        }
+        self.process_path(&canonical_path, pending, summary);
+    }
+
+    /// Parses the file at `canonical_path` and replaces its cached directives.
+    fn process_path(&mut self, canonical_path: &Path, pending: &mut PendingLinkUpdate, summary: &mut ChangeSummary) {
+        let path_key = normalize_path(canonical_path);
+        // Old IDs from this file, whose links are cleared and whose neighbors are reprocessed
+        // unless they are parsed again with the same links
+        let mut old_ids: HashSet<String> = self.directives_map.get(&path_key)
+            .map(|old_file_directives| old_file_directives.keys().cloned().collect())
+            .unwrap_or_default();
+
+        match self.processor.process_file_watch(canonical_path) {
+            Ok(processed_directives_arcs_for_file) => {
+                let mut new_file_map = HashMap::new();
+                for dws_arc in processed_directives_arcs_for_file {
+                    let dws_guard = dws_arc.lock().unwrap();
+                    new_file_map.insert(dws_guard.id.clone(), dws_arc.clone());
+                    if old_ids.contains(&dws_guard.id) && !self.function_applicator.has_changed_links(&dws_guard, &self.link_graph) {
+                        debug!("Links of '{}' are unchanged, not reapplying directive functions", dws_guard.id);
+                        old_ids.remove(&dws_guard.id);
+                        continue;
+                    }
+                    pending.arcs_for_subset_application.push(dws_arc.clone());
+                    pending.ids_to_clear_from_graph.insert(dws_guard.id.clone()); // Also clear new IDs in case they existed before with different content
+                    pending.affected_ids_for_neighbor_scan.insert(dws_guard.id.clone());
+                }
+                self.directives_map.insert(path_key.clone(), new_file_map);
+                pending.changed = true;
+                summary.updated_files.push(canonical_path.to_path_buf());
+                info!("Updated/added directives for {}", canonical_path.display());
+            }
+            Err(e) => error!("Error processing file {}: {}", canonical_path.display(), e),
+        }
+        for old_id in old_ids {
+            pending.ids_to_clear_from_graph.insert(old_id.clone());
+            pending.affected_ids_for_neighbor_scan.insert(old_id);
+        }
+        self.event_throttle.record_processed(&path_key, Instant::now());
+    }
+
+    /// Drops cached directives for a removed file, or for all files under a removed directory.
+    /// Returns `true` if anything was cached for the path.
+    fn remove_cached_path(&mut self, removed_path_item_orig: &Path, pending: &mut PendingLinkUpdate, summary: &mut ChangeSummary) -> bool {
+        let path_key_candidate = event_path_key(removed_path_item_orig);
+        self.deferred_files.retain(|path, _| !normalize_path(path).starts_with(&path_key_candidate));
+        let keys_to_remove_from_map: Vec<PathBuf> = self.directives_map.keys()
+            .filter(|k| **k == path_key_candidate || k.starts_with(&path_key_candidate))
+            .cloned()
+            .collect();
+
+        let mut removed_anything = false;
+        for key_to_remove in keys_to_remove_from_map {
+            self.event_throttle.forget(&key_to_remove);
+            if let Some(removed_file_directives) = self.directives_map.remove(&key_to_remove) {
+                for id in removed_file_directives.keys() {
+                    pending.ids_to_clear_from_graph.insert(id.clone());
+                    pending.affected_ids_for_neighbor_scan.insert(id.clone());
+                }
+                info!("Removed directives from cache for {}", key_to_remove.display());
+                pending.changed = true;
+                removed_anything = true;
+                summary.removed_files.push(key_to_remove);
+            }
+        }
+        removed_anything
+    }
+
+    /// Incrementally updates the link graph for the directives touched by an event.
+    fn update_link_graph(&mut self, pending: PendingLinkUpdate) {
+        let PendingLinkUpdate {
+            ids_to_clear_from_graph,
+            mut arcs_for_subset_application,
+            affected_ids_for_neighbor_scan,
+            ..
+        } = pending;
+
+        // Find neighbors of affected IDs (those that linked TO or were targeted BY affected_ids_for_neighbor_scan)
+        // This scan must happen BEFORE clearing links from the graph.
+        let mut neighbor_arcs_to_reprocess: HashMap<String, Arc<Mutex<DirectiveWithSource>>> = HashMap::new();
+        if !affected_ids_for_neighbor_scan.is_empty() {
+            debug!("Scanning for neighbors of {} affected/removed IDs...", affected_ids_for_neighbor_scan.len());
+            let directives_by_id = index_by_id(&self.directives_map);
+            for (source_id, node_data) in self.link_graph.iter() {
+                // Check if this source_id is one of the directly affected ones (already in arcs_for_subset_application or to be removed)
+                // If not, check its links.
+                if !affected_ids_for_neighbor_scan.contains(source_id) {
+                    for targets in node_data.outgoing_links.values() {
+                        if targets.iter().any(|target_id| affected_ids_for_neighbor_scan.contains(target_id)) {
+                            // This source_id links to an affected ID. It needs reprocessing.
+                            if let Some(arc) = directives_by_id.get(source_id.as_str()) {
+                                neighbor_arcs_to_reprocess.insert(source_id.clone(), Arc::clone(arc));
+                            }
+                            break; // Found a reason to reprocess this source_id, move to next in graph
+                        }
+                    }
+                }
+            }
+            // Directives that were targets of affected IDs get their incoming links updated when
+            // apply_to_subset re-runs on the sources, after remove_links_for_ids cleared the stale ones.
+        }
+
+        // Add collected neighbors to the main list for subset application, avoiding duplicates
+        for (id, arc) in neighbor_arcs_to_reprocess {
+            if !arcs_for_subset_application.iter().any(|a| a.lock().unwrap().id == id) {
+                arcs_for_subset_application.push(arc);
+            }
+        }
+
+        let mut still_valid_directive_ids = HashSet::new();
+        for file_directives in self.directives_map.values() {
+            for id in file_directives.keys() {
+                still_valid_directive_ids.insert(id.clone());
+            }
+        }
+
+        if !ids_to_clear_from_graph.is_empty() {
+            debug!("Clearing links for {} directive IDs from graph...", ids_to_clear_from_graph.len());
+            remove_links_for_ids(&mut self.link_graph, &ids_to_clear_from_graph, RemovalMode::IdsAndOrphans, &still_valid_directive_ids);
+        }
+
+        if !arcs_for_subset_application.is_empty() {
+            debug!("Re-applying directive functions to {} directives (modified + neighbors)...", arcs_for_subset_application.len());
+            log_diagnostics(&self.function_applicator.apply_to_subset(&arcs_for_subset_application, &self.directives_map, &mut self.link_graph));
+        }
+
+        // Final cleanup: remove any LinkGraph nodes for directives that no longer exist in directives_map
+        self.link_graph.retain(|id, _| still_valid_directive_ids.contains(id));
+        debug!("Directive functions updated. Link graph has {} entries.", self.link_graph.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::GroupBy;
+    use crate::link_data::{LinkConfig, LinkTypeConfig};
+    use notify::Event;
+    use tempfile::{tempdir, TempDir};
+
+    const BASE: Duration = Duration::from_millis(100);
+
+    // Simulates the watch loop: each event waits out the throttle, is processed, and is recorded.
+    // Returns the offsets (from `start`) at which processing happened.
+    fn simulate(throttle: &mut EventThrottle, path: &Path, start: Instant, event_offsets_ms: &[u64]) -> Vec<Duration> {
+        let mut clock = start;
+        let mut processed_at = Vec::new();
+        for &offset in event_offsets_ms {
+            clock = clock.max(start + Duration::from_millis(offset));
+            clock += throttle.required_wait(path, clock);
+            processed_at.push(clock - start);
+            throttle.record_processed(path, clock);
+        }
+        processed_at
+    }
+
+    #[test]
+    fn test_event_throttle_first_event_is_not_delayed() {
+        let mut throttle = EventThrottle::new(BASE);
+        assert_eq!(throttle.required_wait(Path::new("a.rst"), Instant::now()), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_event_throttle_backs_off_exponentially_for_rapid_events() {
+        let mut throttle = EventThrottle::new(BASE);
+        let path = Path::new("a.rst");
+        let events: Vec<u64> = (0..20).map(|i| i * 10).collect(); // An event every 10 ms
+        let processed_at = simulate(&mut throttle, path, Instant::now(), &events);
+
+        let gaps: Vec<u128> = processed_at.windows(2).map(|w| (w[1] - w[0]).as_millis()).collect();
+        assert_eq!(&gaps[..6], &[100, 200, 400, 800, 1600, 3200]);
+        // Processing never happens more than once per backoff window, and the window is capped.
+        assert!(gaps.iter().all(|&gap| gap >= 100));
+        assert!(gaps[6..].iter().all(|&gap| gap == 5000));
+    }
+
+    #[test]
+    fn test_event_throttle_resets_after_stable_period() {
+        let mut throttle = EventThrottle::new(BASE);
+        let path = Path::new("a.rst");
+        let start = Instant::now();
+        simulate(&mut throttle, path, start, &[0, 10, 20]); // Processed at 0, 100, 300; window now 400
+        let last_processed = start + Duration::from_millis(300);
+
+        // Quiet for less than 2x the window: the window is kept.
+        assert_eq!(throttle.required_wait(path, last_processed + Duration::from_millis(500)), Duration::ZERO);
+        assert_eq!(throttle.files[path].current_delay, Duration::from_millis(400));
+
+        // Quiet for 2x the window: back to the base delay.
+        assert_eq!(throttle.required_wait(path, last_processed + Duration::from_millis(800)), Duration::ZERO);
+        assert_eq!(throttle.files[path].current_delay, BASE);
+    }
+
+    #[test]
+    fn test_event_throttle_tracks_files_independently() {
+        let mut throttle = EventThrottle::new(BASE);
+        let now = Instant::now();
+        throttle.record_processed(Path::new("a.rst"), now);
+        assert_eq!(throttle.required_wait(Path::new("b.rst"), now), Duration::ZERO);
+        assert_eq!(throttle.required_wait(Path::new("a.rst"), now + Duration::from_millis(40)), Duration::from_millis(60));
+
+        throttle.forget(Path::new("a.rst"));
+        assert_eq!(throttle.required_wait(Path::new("a.rst"), now), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_watch_event_from_notify() {
+        let from = PathBuf::from("/does/not/exist/a.rst");
+        let to = PathBuf::from("/does/not/exist/b.rst");
+        let both = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both))).add_path(from.clone()).add_path(to.clone());
+        assert_eq!(WatchEvent::from_notify(&both), vec![WatchEvent::Renamed { from: from.clone(), to: to.clone() }]);
+
+        let any = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Any))).add_path(from.clone());
+        assert_eq!(WatchEvent::from_notify(&any), vec![WatchEvent::RenamedFrom(from.clone())]);
+
+        let removed = Event::new(EventKind::Remove(notify::event::RemoveKind::File)).add_path(from.clone()).add_path(to.clone());
+        assert_eq!(WatchEvent::from_notify(&removed), vec![WatchEvent::Removed(from), WatchEvent::Removed(to)]);
+
+        assert!(WatchEvent::from_notify(&Event::new(EventKind::Access(notify::event::AccessKind::Any))).is_empty());
+    }
+
+    /// A session looking for `req` directives in `.rst` files, linking via `:links:` and
+    /// writing all directives to `all_directives.json` in its own output directory.
+    fn test_session() -> (WatchSession, TempDir) {
+        let output_dir = tempdir().unwrap();
+        let link_config = LinkConfig {
+            link_types: vec![LinkTypeConfig::new("links")],
+            ..Default::default()
+        };
+        let session = WatchSession::new(
+            Processor::new(vec!["req".to_string()]),
+            FunctionApplicator::new(Arc::new(link_config)),
+            Aggregator::new(output_dir.path(), GroupBy::All),
+            FileWalker::new().with_extensions(vec!["rst".to_string()]),
+        );
+        (session, output_dir)
+    }
+
+    /// IDs written to `all_directives.json`, sorted.
+    fn written_ids(output_dir: &TempDir) -> Vec<String> {
+        let json = std::fs::read_to_string(output_dir.path().join("all_directives.json")).unwrap();
+        let outputs: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        let mut ids: Vec<String> = outputs.iter().map(|o| o["id"].as_str().unwrap().to_string()).collect();
+        ids.sort();
+        ids
+    }
+
+    fn cached_ids(session: &WatchSession) -> Vec<String> {
+        let mut ids: Vec<String> = session.directives_map().values().flat_map(|file_map| file_map.keys().cloned()).collect();
+        ids.sort();
+        ids
+    }
+
+    fn rename_events(from: &Path, to: &Path) -> Vec<WatchEvent> {
+        // The order in which the inotify backend reports a single rename.
+        vec![
+            WatchEvent::RenamedFrom(from.to_path_buf()),
+            WatchEvent::RenamedTo(to.to_path_buf()),
+            WatchEvent::Renamed { from: from.to_path_buf(), to: to.to_path_buf() },
+        ]
+    }
+
+    fn both_only_rename_event(from: &Path, to: &Path) -> Vec<WatchEvent> {
+        vec![WatchEvent::Renamed { from: from.to_path_buf(), to: to.to_path_buf() }]
+    }
+
+    fn check_rename_migrates_entry(events_for: fn(&Path, &Path) -> Vec<WatchEvent>) {
+        let temp_dir = tempdir().unwrap();
+        let old_path = temp_dir.path().join("old.rst");
+        let new_path = temp_dir.path().join("new.rst");
+        let linker_path = temp_dir.path().join("linker.rst");
+        std::fs::write(&old_path, ".. req::\n\n   Generated id.\n").unwrap();
+        let old_id = format!("{}:req:1", std::fs::canonicalize(&old_path).unwrap().display());
+        std::fs::write(&linker_path, format!(".. req::\n   :id: linker\n   :links: {}\n", old_id)).unwrap();
+
+        let (mut session, _output_dir) = test_session();
+        assert!(session.handle_event(WatchEvent::Created(old_path.clone())).has_changes());
+        assert!(session.handle_event(WatchEvent::Created(linker_path.clone())).has_changes());
+        assert!(session.link_graph()[&old_id].incoming_links.contains_key("links_back"));
+
+        std::fs::rename(&old_path, &new_path).unwrap();
+        for event in events_for(&old_path, &new_path) {
+            session.handle_event(event);
+        }
+
+        let canonical_new_path = std::fs::canonicalize(&new_path).unwrap();
+        assert_eq!(session.directives_map().len(), 2);
+        assert!(!session.directives_map().keys().any(|k| k.ends_with("old.rst")));
+        let new_ids: Vec<&String> = session.directives_map()[&normalize_path(&canonical_new_path)].keys().collect();
+        assert_eq!(new_ids, vec![&format!("{}:req:1", canonical_new_path.display())]);
+        assert!(!session.link_graph().contains_key(&old_id));
+    }
+
+    #[test]
+    fn test_watch_rename_migrates_entry_and_regenerates_ids() {
+        check_rename_migrates_entry(rename_events);
+    }
+
+    #[test]
+    fn test_watch_rename_reported_as_single_event() {
+        check_rename_migrates_entry(both_only_rename_event);
+    }
+
+    #[test]
+    fn test_watch_rename_to_unwatched_extension_is_removal() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("doc.rst");
+        let backup_path = temp_dir.path().join("doc.rst~");
+        std::fs::write(&path, ".. req::\n   :id: r1\n").unwrap();
+
+        let (mut session, _output_dir) = test_session();
+        session.handle_event(WatchEvent::Created(path.clone()));
+        assert_eq!(session.directives_map().len(), 1);
+
+        std::fs::rename(&path, &backup_path).unwrap();
+        for event in rename_events(&path, &backup_path) {
+            session.handle_event(event);
+        }
+        assert!(session.directives_map().is_empty());
+    }
+
+    #[test]
+    fn test_watch_atomic_save_via_temp_file() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("doc.rst");
+        let temp_path = temp_dir.path().join("doc.rst.tmp");
+        std::fs::write(&path, ".. req::\n   :id: r1\n\n   Old content.\n").unwrap();
+
+        let (mut session, _output_dir) = test_session();
+        session.handle_event(WatchEvent::Created(path.clone()));
+
+        std::fs::write(&temp_path, ".. req::\n   :id: r1\n\n   New content.\n").unwrap();
+        assert!(!session.handle_event(WatchEvent::Created(temp_path.clone())).has_changes());
+        std::fs::rename(&temp_path, &path).unwrap();
+        for event in rename_events(&temp_path, &path) {
+            session.handle_event(event);
+        }
+
+        assert_eq!(session.directives_map().len(), 1);
+        let file_map = &session.directives_map()[&normalize_path(&std::fs::canonicalize(&path).unwrap())];
+        assert_eq!(file_map["r1"].lock().unwrap().directive.content, "New content.");
+    }
+
+    #[test]
+    fn test_watch_new_and_removed_directory() {
+        let temp_dir = tempdir().unwrap();
+        let new_dir = temp_dir.path().join("new_dir");
+        let nested_dir = new_dir.join("nested");
+        std::fs::create_dir_all(&nested_dir).unwrap();
+        std::fs::write(new_dir.join("a.rst"), ".. req::\n   :id: a\n").unwrap();
+        std::fs::write(nested_dir.join("b.rst"), ".. req::\n   :id: b\n").unwrap();
+        std::fs::write(nested_dir.join("notes.txt"), ".. req::\n   :id: ignored\n").unwrap();
+        let kept_path = temp_dir.path().join("kept.rst");
+        std::fs::write(&kept_path, ".. req::\n   :id: kept\n").unwrap();
+
+        let (mut session, _output_dir) = test_session();
+        session.handle_event(WatchEvent::Created(kept_path.clone()));
+
+        assert!(session.handle_event(WatchEvent::Created(new_dir.clone())).has_changes());
+        assert_eq!(cached_ids(&session), vec!["a", "b", "kept"]);
+
+        // The whole tree is gone by the time the events arrive, so the removed paths
+        // can no longer be canonicalized themselves.
+        std::fs::remove_dir_all(&new_dir).unwrap();
+        assert!(session.handle_event(WatchEvent::Removed(nested_dir)).has_changes());
+        assert!(session.handle_event(WatchEvent::Removed(new_dir)).has_changes());
+        assert_eq!(session.directives_map().len(), 1);
+        assert!(session.directives_map().contains_key(&normalize_path(&std::fs::canonicalize(&kept_path).unwrap())));
+    }
+
+    #[test]
+    fn test_watch_session_create_modify_remove_sequence() {
+        let watched_dir = tempdir().unwrap();
+        let target_path = watched_dir.path().join("target.rst");
+        let source_path = watched_dir.path().join("source.rst");
+        std::fs::write(&target_path, ".. req::\n   :id: target\n").unwrap();
+
+        let (mut session, output_dir) = test_session();
+        let summary = session.initial_scan(watched_dir.path()).unwrap();
+        assert_eq!(summary.updated_files, vec![std::fs::canonicalize(&target_path).unwrap()]);
+        assert_eq!(written_ids(&output_dir), vec!["target"]);
+
+        // Create: a new directive linking to the existing one
+        std::fs::write(&source_path, ".. req::\n   :id: source\n   :links: target\n").unwrap();
+        let summary = session.handle_event(WatchEvent::Created(source_path.clone()));
+        assert_eq!(summary.output_files, vec![output_dir.path().join("all_directives.json")]);
+        assert_eq!(written_ids(&output_dir), vec!["source", "target"]);
+        assert_eq!(session.link_graph()["target"].incoming_links["links_back"], vec!["source".to_string()]);
+        let json = std::fs::read_to_string(output_dir.path().join("all_directives.json")).unwrap();
+        assert!(json.contains("\"links_back\": \"source\""));
+
+        // Modify: the link is dropped and the directive gets a new ID
+        std::fs::write(&source_path, ".. req::\n   :id: renamed-source\n").unwrap();
+        let summary = session.handle_event(WatchEvent::Modified(source_path.clone()));
+        assert_eq!(summary.updated_files, vec![std::fs::canonicalize(&source_path).unwrap()]);
+        assert_eq!(cached_ids(&session), vec!["renamed-source", "target"]);
+        assert_eq!(written_ids(&output_dir), vec!["renamed-source", "target"]);
+        assert!(!session.link_graph().contains_key("source"));
+        assert!(session.link_graph().get("target").is_none_or(|node| node.incoming_links.is_empty()));
+
+        // Remove: only the target is left, in the cache and in the output
+        std::fs::remove_file(&source_path).unwrap();
+        let summary = session.handle_event(WatchEvent::Removed(source_path.clone()));
+        assert_eq!(summary.removed_files.len(), 1);
+        assert_eq!(cached_ids(&session), vec!["target"]);
+        assert_eq!(written_ids(&output_dir), vec!["target"]);
+
+        // Events for unwatched files change nothing and write nothing
+        let unwatched = watched_dir.path().join("notes.txt");
+        std::fs::write(&unwatched, ".. req::\n   :id: ignored\n").unwrap();
+        let summary = session.handle_event(WatchEvent::Created(unwatched));
+        assert!(!summary.has_changes());
+        assert!(summary.output_files.is_empty());
+    }
+
+    #[test]
+    fn test_watch_modify_with_unchanged_links_skips_function_application() {
+        let watched_dir = tempdir().unwrap();
+        let source_path = watched_dir.path().join("source.rst");
+        std::fs::write(watched_dir.path().join("target.rst"), ".. req::\n   :id: target\n").unwrap();
+        std::fs::write(&source_path, ".. req::\n   :id: source\n   :links: target\n").unwrap();
+        let (mut session, output_dir) = test_session();
+        session.initial_scan(watched_dir.path()).unwrap();
+
+        // Only the content changes, so the link graph is kept as is
+        std::fs::write(&source_path, ".. req::\n   :id: source\n   :links: target\n\n   New text.\n").unwrap();
+        let mut pending = PendingLinkUpdate::default();
+        session.reprocess_path(&source_path, &mut pending, &mut ChangeSummary::default());
+        assert!(pending.changed);
+        assert!(pending.arcs_for_subset_application.is_empty());
+        assert!(pending.ids_to_clear_from_graph.is_empty());
+
+        let summary = session.handle_event(WatchEvent::Modified(source_path.clone()));
+        assert!(summary.has_changes());
+        assert_eq!(session.link_graph()["target"].incoming_links["links_back"], vec!["source".to_string()]);
+        let json = std::fs::read_to_string(output_dir.path().join("all_directives.json")).unwrap();
+        assert!(json.contains("New text."));
+
+        // A changed link option is still applied
+        std::fs::write(&source_path, ".. req::\n   :id: source\n").unwrap();
+        let mut pending = PendingLinkUpdate::default();
+        session.reprocess_path(&source_path, &mut pending, &mut ChangeSummary::default());
+        assert_eq!(pending.arcs_for_subset_application.len(), 1);
+        assert!(pending.ids_to_clear_from_graph.contains("source"));
+    }
+
+    #[test]
+    fn test_initial_scan_applies_events_received_during_the_scan() {
+        let watched_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+        let target_path = watched_dir.path().join("target.rst");
+        let source_path = watched_dir.path().join("source.rst");
+        std::fs::write(&target_path, ".. req::\n   :id: target\n").unwrap();
+
+        // Once the scan has read `target.rst` (and listed the files), the tree changes and the
+        // armed watcher queues the events
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (changed_target, new_source) = (target_path.clone(), source_path.clone());
+        let processor = Processor::new(vec!["req".to_string()]).with_progress(Box::new(move |_, _| {
+            std::fs::write(&changed_target, ".. req::\n   :id: target\n\n   Changed during the scan.\n").unwrap();
+            std::fs::write(&new_source, ".. req::\n   :id: source\n   :links: target\n").unwrap();
+            let modified = Event::new(EventKind::Modify(ModifyKind::Any)).add_path(changed_target.clone());
+            tx.send(Ok(modified.clone())).unwrap();
+            tx.send(Ok(modified)).unwrap();
+            tx.send(Ok(Event::new(EventKind::Create(notify::event::CreateKind::File)).add_path(new_source.clone()))).unwrap();
+        }));
+        let link_config = LinkConfig { link_types: vec![LinkTypeConfig::new("links")], ..Default::default() };
+        let mut session = WatchSession::new(
+            processor,
+            FunctionApplicator::new(Arc::new(link_config)),
+            Aggregator::new(output_dir.path(), GroupBy::All),
+            FileWalker::new().with_extensions(vec!["rst".to_string()]),
+        );
+
+        let summary = session.initial_scan_with_buffered_events(watched_dir.path(), &rx).unwrap();
+        let mut expected_files = vec![std::fs::canonicalize(&source_path).unwrap(), std::fs::canonicalize(&target_path).unwrap()];
+        expected_files.sort();
+        assert_eq!(summary.updated_files, expected_files);
+        assert_eq!(summary.output_files, vec![output_dir.path().join("all_directives.json")]);
+        assert_eq!(cached_ids(&session), vec!["source", "target"]);
+        assert_eq!(session.link_graph()["target"].incoming_links["links_back"], vec!["source".to_string()]);
+        let json = std::fs::read_to_string(output_dir.path().join("all_directives.json")).unwrap();
+        assert!(json.contains("Changed during the scan."));
+        assert!(rx.try_recv().is_err(), "all buffered events are consumed");
+    }
+
+    #[test]
+    fn test_throttled_file_is_deferred_without_stalling_the_batch() {
+        let watched_dir = tempdir().unwrap();
+        let busy = watched_dir.path().join("busy.rst");
+        let other = watched_dir.path().join("other.rst");
+        std::fs::write(&busy, ".. req::\n   :id: B1\n").unwrap();
+        let (session, output_dir) = test_session();
+        let mut session = session.with_watch_delay(Duration::from_secs(2));
+        assert!(session.handle_event(WatchEvent::Created(busy.clone())).has_changes());
+        assert_eq!(session.next_deferred_deadline(), None);
+
+        // The second event for `busy` falls into its delay; `other` is processed right away
+        std::fs::write(&busy, ".. req::\n   :id: B2\n").unwrap();
+        std::fs::write(&other, ".. req::\n   :id: O1\n").unwrap();
+        let started = Instant::now();
+        let summary = session.handle_events(vec![WatchEvent::Modified(busy.clone()), WatchEvent::Created(other.clone())]);
+        assert!(started.elapsed() < Duration::from_secs(1), "the batch waited for the throttled file");
+        assert_eq!(summary.updated_files, vec![std::fs::canonicalize(&other).unwrap()]);
+        assert_eq!(written_ids(&output_dir), vec!["B1", "O1"]);
+
+        // Further events keep the pending deadline, and the file is processed once it is due
+        let deadline = session.next_deferred_deadline().expect("busy.rst is pending");
+        session.handle_event(WatchEvent::Modified(busy.clone()));
+        assert_eq!(session.next_deferred_deadline(), Some(deadline));
+        let (_tx, rx) = std::sync::mpsc::channel();
+        assert_eq!(session.next_batch(&rx, Duration::from_millis(20)), Some(Vec::new()));
+        assert!(Instant::now() >= deadline);
+        let summary = session.handle_events(Vec::new());
+        assert_eq!(summary.updated_files, vec![std::fs::canonicalize(&busy).unwrap()]);
+        assert_eq!(written_ids(&output_dir), vec!["B2", "O1"]);
+        assert_eq!(session.next_deferred_deadline(), None);
+    }
+
+    #[test]
+    fn test_removing_a_pending_file_drops_it() {
+        let watched_dir = tempdir().unwrap();
+        let busy = watched_dir.path().join("busy.rst");
+        std::fs::write(&busy, ".. req::\n   :id: B1\n").unwrap();
+        let (session, _output_dir) = test_session();
+        let mut session = session.with_watch_delay(Duration::from_secs(2));
+        session.handle_event(WatchEvent::Created(busy.clone()));
+        session.handle_event(WatchEvent::Modified(busy.clone()));
+        assert!(session.next_deferred_deadline().is_some());
+
+        std::fs::remove_file(&busy).unwrap();
+        session.handle_event(WatchEvent::Removed(busy));
+        assert_eq!(session.next_deferred_deadline(), None);
+        assert!(cached_ids(&session).is_empty());
+    }
+
+    #[test]
+    fn test_receive_batch_coalesces_rapid_events() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let paths: Vec<PathBuf> = (0..4).map(|index| PathBuf::from(format!("/does/not/exist/doc{}.rst", index))).collect();
+        for index in 0..20 {
+            let path = paths[index % paths.len()].clone();
+            let kind = if index == 17 { EventKind::Remove(notify::event::RemoveKind::File) } else { EventKind::Modify(ModifyKind::Any) };
+            tx.send(Ok(Event::new(kind).add_path(path))).unwrap();
+        }
+
+        let batch = receive_batch(&rx, Duration::from_millis(20)).unwrap();
+        // One event per path, ordered by each path's last event; the removal of doc1 (event 17)
+        // is superseded by no later event
+        assert_eq!(batch, vec![
+            WatchEvent::Modified(paths[0].clone()),
+            WatchEvent::Removed(paths[1].clone()),
+            WatchEvent::Modified(paths[2].clone()),
+            WatchEvent::Modified(paths[3].clone()),
+        ]);
+
+        drop(tx);
+        assert_eq!(receive_batch(&rx, Duration::from_millis(20)), None);
+    }
+
+    #[test]
+    fn test_handle_events_processes_each_file_once() {
+        let watched_dir = tempdir().unwrap();
+        let paths: Vec<PathBuf> = (0..4).map(|index| watched_dir.path().join(format!("doc{}.rst", index))).collect();
+        for (index, path) in paths.iter().enumerate() {
+            std::fs::write(path, format!(".. req::\n   :id: R{}\n", index)).unwrap();
+        }
+        let (mut session, output_dir) = test_session();
+        session.initial_scan(watched_dir.path()).unwrap();
+
+        // A checkout rewrites every file several times in quick succession
+        let (tx, rx) = std::sync::mpsc::channel();
+        for index in 0..20 {
+            let path = &paths[index % paths.len()];
+            let links = if index % paths.len() == 0 { "" } else { "   :links: R0\n" };
+            std::fs::write(path, format!(".. req::\n   :id: R{}\n{}", index % paths.len(), links)).unwrap();
+            tx.send(Ok(Event::new(EventKind::Modify(ModifyKind::Any)).add_path(path.clone()))).unwrap();
+        }
+        let batch = receive_batch(&rx, Duration::from_millis(20)).unwrap();
+        let summary = session.handle_events(batch);
+
+        assert_eq!(summary.updated_files.len(), paths.len(), "each file is processed once");
+        assert_eq!(summary.output_files, vec![output_dir.path().join("all_directives.json")]);
+        assert_eq!(written_ids(&output_dir), vec!["R0", "R1", "R2", "R3"]);
+        let mut backlinks = session.link_graph()["R0"].incoming_links["links_back"].clone();
+        backlinks.sort();
+        assert_eq!(backlinks, vec!["R1", "R2", "R3"]);
+    }
+
+}
@@ -0,0 +1,27 @@
+use std::fs;
+use std::process::Command;
+use tempfile::tempdir;
+
+#[test]
+fn test_aliases_are_grouped_with_their_canonical_directive() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("rstparser_links.toml"), "[aliases]\nreq = [\"requirement\"]\n").unwrap();
+    fs::write(dir.path().join("old.rst"), ".. requirement:: Old\n   :id: old\n").unwrap();
+    fs::write(dir.path().join("new.rst"), ".. req:: New\n   :id: new\n\n.. note:: Unrelated\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rstparser"))
+        .current_dir(dir.path())
+        .args(["-q", "-e", "rst", "-D", "req,note"])
+        .output()
+        .expect("failed to run rstparser");
+    assert!(output.status.success(), "rstparser failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let reqs: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.path().join("output/req.json")).unwrap()).unwrap();
+    let mut found: Vec<(&str, &str)> = reqs.as_array().unwrap().iter()
+        .map(|directive| (directive["name"].as_str().unwrap(), directive["id"].as_str().unwrap()))
+        .collect();
+    found.sort();
+    assert_eq!(found, [("req", "new"), ("req", "old")]);
+    assert!(!dir.path().join("output/requirement.json").exists());
+    assert!(dir.path().join("output/note.json").exists());
+}
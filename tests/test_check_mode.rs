@@ -0,0 +1,90 @@
+use std::fs;
+use std::path::Path;
+use assert_cmd::assert::Assert;
+use assert_cmd::Command;
+use tempfile::tempdir;
+
+const LINKS_TOML: &str = "[[links]]\nname = \"links\"\n";
+
+/// Runs the binary with `--check` inside `dir`, so that `rstparser_links.toml` is picked up from there.
+fn run_check(dir: &Path, extra_args: &[&str]) -> Assert {
+    Command::cargo_bin("rstparser")
+        .unwrap()
+        .current_dir(dir)
+        .args(["--check", "--no-output", "-q", "-e", "rst", "-D", "req"])
+        .args(extra_args)
+        .assert()
+}
+
+fn problems(assert: &Assert) -> Vec<serde_json::Value> {
+    serde_json::from_slice::<serde_json::Value>(&assert.get_output().stdout).unwrap().as_array().unwrap().clone()
+}
+
+#[test]
+fn test_check_clean_exits_zero() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("rstparser_links.toml"), LINKS_TOML).unwrap();
+    fs::write(dir.path().join("reqs.rst"), ".. req::\n   :id: a\n\n.. req::\n   :id: b\n   :links: a\n").unwrap();
+
+    let output = run_check(dir.path(), &[]).code(0);
+    assert!(problems(&output).is_empty());
+    assert!(!dir.path().join("output").exists(), "--no-output must not create the output directory");
+}
+
+#[test]
+fn test_check_dangling_link_exits_two() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("rstparser_links.toml"), LINKS_TOML).unwrap();
+    fs::write(dir.path().join("reqs.rst"), ".. req::\n   :id: a\n   :links: missing\n").unwrap();
+
+    let output = run_check(dir.path(), &[]).code(2);
+    let problems = problems(&output);
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0]["rule"], "dangling-link");
+    assert_eq!(problems[0]["line"], 1);
+    assert!(problems[0]["file"].as_str().unwrap().ends_with("reqs.rst"));
+
+    // Disabled categories are still reported but do not fail
+    let output = run_check(dir.path(), &["--fail-on-dangling-links", "false"]).code(0);
+    assert_eq!(self::problems(&output).len(), 1);
+}
+
+#[test]
+fn test_check_duplicate_id_exits_two() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("reqs.rst"), ".. req::\n   :id: a\n\n.. req::\n   :id: a\n").unwrap();
+
+    let output = run_check(dir.path(), &[]).code(2);
+    let problems = problems(&output);
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0]["rule"], "duplicate-id");
+    assert_eq!(problems[0]["line"], 4);
+}
+
+#[test]
+fn test_check_missing_required_option_exits_two() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("reqs.rst"), ".. req::\n   :id: a\n").unwrap();
+
+    let output = run_check(dir.path(), &["--require-option", "req:status"]).code(2);
+    assert_eq!(problems(&output)[0]["rule"], "missing-required-option");
+
+    run_check(dir.path(), &["--require-option", "req:status", "--fail-on-missing-required", "false"]).code(0);
+}
+
+#[test]
+fn test_check_bad_link_config_exits_one() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("rstparser_links.toml"), "[[links]\nname = ").unwrap();
+    fs::write(dir.path().join("reqs.rst"), ".. req::\n   :id: a\n").unwrap();
+
+    run_check(dir.path(), &[]).code(1);
+}
+
+#[test]
+fn test_check_unreadable_file_exits_one() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("binary.rst"), [0xff, 0xfe, 0x00, 0x80]).unwrap();
+
+    run_check(dir.path(), &[]).code(1);
+}
@@ -1,5 +1,7 @@
 use std::fs::File;
 use std::io::Write;
+use assert_json_diff::assert_json_include;
+use serde_json::json;
 use tempfile::tempdir;
 use rstparser::{FileWalker, Processor};
 
@@ -31,18 +33,13 @@ fn test_cpp_file_extraction() {
     
     // Should find 1 directive
     assert_eq!(result.len(), 1);
-    
-    // Check directive name
-    assert_eq!(result[0].directive.name, "mydirective");
-    
-    // Check options
-    assert_eq!(result[0].directive.options.get("option1").unwrap(), "value1");
-    
-    // Check content
-    assert_eq!(result[0].directive.content, "This is RST content in a C++ file.");
-    
-    // Check source file
-    assert_eq!(result[0].source_file, file_path.to_string_lossy().to_string());
+    assert_json_include!(
+        actual: serde_json::to_value(&result).unwrap(),
+        expected: json!([{
+            "directive": {"name": "mydirective", "options": {"option1": "value1"}, "content": "This is RST content in a C++ file."},
+            "source_file": file_path.to_string_lossy(),
+        }])
+    );
 }
 
 #[test]
@@ -77,18 +74,13 @@ def some_function():
     
     // Should find 1 directive
     assert_eq!(result.len(), 1);
-    
-    // Check directive name
-    assert_eq!(result[0].directive.name, "mydirective");
-    
-    // Check options
-    assert_eq!(result[0].directive.options.get("option1").unwrap(), "value1");
-    
-    // Check content
-    assert_eq!(result[0].directive.content, "This is RST content in a Python file.");
-    
-    // Check source file
-    assert_eq!(result[0].source_file, file_path.to_string_lossy().to_string());
+    assert_json_include!(
+        actual: serde_json::to_value(&result).unwrap(),
+        expected: json!([{
+            "directive": {"name": "mydirective", "options": {"option1": "value1"}, "content": "This is RST content in a Python file."},
+            "source_file": file_path.to_string_lossy(),
+        }])
+    );
 }
 
 #[test]
@@ -154,18 +146,13 @@ fn test_multiple_rst_blocks_in_cpp() {
     
     // Should find 2 directives
     assert_eq!(result.len(), 2);
-    
-    // Check directive names
-    assert_eq!(result[0].directive.name, "directive1");
-    assert_eq!(result[1].directive.name, "directive2");
-    
-    // Check options
-    assert_eq!(result[0].directive.options.get("option1").unwrap(), "value1");
-    assert_eq!(result[1].directive.options.get("option2").unwrap(), "value2");
-    
-    // Check content
-    assert_eq!(result[0].directive.content, "Content for directive1.");
-    assert_eq!(result[1].directive.content, "Content for directive2.");
+    assert_json_include!(
+        actual: serde_json::to_value(&result).unwrap(),
+        expected: json!([
+            {"directive": {"name": "directive1", "options": {"option1": "value1"}, "content": "Content for directive1."}},
+            {"directive": {"name": "directive2", "options": {"option2": "value2"}, "content": "Content for directive2."}},
+        ])
+    );
 }
 
 #[test]
@@ -205,18 +192,13 @@ def some_function():
     
     // Should find 2 directives
     assert_eq!(result.len(), 2);
-    
-    // Check directive names
-    assert_eq!(result[0].directive.name, "directive1");
-    assert_eq!(result[1].directive.name, "directive2");
-    
-    // Check options
-    assert_eq!(result[0].directive.options.get("option1").unwrap(), "value1");
-    assert_eq!(result[1].directive.options.get("option2").unwrap(), "value2");
-    
-    // Check content
-    assert_eq!(result[0].directive.content, "Content for directive1.");
-    assert_eq!(result[1].directive.content, "Content for directive2.");
+    assert_json_include!(
+        actual: serde_json::to_value(&result).unwrap(),
+        expected: json!([
+            {"directive": {"name": "directive1", "options": {"option1": "value1"}, "content": "Content for directive1."}},
+            {"directive": {"name": "directive2", "options": {"option2": "value2"}, "content": "Content for directive2."}},
+        ])
+    );
 }
 
 #[test]
@@ -250,9 +232,6 @@ fn test_multiline_option_as_last_option_in_cpp() {
     // Should find 1 directive
     assert_eq!(result.len(), 1);
     
-    // Check directive name
-    assert_eq!(result[0].directive.name, "mydirective");
-    
     // Debug output
     println!("Options: {:?}", result[0].directive.options);
     println!("Content: {:?}", result[0].directive.content);
@@ -268,12 +247,14 @@ fn test_multiline_option_as_last_option_in_cpp() {
     let parsed_results_vec = rstparser::parser::parse_rst_multiple(&raw_content, &["mydirective"]);
     println!("Manually parsed options: {:?}", parsed_results_vec.first().map(|(d, _)| &d.options));
     
-    // Check options
-    assert_eq!(result[0].directive.options.get("option1").unwrap(), "value1");
-    assert_eq!(result[0].directive.options.get("option2").unwrap(), "indented line1\nindented line2");
-    
-    // Check content
-    assert_eq!(result[0].directive.content, "Content after multiline option.");
+    assert_json_include!(
+        actual: serde_json::to_value(&result[0].directive).unwrap(),
+        expected: json!({
+            "name": "mydirective",
+            "options": {"option1": "value1", "option2": "indented line1\nindented line2"},
+            "content": "Content after multiline option.",
+        })
+    );
 }
 
 #[test]
@@ -308,17 +289,16 @@ def some_function():
     // Should find 1 directive
     assert_eq!(result.len(), 1);
     
-    // Check directive name
-    assert_eq!(result[0].directive.name, "mydirective");
-    
     // Debug output
     println!("Python Options: {:?}", result[0].directive.options);
     println!("Python Content: {:?}", result[0].directive.content);
     
-    // Check options
-    assert_eq!(result[0].directive.options.get("option1").unwrap(), "value1");
-    assert_eq!(result[0].directive.options.get("option2").unwrap(), "indented line1\nindented line2");
-    
-    // Check content
-    assert_eq!(result[0].directive.content, "Content after multiline option.");
+    assert_json_include!(
+        actual: serde_json::to_value(&result[0].directive).unwrap(),
+        expected: json!({
+            "name": "mydirective",
+            "options": {"option1": "value1", "option2": "indented line1\nindented line2"},
+            "content": "Content after multiline option.",
+        })
+    );
 }
@@ -0,0 +1,43 @@
+use std::fs;
+use std::process::Command;
+use tempfile::tempdir;
+
+#[test]
+fn test_dry_run_reports_without_writing() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("reqs.rst"), ".. req::\n   :id: a\n\n.. req::\n   :id: b\n").unwrap();
+    fs::write(dir.path().join("lib.cpp"), "/// @rst\n/// .. note:: Hi\n/// @endrst\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rstparser"))
+        .current_dir(dir.path())
+        .args(["--dry-run", "-q", "-e", "rst,cpp", "-D", "req,note"])
+        .output()
+        .expect("failed to run rstparser");
+    assert!(output.status.success(), "dry run failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Files matched: 2\n  cpp  1\n  rst  1\nSample of matched files:\n  lib.cpp\n  reqs.rst\n\
+         Directives that would be extracted:\nDirective  Count\nnote           1\nreq            2\nTotal          3\n\
+         Output files that would be written:\n  create     output/note.json\n  create     output/req.json\n"
+    );
+    assert!(!dir.path().join("output").exists(), "--dry-run must not create the output directory");
+}
+
+#[test]
+fn test_default_extensions_skip_hidden_files() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join(".venv")).unwrap();
+    fs::write(dir.path().join("index.rst"), ".. req::\n   :id: a\n").unwrap();
+    fs::write(dir.path().join("api.h"), "/// @rst\n/// .. req::\n///    :id: b\n/// @endrst\n").unwrap();
+    fs::write(dir.path().join(".venv/vendored.rst"), ".. req::\n   :id: c\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rstparser"))
+        .current_dir(dir.path())
+        .env_remove("RSTPARSER_EXTENSIONS")
+        .args(["--dry-run", "-q", "-D", "req"])
+        .output()
+        .expect("failed to run rstparser");
+    assert!(output.status.success(), "dry run failed: {}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Files matched: 2\n  h    1\n  rst  1\n"), "unexpected report:\n{}", stdout);
+}
@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+const ENV_VARS: [&str; 6] = [
+    "RSTPARSER_DIR",
+    "RSTPARSER_DIRECTIVES",
+    "RSTPARSER_OUTPUT",
+    "RSTPARSER_EXTENSIONS",
+    "RSTPARSER_GROUP_BY",
+    "RSTPARSER_LINK_CONFIG",
+];
+
+/// Runs a dry run in `dir` and returns the output files it would write.
+fn planned_outputs(dir: &Path, env: &[(&str, &str)], args: &[&str]) -> Vec<String> {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rstparser"));
+    command.current_dir(dir).args(["--dry-run", "-q"]).args(args);
+    for var in ENV_VARS {
+        command.env_remove(var);
+    }
+    command.envs(env.iter().copied());
+    let output = command.output().expect("failed to run rstparser");
+    assert!(output.status.success(), "dry run failed: {}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let (_, planned) = stdout.split_once("Output files that would be written:\n").expect("no output file plan");
+    planned.lines().map(|line| line.split_whitespace().last().unwrap().to_string()).collect()
+}
+
+#[test]
+fn test_precedence_cli_over_env_over_config_over_default() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("reqs.rst"), ".. req::\n   :id: a\n").unwrap();
+
+    assert_eq!(planned_outputs(dir.path(), &[], &["-D", "req"]), ["output/req.json"]);
+
+    fs::write(
+        dir.path().join("rstparser_links.toml"),
+        "[defaults]\ndirectives = \"req\"\noutput = \"from_config\"\ngroup_by = \"all\"\n",
+    )
+    .unwrap();
+    assert_eq!(planned_outputs(dir.path(), &[], &[]), ["from_config/all_directives.json"]);
+
+    let env = [("RSTPARSER_OUTPUT", "from_env"), ("RSTPARSER_GROUP_BY", "directive-name")];
+    assert_eq!(planned_outputs(dir.path(), &env, &[]), ["from_env/req.json"]);
+    assert_eq!(planned_outputs(dir.path(), &env, &["-o", "from_cli"]), ["from_cli/req.json"]);
+}
+
+#[test]
+fn test_link_config_path_from_env() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("docs")).unwrap();
+    fs::write(dir.path().join("docs/notes.rst"), ".. note:: Hi\n").unwrap();
+    fs::write(dir.path().join("top.rst"), ".. req:: Top\n").unwrap();
+    fs::write(dir.path().join("custom.toml"), "[defaults]\ndir = \"docs\"\nextensions = \"rst\"\n").unwrap();
+
+    let directives = ("RSTPARSER_DIRECTIVES", "note,req");
+    assert_eq!(planned_outputs(dir.path(), &[directives], &[]), ["output/note.json", "output/req.json"]);
+    let env = [directives, ("RSTPARSER_LINK_CONFIG", "custom.toml")];
+    assert_eq!(planned_outputs(dir.path(), &env, &[]), ["output/note.json"]);
+}
+
+#[test]
+fn test_links_config_flag_with_custom_and_missing_path() {
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("ci")).unwrap();
+    fs::write(dir.path().join("reqs.rst"), ".. req::\n   :id: a\n").unwrap();
+    fs::write(dir.path().join("rstparser_links.toml"), "[defaults]\noutput = \"from_default\"\n").unwrap();
+    fs::write(dir.path().join("ci/links.toml"), "[defaults]\noutput = \"from_custom\"\n").unwrap();
+
+    assert_eq!(planned_outputs(dir.path(), &[], &["-D", "req"]), ["from_default/req.json"]);
+    assert_eq!(planned_outputs(dir.path(), &[], &["-D", "req", "--links-config", "ci/links.toml"]), ["from_custom/req.json"]);
+    // A missing custom file is not an error; it is not replaced by the default file either
+    assert_eq!(planned_outputs(dir.path(), &[], &["-D", "req", "--links-config", "ci/missing.toml"]), ["output/req.json"]);
+}
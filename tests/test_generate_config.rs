@@ -0,0 +1,27 @@
+use rstparser::link_data::LinkConfig;
+use std::fs;
+use std::process::Command;
+use tempfile::tempdir;
+
+#[test]
+fn test_generate_config_writes_link_fields() {
+    let dir = tempdir().unwrap();
+    fs::write(
+        dir.path().join("reqs.rst"),
+        ".. req::\n   :id: R1\n   :status: open\n\n.. req::\n   :id: R2\n   :status: done\n   :derives: R1\n\n.. test::\n   :id: T1\n   :covers: R1, R2\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rstparser"))
+        .current_dir(dir.path())
+        .args(["generate-config", "-q", "-e", "rst", "-D", "req,test", "--file", "links.toml"])
+        .output()
+        .expect("failed to run rstparser");
+    assert!(output.status.success(), "generate-config failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let template = fs::read_to_string(dir.path().join("links.toml")).unwrap();
+    let config: LinkConfig = toml::from_str(&template).unwrap();
+    let names: Vec<&str> = config.link_types.iter().map(|link_type| link_type.name.as_str()).collect();
+    assert_eq!(names, ["covers", "derives"]);
+    assert!(!dir.path().join("output").exists(), "generate-config must not create the output directory");
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+
+/// Kills the server when the test ends, also on failure.
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Sends `GET path` and returns the JSON body of the response.
+fn get(address: &str, path: &str) -> serde_json::Value {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, address).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").expect("an HTTP response");
+    assert!(head.starts_with("HTTP/1.1 200"), "unexpected response: {}", head);
+    serde_json::from_str(body).unwrap()
+}
+
+fn ids(directives: &serde_json::Value) -> Vec<&str> {
+    directives.as_array().unwrap().iter().map(|directive| directive["id"].as_str().unwrap()).collect()
+}
+
+#[test]
+fn test_serve_reflects_file_changes() {
+    let dir = tempdir().unwrap();
+    let docs = dir.path().join("docs");
+    fs::create_dir(&docs).unwrap();
+    fs::write(docs.join("reqs.rst"), ".. req::\n   :id: R1\n").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rstparser"))
+        .current_dir(dir.path())
+        .args(["--dir", "docs", "-e", "rst", "-D", "req", "--batch-window-ms", "50", "serve", "--bind", "127.0.0.1:0"])
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run rstparser");
+    let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+    let server = Server(child);
+
+    // The log line announcing the server carries the port picked by the system
+    let address = stderr.by_ref()
+        .map(|line| line.unwrap())
+        .find_map(|line| line.split_once("http://").and_then(|(_, rest)| rest.split_whitespace().next()).map(|address| address.trim_end_matches('.').to_string()))
+        .expect("the server address is logged");
+    // Keep reading the log so the server never blocks on a full pipe
+    thread::spawn(move || stderr.for_each(drop));
+
+    assert_eq!(ids(&get(&address, "/directives")), vec!["R1"]);
+    assert_eq!(get(&address, "/directives/R1")["source_file"].as_str().map(|file| file.ends_with("reqs.rst")), Some(true));
+    assert_eq!(get(&address, "/summary")["directive_count"], 1);
+
+    fs::write(docs.join("reqs.rst"), ".. req::\n   :id: R1\n\n.. req::\n   :id: R2\n   :links: R1\n").unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while ids(&get(&address, "/directives")) != vec!["R1", "R2"] {
+        assert!(Instant::now() < deadline, "the served directives were not updated");
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert_eq!(get(&address, "/summary")["directives_by_name"]["req"], 2);
+    assert!(get(&address, "/links").is_object());
+    drop(server);
+}
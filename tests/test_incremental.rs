@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+fn run(dir: &Path, extra_args: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_rstparser"))
+        .current_dir(dir)
+        .args(["-q", "-e", "rst", "-D", "req"])
+        .args(extra_args)
+        .output()
+        .expect("failed to run rstparser");
+    assert!(output.status.success(), "run failed: {}", String::from_utf8_lossy(&output.stderr));
+}
+
+/// `(id, arguments, links_back)` of the directives in `output/req.json`, sorted by ID.
+fn written(dir: &Path) -> Vec<(String, String, Option<String>)> {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("output/req.json")).unwrap()).unwrap();
+    let mut directives: Vec<(String, String, Option<String>)> = json.as_array().unwrap().iter()
+        .map(|d| (
+            d["id"].as_str().unwrap().to_string(),
+            d["arguments"].as_str().unwrap().to_string(),
+            d["options"]["links_back"].as_str().map(str::to_string),
+        ))
+        .collect();
+    directives.sort();
+    directives
+}
+
+#[test]
+fn test_changed_files_only_reprocesses_listed_files() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("rstparser_links.toml"), "[[links]]\nname = \"links\"\n").unwrap();
+    fs::write(dir.path().join("a.rst"), ".. req:: First\n   :id: A\n").unwrap();
+    fs::write(dir.path().join("b.rst"), ".. req:: First\n   :id: B\n   :links: A\n").unwrap();
+    fs::write(dir.path().join("c.rst"), ".. req:: First\n   :id: C\n").unwrap();
+    run(dir.path(), &[]);
+
+    fs::write(dir.path().join("a.rst"), ".. req:: Second\n   :id: A\n").unwrap();
+    fs::write(dir.path().join("b.rst"), ".. req:: Second\n   :id: B\n").unwrap(); // Not listed, so not reprocessed
+    fs::remove_file(dir.path().join("c.rst")).unwrap();
+    fs::write(dir.path().join("changed.txt"), "a.rst\nc.rst\n").unwrap();
+    run(dir.path(), &["--changed-files", "changed.txt"]);
+
+    assert_eq!(written(dir.path()), vec![
+        ("A".to_string(), "Second".to_string(), Some("B".to_string())),
+        ("B".to_string(), "First".to_string(), None),
+    ]);
+}
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .current_dir(dir)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .status()
+        .expect("failed to run git");
+    assert!(status.success(), "git {:?} failed", args);
+}
+
+#[test]
+fn test_since_includes_untracked_files() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("rstparser_links.toml"), "[[links]]\nname = \"links\"\n").unwrap();
+    fs::write(dir.path().join(".gitignore"), "output/\nignored.rst\n").unwrap();
+    fs::write(dir.path().join("a.rst"), ".. req:: First\n   :id: A\n").unwrap();
+    git(dir.path(), &["init", "-q"]);
+    git(dir.path(), &["add", "."]);
+    git(dir.path(), &["commit", "-q", "-m", "Initial"]);
+    run(dir.path(), &[]);
+
+    fs::write(dir.path().join("a.rst"), ".. req:: Second\n   :id: A\n").unwrap();
+    fs::write(dir.path().join("new.rst"), ".. req:: First\n   :id: N\n   :links: A\n").unwrap();
+    fs::write(dir.path().join("ignored.rst"), ".. req:: First\n   :id: I\n").unwrap();
+    run(dir.path(), &["--since", "HEAD"]);
+
+    assert_eq!(written(dir.path()), vec![
+        ("A".to_string(), "Second".to_string(), Some("N".to_string())),
+        ("N".to_string(), "First".to_string(), None),
+    ]);
+}
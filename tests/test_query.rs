@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+fn write_tree(dir: &Path) {
+    fs::write(dir.join("rstparser_links.toml"), "[[links]]\nname = \"links\"\n").unwrap();
+    fs::create_dir_all(dir.join("src/module_x")).unwrap();
+    fs::create_dir_all(dir.join("src/module_y")).unwrap();
+    fs::write(dir.join("src/module_x/reqs.rst"), ".. req::\n   :id: R1\n   :status: open\n\n.. req::\n   :id: R2\n   :status: done\n").unwrap();
+    fs::write(dir.join("src/module_y/reqs.rst"), ".. req::\n   :id: R3\n   :status: open\n   :links: R1\n").unwrap();
+}
+
+fn query_ids(dir: &Path, args: &[&str]) -> Vec<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rstparser"))
+        .current_dir(dir)
+        .args(["query", "-q", "--json", "--name", "req"])
+        .args(args)
+        .output()
+        .expect("failed to run rstparser");
+    assert!(output.status.success(), "query failed: {}", String::from_utf8_lossy(&output.stderr));
+    let results: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    results.as_array().unwrap().iter().map(|dws| dws["id"].as_str().unwrap().to_string()).collect()
+}
+
+#[test]
+fn test_query_filters_without_writing_output() {
+    let dir = tempdir().unwrap();
+    write_tree(dir.path());
+
+    assert_eq!(query_ids(dir.path(), &["--where", "status=open", "--path", "src/module_x/**"]), vec!["R1"]);
+    assert_eq!(query_ids(dir.path(), &["--where", "status!=open"]), vec!["R2"]);
+    let mut linked = query_ids(dir.path(), &["--linked", "links"]);
+    linked.sort();
+    assert_eq!(linked, vec!["R1", "R3"]);
+    assert_eq!(query_ids(dir.path(), &["--not-linked"]), vec!["R2"]);
+
+    assert!(!dir.path().join("output").exists(), "query must not create the output directory");
+}
+
+#[test]
+fn test_query_table_output() {
+    let dir = tempdir().unwrap();
+    write_tree(dir.path());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rstparser"))
+        .current_dir(dir.path())
+        .args(["query", "-q", "--name", "req", "--where", "has:links"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "ID  Directive  Location\nR3  req        reqs.rst:1\n");
+}
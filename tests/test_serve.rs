@@ -0,0 +1,33 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tempfile::tempdir;
+
+#[test]
+fn test_serve_answers_a_request_frame() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("reqs.rst"), "Requirements\n============\n\n.. req:: Serve\n   :id: REQ-7\n").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rstparser"))
+        .current_dir(dir.path())
+        .args(["-D", "req", "--serve"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run rstparser");
+    let request = r#"{"jsonrpc":"2.0","id":1,"method":"getDirectives","params":{"path":"reqs.rst"}}"#;
+    write!(child.stdin.take().unwrap(), "Content-Length: {}\r\n\r\n{}", request.len(), request).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "serve failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let (header, body) = stdout.split_once("\r\n\r\n").expect("a framed response");
+    assert_eq!(header, format!("Content-Length: {}", body.len()));
+    let response: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(response["id"], 1);
+    let directive = &response["result"]["directives"][0];
+    assert_eq!(directive["id"], "REQ-7");
+    assert_eq!(directive["line_number"], 4);
+    assert!(!dir.path().join("output").exists());
+}
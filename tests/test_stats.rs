@@ -0,0 +1,23 @@
+use std::fs;
+use std::process::Command;
+use tempfile::tempdir;
+
+#[test]
+fn test_stats_reports_phases_and_slowest_files() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("reqs.rst"), ".. req::\n   :id: a\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rstparser"))
+        .current_dir(dir.path())
+        .args(["-q", "-e", "rst", "-D", "req", "--stats"])
+        .output()
+        .expect("failed to run rstparser");
+    assert!(output.status.success(), "rstparser failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let phases: Vec<&str> = stdout.lines().skip(1).take(5).map(|line| line.split_whitespace().next().unwrap()).collect();
+    assert_eq!(phases, ["walk", "parse", "functions", "aggregate", "total"], "unexpected report:\n{}", stdout);
+    assert!(stdout.contains("Slowest files:\n"));
+    assert!(stdout.contains("reqs.rst\nLargest directives:\n"), "unexpected report:\n{}", stdout);
+    assert!(stdout.trim_end().ends_with("a (req at reqs.rst:1)"));
+}
@@ -0,0 +1,83 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::tempdir;
+
+fn run(dir: &Path, extra_args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rstparser"))
+        .current_dir(dir)
+        .args(["-o", "-", "-e", "rst", "-D", "req,note"])
+        .args(extra_args)
+        .output()
+        .expect("failed to run rstparser")
+}
+
+#[test]
+fn test_output_to_stdout_is_pure_json() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("reqs.rst"), ".. req::\n   :id: a\n\n.. note:: N\n").unwrap();
+
+    let output = run(dir.path(), &["--group-by", "all", "--summary", "-v"]);
+    assert!(output.status.success(), "run failed: {}", String::from_utf8_lossy(&output.stderr));
+    let directives: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(directives.as_array().unwrap().len(), 2);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Total"), "the summary goes to stderr");
+    assert!(!dir.path().join("output").exists());
+    assert!(!dir.path().join("-").exists());
+
+    let output = run(dir.path(), &["-q"]);
+    assert!(output.status.success());
+    let names: Vec<String> = String::from_utf8(output.stdout).unwrap().lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["name"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(names, vec!["note", "req"]);
+}
+
+#[test]
+fn test_output_to_stdout_rejects_check() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("reqs.rst"), ".. req::\n   :id: a\n").unwrap();
+
+    assert_eq!(run(dir.path(), &["--check"]).status.code(), Some(1));
+}
+
+#[test]
+fn test_csv_output_has_one_row_per_directive() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("reqs.rst"), ".. req:: One\n   :id: a\n   :status: open\n\n.. req:: Two\n   :id: b\n   :owner: me\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rstparser"))
+        .current_dir(dir.path())
+        .args(["-q", "-e", "rst", "-D", "req", "--output-format", "csv"])
+        .output()
+        .expect("failed to run rstparser");
+    assert!(output.status.success(), "rstparser failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let csv = fs::read_to_string(dir.path().join("output/req.csv")).unwrap();
+    let rows: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+    assert_eq!(rows[0], ["id", "name", "arguments", "source_file", "line_number", "owner", "status"]);
+    assert!(rows.iter().all(|row| row.len() == rows[0].len()));
+    let mut ids: Vec<&str> = rows[1..].iter().map(|row| row[0]).collect();
+    ids.sort();
+    assert_eq!(ids, ["a", "b"]);
+}
+
+#[test]
+fn test_link_types_filter_only_adds_selected_backlinks() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("rstparser_links.toml"), "[[links]]\nname = \"derives\"\n\n[[links]]\nname = \"tests\"\n").unwrap();
+    fs::write(
+        dir.path().join("reqs.rst"),
+        ".. req::\n   :id: a\n   :derives: b\n   :tests: b\n\n.. req::\n   :id: b\n",
+    )
+    .unwrap();
+
+    let output = run(dir.path(), &["-q", "--group-by", "all", "--link-types", "derives"]);
+    assert!(output.status.success(), "run failed: {}", String::from_utf8_lossy(&output.stderr));
+    let directives: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let b = directives.as_array().unwrap().iter().find(|directive| directive["id"] == "b").unwrap();
+    assert_eq!(b["options"]["derives_back"], "a");
+    assert!(b["options"].get("tests_back").is_none());
+    let a = directives.as_array().unwrap().iter().find(|directive| directive["id"] == "a").unwrap();
+    assert_eq!(a["options"]["tests"], "b", "unselected link options are kept as written");
+}
@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use rstparser::aggregator::{Aggregator, GroupBy};
+use rstparser::directive_functions::FunctionApplicator;
+use rstparser::file_walker::FileWalker;
+use rstparser::link_data::{LinkConfig, LinkGraph, LinkTypeConfig};
+use rstparser::processor::Processor;
+use rstparser::watcher::{WatchEvent, WatchSession};
+use tempfile::tempdir;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+type RecordedSpan = (String, BTreeMap<String, String>);
+
+/// Keeps the name and the recorded fields of every span.
+#[derive(Clone, Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<RecordedSpan>>>,
+}
+
+struct FieldVisitor<'a>(&'a mut BTreeMap<String, String>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let mut spans = self.spans.lock().unwrap();
+        let mut fields = BTreeMap::new();
+        attributes.record(&mut FieldVisitor(&mut fields));
+        spans.push((attributes.metadata().name().to_string(), fields));
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        values.record(&mut FieldVisitor(&mut spans[span.into_u64() as usize - 1].1));
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event<'_>) {}
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+impl Recorder {
+    /// The fields of the spans named `name`.
+    fn spans_named(&self, name: &str) -> Vec<BTreeMap<String, String>> {
+        self.spans.lock().unwrap().iter().filter(|(span_name, _)| span_name == name).map(|(_, fields)| fields.clone()).collect()
+    }
+}
+
+#[test]
+fn test_stages_create_spans_with_fields() {
+    let dir = tempdir().unwrap();
+    let output_dir = tempdir().unwrap();
+    let file = dir.path().join("reqs.rst");
+    let rst = ".. req::\n   :id: R1\n\n.. req::\n   :id: R2\n   :links: R1\n";
+    fs::write(&file, rst).unwrap();
+    let link_config = Arc::new(LinkConfig { link_types: vec![LinkTypeConfig::new("links")], ..Default::default() });
+    let recorder = Recorder::default();
+
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let directives = Processor::new(vec!["req".to_string()]).process_file(&file).unwrap();
+        let aggregator = Aggregator::new(output_dir.path(), GroupBy::All);
+        let mut directives_map = rstparser::directive_functions::PlainDirectivesMap::new();
+        for dws in directives {
+            directives_map.entry(file.canonicalize().unwrap()).or_default().insert(dws.id.clone(), dws);
+        }
+        let mut link_graph = LinkGraph::new();
+        FunctionApplicator::new(Arc::clone(&link_config)).apply_to_all(&directives_map, &mut link_graph);
+        aggregator.aggregate_map_to_json_with_links(&directives_map, &link_graph).unwrap();
+
+        let mut session = WatchSession::new(
+            Processor::new(vec!["req".to_string()]),
+            FunctionApplicator::new(Arc::clone(&link_config)),
+            Aggregator::new(output_dir.path(), GroupBy::All),
+            FileWalker::new().with_extensions(vec!["rst".to_string()]),
+        );
+        session.handle_event(WatchEvent::Created(file.clone()));
+    });
+
+    let process_file = &recorder.spans_named("process_file")[0];
+    assert!(process_file["file"].ends_with("reqs.rst"));
+    assert_eq!(process_file["directives"], "2");
+    assert!(process_file.contains_key("duration_ms"));
+    assert_eq!(recorder.spans_named("extract")[0]["rst_bytes"], rst.len().to_string());
+    assert_eq!(recorder.spans_named("apply_to_all")[0]["directives"], "2");
+    assert_eq!(recorder.spans_named("aggregate")[0]["directives"], "2");
+    assert_eq!(recorder.spans_named("write_outputs")[0]["files"], "1");
+    let watch_event = &recorder.spans_named("watch_event")[0];
+    assert!(watch_event["event"].starts_with("Created("));
+    assert_eq!(watch_event["affected_directives"], "2");
+    assert_eq!(recorder.spans_named("handle_events")[0]["affected_directives"], "2");
+}
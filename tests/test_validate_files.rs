@@ -0,0 +1,67 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::tempdir;
+
+const LINKS_TOML: &str = "[[links]]\nname = \"links\"\n";
+
+/// Runs the binary inside `dir`, so that `rstparser_links.toml` is picked up from there.
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rstparser"))
+        .current_dir(dir)
+        .args(["-e", "rst", "-D", "req"])
+        .args(args)
+        .output()
+        .expect("failed to run rstparser")
+}
+
+fn problems(output: &Output) -> Vec<serde_json::Value> {
+    serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap().as_array().unwrap().clone()
+}
+
+#[test]
+fn test_validate_staged_file_against_baseline() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("rstparser_links.toml"), LINKS_TOML).unwrap();
+    fs::write(dir.path().join("targets.rst"), ".. req::\n   :id: a\n").unwrap();
+    fs::write(dir.path().join("staged.rst"), ".. req::\n   :id: b\n   :links: a\n").unwrap();
+    assert!(run(dir.path(), &["-o", "baseline"]).status.success());
+
+    // Only the staged file is parsed; its link into the unstaged file resolves via the baseline
+    fs::remove_file(dir.path().join("targets.rst")).unwrap();
+    let output = run(dir.path(), &["validate", "--check", "--baseline", "baseline", "--files", "staged.rst"]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(problems(&output).is_empty());
+
+    fs::write(dir.path().join("staged.rst"), ".. req::\n   :id: b\n   :links: a, missing\n").unwrap();
+    let output = run(dir.path(), &["validate", "--baseline", "baseline", "--files", "staged.rst", "--check"]);
+    assert_eq!(output.status.code(), Some(2));
+    let problems = problems(&output);
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0]["rule"], "dangling-link");
+    assert!(problems[0]["file"].as_str().unwrap().ends_with("staged.rst"));
+
+    let output = run(dir.path(), &["validate", "--check", "--fail-on-dangling-links", "false", "--baseline", "baseline", "--files", "staged.rst"]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = run(dir.path(), &["validate", "--baseline", "baseline", "--files", "staged.rst"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown ID 'missing'"));
+    assert!(!dir.path().join("output").exists(), "validate must not write outputs");
+}
+
+#[test]
+fn test_validate_deleted_file_given_as_relative_path() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("rstparser_links.toml"), LINKS_TOML).unwrap();
+    fs::create_dir(dir.path().join("docs")).unwrap();
+    fs::write(dir.path().join("docs/old.rst"), ".. req::\n   :id: a\n").unwrap();
+    assert!(run(dir.path(), &["-o", "baseline"]).status.success());
+
+    // The directive moved to a new file; the baseline entry of the deleted one must be dropped
+    fs::remove_file(dir.path().join("docs/old.rst")).unwrap();
+    fs::write(dir.path().join("docs/new.rst"), ".. req::\n   :id: a\n").unwrap();
+    let output = run(dir.path(), &["validate", "--check", "--baseline", "baseline", "--files", "docs/old.rst", "docs/new.rst"]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(problems(&output).is_empty());
+}